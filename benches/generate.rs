@@ -0,0 +1,36 @@
+// full generation at a few map sizes, with features scaled to fill them.
+// cargo bench --bench generate
+
+extern crate criterion;
+extern crate dungen;
+
+use criterion::{ criterion_group, criterion_main, BenchmarkId, Criterion };
+use dungen::dungeon::Dungeon;
+
+// width, height, features
+const SIZES: [(isize, isize, isize); 3] = [(50, 50, 35), (200, 200, 500), (1000, 1000, 5000)];
+
+fn bench_generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate");
+    for &(width, height, features) in &SIZES {
+        if width >= 1000 {
+            group.sample_size(10);
+        }
+
+        let id = BenchmarkId::from_parameter(format!("{}x{}", width, height));
+        group.bench_function(id, |b| {
+            let mut seed = 0;
+            b.iter(|| {
+                let mut d = Dungeon::new(width, height);
+                // a failed layout has done its work all the same
+                let _ = d.generate_seeded(features, seed);
+                seed += 1;
+                d
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_generate);
+criterion_main!(benches);
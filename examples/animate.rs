@@ -0,0 +1,27 @@
+// replays generation one placement at a time, printing a frame after each.
+// cargo run --example animate
+
+extern crate dungen;
+
+use std::thread;
+use std::time::Duration;
+use dungen::dungeon::Dungeon;
+use dungen::trace::DebugTrace;
+
+fn main() {
+    let mut d = Dungeon::new(60, 30);
+    let (report, trace) = d.generate_debug(30, 99);
+    report.expect("generation failed");
+
+    // a trace cut short after n events rebuilds the map as it was at that point
+    for n in 1..=trace.events.len() {
+        let partial = DebugTrace { events: trace.events[..n].to_vec(), ..trace.clone() };
+
+        // clear the terminal and draw from the top left
+        print!("\x1b[2J\x1b[H{}", partial.replay().render());
+        println!("step {}/{}: {:?}", n, trace.events.len(), trace.events[n - 1]);
+        thread::sleep(Duration::from_millis(40));
+    }
+
+    assert_eq!(trace.replay().render(), d.render());
+}
@@ -0,0 +1,17 @@
+// generates a dungeon and prints it.
+// cargo run --example basic
+
+extern crate dungen;
+
+use dungen::dungeon::Dungeon;
+
+fn main() {
+    let mut d = Dungeon::new(80, 40);
+
+    // features are rooms and corridors. generation stops early if the map fills up.
+    let report = d.generate(60).expect("generation failed");
+
+    print!("{}", d.render());
+    println!("{} features, {} rooms, {:?} steps from entrance to exit",
+        report.features, report.rooms, report.exit_distance);
+}
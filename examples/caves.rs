@@ -0,0 +1,17 @@
+// grows a cave level and prints it. pass a seed to see a different cave.
+// cargo run --example caves -- 7
+
+extern crate dungen;
+
+use std::env;
+use dungen::dungeon::Dungeon;
+
+fn main() {
+    let seed = env::args().nth(1).map(|s| s.parse().expect("seed must be a number")).unwrap_or(1);
+
+    let mut d = Dungeon::new(80, 40);
+    let report = d.generate_caves_seeded(seed).expect("generation failed");
+
+    print!("{}", d.render());
+    println!("seed {}: {:?} steps from entrance to exit", seed, report.exit_distance);
+}
@@ -0,0 +1,30 @@
+// writes a generated dungeon's summary, audio zones and rebuildable trace as json.
+// cargo run --example export_json --features serde
+
+extern crate dungen;
+extern crate serde_json;
+
+use dungen::dungeon::Dungeon;
+
+fn main() {
+    let mut d = Dungeon::new(60, 30);
+
+    // generate_debug records every placement, so the trace rebuilds this exact map
+    let (report, trace) = d.generate_debug(40, 7);
+    let report = report.expect("generation failed");
+
+    let json = serde_json::json!({
+        "report": report,
+        "metrics": d.graph_metrics(),
+        "rows": d.render().lines().collect::<Vec<_>>(),
+        "audio_zones": d.audio_zones(),
+        "trace": trace,
+    });
+    let text = serde_json::to_string_pretty(&json).unwrap();
+
+    // reading the trace back gives the same dungeon
+    let back: dungen::trace::DebugTrace = serde_json::from_value(json["trace"].clone()).unwrap();
+    assert_eq!(back.replay().render(), d.render());
+
+    println!("{}", text);
+}
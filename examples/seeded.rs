@@ -0,0 +1,24 @@
+// the same seed gives the same dungeon, on every platform, for a given GENERATION_VERSION.
+// cargo run --example seeded
+
+extern crate dungen;
+
+use dungen::dungeon::{ Dungeon, GENERATION_VERSION };
+
+fn main() {
+    let seed = 12345;
+
+    let mut a = Dungeon::new(80, 40);
+    let mut b = Dungeon::new(80, 40);
+    a.generate_seeded(40, seed).expect("generation failed");
+    b.generate_seeded(40, seed).expect("generation failed");
+    assert_eq!(a.render(), b.render());
+
+    // a different seed almost always gives a different map
+    let mut c = Dungeon::new(80, 40);
+    c.generate_seeded(40, seed + 1).expect("generation failed");
+    assert_ne!(a.render(), c.render());
+
+    print!("{}", a.render());
+    println!("seed {} is stable for generation version {}", seed, GENERATION_VERSION);
+}
@@ -0,0 +1,71 @@
+// exports a dungeon as a dense texture index buffer for a gpu tilemap.
+// cargo run --example tilemap_layer
+//
+// with bevy_ecs_tilemap the buffer maps straight onto a TileStorage. tilemaps count rows
+// from the bottom, so y is flipped on the way in:
+//
+//     let (width, height) = d.layer_dimensions();
+//     let size = TilemapSize { x: width, y: height };
+//     let mut storage = TileStorage::empty(size);
+//     for (i, &index) in indices.iter().enumerate() {
+//         let (x, y) = (i as u32 % width, i as u32 / width);
+//         let pos = TilePos { x, y: height - 1 - y };
+//         let tile = commands.spawn(TileBundle {
+//             position: pos,
+//             tilemap_id: TilemapId(tilemap),
+//             texture_index: TileTextureIndex(index),
+//             ..Default::default()
+//         }).id();
+//         storage.set(&pos, tile);
+//     }
+
+extern crate dungen;
+
+use dungen::dungeon::{ Dungeon, Tile };
+
+// one past the largest index atlas_index gives
+const ATLAS_SIZE: usize = 18;
+
+// positions in a hypothetical tileset atlas
+fn atlas_index(tile: Tile) -> u32 {
+    match tile {
+        Tile::Unused => 0,
+        Tile::Floor => 1,
+        Tile::Corridor => 2,
+        Tile::Wall => 3,
+        Tile::ClosedDoor => 4,
+        Tile::OpenDoor => 5,
+        Tile::Entrance => 6,
+        Tile::Exit => 7,
+        Tile::Npc => 8,
+        Tile::Torch => 9,
+        Tile::Grass => 10,
+        Tile::Pit => 11,
+        Tile::Archway => 12,
+        Tile::Window => 13,
+        Tile::Altar => 14,
+        Tile::Pillar => 15,
+        Tile::Water => 16,
+        Tile::SecretDoor => 17,
+        // tiles added after this was written, drawn as floor or wall
+        _ if tile.is_walkable_default() => 1,
+        _ => 3,
+    }
+}
+
+fn main() {
+    let mut d = Dungeon::new(80, 50);
+    d.generate_seeded(50, 42).expect("generation failed");
+
+    let (width, height) = d.layer_dimensions();
+    let indices = d.tile_indices(atlas_index);
+    assert_eq!(indices.len(), (width * height) as usize);
+
+    // every tile has a slot, whatever the map holds
+    assert!(Tile::ALL.iter().all(|&tile| (atlas_index(tile) as usize) < ATLAS_SIZE));
+    let mut counts = [0; ATLAS_SIZE];
+    for &i in &indices {
+        counts[i as usize] += 1;
+    }
+    println!("{}x{} layer, tiles per atlas index: {:?}", width, height, counts);
+}
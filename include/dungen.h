@@ -0,0 +1,73 @@
+/*
+C interface to dungen, built with `cargo build --release --features ffi`. Link against
+the cdylib (libdungen.so, dungen.dll or libdungen.dylib) in target/release.
+
+Dungeons are opaque handles. Every pointer passed in must come from dungen_new and not yet
+have been given to dungen_free; null is reported as DUNGEN_ERR_NULL rather than crashing.
+Out buffers must hold at least len bytes.
+*/
+
+#ifndef DUNGEN_H
+#define DUNGEN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Dungeon Dungeon;
+
+#define DUNGEN_OK 0
+#define DUNGEN_ERR_NULL -1
+#define DUNGEN_ERR_GENERATION -2
+#define DUNGEN_ERR_BUFFER -3
+#define DUNGEN_ERR_PANIC -4
+
+/* tile bytes */
+#define DUNGEN_TILE_UNUSED 0
+#define DUNGEN_TILE_FLOOR 1
+#define DUNGEN_TILE_CORRIDOR 2
+#define DUNGEN_TILE_WALL 3
+#define DUNGEN_TILE_CLOSED_DOOR 4
+#define DUNGEN_TILE_OPEN_DOOR 5
+#define DUNGEN_TILE_EXIT 6
+#define DUNGEN_TILE_ENTRANCE 7
+#define DUNGEN_TILE_NPC 8
+#define DUNGEN_TILE_TORCH 9
+#define DUNGEN_TILE_GRASS 10
+#define DUNGEN_TILE_PIT 11
+#define DUNGEN_TILE_ARCHWAY 12
+#define DUNGEN_TILE_WINDOW 13
+#define DUNGEN_TILE_ALTAR 14
+#define DUNGEN_TILE_PILLAR 15
+#define DUNGEN_TILE_WATER 16
+#define DUNGEN_TILE_SECRET_DOOR 17
+#define DUNGEN_TILE_INVALID 255
+
+/* an empty map, or null if the dimensions are unusable */
+Dungeon *dungen_new(int32_t width, int32_t height);
+
+/* replaces the map with a fresh one from the seed. DUNGEN_OK on success. */
+int32_t dungen_generate(Dungeon *d, int32_t maxfeatures, uint64_t seed);
+
+/* 0 for a null dungeon */
+int32_t dungen_width(const Dungeon *d);
+int32_t dungen_height(const Dungeon *d);
+
+/* tiles outside the map read as DUNGEN_TILE_UNUSED, a null dungeon as DUNGEN_TILE_INVALID */
+uint8_t dungen_tile_at(const Dungeon *d, int32_t x, int32_t y);
+
+/* writes width * height tile bytes, row-major, x + y * width. DUNGEN_ERR_BUFFER and nothing
+   written if len is short. */
+int32_t dungen_copy_tiles(const Dungeon *d, uint8_t *out, size_t len);
+
+/* null is ignored */
+void dungen_free(Dungeon *d);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
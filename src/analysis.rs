@@ -0,0 +1,174 @@
+/*
+Read-only analysis of the corridor network: where corridors branch, and how each
+corridor run ends. Everything here is a pure function of the tile grid and scans it in
+row-major order, so results are deterministic.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+
+// a corridor tile where three or four walkable paths meet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Junction {
+    pub pos: Point,
+    // walkable orthogonal neighbours: 3 for a T-junction, 4 for a crossroads
+    pub arms: u8,
+}
+
+impl Junction {
+    pub fn is_crossroads(&self) -> bool {
+        self.arms == 4
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EndKind {
+    // the corridor stops with nowhere else to go
+    DeadEnd,
+    // the corridor ends at a door
+    Door,
+    // the corridor opens straight onto floor without a door
+    Open,
+}
+
+// the last tile of a corridor run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CorridorEnd {
+    pub pos: Point,
+    pub kind: EndKind,
+}
+
+impl Dungeon {
+    pub fn junctions(&self) -> Vec<Junction> {
+        let mut out = Vec::new();
+        self.each_corridor(|p| {
+            let arms = self.walkable_arms(p);
+            if arms >= 3 {
+                out.push(Junction { pos: p, arms });
+            }
+        });
+
+        out
+    }
+
+    // corridor tiles with at most one corridor neighbour, classified by what lies past them
+    pub fn corridor_ends(&self) -> Vec<CorridorEnd> {
+        let mut out = Vec::new();
+        self.each_corridor(|p| {
+            let mut corridors = 0;
+            let mut doors = 0;
+            let mut other = 0;
+
+            for dir in Dir::iterator() {
+                let (dx, dy) = dir.offset();
+                match self.get(p.offset(dx, dy)) {
+                    Tile::Corridor => corridors += 1,
+                    Tile::ClosedDoor | Tile::OpenDoor => doors += 1,
+                    t if t.is_walkable() => other += 1,
+                    _ => {}
+                }
+            }
+
+            if corridors > 1 {
+                return
+            }
+
+            let kind = if doors > 0 {
+                EndKind::Door
+            }
+
+            else if other > 0 {
+                EndKind::Open
+            }
+
+            // nothing but the rest of the corridor, or nothing at all for a lone tile
+            else {
+                EndKind::DeadEnd
+            };
+
+            out.push(CorridorEnd { pos: p, kind });
+        });
+
+        out
+    }
+
+    fn each_corridor<F: FnMut(Point)>(&self, mut f: F) {
+        let size = self.size();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                if self.get((x, y)) == Tile::Corridor {
+                    f(Point::new(x, y));
+                }
+            }
+        }
+    }
+
+    fn walkable_arms(&self, p: Point) -> u8 {
+        Dir::iterator()
+            .filter(|dir| {
+                let (dx, dy) = dir.offset();
+                self.get(p.offset(dx, dy)).is_walkable()
+            })
+            .count() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use analysis::*;
+    use dungeon::Dungeon;
+    use geom::Point;
+
+    #[test]
+    fn test_junction_degrees() {
+        let d = Dungeon::parse(&[
+            "#########",
+            "#,,,,,,,#",
+            "#,###,###",
+            "#,# #,#  ",
+            "###,,,,,#",
+            "  #######",
+        ]);
+        let junctions = d.junctions();
+
+        assert_eq!(junctions, vec![
+            Junction { pos: Point::new(5, 1), arms: 3 },
+            Junction { pos: Point::new(5, 4), arms: 3 },
+        ]);
+        assert!(!junctions[0].is_crossroads());
+
+        let cross = Dungeon::parse(&[
+            " ### ",
+            "##,##",
+            "#,,,#",
+            "##,##",
+            " ### ",
+        ]);
+        assert_eq!(cross.junctions(), vec![Junction { pos: Point::new(2, 2), arms: 4 }]);
+        assert!(cross.junctions()[0].is_crossroads());
+    }
+
+    #[test]
+    fn test_corridor_end_kinds() {
+        let d = Dungeon::parse(&[
+            "##########",
+            "#...+,,,,#",
+            "#...######",
+            "#....,,,,#",
+            "##########",
+        ]);
+        let ends = d.corridor_ends();
+
+        assert_eq!(ends, vec![
+            CorridorEnd { pos: Point::new(5, 1), kind: EndKind::Door },
+            CorridorEnd { pos: Point::new(8, 1), kind: EndKind::DeadEnd },
+            CorridorEnd { pos: Point::new(5, 3), kind: EndKind::Open },
+            CorridorEnd { pos: Point::new(8, 3), kind: EndKind::DeadEnd },
+        ]);
+    }
+}
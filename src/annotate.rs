@@ -0,0 +1,210 @@
+/*
+ASCII rendering with overlays for sharing maps: room numbers written over the floor, rulers
+every 10 tiles along the top and left, and a legend naming every glyph on the map. Each
+overlay can be turned off on its own.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+use geom::Point;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AnnotateOptions {
+    pub legend: bool,
+    // each room's id written on its floor, near the middle
+    pub room_numbers: bool,
+    // tile coordinates every 10 tiles along the top and left edges
+    pub rulers: bool,
+}
+
+impl Default for AnnotateOptions {
+    fn default() -> AnnotateOptions {
+        AnnotateOptions { legend: true, room_numbers: true, rulers: true }
+    }
+}
+
+static TILES: [Tile; 18] = [Tile::Floor, Tile::Grass, Tile::Corridor, Tile::Wall, Tile::Torch, Tile::Window, Tile::ClosedDoor,
+                            Tile::OpenDoor, Tile::Archway, Tile::SecretDoor, Tile::Entrance, Tile::Exit, Tile::Pit, Tile::Altar, Tile::Pillar,
+                            Tile::Water, Tile::Npc, Tile::Unused];
+
+fn tile_name(tile: Tile) -> &'static str {
+    match tile {
+        Tile::Unused => "unused",
+        Tile::Floor => "floor",
+        Tile::Corridor => "corridor",
+        Tile::Wall => "wall",
+        Tile::ClosedDoor => "closed door",
+        Tile::OpenDoor => "open door",
+        Tile::Entrance => "entrance",
+        Tile::Exit => "exit",
+        Tile::Npc => "npc",
+        Tile::Torch => "torch",
+        Tile::Grass => "grass",
+        Tile::Pit => "pit",
+        Tile::Archway => "archway",
+        Tile::Window => "window",
+        Tile::Altar => "altar",
+        Tile::Pillar => "pillar",
+        Tile::Water => "water",
+        Tile::SecretDoor => "secret door",
+    }
+}
+
+impl Dungeon {
+    // rooms whose number won't fit on a single row of their floor get a '*' instead, and are
+    // listed with their position under the map
+    pub fn render_annotated(&self, opts: AnnotateOptions) -> String {
+        let size = self.size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let mut grid: Vec<char> = self.render().chars().filter(|&c| c != '\n').collect();
+        let mut unlabeled = Vec::new();
+
+        if opts.room_numbers {
+            let graph = self.graph();
+            for id in graph.room_ids() {
+                let tiles = graph.room_tiles(id);
+                let label = id.0.to_string();
+                let (cx, cy) = (
+                    tiles.iter().map(|p| p.x).sum::<isize>() as f32 / tiles.len() as f32,
+                    tiles.iter().map(|p| p.y).sum::<isize>() as f32 / tiles.len() as f32,
+                );
+
+                let mut floor: Vec<Point> = tiles.iter().cloned().filter(|&p| matches!(self.get(p), Tile::Floor | Tile::Grass)).collect();
+                floor.sort_by(|a, b| {
+                    let d = |p: &Point| (p.x as f32 + (label.len() - 1) as f32 / 2.0 - cx).powi(2) + (p.y as f32 - cy).powi(2);
+                    d(a).partial_cmp(&d(b)).unwrap().then((a.y, a.x).cmp(&(b.y, b.x)))
+                });
+
+                // the label runs rightward from its first digit over floor of this room only
+                let fits = |p: &Point| (0..label.len() as isize).all(|i| {
+                    let q = p.offset(i, 0);
+                    q.x < size.width && floor.contains(&q)
+                });
+
+                match floor.iter().find(|p| fits(p)) {
+                    Some(&p) => {
+                        for (i, c) in label.chars().enumerate() {
+                            grid[p.x as usize + i + p.y as usize * width] = c;
+                        }
+                    }
+                    None => {
+                        if let Some(&p) = floor.first() {
+                            grid[p.x as usize + p.y as usize * width] = '*';
+                            unlabeled.push((label, p));
+                        }
+                    }
+                }
+            }
+        }
+
+        let margin = if opts.rulers { (height.saturating_sub(1)).to_string().len() + 1 } else { 0 };
+        let mut out = String::new();
+
+        if opts.rulers {
+            let mut ruler = vec![' '; width];
+            for x in (0..width).step_by(10) {
+                for (i, c) in x.to_string().chars().enumerate() {
+                    if x + i < width {
+                        ruler[x + i] = c;
+                    }
+                }
+            }
+
+            out.push_str(&" ".repeat(margin));
+            out.extend(ruler);
+            out.push('\n');
+        }
+
+        for (y, row) in grid.chunks(width.max(1)).enumerate().take(height) {
+            if opts.rulers {
+                let number = if y % 10 == 0 { y.to_string() } else { String::new() };
+                out.push_str(&format!("{:>w$} ", number, w = margin - 1));
+            }
+            out.extend(row);
+            out.push('\n');
+        }
+
+        if opts.legend {
+            out.push('\n');
+            for &tile in TILES.iter().filter(|&&t| t != Tile::Unused) {
+                if self.tile_indices(|t| (t == tile) as u32).contains(&1) {
+                    out.push_str(&format!("{} {}\n", tile.glyph(), tile_name(tile)));
+                }
+            }
+            if opts.room_numbers {
+                out.push_str("0-9 room number\n");
+            }
+        }
+
+        if !unlabeled.is_empty() {
+            out.push('\n');
+            for (label, p) in unlabeled {
+                out.push_str(&format!("* room {} at ({}, {})\n", label, p.x, p.y));
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use annotate::*;
+    use dungeon::Dungeon;
+
+    fn fixture() -> Dungeon {
+        Dungeon::parse(&[
+            "############",
+            "#<...+,,+..#",
+            "#....#  #..#",
+            "######  ####",
+        ])
+    }
+
+    #[test]
+    fn test_overlays_toggle_independently() {
+        let d = fixture();
+        let none = AnnotateOptions { legend: false, room_numbers: false, rulers: false };
+
+        assert_eq!(d.render_annotated(none), d.render());
+        assert_eq!(d.render_annotated(AnnotateOptions { legend: true, ..none }), format!("{}\n{}", d.render(),
+            ". floor\n, corridor\n# wall\n+ closed door\n< entrance\n"));
+        assert_eq!(d.render_annotated(AnnotateOptions { rulers: true, ..none }), [
+            "  0         10",
+            "0 ############",
+            "  #<...+,,+..#",
+            "  #....#  #..#",
+            "  ######  ####",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_room_numbers() {
+        let d = fixture();
+        let graph = d.graph();
+        let (left, right) = (graph.room_at((1, 1).into()).unwrap(), graph.room_at((9, 1).into()).unwrap());
+        let text = d.render_annotated(AnnotateOptions { legend: false, room_numbers: true, rulers: false });
+        let rows: Vec<&str> = text.lines().collect();
+
+        assert!(rows[1].contains(&left.0.to_string()) || rows[2].contains(&left.0.to_string()));
+        assert!(text.contains(&right.0.to_string()));
+        assert!(!text.contains('*'));
+    }
+
+    #[test]
+    fn test_room_too_small_for_its_number() {
+        // enough one-tile rooms that the last ones need two digits
+        let mut rows = vec!["#".repeat(25)];
+        rows.push(format!("#{}", ".#".repeat(12)));
+        rows.push("#".repeat(25));
+        let rows: Vec<&str> = rows.iter().map(|r| r.as_str()).collect();
+        let d = Dungeon::parse(&rows);
+        let text = d.render_annotated(AnnotateOptions { legend: false, room_numbers: true, rulers: false });
+
+        assert_eq!(text.lines().nth(1).unwrap(), "#0#1#2#3#4#5#6#7#8#9#*#*#");
+        assert!(text.contains("* room 10 at (21, 1)\n* room 11 at (23, 1)\n"));
+    }
+}
@@ -0,0 +1,172 @@
+/*
+Acoustic zones for audio engines that want axis-aligned trigger boxes rather than tiles.
+Every room becomes one or more boxes covering its tiles, pillars and objects included, of a
+kind picked from its size and environment: a hall, a chamber, a courtyard open to the sky, or
+a cave. All corridor tiles together become tunnel boxes, and all water tiles water boxes, cut
+out of the rooms they lie in. Doors and archways sit between zones and belong to none.
+
+A room that isn't a rect, or a run of corridors, is covered greedily: the first tile not yet
+covered starts a box, grown along its row as far as it goes and then down for as many whole
+rows as it can take. Corridors laid end to end in a line come out as one long box. The same
+is done with rows and columns swapped, and whichever takes fewer boxes wins, so a corridor
+meeting another side on gets two boxes rather than three.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+use geom::{ self, Point, Rect };
+use rooms::RoomEnvironment;
+
+// interior rooms of at least this many tiles are halls, smaller ones chambers
+pub const HALL_MIN_AREA: usize = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ZoneKind {
+    Hall,
+    Chamber,
+    Tunnel,
+    Courtyard,
+    Cave,
+    Water,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AudioZone {
+    pub rect: Rect,
+    pub kind: ZoneKind,
+}
+
+impl Dungeon {
+    // the zones of every room, in id order, then the tunnels, then the water. each room's,
+    // the tunnels' or the water's boxes are in row-major order of their top left tiles, and
+    // no two boxes overlap.
+    pub fn audio_zones(&self) -> Vec<AudioZone> {
+        let graph = self.graph();
+        let mut zones = Vec::new();
+
+        for id in graph.room_ids() {
+            let tiles = graph.room_tiles(id);
+            let kind = match self.environment(id) {
+                RoomEnvironment::Courtyard => ZoneKind::Courtyard,
+                RoomEnvironment::Cave => ZoneKind::Cave,
+                RoomEnvironment::Interior if tiles.len() >= HALL_MIN_AREA => ZoneKind::Hall,
+                RoomEnvironment::Interior => ZoneKind::Chamber,
+            };
+            let dry: Vec<Point> = tiles.iter().cloned().filter(|&p| self.get(p) != Tile::Water).collect();
+            zones.extend(geom::cover(&dry).into_iter().map(|rect| AudioZone { rect, kind }));
+        }
+
+        let size = self.size();
+        for &(tile, kind) in &[(Tile::Corridor, ZoneKind::Tunnel), (Tile::Water, ZoneKind::Water)] {
+            let tiles: Vec<Point> = (0..size.height)
+                .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+                .filter(|&p| self.get(p) == tile)
+                .collect();
+            zones.extend(geom::cover(&tiles).into_iter().map(|rect| AudioZone { rect, kind }));
+        }
+        zones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use audio::*;
+    use dungeon::Dungeon;
+    use rng::DungenRng;
+
+    #[test]
+    fn test_cover() {
+        // a corridor running into the side of another, and a room that isn't a rect
+        let d = Dungeon::parse(&[
+            "#################",
+            "#,,,,,,,,,,,#...#",
+            "#####,#######...#",
+            "    #,#  #......#",
+            "    #,#  ########",
+            "    ###          ",
+        ]);
+        let zones = d.audio_zones();
+        let rects = |kind: ZoneKind| zones.iter().filter(|z| z.kind == kind).map(|z| z.rect).collect::<Vec<_>>();
+
+        assert_eq!(rects(ZoneKind::Tunnel), vec![Rect::new(1, 1, 11, 1), Rect::new(5, 2, 1, 3)]);
+        assert_eq!(rects(ZoneKind::Chamber), vec![Rect::new(13, 1, 3, 3), Rect::new(10, 3, 3, 1)]);
+        assert_eq!(zones.len(), 4);
+    }
+
+    #[test]
+    fn test_water() {
+        // a pool in the middle of a room, and a room that's all water
+        let d = Dungeon::parse(&[
+            "###########",
+            "#.....#~~~#",
+            "#.~~..+~~~#",
+            "#.~~..#####",
+            "#.....#    ",
+            "#######    ",
+        ]);
+        let zones = d.audio_zones();
+        let rects = |kind: ZoneKind| zones.iter().filter(|z| z.kind == kind).map(|z| z.rect).collect::<Vec<_>>();
+
+        assert_eq!(rects(ZoneKind::Water), vec![Rect::new(7, 1, 3, 2), Rect::new(2, 2, 2, 2)]);
+        assert_eq!(rects(ZoneKind::Chamber).iter().map(|r| r.width * r.height).sum::<isize>(), 20 - 4);
+        assert!(zones.iter().all(|z| z.rect.points().all(|p| (d.get(p) == Tile::Water) == (z.kind == ZoneKind::Water))));
+    }
+
+    #[test]
+    fn test_generated_zones() {
+        let mut seen = BTreeSet::new();
+        for seed in 0..4 {
+            let mut d = Dungeon::new(80, 50);
+            d.generate_hybrid_seeded(60, seed).unwrap();
+            d.assign_courtyards(0.5, &mut DungenRng::new(seed));
+            let zones = d.audio_zones();
+
+            // every room and corridor tile is covered once, and nothing else
+            let size = d.size();
+            let mut covered = vec![0; (size.width * size.height) as usize];
+            for zone in &zones {
+                for p in zone.rect.points() {
+                    covered[(p.x + p.y * size.width) as usize] += 1;
+                }
+            }
+            let graph = d.graph();
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    let p = Point::new(x, y);
+                    let zoned = graph.room_at(p).is_some() || d.get(p) == Tile::Corridor;
+                    assert_eq!(covered[(x + y * size.width) as usize], zoned as usize, "seed {}: {:?}", seed, p);
+                }
+            }
+
+            // and each room's boxes are of the one kind
+            for id in graph.room_ids() {
+                let kinds: BTreeSet<_> = zones.iter()
+                    .filter(|z| z.kind != ZoneKind::Water && graph.room_at(z.rect.origin()) == Some(id))
+                    .map(|z| z.kind as u8)
+                    .collect();
+                assert!(kinds.len() <= 1, "seed {}: {:?}", seed, id);
+            }
+            seen.extend(zones.iter().map(|z| z.kind as u8));
+        }
+        // a cave can be made a courtyard, so caves only turn up on some seeds
+        for kind in [ZoneKind::Hall, ZoneKind::Chamber, ZoneKind::Tunnel, ZoneKind::Cave, ZoneKind::Courtyard] {
+            assert!(seen.contains(&(kind as u8)), "no {:?}", kind);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_zones_serialize() {
+        use serde_json;
+
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(30, 1).unwrap();
+        let zones = d.audio_zones();
+        let back: Vec<AudioZone> = serde_json::from_str(&serde_json::to_string(&zones).unwrap()).unwrap();
+        assert_eq!(back, zones);
+    }
+}
@@ -0,0 +1,303 @@
+/*
+Command line generator.
+
+dungen [--width 80] [--height 40] [--features 60] [--seed N] [--count 1]
+       [--format ascii|json|png] [--scale 4] [--out FILE]
+
+Maps go to stdout unless --out is given; png needs --out. With --count N, seeds run from
+--seed upward and each map is written to FILE with its seed inserted before the extension,
+e.g. map-42.txt, map-43.txt. Exits with 1 when generation fails and 2 on bad arguments.
+*/
+
+extern crate dungen;
+
+use std::env;
+use std::fs::File;
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+use std::process;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use dungen::dungeon::{ Dungeon, Tile };
+
+const USAGE: &str = "usage: dungen [--width 80] [--height 40] [--features 60] [--seed N] [--count 1]
+              [--format ascii|json|png] [--scale 4] [--out FILE]";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    Json,
+    Png,
+}
+
+struct Options {
+    width: isize,
+    height: isize,
+    features: isize,
+    seed: u64,
+    count: u64,
+    format: Format,
+    scale: usize,
+    out: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        width: 80,
+        height: 40,
+        features: 60,
+        seed: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0),
+        count: 1,
+        format: Format::Ascii,
+        scale: 4,
+        out: None,
+    };
+
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        if flag == "--help" || flag == "-h" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+
+        let value = iter.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--width" => opts.width = number(flag, value)?,
+            "--height" => opts.height = number(flag, value)?,
+            "--features" => opts.features = number(flag, value)?,
+            "--seed" => opts.seed = number(flag, value)?,
+            "--count" => opts.count = number(flag, value)?,
+            "--scale" => opts.scale = number(flag, value)?,
+            "--out" => opts.out = Some(PathBuf::from(value)),
+            "--format" => {
+                opts.format = match value.as_str() {
+                    "ascii" => Format::Ascii,
+                    "json" => Format::Json,
+                    "png" => Format::Png,
+                    _ => return Err(format!("unknown format '{}', expected ascii, json or png", value)),
+                }
+            }
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+
+    if opts.count == 0 {
+        return Err("--count must be at least 1".to_string())
+    }
+    if opts.scale == 0 {
+        return Err("--scale must be at least 1".to_string())
+    }
+    if opts.format == Format::Png && opts.out.is_none() {
+        return Err("png output needs --out".to_string())
+    }
+
+    Ok(opts)
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", flag, value))
+}
+
+// map.txt becomes map-42.txt for seed 42
+fn numbered(path: &Path, seed: u64) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, seed, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, seed),
+    };
+
+    path.with_file_name(name)
+}
+
+fn to_json(d: &Dungeon, seed: u64) -> String {
+    let point = |p: Option<dungen::dungeon::Point>| match p {
+        Some(p) => format!("[{},{}]", p.x, p.y),
+        None => "null".to_string(),
+    };
+    // grass is drawn as a quote, so glyphs are escaped like any other string
+    let rows: Vec<String> = d.render().lines().map(|row| format!("\"{}\"", escape(row))).collect();
+    let size = d.size();
+
+    format!("{{\"width\":{},\"height\":{},\"seed\":{},\"entrance\":{},\"exit\":{},\"rows\":[{}]}}\n",
+        size.width, size.height, seed, point(d.entrance()), point(d.exit()), rows.join(","))
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn color(tile: Tile) -> [u8; 3] {
+    match tile {
+        Tile::Unused => [0, 0, 0],
+        Tile::Floor => [200, 200, 190],
+        Tile::Corridor => [150, 150, 140],
+        Tile::Wall => [70, 60, 60],
+        Tile::ClosedDoor => [140, 90, 40],
+        Tile::OpenDoor => [190, 140, 80],
+        Tile::Exit => [200, 40, 40],
+        Tile::Entrance => [40, 170, 60],
+        Tile::Npc => [60, 110, 210],
+        Tile::Torch => [240, 180, 60],
+        Tile::Grass => [90, 160, 70],
+        Tile::Pit => [25, 20, 20],
+        Tile::Archway => [170, 150, 120],
+        Tile::Window => [120, 170, 200],
+        Tile::Altar => [220, 210, 120],
+        Tile::Pillar => [110, 100, 95],
+        Tile::Water => [50, 90, 160],
+        Tile::SecretDoor => [90, 70, 65],
+        _ if tile.is_walkable_default() => [200, 200, 190],
+        _ => [70, 60, 60],
+    }
+}
+
+// an rgb png with each tile drawn as a scale x scale square, stored uncompressed
+fn to_png(d: &Dungeon, scale: usize) -> Vec<u8> {
+    let (width, height) = d.layer_dimensions();
+    let (w, h) = (width as usize * scale, height as usize * scale);
+    let colors = d.tile_indices(|t| {
+        let [r, g, b] = color(t);
+        u32::from_be_bytes([0, r, g, b])
+    });
+
+    let mut raw = Vec::with_capacity((w * 3 + 1) * h);
+    for y in 0..h {
+        // filter type none
+        raw.push(0);
+        for x in 0..w {
+            raw.extend_from_slice(&colors[x / scale + (y / scale) * width as usize].to_be_bytes()[1..]);
+        }
+    }
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    let mut header = Vec::new();
+    header.extend_from_slice(&(w as u32).to_be_bytes());
+    header.extend_from_slice(&(h as u32).to_be_bytes());
+    // 8 bits per channel, rgb, deflate, standard filters, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(65535).collect() };
+
+    for (i, block) in blocks.iter().enumerate() {
+        out.push((i + 1 == blocks.len()) as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn run(opts: &Options) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for i in 0..opts.count {
+        let seed = opts.seed.wrapping_add(i);
+        let mut d = Dungeon::try_new(opts.width, opts.height).map_err(|e| e.to_string())?;
+        d.generate_seeded(opts.features, seed).map_err(|e| format!("seed {}: {}", seed, e))?;
+
+        let bytes = match opts.format {
+            Format::Ascii => d.render().into_bytes(),
+            Format::Json => to_json(&d, seed).into_bytes(),
+            Format::Png => to_png(&d, opts.scale),
+        };
+
+        match opts.out {
+            Some(ref path) => {
+                let path = if opts.count > 1 { numbered(path, seed) } else { path.clone() };
+                File::create(&path).and_then(|mut f| f.write_all(&bytes))
+                    .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+            }
+            None => {
+                // blank line between ascii maps. json is one object per line already.
+                if i > 0 && opts.format == Format::Ascii {
+                    writeln!(stdout).map_err(|e| e.to_string())?;
+                }
+                stdout.write_all(&bytes).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("dungen: {}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&opts) {
+        eprintln!("dungen: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+
+    #[test]
+    fn test_json_escapes_glyphs() {
+        // a surface level opens onto grass, drawn as a quote
+        let mut d = Dungeon::new(60, 40);
+        d.generate_with_surface_seeded(6, 40, 3).unwrap();
+        assert!(d.render().contains('"'));
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&d, 3)).unwrap();
+        let rows: Vec<&str> = json["rows"].as_array().unwrap().iter().map(|row| row.as_str().unwrap()).collect();
+        assert_eq!(rows, d.render().lines().collect::<Vec<_>>());
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,172 @@
+/*
+Tiles taken by things that move: monsters, the player, a pushed boulder. Blockers is a bitset
+over the dungeon's tile indices that the caller updates each turn, so pathing and placement
+can route around occupants without copying or touching the map.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+use geom::{ Point, Size };
+use nav::Connectivity;
+use rng::DungenRng;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Blockers {
+    size: Size,
+    wraps: bool,
+    bits: Vec<u64>,
+}
+
+impl Blockers {
+    // nothing blocked, sized for the dungeon
+    pub fn new(dungeon: &Dungeon) -> Blockers {
+        let size = dungeon.size();
+        Blockers { size, wraps: dungeon.wraps(), bits: vec![0; (size.area() as usize).div_ceil(64)] }
+    }
+
+    // points off the map are ignored
+    pub fn set_blocked<P: Into<Point>>(&mut self, p: P) {
+        if let Some(i) = self.index(p.into()) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    pub fn clear_blocked<P: Into<Point>>(&mut self, p: P) {
+        if let Some(i) = self.index(p.into()) {
+            self.bits[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    // off-map points are never blocked
+    pub fn is_blocked<P: Into<Point>>(&self, p: P) -> bool {
+        match self.index(p.into()) {
+            Some(i) => self.bits[i / 64] & (1 << (i % 64)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for word in self.bits.iter_mut() {
+            *word = 0;
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    fn index(&self, p: Point) -> Option<usize> {
+        let Size { width, height } = self.size;
+        let p = if self.wraps { Point::new(p.x.rem_euclid(width), p.y.rem_euclid(height)) } else { p };
+
+        if p.x < 0 || p.y < 0 || p.x >= width || p.y >= height {
+            return None
+        }
+
+        Some((p.x + p.y * width) as usize)
+    }
+}
+
+impl Dungeon {
+    // a shortest path that steps on no blocked tile. the start is usually the mover's own
+    // tile and may be blocked; a blocked destination has no path.
+    pub fn find_path_avoiding(&self, from: Point, to: Point, conn: Connectivity, blockers: &Blockers) -> Option<Vec<Point>> {
+        self.find_path_where(from, to, conn, |p| !blockers.is_blocked(p))
+    }
+
+    // a uniformly random floor tile nobody stands on. none if there isn't one.
+    pub fn random_floor_tile_avoiding(&self, blockers: &Blockers, rng: &mut DungenRng) -> Option<Point> {
+        let size = self.size();
+        let free: Vec<Point> = (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|&p| self.get(p) == Tile::Floor && !blockers.is_blocked(p))
+            .collect();
+        if free.is_empty() {
+            return None
+        }
+
+        Some(free[rng.range_exclusive(free.len() as isize) as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blockers::*;
+    use dungeon::Dungeon;
+    use geom::Point;
+    use nav::Connectivity;
+    use rng::DungenRng;
+
+    fn fixture() -> Dungeon {
+        Dungeon::parse(&[
+            "#######",
+            "#.....#",
+            "#.###.#",
+            "#.....#",
+            "#######",
+        ])
+    }
+
+    #[test]
+    fn test_bitset() {
+        let d = fixture();
+        let mut b = Blockers::new(&d);
+
+        b.set_blocked((6, 4));
+        b.set_blocked(Point::new(1, 1));
+        b.set_blocked((-1, 2));
+        assert!(b.is_blocked((6, 4)) && b.is_blocked((1, 1)));
+        assert!(!b.is_blocked((0, 1)) && !b.is_blocked((-1, 2)));
+        assert_eq!(b.count(), 2);
+
+        b.clear_blocked((1, 1));
+        assert!(!b.is_blocked((1, 1)));
+        b.clear();
+        assert_eq!(b.count(), 0);
+    }
+
+    #[test]
+    fn test_path_goes_round_blockers() {
+        let d = fixture();
+        let mut b = Blockers::new(&d);
+        let (from, to) = (Point::new(1, 1), Point::new(5, 1));
+
+        // the start may be occupied by the mover itself
+        b.set_blocked(from);
+        assert_eq!(d.find_path_avoiding(from, to, Connectivity::Four, &b).unwrap().len(), 5);
+
+        b.set_blocked((3, 1));
+        let path = d.find_path_avoiding(from, to, Connectivity::Four, &b).unwrap();
+        assert_eq!(path.len(), 9);
+        assert!(!path.contains(&Point::new(3, 1)));
+
+        b.set_blocked((3, 3));
+        assert_eq!(d.find_path_avoiding(from, to, Connectivity::Four, &b), None);
+        b.clear();
+        b.set_blocked(to);
+        assert_eq!(d.find_path_avoiding(from, to, Connectivity::Four, &b), None);
+    }
+
+    #[test]
+    fn test_random_floor_tile_avoiding() {
+        let d = fixture();
+        let mut b = Blockers::new(&d);
+        let mut rng = DungenRng::new(3);
+
+        for y in 1..4 {
+            for x in 1..6 {
+                if (x, y) != (5, 3) {
+                    b.set_blocked((x, y));
+                }
+            }
+        }
+        for _ in 0..10 {
+            assert_eq!(d.random_floor_tile_avoiding(&b, &mut rng), Some(Point::new(5, 3)));
+        }
+
+        b.set_blocked((5, 3));
+        assert_eq!(d.random_floor_tile_avoiding(&b, &mut rng), None);
+    }
+}
@@ -0,0 +1,348 @@
+/*
+Bones files: a level as it stood when a player died there, saved so it can turn up again in
+someone else's game. What goes in is the level itself and only that:
+
+  kept       the tiles, in the binary form codec.rs writes, with its entrances, exits,
+             broken and barred doors and room ids; the tags of every room that has any,
+             its purpose, name, boss, safe, dead-end, courtyard and cave marks; and the
+             chests
+  left out   the player's exploration, which lives in an Exploration and never in the
+             map; what only generation uses, the growth frontier, density grid, attempt
+             counts and trace; and whatever is transient, the characters standing on the
+             map, which are written as the floor under them. a shop comes back without
+             its shopkeeper.
+
+A file is a header (magic and format version), the map, the room tags, each room found again
+by a tile it had, the chests, and last a checksum of all that, fnv-1a over every byte before
+it. A file that has been cut short or changed since gives BonesError::Checksum rather than a
+map that's subtly wrong.
+
+Newer builds always read older files. A later version only adds to the end of a section,
+read when the version says it's there, and the map inside reads every codec version there
+has been. A file from a newer build than this one is UnsupportedVersion.
+*/
+
+use std::io::{ self, Read, Write };
+use codec::{ self, Reader };
+use dungeon::{ Dungeon, Point, Tile };
+use error::{ BonesError, LoadError };
+use graph::RoomId;
+use rooms::RoomPurpose;
+use spawn::ChestPlacement;
+
+const MAGIC: &[u8; 4] = b"DGNB";
+const VERSION: u16 = 1;
+// the magic and version
+const HEADER: usize = 6;
+// tiles that stand for characters rather than the level
+const TRANSIENT: &[Tile] = &[Tile::Npc];
+
+// a room's marks, one bit each
+const DEAD_END: u8 = 1;
+const COURTYARD: u8 = 2;
+const CAVE: u8 = 4;
+const BOSS: u8 = 8;
+const SAFE: u8 = 16;
+const NAMED: u8 = 32;
+const FLAGS: u8 = 63;
+
+// purposes are written as one more than their index here, no purpose as 0, and a custom
+// purpose as CUSTOM and then its number
+const PURPOSES: [RoomPurpose; 7] = [RoomPurpose::Barracks, RoomPurpose::Library, RoomPurpose::Shrine, RoomPurpose::Storage,
+    RoomPurpose::Prison, RoomPurpose::Start, RoomPurpose::Secret];
+const CUSTOM: u8 = 255;
+
+impl Dungeon {
+    // writes the level as a bones file, see the module comment
+    pub fn export_bones<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        codec::put_u16(&mut out, VERSION);
+        self.write_with(&mut out, |tile| if TRANSIENT.contains(&tile) { Tile::Floor } else { tile });
+
+        let rooms: Vec<(RoomId, Point, u8)> = self.room_anchors().into_iter()
+            .map(|(id, anchor)| (id, anchor, self.room_flags(id)))
+            .filter(|&(id, _, flags)| flags != 0 || self.purposes.contains_key(&id))
+            .collect();
+        codec::put_u32(&mut out, rooms.len() as u32);
+        for (id, anchor, flags) in rooms {
+            codec::put_u32(&mut out, anchor.x as u32);
+            codec::put_u32(&mut out, anchor.y as u32);
+            out.push(flags);
+            match self.purposes.get(&id) {
+                None => out.push(0),
+                Some(&RoomPurpose::Custom(n)) => {
+                    out.push(CUSTOM);
+                    codec::put_u16(&mut out, n);
+                }
+                Some(purpose) => out.push(PURPOSES.iter().position(|p| p == purpose).unwrap() as u8 + 1),
+            }
+            if let Some(name) = self.names.get(&id) {
+                codec::put_u32(&mut out, name.len() as u32);
+                out.extend_from_slice(name.as_bytes());
+            }
+        }
+
+        codec::put_u32(&mut out, self.chests.len() as u32);
+        for chest in &self.chests {
+            codec::put_u32(&mut out, chest.pos.x as u32);
+            codec::put_u32(&mut out, chest.pos.y as u32);
+            out.push(chest.tier);
+        }
+
+        let sum = checksum(&out);
+        out.extend_from_slice(&sum.to_le_bytes());
+        writer.write_all(&out)
+    }
+
+    // reads a level written by export_bones, by this build or any before it
+    pub fn import_bones<R: Read>(mut reader: R) -> Result<Dungeon, BonesError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|e| BonesError::Load(LoadError::Io(e.kind())))?;
+
+        let body = verified(&bytes)?;
+        let mut r = Reader::new(body);
+        let mut d = Dungeon::read_from(&mut r).map_err(BonesError::Load)?;
+        d.read_bones_records(&mut r).map_err(BonesError::Load)?;
+        r.finish().map_err(BonesError::Load)?;
+        Ok(d)
+    }
+
+    fn room_flags(&self, id: RoomId) -> u8 {
+        let marks = [
+            (self.dead_end_rooms.contains(&id), DEAD_END),
+            (self.courtyards.contains(&id), COURTYARD),
+            (self.caves.contains(&id), CAVE),
+            (self.boss_room == Some(id), BOSS),
+            (self.safe_room == Some(id), SAFE),
+            (self.names.contains_key(&id), NAMED),
+        ];
+        marks.iter().filter(|&&(marked, _)| marked).fold(0, |flags, &(_, bit)| flags | bit)
+    }
+
+    // the room tags and chests, onto the map read just before them
+    fn read_bones_records(&mut self, r: &mut Reader) -> Result<(), LoadError> {
+        let graph = self.graph();
+        let size = self.size();
+        let point = |r: &mut Reader, what| {
+            let (x, y) = (r.u32()? as u64, r.u32()? as u64);
+            if x >= size.width as u64 || y >= size.height as u64 {
+                return Err(LoadError::Invalid(what))
+            }
+            Ok(Point::new(x as isize, y as isize))
+        };
+
+        let count = r.u32()?;
+        let mut seen = Vec::new();
+        for _ in 0..count {
+            let id = match graph.room_at(point(r, "room")?) {
+                Some(id) if !seen.contains(&id) => id,
+                _ => return Err(LoadError::Invalid("room")),
+            };
+            seen.push(id);
+
+            let flags = r.u8()?;
+            if flags & !FLAGS != 0 {
+                return Err(LoadError::Invalid("room flags"))
+            }
+            let purpose = match r.u8()? {
+                0 => None,
+                CUSTOM => Some(RoomPurpose::Custom(r.u16()?)),
+                code => Some(*PURPOSES.get(code as usize - 1).ok_or(LoadError::Invalid("purpose"))?),
+            };
+            if flags & NAMED != 0 {
+                let len = r.u32()? as usize;
+                let name = String::from_utf8(r.take(len)?.to_vec()).map_err(|_| LoadError::Invalid("name"))?;
+                self.names.insert(id, name);
+            }
+
+            if let Some(purpose) = purpose {
+                self.purposes.insert(id, purpose);
+            }
+            if flags & DEAD_END != 0 {
+                self.dead_end_rooms.insert(id);
+            }
+            if flags & COURTYARD != 0 {
+                self.courtyards.insert(id);
+            }
+            if flags & CAVE != 0 {
+                self.caves.insert(id);
+            }
+            if flags & BOSS != 0 {
+                self.boss_room = Some(id);
+            }
+            if flags & SAFE != 0 {
+                self.safe_room = Some(id);
+            }
+        }
+
+        let count = r.u32()?;
+        for _ in 0..count {
+            let pos = point(r, "chest")?;
+            let tier = r.u8()?;
+            let room = graph.room_at(pos).ok_or(LoadError::Invalid("chest"))?;
+            self.chests.push(ChestPlacement { pos, tier, room });
+        }
+
+        self.sync_rooms();
+        Ok(())
+    }
+}
+
+// the map and records of a bones file, once its header and checksum check out
+fn verified(bytes: &[u8]) -> Result<&[u8], BonesError> {
+    let mut r = Reader::new(bytes);
+    r.magic(MAGIC).map_err(BonesError::Load)?;
+    let version = r.u16().map_err(BonesError::Load)?;
+    if version == 0 || version > VERSION {
+        return Err(BonesError::Load(LoadError::UnsupportedVersion(version)))
+    }
+    if bytes.len() < HEADER + 8 {
+        return Err(BonesError::Load(LoadError::Truncated))
+    }
+
+    let (data, sum) = bytes.split_at(bytes.len() - 8);
+    let mut stored = [0; 8];
+    stored.copy_from_slice(sum);
+    let (stored, computed) = (u64::from_le_bytes(stored), checksum(data));
+    if stored != computed {
+        return Err(BonesError::Checksum { stored, computed })
+    }
+    Ok(&data[HEADER..])
+}
+
+// fnv-1a
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use bones::*;
+    use doors::DoorCondition;
+    use error::{ BonesError, LoadError };
+    use names::NameStyle;
+    use rng::DungenRng;
+    use rooms::Constraint;
+
+    // a level with some of everything bones keep, and a shopkeeper they don't
+    fn level() -> Dungeon {
+        let mut d = Dungeon::new(70, 45);
+        d.generate_seeded(50, 2).unwrap();
+        let mut rng = DungenRng::new(2);
+        d.designate_boss_room(20).unwrap();
+        d.place_safe_room(&mut rng).unwrap();
+        d.assign_courtyards(0.3, &mut rng);
+        d.assign_purposes(&[(RoomPurpose::Library, Constraint::MinArea(1))], &mut rng);
+        let free = d.room_ids().find(|id| !d.purposes.contains_key(id)).unwrap();
+        d.purposes.insert(free, RoomPurpose::Custom(7));
+        d.name_rooms(NameStyle::Grim, &mut rng);
+        d.place_chests(4, 3, &mut rng);
+        let doors = d.doors();
+        d.set_door_state(doors[0], DoorCondition::Broken);
+        d.set_door_state(doors[1], DoorCondition::Barred);
+        d
+    }
+
+    // the map, its rooms' tags and its chests. files from before room ids were kept give
+    // rooms fresh ones, so rooms are listed by their tags alone.
+    fn describe(d: &Dungeon) -> String {
+        let mut rooms: Vec<String> = d.room_ids().map(|id| {
+            let room = d.room(id).unwrap();
+            format!("{} {:?} {} {} {} {:?} {:?}", room.area, room.purpose, room.boss, room.safe, room.dead_end, room.environment, room.name)
+        }).collect();
+        rooms.sort();
+        let chests: Vec<String> = d.chests().iter().map(|chest| format!("chest {:?} {}", chest.pos, chest.tier)).collect();
+        format!("{}{}\n{}\n{:?}\n", d.render(), rooms.join("\n"), chests.join("\n"), d.door_details())
+    }
+
+    fn export(d: &Dungeon) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        d.export_bones(&mut bytes).unwrap();
+        bytes
+    }
+
+    // bytes with the checksum written afresh
+    fn resummed(mut bytes: Vec<u8>) -> Vec<u8> {
+        let n = bytes.len() - 8;
+        let sum = checksum(&bytes[..n]);
+        bytes[n..].copy_from_slice(&sum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_bones_round_trip() {
+        let d = level();
+        assert!(d.render().contains(Tile::Npc.glyph()));
+        let back = Dungeon::import_bones(&export(&d)[..]).unwrap();
+
+        // the same level, without the shopkeeper
+        let expected = describe(&d).replace(Tile::Npc.glyph(), &Tile::Floor.glyph().to_string());
+        assert_eq!(describe(&back), expected);
+        assert_eq!(back.entrances(), d.entrances());
+        assert_eq!(back.exits(), d.exits());
+        assert!(back.safe_room().is_some() && back.boss_room().is_some());
+        assert!(back.room_ids().any(|id| back.room(id).unwrap().purpose == Some(RoomPurpose::Custom(7))));
+
+        // every room keeps its id, and with it its tags and chests
+        assert_eq!(back.room_ids().collect::<Vec<_>>(), d.room_ids().collect::<Vec<_>>());
+        let (graph, read) = (d.graph(), back.graph());
+        for y in 0..45 {
+            for x in 0..70 {
+                assert_eq!(read.room_at(Point::new(x, y)), graph.room_at(Point::new(x, y)), "{},{}", x, y);
+            }
+        }
+        for id in d.room_ids() {
+            assert_eq!(back.room(id).unwrap().purpose, d.room(id).unwrap().purpose);
+            assert_eq!(back.room(id).unwrap().name, d.room(id).unwrap().name);
+        }
+        assert_eq!(back.chests(), d.chests());
+    }
+
+    #[test]
+    fn test_bones_corrupt() {
+        let bytes = export(&level());
+        let n = bytes.len();
+
+        // any byte changed or lost after the header is caught by the checksum
+        for &at in &[HEADER, n / 2, n - 20, n - 1] {
+            let mut bad = bytes.clone();
+            bad[at] ^= 0x10;
+            assert!(matches!(Dungeon::import_bones(&bad[..]), Err(BonesError::Checksum { .. })), "{}", at);
+        }
+        assert!(matches!(Dungeon::import_bones(&bytes[..n - 1]), Err(BonesError::Checksum { .. })));
+
+        assert_eq!(Dungeon::import_bones(&b"nope"[..]).err(), Some(BonesError::Load(LoadError::BadMagic)));
+        assert_eq!(Dungeon::import_bones(&bytes[..HEADER + 3]).err(), Some(BonesError::Load(LoadError::Truncated)));
+        let mut future = bytes.clone();
+        future[4] = VERSION as u8 + 1;
+        assert_eq!(Dungeon::import_bones(&future[..]).err(), Some(BonesError::Load(LoadError::UnsupportedVersion(VERSION + 1))));
+
+        // and what gets past it is still checked
+        let mut longer = bytes.clone();
+        longer.insert(n - 8, 0);
+        assert_eq!(Dungeon::import_bones(&resummed(longer)[..]).err(), Some(BonesError::Load(LoadError::Invalid("trailing data"))));
+    }
+
+    #[test]
+    fn test_bones_older_files() {
+        // written by this format's first version, which every later build must still read
+        let old = Dungeon::import_bones(&include_bytes!("../tests/golden/bones_v1.bin")[..]).unwrap();
+        assert_eq!(describe(&old), include_str!("../tests/golden/bones_v1.txt"));
+
+        // the map inside in the codec's first version, from before door states, room ids and
+        // corridors
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(40, 2).unwrap();
+        d.name_rooms(NameStyle::Classic, &mut DungenRng::new(2));
+        let bytes = export(&d);
+        let map = d.to_bytes().len();
+        let corridors = 4 + d.corridors().iter().map(|c| 20 + 8 * c.path.len()).sum::<usize>();
+        let ids = 8 + 12 * d.room_ids().count() + corridors;
+        let mut older = bytes[..HEADER + map - ids - 4].to_vec();
+        older[HEADER + 4] = 1;
+        older.extend_from_slice(&bytes[HEADER + map..]);
+        let back = Dungeon::import_bones(&resummed(older)[..]).unwrap();
+        assert_eq!(describe(&back), describe(&d));
+    }
+}
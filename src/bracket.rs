@@ -0,0 +1,79 @@
+/*
+bracket-lib interop, behind the "bracket" feature. DungeonMapAdapter borrows a dungeon
+and implements bracket's BaseMap and Algorithm2D, so its field of view and a_star_search
+run directly on a generated map. Tile indices are x + y * width, as everywhere else here.
+
+bracket 0.8's a_star_search scores each step by that step's cost alone rather than the
+cost so far, so its route isn't always the shortest and on a large map it can give up
+after its step limit. DijkstraMap over the adapter gives the same distances as nav does.
+*/
+
+use bracket_pathfinding::prelude::{ Algorithm2D, BaseMap, SmallVec };
+use bracket_pathfinding::prelude::Point as BPoint;
+use dungeon::Dungeon;
+use geom::Point;
+use nav::Connectivity;
+
+pub struct DungeonMapAdapter<'a> {
+    dungeon: &'a Dungeon,
+}
+
+impl<'a> DungeonMapAdapter<'a> {
+    pub fn new(dungeon: &'a Dungeon) -> DungeonMapAdapter<'a> {
+        DungeonMapAdapter { dungeon }
+    }
+
+    pub fn dungeon(&self) -> &'a Dungeon {
+        self.dungeon
+    }
+
+    pub fn index_of(&self, p: Point) -> usize {
+        let p = self.dungeon.wrap_point(p);
+        (p.x + p.y * self.dungeon.size().width) as usize
+    }
+
+    pub fn point_of(&self, idx: usize) -> Point {
+        let width = self.dungeon.size().width as usize;
+        Point::new((idx % width) as isize, (idx / width) as isize)
+    }
+}
+
+impl<'a> BaseMap for DungeonMapAdapter<'a> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.dungeon.get(self.point_of(idx)).blocks_sight()
+    }
+
+    // generation is 4-directional, so pathing is too
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        self.dungeon.neighbors(self.point_of(idx), Connectivity::Four).into_iter()
+            .map(|n| (self.index_of(n), 1.0))
+            .collect()
+    }
+
+    // manhattan distance, the short way round on wrapping maps
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        let (a, b) = (self.point_of(idx1), self.point_of(idx2));
+        let size = self.dungeon.size();
+        let (mut dx, mut dy) = ((a.x - b.x).abs(), (a.y - b.y).abs());
+
+        if self.dungeon.wraps() {
+            dx = dx.min(size.width - dx);
+            dy = dy.min(size.height - dy);
+        }
+
+        (dx + dy) as f32
+    }
+}
+
+impl<'a> Algorithm2D for DungeonMapAdapter<'a> {
+    fn dimensions(&self) -> BPoint {
+        let size = self.dungeon.size();
+        BPoint::new(size.width as i32, size.height as i32)
+    }
+}
+
+impl Dungeon {
+    pub fn bracket_map(&self) -> DungeonMapAdapter<'_> {
+        DungeonMapAdapter::new(self)
+    }
+}
@@ -0,0 +1,286 @@
+/*
+Cave levels: open, irregular caverns grown by cellular automaton instead of carved from
+rooms and corridors. Everything inside the border starts as rock or open ground at random,
+then a few smoothing rounds settle it: a tile becomes rock when five or more of the nine
+tiles around it, itself included, are rock. Only the largest open region is kept, so the
+whole cave can be walked; the rest is filled back in.
+
+The entrance goes on a random tile of the cave and the exit on the tile furthest from it.
+*/
+
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use config::Layout;
+use dungeon::{ Dir, Dungeon, GenerationReport, Tile };
+use error::DungeonError;
+use geom::Point;
+use nav::Connectivity;
+use rng::{ self, DungenRng };
+
+// chance that a tile starts out as rock, and rounds of smoothing, unless the config's
+// layout gives its own
+pub(crate) const CAVE_FILL: f64 = 0.45;
+pub(crate) const CAVE_ROUNDS: usize = 4;
+// caves smaller than this are filled back in rather than joined to a hybrid level
+const MIN_HYBRID_CAVE: usize = 16;
+
+impl Dungeon {
+    // lays a cave out over a blank map. the same seed gives the same cave.
+    pub fn generate_caves_seeded(&mut self, seed: u64) -> Result<GenerationReport, DungeonError> {
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "caves"));
+        let open = self.grow_caves(&mut rng, |_, _| true);
+        let cave = regions(&open, self.size().width).into_iter().fold(Vec::new(), |best, r| if r.len() > best.len() { r } else { best });
+        if cave.len() < 2 {
+            return Err(DungeonError::NoOpenSpace)
+        }
+
+        for &p in &cave {
+            self.set_tile(p.x, p.y, Tile::Floor);
+        }
+        self.wall_in();
+
+        let start = cave[rng.range_exclusive(cave.len() as isize) as usize];
+        self.place_stairs(start, Tile::Floor);
+        self.sync_rooms();
+        self.tag_caves(&[cave]);
+
+        self.finish(1, seed)
+    }
+
+    // rooms and corridors as generate_seeded lays them out, then caves grown in the rock
+    // left between them, each joined on by a tunnel to the nearest walkable tile. a tunnel
+    // never opens a second way between two places, so walking distances stay as they were.
+    // each cave counts as a feature.
+    pub fn generate_hybrid_seeded(&mut self, maxfeatures: isize, seed: u64) -> Result<GenerationReport, DungeonError> {
+        let mut report = self.run(maxfeatures, seed, None)?;
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "hybrid"));
+
+        // rock or bare wall all around, so nothing already there is broken into
+        let open = self.grow_caves(&mut rng, |d, p| {
+            (-1..=1).all(|dy| (-1..=1).all(|dx| matches!(d.get(p.offset(dx, dy)), Tile::Unused | Tile::Wall)))
+        });
+
+        let mut caves = Vec::new();
+        for cave in regions(&open, self.size().width).into_iter().filter(|r| r.len() >= MIN_HYBRID_CAVE) {
+            for &p in &cave {
+                self.set_tile(p.x, p.y, Tile::Floor);
+            }
+            let inset = self.config().border.max(0) + 1;
+            if self.tunnel_from(&cave, Tile::Corridor, inset) {
+                report.features += 1;
+                caves.push(cave);
+            }
+
+            else {
+                for &p in &cave {
+                    self.set_tile(p.x, p.y, Tile::Wall);
+                }
+            }
+        }
+
+        self.wall_in();
+        self.sync_rooms();
+        self.tag_caves(&caves);
+        self.enforce_validation(seed)?;
+        Ok(report)
+    }
+
+    // tags the rooms holding the caves' tiles, once sync_rooms has given them ids
+    fn tag_caves(&mut self, caves: &[Vec<Point>]) {
+        let graph = self.graph();
+        self.caves.extend(caves.iter().filter_map(|cave| graph.room_at(cave[0])));
+        if !caves.is_empty() {
+            self.sync_rooms();
+        }
+    }
+
+    // the entrance at start, and the exit on the tile of kind open furthest from it. ties
+    // go to the first tile in row-major order.
+    pub(crate) fn place_stairs(&mut self, start: Point, open: Tile) {
+        let dist = self.distance_map(start, Connectivity::Four);
+        let width = self.size().width;
+        let at = |i: usize| Point::new(i as isize % width, i as isize / width);
+        let far = dist.iter().enumerate()
+            .filter_map(|(i, d)| d.map(|d| (d, i)))
+            .filter(|&(_, i)| self.get(at(i)) == open)
+            .min_by_key(|&(d, i)| (Reverse(d), i));
+
+        self.set_object(start, Tile::Entrance);
+        if let Some((_, i)) = far {
+            self.set_object(at(i), Tile::Exit);
+        }
+    }
+
+    // digs the shortest way through rock from region, which must already be walkable, to
+    // the nearest walkable tile outside it, in tiles of kind open. the tunnel keeps at
+    // least inset tiles from the edges. false if nothing walkable can be reached. walls
+    // still have to be put around what was dug.
+    pub(crate) fn tunnel_from(&mut self, region: &[Point], open: Tile, inset: isize) -> bool {
+        let size = self.size();
+        self.tunnel_within(region, open, |p| p.x >= inset && p.y >= inset && p.x < size.width - inset && p.y < size.height - inset)
+    }
+
+    // tunnel_from, with the tunnel kept to the tiles can_dig allows, which must all be on
+    // the map
+    pub(crate) fn tunnel_within<F>(&mut self, region: &[Point], open: Tile, can_dig: F) -> bool
+        where F: Fn(Point) -> bool
+    {
+        let size = self.size();
+        let index = |p: Point| (p.x + p.y * size.width) as usize;
+        let mut from: Vec<Option<Point>> = vec![None; (size.width * size.height) as usize];
+        let mut seen = vec![false; from.len()];
+        let mut queue: VecDeque<Point> = region.iter().cloned().collect();
+        for &p in region {
+            seen[index(p)] = true;
+        }
+
+        while let Some(p) = queue.pop_front() {
+            for dir in Dir::iterator() {
+                let (dx, dy) = dir.offset();
+                let n = p.offset(dx, dy);
+                if !can_dig(n) || seen[index(n)] {
+                    continue;
+                }
+
+                seen[index(n)] = true;
+                // pits lead down, never through
+                if self.get(n) == Tile::Pit {
+                    continue;
+                }
+
+                from[index(n)] = Some(p);
+                if !self.get(n).is_walkable() {
+                    queue.push_back(n);
+                    continue;
+                }
+
+                // dig back to the region
+                let mut step = p;
+                while !self.get(step).is_walkable() {
+                    self.set_tile(step.x, step.y, open);
+                    step = from[index(step)].unwrap();
+                }
+                return true
+            }
+        }
+
+        false
+    }
+
+    // grows caves by cellular automaton over the tiles inside the border that can_dig
+    // allows. returns which tiles came out open, row by row.
+    fn grow_caves<F>(&self, rng: &mut DungenRng, can_dig: F) -> Vec<bool>
+        where F: Fn(&Dungeon, Point) -> bool
+    {
+        let size = self.size();
+        // open tiles need a wall between them and the border
+        let inset = self.config().border.max(0) + 1;
+        let diggable: Vec<bool> = (0..size.width * size.height).map(|i| {
+            let p = Point::new(i % size.width, i / size.width);
+            p.x >= inset && p.y >= inset && p.x < size.width - inset && p.y < size.height - inset && can_dig(self, p)
+        }).collect();
+
+        let (fill, rounds) = match self.config().layout {
+            Layout::Caves { fill, rounds } => (fill, rounds),
+            Layout::Rooms => (CAVE_FILL, CAVE_ROUNDS),
+        };
+        let mut open: Vec<bool> = diggable.iter().map(|&dig| dig && rng.next_f64() >= fill).collect();
+        for _ in 0..rounds {
+            let mut next = vec![false; open.len()];
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    if !diggable[(x + y * size.width) as usize] {
+                        continue;
+                    }
+
+                    let mut rock = 0;
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let (nx, ny) = (x + dx, y + dy);
+                            if !open[(nx + ny * size.width) as usize] {
+                                rock += 1;
+                            }
+                        }
+                    }
+                    next[(x + y * size.width) as usize] = rock < 5;
+                }
+            }
+            open = next;
+        }
+
+        open
+    }
+}
+
+// every 4-connected run of open tiles, each in the order its tiles were reached, in the
+// row-major order of their first tiles
+fn regions(open: &[bool], width: isize) -> Vec<Vec<Point>> {
+    let mut seen = vec![false; open.len()];
+    let mut out = Vec::new();
+
+    for start in 0..open.len() {
+        if !open[start] || seen[start] {
+            continue;
+        }
+
+        seen[start] = true;
+        let mut region = vec![Point::new(start as isize % width, start as isize / width)];
+        let mut next = 0;
+        while next < region.len() {
+            let p = region[next];
+            next += 1;
+
+            for dir in Dir::iterator() {
+                let (dx, dy) = dir.offset();
+                let n = p.offset(dx, dy);
+                let i = (n.x + n.y * width) as usize;
+                // open tiles are never on the map's edge, so n is always on the map
+                if open[i] && !seen[i] {
+                    seen[i] = true;
+                    region.push(n);
+                }
+            }
+        }
+        out.push(region);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use nav::Connectivity;
+    use rooms::RoomEnvironment;
+
+    #[test]
+    fn test_caves() {
+        for seed in 0..5 {
+            let mut a = Dungeon::new(60, 40);
+            let mut b = Dungeon::new(60, 40);
+            let report = a.generate_caves_seeded(seed).unwrap();
+            b.generate_caves_seeded(seed).unwrap();
+
+            assert_eq!(a.render(), b.render());
+            assert!(a.validate_boundaries().is_empty(), "seed {}", seed);
+            assert!(report.exit_distance.unwrap() > 0);
+
+            // every floor tile is part of the one cave
+            let dist = a.distance_map(a.entrance().unwrap(), Connectivity::Four);
+            let size = a.size();
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    if a.get((x, y)).is_walkable() {
+                        assert!(dist[(x + y * size.width) as usize].is_some(), "seed {}: ({}, {})", seed, x, y);
+                    }
+                }
+            }
+            assert!(a.render().matches('.').count() > 200);
+            assert_eq!(a.get((0, 0)), Tile::Unused);
+
+            // the cave is the one room, and tagged as one
+            assert_eq!(a.caves(), a.room_ids().collect::<Vec<_>>());
+            assert_eq!(a.room(a.caves()[0]).unwrap().environment, RoomEnvironment::Cave);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+/*
+Endless dungeons, as a grid of fixed-size chunks generated the first time they are asked for
+and kept from then on. A chunk depends only on the world seed and its own coordinates, so
+chunks can be generated in any order, or thrown away and generated again, and come out the
+same.
+
+What joins a chunk to its neighbours is where their shared edge is opened. Every edge
+between two chunks has its own seed, derived from the world seed and the edge's position,
+and the openings along it are drawn from that alone, so the chunks either side agree on
+them without either having to exist yet. Each chunk is laid out as an ordinary rooms level,
+then the border tiles at its openings are made corridor and tunnelled to the nearest open
+ground. An opening on the east edge of one chunk sits beside the one on the west edge of
+the next, and likewise north and south.
+
+Chunk coordinates grow east and south like tile coordinates do. Wrapping is turned off in
+the config, since chunks join their neighbours instead.
+*/
+
+use std::collections::BTreeMap;
+use config::DungeonConfig;
+use dungeon::{ Dir, Dungeon, Tile };
+use error::DimensionError;
+use geom::Point;
+use rng::{ self, DungenRng };
+
+// width and height of every chunk, in tiles
+pub const CHUNK_SIZE: isize = 64;
+const CHUNK_FEATURES: isize = 40;
+// most openings on one edge between chunks
+const MAX_EDGE_OPENINGS: isize = 2;
+
+pub struct ChunkedDungeon {
+    world_seed: u64,
+    config: DungeonConfig,
+    chunks: BTreeMap<(i64, i64), Dungeon>,
+}
+
+impl ChunkedDungeon {
+    pub fn new(world_seed: u64) -> ChunkedDungeon {
+        match ChunkedDungeon::with_config(world_seed, DungeonConfig::default()) {
+            Ok(world) => world,
+            Err(e) => panic!("default config doesn't fit a chunk: {}", e),
+        }
+    }
+
+    // errors if the config's rooms don't fit in a chunk
+    pub fn with_config(world_seed: u64, mut config: DungeonConfig) -> Result<ChunkedDungeon, DimensionError> {
+        config.wrap = false;
+        Dungeon::try_with_config(CHUNK_SIZE, CHUNK_SIZE, config.clone())?;
+        Ok(ChunkedDungeon { world_seed, config, chunks: BTreeMap::new() })
+    }
+
+    pub fn world_seed(&self) -> u64 {
+        self.world_seed
+    }
+
+    pub fn config(&self) -> &DungeonConfig {
+        &self.config
+    }
+
+    // generates the chunk if it hasn't been yet
+    pub fn get_chunk(&mut self, cx: i64, cy: i64) -> &Dungeon {
+        let (world_seed, config) = (self.world_seed, &self.config);
+        self.chunks.entry((cx, cy)).or_insert_with(|| build_chunk(world_seed, config, cx, cy))
+    }
+
+    // none unless the chunk has been generated
+    pub fn chunk(&self, cx: i64, cy: i64) -> Option<&Dungeon> {
+        self.chunks.get(&(cx, cy))
+    }
+
+    // the number of chunks generated and kept
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    // drops a generated chunk, which comes back the same if asked for again. false if it
+    // hadn't been generated.
+    pub fn unload(&mut self, cx: i64, cy: i64) -> bool {
+        self.chunks.remove(&(cx, cy)).is_some()
+    }
+
+    // the tile at world coordinates x, y, generating its chunk if need be
+    pub fn tile(&mut self, x: i64, y: i64) -> Tile {
+        let size = CHUNK_SIZE as i64;
+        let p = Point::new(x.rem_euclid(size) as isize, y.rem_euclid(size) as isize);
+        self.get_chunk(x.div_euclid(size), y.div_euclid(size)).get(p)
+    }
+
+    // the border tiles of the chunk that open onto its neighbours, in chunk coordinates.
+    // the same whether or not the chunk has been generated.
+    pub fn openings(&self, cx: i64, cy: i64) -> Vec<Point> {
+        chunk_openings(self.world_seed, &self.config, cx, cy)
+    }
+}
+
+fn build_chunk(world_seed: u64, config: &DungeonConfig, cx: i64, cy: i64) -> Dungeon {
+    let mut d = Dungeon::with_config(CHUNK_SIZE, CHUNK_SIZE, config.clone());
+    // a chunk whose layout fails is left as rock, and its openings are joined to each
+    // other instead
+    if d.generate_seeded(CHUNK_FEATURES, rng::stream_seed(world_seed, &format!("chunk {} {}", cx, cy))).is_err() {
+        d = Dungeon::with_config(CHUNK_SIZE, CHUNK_SIZE, config.clone());
+    }
+
+    for p in chunk_openings(world_seed, config, cx, cy) {
+        d.open_edge(p);
+    }
+    d.wall_in();
+    d.sync_rooms();
+    d
+}
+
+fn chunk_openings(world_seed: u64, config: &DungeonConfig, cx: i64, cy: i64) -> Vec<Point> {
+    let last = CHUNK_SIZE - 1;
+    let mut out = Vec::new();
+    // an edge is named by the chunk east or south of it
+    out.extend(edge_openings(world_seed, config, "west", cx, cy).into_iter().map(|i| Point::new(0, i)));
+    out.extend(edge_openings(world_seed, config, "west", cx + 1, cy).into_iter().map(|i| Point::new(last, i)));
+    out.extend(edge_openings(world_seed, config, "north", cx, cy).into_iter().map(|i| Point::new(i, 0)));
+    out.extend(edge_openings(world_seed, config, "north", cx, cy + 1).into_iter().map(|i| Point::new(i, last)));
+    out
+}
+
+// positions along the edge, kept clear of the corners and of each other
+fn edge_openings(world_seed: u64, config: &DungeonConfig, side: &str, cx: i64, cy: i64) -> Vec<isize> {
+    let mut rng = DungenRng::new(rng::stream_seed(world_seed, &format!("edge {} {} {}", side, cx, cy)));
+    let inset = config.border.max(1) + 1;
+    let count = rng.range_inclusive(1, MAX_EDGE_OPENINGS);
+
+    let mut out: Vec<isize> = Vec::new();
+    for _ in 0..count {
+        let i = rng.range_inclusive(inset, CHUNK_SIZE - 1 - inset);
+        if out.iter().all(|&o| (o - i).abs() > 2) {
+            out.push(i);
+        }
+    }
+    out
+}
+
+impl Dungeon {
+    // makes the border tile p and the one inside it corridor, and tunnels on to the
+    // nearest open ground. walls still have to be put around it.
+    fn open_edge(&mut self, p: Point) {
+        let size = self.size();
+        let inward = Dir::iterator().map(|dir| dir.offset()).map(|(dx, dy)| p.offset(dx, dy))
+            .find(|q| q.x > 0 && q.y > 0 && q.x < size.width - 1 && q.y < size.height - 1)
+            .unwrap();
+
+        let mut stub = vec![p];
+        self.set_tile(p.x, p.y, Tile::Corridor);
+        if !self.get(inward).is_walkable() {
+            self.set_tile(inward.x, inward.y, Tile::Corridor);
+            stub.push(inward);
+        }
+        self.tunnel_from(&stub, Tile::Corridor, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use chunks::*;
+
+    #[test]
+    fn test_chunks_are_deterministic() {
+        let mut a = ChunkedDungeon::new(5);
+        let mut b = ChunkedDungeon::new(5);
+        // generated in a different order
+        b.get_chunk(1, 0);
+        assert_eq!(a.get_chunk(0, 0).render(), b.get_chunk(0, 0).render());
+        assert_eq!(a.get_chunk(1, 0).render(), b.get_chunk(1, 0).render());
+        assert_ne!(a.get_chunk(0, 0).render(), a.get_chunk(1, 0).render());
+
+        let before = a.get_chunk(-3, 2).render();
+        assert!(a.unload(-3, 2));
+        assert!(a.chunk(-3, 2).is_none());
+        assert_eq!(a.get_chunk(-3, 2).render(), before);
+        assert_eq!(a.len(), 3);
+    }
+
+    #[test]
+    fn test_neighbours_agree_on_openings() {
+        let world = ChunkedDungeon::new(11);
+        let last = CHUNK_SIZE - 1;
+        for &(cx, cy) in &[(0, 0), (-1, 4), (7, -2)] {
+            let here = world.openings(cx, cy);
+            let east: Vec<isize> = world.openings(cx + 1, cy).iter().filter(|p| p.x == 0).map(|p| p.y).collect();
+            let south: Vec<isize> = world.openings(cx, cy + 1).iter().filter(|p| p.y == 0).map(|p| p.x).collect();
+            assert!(!east.is_empty() && !south.is_empty());
+            assert_eq!(here.iter().filter(|p| p.x == last).map(|p| p.y).collect::<Vec<_>>(), east);
+            assert_eq!(here.iter().filter(|p| p.y == last).map(|p| p.x).collect::<Vec<_>>(), south);
+        }
+    }
+
+    #[test]
+    fn test_stitched_chunks_connect() {
+        for seed in 0..4 {
+            let mut world = ChunkedDungeon::new(seed);
+            let side = 3 * CHUNK_SIZE;
+            let mut open = vec![false; (side * side) as usize];
+            for cy in 0..3 {
+                for cx in 0..3 {
+                    let chunk = world.get_chunk(cx - 1, cy - 1);
+                    for y in 0..CHUNK_SIZE {
+                        for x in 0..CHUNK_SIZE {
+                            let i = (cx as isize * CHUNK_SIZE + x) + (cy as isize * CHUNK_SIZE + y) * side;
+                            open[i as usize] = chunk.get(Point::new(x, y)).is_walkable();
+                        }
+                    }
+                }
+            }
+
+            let start = open.iter().position(|&o| o).unwrap();
+            let mut seen = vec![false; open.len()];
+            let mut queue = VecDeque::new();
+            seen[start] = true;
+            queue.push_back(start as isize);
+            while let Some(i) = queue.pop_front() {
+                let (x, y) = (i % side, i / side);
+                for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let (nx, ny) = (x + dx, y + dy);
+                    let n = (nx + ny * side) as usize;
+                    if nx >= 0 && ny >= 0 && nx < side && ny < side && open[n] && !seen[n] {
+                        seen[n] = true;
+                        queue.push_back(n as isize);
+                    }
+                }
+            }
+
+            let cut_off = open.iter().zip(&seen).filter(|&(&o, &s)| o && !s).count();
+            assert_eq!(cut_off, 0, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_world_coordinates() {
+        let mut world = ChunkedDungeon::new(2);
+        let p = world.openings(-1, -1)[0];
+        let (x, y) = (p.x as i64 - CHUNK_SIZE as i64, p.y as i64 - CHUNK_SIZE as i64);
+        assert_eq!(world.tile(x, y), Tile::Corridor);
+        assert_eq!(world.len(), 1);
+    }
+}
@@ -0,0 +1,452 @@
+/*
+A compact binary form for maps, for save files. A map is written as a short header (magic,
+format version, size, wrap and sealed flags, border), its tiles as runs of up to 255 of a
+single tile, its entrances and exits in placement order, its broken and barred doors, and
+the id and anchor of each room, so rooms keep their ids when a map is read back, and the
+recorded corridors, each a rect and the path of a meandering one, so feature_at and
+corridors() read as they did. Nothing else is kept: room tags, names and chests belong with
+a game's entities. Version 1, from before door states, version 2, from before room ids,
+and version 3, from before corridors, are still read; their rooms get fresh ids and their
+corridor tiles belong to no corridor.
+
+Integers are little endian. Reading checks everything it reads, so bad input gives a
+LoadError, never a panic or a map with entrances that aren't on entrance tiles.
+*/
+
+use config::DungeonConfig;
+use corridors::CorridorInfo;
+use doors::DoorCondition;
+use dungeon::{ Dungeon, Point, Tile };
+use error::LoadError;
+use geom::Rect;
+use graph::CorridorId;
+
+const MAGIC: &[u8; 4] = b"DGNM";
+const VERSION: u16 = 4;
+// door states are written as their index here
+const DOOR_STATES: [DoorCondition; 2] = [DoorCondition::Broken, DoorCondition::Barred];
+// anything larger is taken for corrupt input rather than allocated
+const MAX_AREA: u64 = 1 << 24;
+
+impl Dungeon {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Dungeon, LoadError> {
+        let mut r = Reader::new(bytes);
+        let d = Dungeon::read_from(&mut r)?;
+        r.finish()?;
+        Ok(d)
+    }
+
+    pub(crate) fn write_to(&self, out: &mut Vec<u8>) {
+        self.write_with(out, |tile| tile);
+    }
+
+    // write_to, with each tile written as tile gives it
+    pub(crate) fn write_with<F: Fn(Tile) -> Tile>(&self, out: &mut Vec<u8>, tile: F) {
+        let size = self.size();
+        out.extend_from_slice(MAGIC);
+        put_u16(out, VERSION);
+        put_u32(out, size.width as u32);
+        put_u32(out, size.height as u32);
+        out.push(self.wraps() as u8 | (self.is_sealed() as u8) << 1);
+        put_u32(out, self.config().border.max(0) as u32);
+        put_runs(out, self.rows().flat_map(|row| row.iter()).map(|&t| tile(t) as u8));
+
+        for points in &[self.entrances(), self.exits()] {
+            put_u32(out, points.len() as u32);
+            for p in points.iter() {
+                put_u32(out, p.x as u32);
+                put_u32(out, p.y as u32);
+            }
+        }
+
+        put_u32(out, self.door_states.len() as u32);
+        for (p, state) in &self.door_states {
+            put_u32(out, p.x as u32);
+            put_u32(out, p.y as u32);
+            out.push(DOOR_STATES.iter().position(|s| s == state).unwrap() as u8);
+        }
+
+        // the ids of retired rooms aren't handed out again, so the count includes them
+        let anchors = self.room_anchors();
+        put_u32(out, self.room_slots.len() as u32);
+        put_u32(out, anchors.len() as u32);
+        for (id, p) in anchors {
+            put_u32(out, id.0);
+            put_u32(out, p.x as u32);
+            put_u32(out, p.y as u32);
+        }
+
+        // a rect may run off a wrapping map, so its corner is signed
+        put_u32(out, self.corridors.len() as u32);
+        for corridor in &self.corridors {
+            let rect = corridor.rect;
+            for &v in &[rect.x, rect.y, rect.width, rect.height] {
+                put_u32(out, v as i32 as u32);
+            }
+            put_u32(out, corridor.path.len() as u32);
+            for p in &corridor.path {
+                put_u32(out, p.x as u32);
+                put_u32(out, p.y as u32);
+            }
+        }
+    }
+
+    pub(crate) fn read_from(r: &mut Reader) -> Result<Dungeon, LoadError> {
+        r.magic(MAGIC)?;
+        let version = r.u16()?;
+        if version == 0 || version > VERSION {
+            return Err(LoadError::UnsupportedVersion(version))
+        }
+
+        let (width, height) = (r.u32()? as u64, r.u32()? as u64);
+        if width == 0 || height == 0 || width * height > MAX_AREA {
+            return Err(LoadError::Invalid("dimensions"))
+        }
+        let flags = r.u8()?;
+        if flags > 3 {
+            return Err(LoadError::Invalid("flags"))
+        }
+        let border = r.u32()? as u64;
+        if border > MAX_AREA {
+            return Err(LoadError::Invalid("border"))
+        }
+
+        let (width, height) = (width as isize, height as isize);
+        let config = DungeonConfig { wrap: flags & 1 != 0, border: border as isize, ..DungeonConfig::default() };
+        let mut d = Dungeon::allocate(width, height, config);
+        let codes = r.runs((width * height) as usize, Tile::iterator().len() as u8)?;
+        for (i, tile) in codes.into_iter().map(|c| *Tile::iterator().nth(c as usize).unwrap()).enumerate() {
+            d.set_tile(i as isize % width, i as isize / width, tile);
+        }
+
+        for &(tile, what) in &[(Tile::Entrance, "entrance"), (Tile::Exit, "exit")] {
+            let count = r.u32()?;
+            for _ in 0..count {
+                let (x, y) = (r.u32()? as u64, r.u32()? as u64);
+                if x >= width as u64 || y >= height as u64 || d.get((x as isize, y as isize)) != tile {
+                    return Err(LoadError::Invalid(what))
+                }
+                d.set_object(Point::new(x as isize, y as isize), tile);
+            }
+        }
+
+        let count = if version > 1 { r.u32()? } else { 0 };
+        for _ in 0..count {
+            let (x, y) = (r.u32()? as u64, r.u32()? as u64);
+            let state = DOOR_STATES.get(r.u8()? as usize).cloned();
+            match state {
+                Some(state) if x < width as u64 && y < height as u64 && d.get((x as isize, y as isize)) == state.tile() => {
+                    d.set_door_state(Point::new(x as isize, y as isize), state);
+                }
+                _ => return Err(LoadError::Invalid("door")),
+            }
+        }
+
+        if version > 2 {
+            let (slots, count) = (r.u32()? as u64, r.u32()? as u64);
+            if slots > (width * height) as u64 || count > slots {
+                return Err(LoadError::Invalid("rooms"))
+            }
+            for _ in 0..count {
+                let (id, x, y) = (r.u32()? as u64, r.u32()? as u64, r.u32()? as u64);
+                // ids in order, each anchored on the map
+                if id < d.room_slots.len() as u64 || id >= slots || x >= width as u64 || y >= height as u64 {
+                    return Err(LoadError::Invalid("room"))
+                }
+                d.room_slots.resize(id as usize, None);
+                d.register_room_at(Point::new(x as isize, y as isize));
+            }
+            d.room_slots.resize(slots as usize, None);
+        }
+
+        let count = if version > 3 { r.u32()? as u64 } else { 0 };
+        if count > (width * height) as u64 {
+            return Err(LoadError::Invalid("corridors"))
+        }
+        for id in 0..count {
+            let mut v = [0; 4];
+            for v in v.iter_mut() {
+                *v = r.u32()? as i32 as isize;
+            }
+            let rect = Rect::new(v[0], v[1], v[2], v[3]);
+            // no further off the map, nor larger, than the map is across
+            let fits = |at: isize, len: isize, side: isize| at > -side && at < 2 * side && len >= 0 && len <= side;
+            let length = r.u32()? as u64;
+            if !fits(rect.x, rect.width, width) || !fits(rect.y, rect.height, height) || length > (width * height) as u64 {
+                return Err(LoadError::Invalid("corridor"))
+            }
+
+            let mut path = Vec::with_capacity(length as usize);
+            for _ in 0..length {
+                let (x, y) = (r.u32()? as u64, r.u32()? as u64);
+                if x >= width as u64 || y >= height as u64 {
+                    return Err(LoadError::Invalid("corridor"))
+                }
+                path.push(Point::new(x as isize, y as isize));
+            }
+            d.corridors.push(CorridorInfo { id: CorridorId(id as u32), rect, path });
+        }
+
+        if flags & 2 != 0 {
+            d.seal();
+        }
+        d.sync_rooms();
+        Ok(d)
+    }
+}
+
+// reads through a byte slice, failing with Truncated rather than running off its end
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(LoadError::Truncated)
+        }
+
+        self.pos += n;
+        Ok(&self.bytes[self.pos - n..self.pos])
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, LoadError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, LoadError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn magic(&mut self, magic: &[u8; 4]) -> Result<(), LoadError> {
+        if self.take(4)? != magic {
+            return Err(LoadError::BadMagic)
+        }
+        Ok(())
+    }
+
+    // everything not read yet
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.bytes[self.pos..];
+        self.pos = self.bytes.len();
+        rest
+    }
+
+    // count codes written by put_runs, each below limit
+    pub(crate) fn runs(&mut self, count: usize, limit: u8) -> Result<Vec<u8>, LoadError> {
+        let mut out = Vec::with_capacity(count);
+        while out.len() < count {
+            let code = self.u8()?;
+            let run = self.u8()? as usize;
+            if code >= limit {
+                return Err(LoadError::Invalid("run code"))
+            }
+            if run == 0 || run > count - out.len() {
+                return Err(LoadError::Invalid("run length"))
+            }
+
+            out.resize(out.len() + run, code);
+        }
+        Ok(out)
+    }
+
+    // fails if anything is left unread
+    pub(crate) fn finish(&self) -> Result<(), LoadError> {
+        if self.pos != self.bytes.len() {
+            return Err(LoadError::Invalid("trailing data"))
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+// codes as (code, run length) byte pairs
+pub(crate) fn put_runs<I: Iterator<Item = u8>>(out: &mut Vec<u8>, codes: I) {
+    let mut run: Option<(u8, u8)> = None;
+    for code in codes {
+        run = match run {
+            Some((c, n)) if c == code && n < u8::MAX => Some((c, n + 1)),
+            Some((c, n)) => {
+                out.extend_from_slice(&[c, n]);
+                Some((code, 1))
+            }
+            None => Some((code, 1)),
+        };
+    }
+
+    if let Some((c, n)) = run {
+        out.extend_from_slice(&[c, n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ CorridorStyle, DungeonConfig };
+    use doors::DoorCondition;
+    use dungeon::{ Dungeon, Point };
+    use error::LoadError;
+
+    // the bytes the room ids and the corridors take up, at the end
+    fn rooms_len(d: &Dungeon) -> usize {
+        8 + 12 * d.room_ids().count() + corridors_len(d)
+    }
+
+    // the bytes the corridors take up, at the very end
+    fn corridors_len(d: &Dungeon) -> usize {
+        4 + d.corridors().iter().map(|c| 20 + 8 * c.path.len()).sum::<usize>()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = DungeonConfig { exits: 2, sealed: true, ..DungeonConfig::default() };
+        let mut d = Dungeon::with_config(60, 40, config);
+        d.generate_seeded(50, 8).unwrap();
+
+        let bytes = d.to_bytes();
+        let back = Dungeon::from_bytes(&bytes).unwrap();
+        assert_eq!(back.render(), d.render());
+        assert_eq!(back.entrances(), d.entrances());
+        assert_eq!(back.exits(), d.exits());
+        assert!(back.is_sealed());
+        assert_eq!(back.room_ids().count(), d.graph().room_ids().len());
+        // the runs keep the tiles well under a byte each
+        assert!(bytes.len() - rooms_len(&d) < 60 * 40);
+
+        assert_eq!(Dungeon::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(LoadError::Truncated));
+        assert_eq!(Dungeon::from_bytes(b"nope").err(), Some(LoadError::BadMagic));
+        let mut moved = bytes.clone();
+        let n = moved.len() - rooms_len(&d);
+        // the last exit's y, before the count of door states
+        moved[n - 8] ^= 1;
+        assert_eq!(Dungeon::from_bytes(&moved).err(), Some(LoadError::Invalid("exit")));
+
+        // version 3 has no corridors after the room ids, version 2 no room ids after the
+        // door states, and version 1 no door states
+        let mut old = bytes[..bytes.len() - corridors_len(&d)].to_vec();
+        old[4] = 3;
+        let read = Dungeon::from_bytes(&old).unwrap();
+        assert_eq!(read.render(), d.render());
+        assert!(read.corridors().is_empty());
+        let mut old = bytes[..n].to_vec();
+        old[4] = 2;
+        assert_eq!(Dungeon::from_bytes(&old).unwrap().render(), d.render());
+        let mut old = bytes[..n - 4].to_vec();
+        old[4] = 1;
+        assert_eq!(Dungeon::from_bytes(&old).unwrap().render(), d.render());
+        let mut future = bytes.clone();
+        future[4] = 5;
+        assert_eq!(Dungeon::from_bytes(&future).err(), Some(LoadError::UnsupportedVersion(5)));
+    }
+
+    #[test]
+    fn test_door_states() {
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(40, 3).unwrap();
+        let doors = d.doors();
+        d.set_door_state(doors[0], DoorCondition::Barred);
+        d.set_door_state(doors[1], DoorCondition::Broken);
+        let bytes = d.to_bytes();
+        assert_eq!(Dungeon::from_bytes(&bytes).unwrap().door_details(), d.door_details());
+
+        // the last door's state made one that isn't, or the other one, which doesn't agree
+        // with its tile, or its x off the map
+        let n = bytes.len() - rooms_len(&d);
+        for &(at, flip) in &[(n - 1, 2), (n - 1, 1), (n - 6, 0xff)] {
+            let mut bad = bytes.clone();
+            bad[at] ^= flip;
+            assert_eq!(Dungeon::from_bytes(&bad).err(), Some(LoadError::Invalid("door")), "{}", at);
+        }
+    }
+
+    #[test]
+    fn test_room_ids() {
+        for seed in 0..20 {
+            let mut d = Dungeon::new(80, 50);
+            d.generate_seeded(50, seed).unwrap();
+            let bytes = d.to_bytes();
+            let back = Dungeon::from_bytes(&bytes).unwrap();
+
+            let (graph, read) = (d.graph(), back.graph());
+            for y in 0..50 {
+                for x in 0..80 {
+                    let p = Point::new(x, y);
+                    assert_eq!(read.room_at(p), graph.room_at(p), "seed {} at {:?}", seed, p);
+                }
+            }
+            assert_eq!(back.room_ids().collect::<Vec<_>>(), d.room_ids().collect::<Vec<_>>(), "seed {}", seed);
+            // and the next room carved gets the same id on both
+            assert_eq!(back.room_slots.len(), d.room_slots.len());
+        }
+
+        // an id out of order, or a room anchored off the map
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(40, 3).unwrap();
+        let bytes = d.to_bytes();
+        let n = bytes.len() - rooms_len(&d);
+        for &(at, flip) in &[(n + 8, 0xff), (n + 12, 0xff)] {
+            let mut bad = bytes.clone();
+            bad[at] ^= flip;
+            assert_eq!(Dungeon::from_bytes(&bad).err(), Some(LoadError::Invalid("room")), "{}", at);
+        }
+    }
+
+    #[test]
+    fn test_corridors() {
+        let configs = [
+            DungeonConfig::default(),
+            DungeonConfig { corridor_style: CorridorStyle::Meandering { jitter: 0.4 }, ..DungeonConfig::default() },
+            DungeonConfig { wrap: true, corridor_width: 2, ..DungeonConfig::default() },
+        ];
+        for (i, config) in configs.iter().enumerate() {
+            for seed in 0..8 {
+                let mut d = Dungeon::with_config(70, 45, config.clone());
+                d.generate_seeded(60, seed).unwrap();
+                let back = Dungeon::from_bytes(&d.to_bytes()).unwrap();
+
+                assert_eq!(back.corridors(), d.corridors(), "config {} seed {}", i, seed);
+                for y in 0..45 {
+                    for x in 0..70 {
+                        assert_eq!(back.feature_at((x, y)), d.feature_at((x, y)), "config {} seed {} at ({}, {})", i, seed, x, y);
+                    }
+                }
+            }
+        }
+
+        // a path tile off the map, or a rect larger than the map
+        let mut d = Dungeon::with_config(60, 40, configs[1].clone());
+        d.generate_seeded(40, 3).unwrap();
+        let bytes = d.to_bytes();
+        let last = d.corridors().last().unwrap();
+        assert!(!last.path.is_empty());
+        let rect = bytes.len() - 8 * last.path.len() - 20;
+        for &(at, flip) in &[(bytes.len() - 5, 0x7f), (rect + 10, 0x7f)] {
+            let mut bad = bytes.clone();
+            bad[at] ^= flip;
+            assert_eq!(Dungeon::from_bytes(&bad).err(), Some(LoadError::Invalid("corridor")), "{}", at);
+        }
+    }
+}
@@ -0,0 +1,548 @@
+/*
+Tunable parameters for dungeon generation. Every field has a default matching the
+original hard-coded behaviour, so DungeonConfig::default() generates the classic layout.
+
+Presets are named, fully spelled out configs for a few kinds of level. They're plain values
+built in const fns, so a preset can seed a const and be tweaked field by field like any other
+config. A config's layout says whether generate_seeded grows rooms and corridors or a cave;
+Preset::algorithm names the LevelAlgorithm a preset is meant for.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use caves::{ CAVE_FILL, CAVE_ROUNDS };
+use dungeon::Tile;
+use geom::{ Point, Rect };
+use rng::DungenRng;
+use stack::LevelAlgorithm;
+
+// how room widths and heights are rolled. each side is sampled independently.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RoomSizeDistribution {
+    // every size in min..=max equally likely
+    Uniform { min: isize, max: isize },
+    // normally distributed around mean, rounded and clamped to min..=max
+    Normal { mean: f64, std_dev: f64, min: isize, max: isize },
+    // explicit (size, weight) table
+    Weighted(Vec<(isize, u32)>),
+}
+
+impl RoomSizeDistribution {
+    pub fn sample(&self, rng: &mut DungenRng) -> isize {
+        match *self {
+            RoomSizeDistribution::Uniform { min, max } => rng.range_inclusive(min, max),
+            RoomSizeDistribution::Normal { mean, std_dev, min, max } => {
+                let size = (mean + std_dev * rng.standard_normal()).round() as isize;
+                size.max(min).min(max)
+            }
+            RoomSizeDistribution::Weighted(ref table) => {
+                let weights: Vec<u32> = table.iter().map(|&(_, w)| w).collect();
+                table[rng.weighted_index(&weights)].0
+            }
+        }
+    }
+
+    // smallest size this distribution can produce
+    pub fn min_size(&self) -> isize {
+        match *self {
+            RoomSizeDistribution::Uniform { min, .. } => min,
+            RoomSizeDistribution::Normal { min, .. } => min,
+            RoomSizeDistribution::Weighted(ref table) => {
+                table.iter().filter(|&&(_, w)| w > 0).map(|&(s, _)| s).min().unwrap_or(0)
+            }
+        }
+    }
+
+    // largest size this distribution can produce
+    pub fn max_size(&self) -> isize {
+        match *self {
+            RoomSizeDistribution::Uniform { max, .. } => max,
+            RoomSizeDistribution::Normal { max, .. } => max,
+            RoomSizeDistribution::Weighted(ref table) => {
+                table.iter().filter(|&&(_, w)| w > 0).map(|&(s, _)| s).max().unwrap_or(0)
+            }
+        }
+    }
+}
+
+impl Default for RoomSizeDistribution {
+    fn default() -> RoomSizeDistribution {
+        RoomSizeDistribution::Uniform { min: 3, max: 16 }
+    }
+}
+
+// what growth does once DungeonConfig::max_rooms is reached
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RoomCapBehavior {
+    // keep growing, but only corridors
+    #[default]
+    CorridorsOnly,
+    // place no more features at all
+    Stop,
+}
+
+// whether doors are written open or closed
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DoorState {
+    #[default]
+    AllClosed,
+    AllOpen,
+    // each door on its own roll
+    Random { open_chance: f32 },
+}
+
+impl DoorState {
+    // only Random draws
+    pub(crate) fn roll(self, rng: &mut DungenRng) -> Tile {
+        let open = match self {
+            DoorState::AllClosed => false,
+            DoorState::AllOpen => true,
+            DoorState::Random { open_chance } => rng.next_f64() < open_chance as f64,
+        };
+
+        if open { Tile::OpenDoor } else { Tile::ClosedDoor }
+    }
+}
+
+// how corridors grown off the map are carved
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CorridorStyle {
+    // straight runs, corridor_width across
+    #[default]
+    Straight,
+    // one tile across, each step turning aside with chance jitter but never doubling back.
+    // min_corridor_length..=max_corridor_length counts the steps.
+    Meandering { jitter: f32 },
+    // meandering, and two tiles across in places
+    Organic,
+}
+
+// what generate_seeded lays out
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Layout {
+    // rooms and corridors grown off one another
+    #[default]
+    Rooms,
+    // one open cavern, see Dungeon::generate_caves_seeded. each tile starts out as rock with
+    // chance fill, then rounds of smoothing settle it.
+    Caves { fill: f64, rounds: usize },
+}
+
+// what Dungeon::reinforce_thin_walls does with a wall that two rooms share
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ThinWallPolicy {
+    // a door through it, for each pair of rooms no door joins yet
+    #[default]
+    Door,
+    // a second layer of wall, built over the row of floor beside it
+    Thicken,
+}
+
+// where generation tries the first room. the edges are those of growth_bounds where it's
+// set, otherwise of the map inside its border.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StartPosition {
+    #[default]
+    Center,
+    // against the top edge, halfway across, growing south
+    TopCenter,
+    // against the bottom edge, halfway across, growing north
+    BottomCenter,
+    // grown from this tile in the direction drawn for it, like the center
+    At(Point),
+}
+
+// which side of which room or corridor growth tries next
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FrontierStrategy {
+    // any, all equally likely
+    #[default]
+    Uniform,
+    // the one in the least carved-out part of the map, so a large map fills out evenly
+    // rather than densely around the first room with whole quarters left empty
+    FillSparse,
+}
+
+// how much a generated level is checked, and repaired, before generation hands it back.
+// see the repair module for what each level holds to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Validation {
+    // nothing is checked
+    #[default]
+    None,
+    // entrances and exits are placed, and nothing walkable is open to the void
+    Basic,
+    // Basic, and everything is reachable, every door is sound and no room is sealed
+    Strict,
+}
+
+// a fixed first room, for levels that need a predictable start. the entrance goes at its
+// center and generation keeps growing from it until it has min_doors ways in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EntranceSpec {
+    pub width: isize,
+    pub height: isize,
+    pub min_doors: usize,
+}
+
+impl EntranceSpec {
+    // a width x height room with at least two ways in
+    pub fn new(width: isize, height: isize) -> EntranceSpec {
+        EntranceSpec { width, height, min_doors: 2 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DungeonConfig {
+    // relative weights for growing features toward north, south, east and west
+    // (same order as Dir::iterator). equal weights leave growth unbiased.
+    pub direction_weights: [u32; 4],
+    // distribution room widths and heights are drawn from
+    pub room_size: RoomSizeDistribution,
+    // if set, the longer side of a room is cut down to at most this multiple of the shorter
+    pub max_aspect_ratio: Option<f32>,
+    // if set, rooms smaller than this many tiles have their shorter side grown until they
+    // aren't. this wins over max_aspect_ratio where the two can't both hold.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub min_room_area: Option<isize>,
+    // if set, generation places no more rooms than this, the first included
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_rooms: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub at_max_rooms: RoomCapBehavior,
+    // toroidal map: coordinates past one edge come back in at the opposite one, and rooms
+    // may straddle the seam
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub wrap: bool,
+    // if set, the first room is built to this spec instead of rolled
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub entrance_room: Option<EntranceSpec>,
+    // how many entrances and exits to place, each in a room of its own. with an entrance
+    // room, the first entrance goes in it.
+    #[cfg_attr(feature = "serde", serde(default = "default_one"))]
+    pub entrances: usize,
+    #[cfg_attr(feature = "serde", serde(default = "default_one"))]
+    pub exits: usize,
+    // tiles kept clear between each edge of the map and anything generated. a wrapping map
+    // has no edges, so it has no border.
+    #[cfg_attr(feature = "serde", serde(default = "default_border"))]
+    pub border: isize,
+    // if set, the border is filled with this tile once generation is done
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub border_tile: Option<Tile>,
+    // seal the map once generation is done, see Dungeon::seal
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sealed: bool,
+    // percent chance that a feature grown off the map is a room rather than a corridor
+    #[cfg_attr(feature = "serde", serde(default = "default_room_chance"))]
+    pub room_chance: u32,
+    // corridor lengths are drawn evenly from min_corridor_length..=max_corridor_length
+    #[cfg_attr(feature = "serde", serde(default = "default_min_corridor_length"))]
+    pub min_corridor_length: isize,
+    #[cfg_attr(feature = "serde", serde(default = "default_max_corridor_length"))]
+    pub max_corridor_length: isize,
+    // tiles across a corridor. the opening a corridor grows from stays a single tile.
+    #[cfg_attr(feature = "serde", serde(default = "default_corridor_width"))]
+    pub corridor_width: isize,
+    // doors to knock through walls once the layout is done, each joining two spaces that
+    // were a long walk apart, to give the map that many more loops
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub loops: usize,
+    // rooms may be laid over the floor of rooms already placed, merging with them into
+    // irregular open spaces, and walls go up around everything once the layout is done
+    // rather than around each room and corridor
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ruins: bool,
+    // chance that a connection where a door fits gets one. the rest are left open, as
+    // archway_tile if set, otherwise as the floor or corridor behind them.
+    #[cfg_attr(feature = "serde", serde(default = "default_door_chance"))]
+    pub door_chance: f32,
+    // usually Tile::Archway, for games that draw a frame where a door could have been
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub archway_tile: Option<Tile>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub door_state: DoorState,
+    // chance that each door is left broken, and that each of the rest is barred, see
+    // Dungeon::damage_doors
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub broken_doors: f32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub barred_doors: f32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub corridor_style: CorridorStyle,
+    // if set, no room or corridor is placed with tiles outside this rect. their walls may
+    // still stand outside it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub growth_bounds: Option<Rect>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub start_position: StartPosition,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frontier: FrontierStrategy,
+    // see Dungeon::reinforce_thin_walls
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub thin_walls: ThinWallPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub validation: Validation,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub layout: Layout,
+}
+
+#[cfg(feature = "serde")]
+fn default_border() -> isize {
+    1
+}
+
+#[cfg(feature = "serde")]
+fn default_one() -> usize {
+    1
+}
+
+#[cfg(feature = "serde")]
+fn default_room_chance() -> u32 {
+    50
+}
+
+#[cfg(feature = "serde")]
+fn default_min_corridor_length() -> isize {
+    3
+}
+
+#[cfg(feature = "serde")]
+fn default_max_corridor_length() -> isize {
+    10
+}
+
+#[cfg(feature = "serde")]
+fn default_corridor_width() -> isize {
+    1
+}
+
+#[cfg(feature = "serde")]
+fn default_door_chance() -> f32 {
+    1.0
+}
+
+impl Default for DungeonConfig {
+    fn default() -> DungeonConfig {
+        DungeonConfig::with_room_size(RoomSizeDistribution::Uniform { min: 3, max: 16 })
+    }
+}
+
+impl DungeonConfig {
+    pub const fn preset(preset: Preset) -> DungeonConfig {
+        match preset {
+            Preset::ClassicRogue => DungeonConfig::with_room_size(RoomSizeDistribution::Uniform { min: 3, max: 16 }),
+            Preset::Catacombs => {
+                let mut config = DungeonConfig::with_room_size(RoomSizeDistribution::Uniform { min: 3, max: 6 });
+                config.room_chance = 30;
+                config.min_corridor_length = 6;
+                config.max_corridor_length = 16;
+                config.loops = 8;
+                config
+            }
+            Preset::GreatHall => {
+                let mut config = DungeonConfig::with_room_size(RoomSizeDistribution::Uniform { min: 10, max: 20 });
+                config.min_room_area = Some(120);
+                config.max_rooms = Some(7);
+                config.at_max_rooms = RoomCapBehavior::Stop;
+                config.room_chance = 60;
+                config.min_corridor_length = 3;
+                config.max_corridor_length = 7;
+                config.corridor_width = 3;
+                config.loops = 1;
+                config
+            }
+            Preset::Warren => {
+                let mut config = DungeonConfig::with_room_size(RoomSizeDistribution::Uniform { min: 2, max: 4 });
+                config.room_chance = 85;
+                config.min_corridor_length = 1;
+                config.max_corridor_length = 3;
+                config.loops = 4;
+                config
+            }
+            Preset::Caverns => {
+                let mut config = DungeonConfig::with_room_size(RoomSizeDistribution::Uniform { min: 3, max: 16 });
+                config.layout = Layout::Caves { fill: CAVE_FILL, rounds: CAVE_ROUNDS };
+                config
+            }
+        }
+    }
+
+    // the defaults, around a given room size. spelled out in full so it can be const.
+    const fn with_room_size(room_size: RoomSizeDistribution) -> DungeonConfig {
+        DungeonConfig {
+            direction_weights: [1, 1, 1, 1],
+            room_size,
+            max_aspect_ratio: None,
+            min_room_area: None,
+            max_rooms: None,
+            at_max_rooms: RoomCapBehavior::CorridorsOnly,
+            wrap: false,
+            entrance_room: None,
+            entrances: 1,
+            exits: 1,
+            border: 1,
+            border_tile: None,
+            sealed: false,
+            room_chance: 50,
+            min_corridor_length: 3,
+            max_corridor_length: 10,
+            corridor_width: 1,
+            loops: 0,
+            ruins: false,
+            door_chance: 1.0,
+            archway_tile: None,
+            door_state: DoorState::AllClosed,
+            broken_doors: 0.0,
+            barred_doors: 0.0,
+            corridor_style: CorridorStyle::Straight,
+            growth_bounds: None,
+            start_position: StartPosition::Center,
+            frontier: FrontierStrategy::Uniform,
+            thin_walls: ThinWallPolicy::Door,
+            validation: Validation::None,
+            layout: Layout::Rooms,
+        }
+    }
+}
+
+// named configs, see DungeonConfig::preset
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Preset {
+    // the defaults
+    ClassicRogue,
+    // small rooms strung along long corridors, with plenty of loops
+    Catacombs,
+    // a few huge rooms joined by wide corridors
+    GreatHall,
+    // lots of tiny rooms packed against each other, mostly opening straight onto one another
+    Warren,
+    // one open cavern, grown by cellular automaton
+    Caverns,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [Preset::ClassicRogue, Preset::Catacombs, Preset::GreatHall, Preset::Warren, Preset::Caverns];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::ClassicRogue => "classic rogue",
+            Preset::Catacombs => "catacombs",
+            Preset::GreatHall => "great hall",
+            Preset::Warren => "warren",
+            Preset::Caverns => "caverns",
+        }
+    }
+
+    pub const fn config(self) -> DungeonConfig {
+        DungeonConfig::preset(self)
+    }
+
+    // the layout the preset's config is tuned for
+    pub fn algorithm(self) -> LevelAlgorithm {
+        match self {
+            Preset::Caverns => LevelAlgorithm::Caves,
+            _ => LevelAlgorithm::Rooms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::*;
+    use dungeon::Dungeon;
+    use stack::LevelSpec;
+
+    // built in a const, as a preset should allow
+    const HALLS: DungeonConfig = DungeonConfig::preset(Preset::GreatHall);
+
+    fn generate(preset: Preset, seed: u64) -> Dungeon {
+        let mut d = Dungeon::with_config(80, 50, preset.config());
+        preset.algorithm().generate(&mut d, 60, seed).unwrap();
+        d
+    }
+
+    fn checksum(d: &Dungeon) -> u64 {
+        d.render().bytes().fold(17, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u64))
+    }
+
+    #[test]
+    fn test_preset_golden_seeds() {
+        // pinned like the default layout's golden seeds in dungeon.rs. a change here means a
+        // preset now generates something else for the same seed.
+        let golden = [
+            (Preset::ClassicRogue, 13367123416377493938),
+            (Preset::Catacombs, 13962486749167246730),
+            (Preset::GreatHall, 2784597757936939141),
+            (Preset::Warren, 7778840168563946321),
+            (Preset::Caverns, 6073370665776149264),
+        ];
+        assert_eq!(golden.len(), Preset::ALL.len());
+
+        for &(preset, sum) in golden.iter() {
+            assert_eq!(checksum(&generate(preset, 7)), sum, "{} changed", preset.name());
+        }
+
+        let mut classic = Dungeon::new(80, 50);
+        classic.generate_seeded(60, 7).unwrap();
+        assert_eq!(classic.render(), generate(Preset::ClassicRogue, 7).render());
+    }
+
+    #[test]
+    fn test_preset_character() {
+        assert_eq!(HALLS, Preset::GreatHall.config());
+        assert_eq!(LevelSpec::preset(Preset::Caverns).algorithm, LevelAlgorithm::Caves);
+
+        for seed in 0..6 {
+            let catacombs = generate(Preset::Catacombs, seed);
+            let halls = generate(Preset::GreatHall, seed);
+            let warren = generate(Preset::Warren, seed);
+            let caverns = generate(Preset::Caverns, seed);
+            let classic = generate(Preset::ClassicRogue, seed);
+
+            // the loops are knocked through on top of whatever growth closed by itself
+            assert!(catacombs.graph().metrics().cycles >= 4, "seed {}", seed);
+            assert!(halls.room_ids().count() <= 7 && halls.corridors().iter().all(|c| c.rect.width.min(c.rect.height) == 3));
+            let mean_area = |d: &Dungeon| {
+                let graph = d.graph();
+                let ids = graph.room_ids();
+                ids.iter().map(|&id| graph.room_tiles(id).len()).sum::<usize>() / ids.len()
+            };
+            assert!(mean_area(&halls) > 120, "seed {}", seed);
+            assert!(warren.room_ids().count() > classic.room_ids().count() * 2, "seed {}", seed);
+            assert!(mean_area(&warren) < 16, "seed {}", seed);
+            assert!(caverns.corridors().is_empty() && caverns.room_ids().count() == 1);
+        }
+    }
+
+    #[test]
+    fn test_caverns_config() {
+        // the config alone makes generate_seeded grow the cave
+        for seed in 0..4 {
+            let mut d = Dungeon::with_config(80, 50, DungeonConfig::preset(Preset::Caverns));
+            d.generate_seeded(60, seed).unwrap();
+            assert!(d.corridors().is_empty() && d.room_ids().count() == 1, "seed {}\n{}", seed, d.render());
+            assert_eq!(d.render(), generate(Preset::Caverns, seed).render());
+        }
+
+        // with its own fill
+        let mut config = Preset::Caverns.config();
+        config.layout = Layout::Caves { fill: 0.3, rounds: CAVE_ROUNDS };
+        let mut sparse = Dungeon::with_config(80, 50, config);
+        sparse.generate_seeded(60, 1).unwrap();
+        let floor = |d: &Dungeon| d.render().chars().filter(|&c| c == '.').count();
+        assert!(floor(&sparse) > floor(&generate(Preset::Caverns, 1)));
+    }
+}
@@ -0,0 +1,214 @@
+/*
+Corridors as placed features. Every corridor rect that generation (or carve_rect) lays down is
+recorded with an id, in placement order, so a tile can be traced back to the corridor it was
+carved for. The graph gives each recorded corridor its own node instead of merging every
+run of corridor tiles into one.
+
+A meandering corridor isn't a rect, so it also keeps the tiles it was carved as, and only
+those belong to it.
+
+Openings, the single corridor tiles that join a corridor to whatever it grew from, lie
+outside every rect. They belong to the newest corridor beside them, which is the one they
+were carved for.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use features::FeatureRef;
+use geom::{ Point, Rect };
+use graph::CorridorId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CorridorInfo {
+    pub id: CorridorId,
+    // the carved tiles, walls not included. may run off the edge of a wrapping map. for a
+    // meandering corridor, the box around its path.
+    pub rect: Rect,
+    // a meandering corridor's tiles in the order they were carved, on the map. empty for a
+    // straight one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub path: Vec<Point>,
+}
+
+impl Dungeon {
+    // in placement order
+    pub fn corridors(&self) -> &[CorridorInfo] {
+        &self.corridors
+    }
+
+    // none unless the tile is corridor
+    pub fn corridor_at<P: Into<Point>>(&self, p: P) -> Option<CorridorId> {
+        let p = self.wrap_point(p.into());
+        if self.get(p) != Tile::Corridor {
+            return None
+        }
+
+        self.corridor_covering(p).or_else(|| {
+            Dir::iterator()
+                .map(|dir| {
+                    let (dx, dy) = dir.offset();
+                    p.offset(dx, dy)
+                })
+                .filter(|&n| self.get(n) == Tile::Corridor)
+                .filter_map(|n| self.corridor_covering(self.wrap_point(n)))
+                .max()
+        })
+    }
+
+    // corridor_at for every tile at once, indexed x + y * width. each record is painted
+    // over the older ones instead of every tile searching them all.
+    pub(crate) fn corridor_owners(&self) -> Vec<Option<CorridorId>> {
+        let size = self.size();
+        let index = |p: Point| (p.x + p.y * size.width) as usize;
+        // as far off the map as corridor_covering's shifts reach
+        let reach = if self.wraps() { 1 } else { 0 };
+        let reaches = |q: Point| q.x >= -reach * size.width && q.x < (reach + 1) * size.width
+            && q.y >= -reach * size.height && q.y < (reach + 1) * size.height;
+
+        let mut covering = vec![None; size.area() as usize];
+        for c in &self.corridors {
+            if !c.path.is_empty() {
+                for &p in &c.path {
+                    covering[index(p)] = Some(c.id);
+                }
+                continue;
+            }
+
+            for y in c.rect.y..c.rect.y + c.rect.height {
+                for x in c.rect.x..c.rect.x + c.rect.width {
+                    let q = Point::new(x, y);
+                    if reaches(q) {
+                        covering[index(self.wrap_point(q))] = Some(c.id);
+                    }
+                }
+            }
+        }
+
+        let mut owners = vec![None; covering.len()];
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) != Tile::Corridor {
+                    continue;
+                }
+
+                owners[index(p)] = covering[index(p)].or_else(|| {
+                    Dir::iterator()
+                        .map(|dir| {
+                            let (dx, dy) = dir.offset();
+                            p.offset(dx, dy)
+                        })
+                        .filter(|&n| self.get(n) == Tile::Corridor)
+                        .filter_map(|n| covering[index(self.wrap_point(n))])
+                        .max()
+                });
+            }
+        }
+        owners
+    }
+
+    pub(crate) fn record_corridor(&mut self, rect: Rect) -> CorridorId {
+        let id = CorridorId(self.corridors.len() as u32);
+        self.corridors.push(CorridorInfo { id, rect, path: Vec::new() });
+        self.stamp_feature(rect.points(), FeatureRef::Corridor(id));
+        id
+    }
+
+    pub(crate) fn record_path(&mut self, path: &[Point]) -> CorridorId {
+        let id = CorridorId(self.corridors.len() as u32);
+        let (left, top) = (path.iter().map(|p| p.x).min().unwrap_or(0), path.iter().map(|p| p.y).min().unwrap_or(0));
+        let (right, bottom) = (path.iter().map(|p| p.x).max().unwrap_or(-1), path.iter().map(|p| p.y).max().unwrap_or(-1));
+        let rect = Rect::new(left, top, right - left + 1, bottom - top + 1);
+        let path: Vec<Point> = path.iter().map(|&p| self.wrap_point(p)).collect();
+        self.stamp_feature(path.iter().cloned(), FeatureRef::Corridor(id));
+        self.corridors.push(CorridorInfo { id, rect, path });
+        id
+    }
+
+    // newest corridor whose rect holds the on-map point p
+    fn corridor_covering(&self, p: Point) -> Option<CorridorId> {
+        let size = self.size();
+        let shifts: &[isize] = if self.wraps() { &[-1, 0, 1] } else { &[0] };
+
+        self.corridors.iter().rev()
+            .find(|c| {
+                if !c.path.is_empty() {
+                    return c.path.contains(&p)
+                }
+                shifts.iter().any(|&i| shifts.iter().any(|&j| c.rect.contains(p.offset(i * size.width, j * size.height))))
+            })
+            .map(|c| c.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ CorridorStyle, DungeonConfig };
+    use dungeon::{ Dungeon, Tile };
+    use geom::{ Point, Rect };
+    use graph::{ CorridorId, NodeKind };
+
+    #[test]
+    fn test_corridor_at_and_graph_nodes() {
+        let mut d = Dungeon::parse(&["            "; 9]);
+        d.carve_rect(Rect::new(2, 2, 5, 1), Tile::Corridor).unwrap();
+        d.carve_rect(Rect::new(8, 2, 1, 4), Tile::Corridor).unwrap();
+        // the opening between the two
+        d.set_tile(7, 2, Tile::Corridor);
+
+        let (first, second) = (CorridorId(0), CorridorId(1));
+        assert_eq!(d.corridors().len(), 2);
+        assert_eq!(d.corridors()[1].rect, Rect::new(8, 2, 1, 4));
+        assert_eq!(d.corridor_at((4, 2)), Some(first));
+        assert_eq!(d.corridor_at((8, 5)), Some(second));
+        assert_eq!(d.corridor_at((7, 2)), Some(second));
+        assert_eq!(d.corridor_at((7, 3)), None);
+
+        let graph = d.graph();
+        let corridors: Vec<usize> = (0..graph.nodes.len()).filter(|&n| graph.nodes[n].kind == NodeKind::Corridor).collect();
+        assert_eq!(corridors.len(), 2);
+        assert_eq!(graph.corridor_at(Point::new(4, 2)), Some(first));
+        assert_eq!(graph.nodes[graph.corridor_node(second).unwrap()].tiles.len(), 5);
+        assert_eq!(graph.edges.len(), 1);
+    }
+
+    #[test]
+    fn test_generation_records_corridors() {
+        let mut d = Dungeon::new(60, 40);
+        let (_, trace) = d.generate_debug(60, 4);
+
+        assert!(!d.corridors().is_empty());
+        let size = d.size();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let corridor = d.get((x, y)) == Tile::Corridor;
+                assert_eq!(d.corridor_at((x, y)).is_some(), corridor, "({}, {})", x, y);
+            }
+        }
+
+        let replayed = Dungeon::from_trace(&trace).unwrap();
+        assert_eq!(replayed.corridors(), d.corridors());
+    }
+
+    #[test]
+    fn test_owners_agree_with_corridor_at() {
+        let styles = [CorridorStyle::Straight, CorridorStyle::Meandering { jitter: 0.4 }, CorridorStyle::Organic];
+        for (i, &style) in styles.iter().enumerate() {
+            for &wrap in &[false, true] {
+                let config = DungeonConfig { corridor_style: style, wrap, ..DungeonConfig::default() };
+                let mut d = Dungeon::with_config(60, 40, config);
+                let _ = d.generate_seeded(60, i as u64);
+
+                let owners = d.corridor_owners();
+                let size = d.size();
+                for y in 0..size.height {
+                    for x in 0..size.width {
+                        assert_eq!(owners[(x + y * size.width) as usize], d.corridor_at((x, y)), "{:?} wrap {}: ({}, {})", style, wrap, x, y);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+/*
+Decoration placed after generation, and the lighting it gives. Torches go on walls facing
+room floor, never within two tiles of each other. light_map is additive: every torch
+lights the tiles it can see within its radius, brightest at the torch and falling off
+linearly, on top of the ambient level.
+
+Windows go in straight walls that part two different spaces, a room from a room or from a
+corridor, so one side can be seen from the other. They stay wall for everything but sight.
+*/
+
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+use rng::DungenRng;
+
+// torches closer than this, counting diagonals, crowd each other
+const TORCH_SPACING: isize = 3;
+
+impl Dungeon {
+    // turns each wall tile beside room floor into a torch with the given chance, keeping
+    // torches spaced out. returns where they went, in row-major order.
+    pub fn decorate(&mut self, torch_chance: f32, rng: &mut DungenRng) -> Vec<Point> {
+        let size = self.size();
+        let mut placed: Vec<Point> = Vec::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                let faces_floor = Dir::iterator().any(|dir| {
+                    let (dx, dy) = dir.offset();
+                    self.get(p.offset(dx, dy)) == Tile::Floor
+                });
+                if self.get(p) != Tile::Wall || !faces_floor {
+                    continue;
+                }
+
+                let crowded = placed.iter().any(|t| (t.x - x).abs() < TORCH_SPACING && (t.y - y).abs() < TORCH_SPACING);
+                if !crowded && rng.next_f64() < torch_chance as f64 {
+                    self.set_tile(x, y, Tile::Torch);
+                    placed.push(p);
+                }
+            }
+        }
+
+        placed
+    }
+
+    // turns each wall tile between two different spaces into a window with the given
+    // chance. the wall has to run straight through the tile, with the spaces on its other
+    // two sides, and no door or archway may touch it. returns where they went, in row-major
+    // order.
+    pub fn add_windows(&mut self, chance: f32, rng: &mut DungenRng) -> Vec<Point> {
+        let size = self.size();
+        let graph = self.graph();
+        let mut placed = Vec::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) != Tile::Wall {
+                    continue;
+                }
+
+                let beside_door = Dir::iterator().any(|dir| {
+                    let (dx, dy) = dir.offset();
+                    let t = self.get(p.offset(dx, dy));
+                    t.is_door() || t == Tile::Archway || t == Tile::SecretDoor
+                });
+                let open = |dx: isize, dy: isize| self.get(p.offset(dx, dy)).is_walkable();
+                let (a, b) = if open(0, -1) && open(0, 1) && !open(-1, 0) && !open(1, 0) {
+                    (p.offset(0, -1), p.offset(0, 1))
+                }
+
+                else if open(-1, 0) && open(1, 0) && !open(0, -1) && !open(0, 1) {
+                    (p.offset(-1, 0), p.offset(1, 0))
+                }
+
+                else {
+                    continue;
+                };
+
+                let parts = match (graph.node_at(self.wrap_point(a)), graph.node_at(self.wrap_point(b))) {
+                    (Some(a), Some(b)) => a != b,
+                    _ => false,
+                };
+                if parts && !beside_door && rng.next_f64() < chance as f64 {
+                    self.set_tile(x, y, Tile::Window);
+                    placed.push(p);
+                }
+            }
+        }
+
+        placed
+    }
+
+    pub fn windows(&self) -> Vec<Point> {
+        let size = self.size();
+        (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|&p| self.get(p) == Tile::Window)
+            .collect()
+    }
+
+    pub fn torches(&self) -> Vec<Point> {
+        let size = self.size();
+        (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|&p| self.get(p) == Tile::Torch)
+            .collect()
+    }
+
+    // a light level per tile, row-major. a torch adds 255 to its own tile and linearly less
+    // out to torch_radius, to tiles it has line of sight to.
+    pub fn light_map(&self, ambient: u8, torch_radius: u8) -> Vec<u8> {
+        let size = self.size();
+        let radius = torch_radius as isize;
+        let mut light = vec![ambient as u32; size.area() as usize];
+
+        for torch in self.torches() {
+            // only the torch's bounding box, not the whole map
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                    if dist > radius as f32 {
+                        continue;
+                    }
+
+                    let target = torch.offset(dx, dy);
+                    let p = self.wrap_point(target);
+                    if p.x < 0 || p.y < 0 || p.x >= size.width || p.y >= size.height {
+                        continue;
+                    }
+
+                    if self.line_of_sight(torch, target) {
+                        light[self.index(p)] += (255.0 * (1.0 - dist / (radius + 1) as f32)) as u32;
+                    }
+                }
+            }
+        }
+
+        light.into_iter().map(|l| l.min(255) as u8).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Point;
+    use rng::DungenRng;
+
+    #[test]
+    fn test_decorate() {
+        let mut d = Dungeon::new(60, 40);
+        let mut before = Dungeon::new(60, 40);
+        d.generate_seeded(30, 5).unwrap();
+        before.generate_seeded(30, 5).unwrap();
+
+        let torches = d.decorate(0.3, &mut DungenRng::new(1));
+        assert!(!torches.is_empty());
+        assert_eq!(d.torches(), torches);
+
+        for &t in &torches {
+            assert_eq!(before.get(t), Tile::Wall);
+            assert!([(0, 1), (0, -1), (1, 0), (-1, 0)].iter().any(|&(dx, dy)| d.get(t.offset(dx, dy)) == Tile::Floor));
+            assert!(torches.iter().filter(|o| (o.x - t.x).abs() < 3 && (o.y - t.y).abs() < 3).count() == 1);
+        }
+
+        // walkability and rooms are untouched
+        assert_eq!(d.graph().room_ids(), before.graph().room_ids());
+        assert_eq!(d.decorate(0.0, &mut DungenRng::new(1)), vec![]);
+    }
+
+    #[test]
+    fn test_light_map() {
+        let d = Dungeon::parse(&[
+            "#!######",
+            "#...#..#",
+            "#...#..#",
+            "########",
+        ]);
+        let light = d.light_map(10, 3);
+        let at = |x: usize, y: usize| light[x + y * 8];
+
+        assert_eq!(at(1, 0), 255);
+        assert_eq!(at(1, 1), 10 + (255.0 * 0.75) as u8);
+        assert!(at(1, 1) > at(2, 2) && at(2, 2) > at(3, 2));
+        // behind the wall
+        assert_eq!(at(5, 1), 10);
+        assert_eq!(at(6, 2), 10);
+        assert_eq!(d.light_map(10, 0)[1], 255);
+        assert_eq!(Dungeon::parse(&["#...#"]).light_map(40, 5), vec![40; 5]);
+    }
+
+    #[test]
+    fn test_torch_blocks_sight() {
+        let d = Dungeon::parse(&["..!.."]);
+        assert!(!d.line_of_sight(Point::new(0, 0), Point::new(4, 0)));
+        assert!(d.get((2, 0)).is_wall());
+    }
+
+    #[test]
+    fn test_add_windows() {
+        let mut d = Dungeon::parse(&[
+            "#########",
+            "#...#...#",
+            "#...#...#",
+            "#...+...#",
+            "#########",
+            "#,,,,,,,#",
+            "#########",
+        ]);
+        // every wall between two spaces but the ones touching the door
+        let windows = d.add_windows(1.0, &mut DungenRng::new(1));
+        assert_eq!(windows[0], Point::new(4, 1));
+        assert_eq!(windows[1..].iter().map(|p| (p.x, p.y)).collect::<Vec<_>>(), vec![(1, 4), (2, 4), (3, 4), (5, 4), (6, 4), (7, 4)]);
+        assert_eq!(d.get((4, 1)), Tile::Window);
+        assert!(!d.get((4, 1)).is_walkable() && d.get((4, 1)).is_wall());
+
+        // seen through from one room into the other, not walked through
+        assert!(d.line_of_sight(Point::new(1, 1), Point::new(7, 1)));
+        assert!(d.field_of_view(Point::new(3, 1), 8).contains(&Point::new(7, 1)));
+        assert_eq!(d.graph().room_ids().len(), 2);
+
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(40, 2).unwrap();
+        let before = d.graph();
+        let windows = d.add_windows(0.5, &mut DungenRng::new(3));
+        assert!(!windows.is_empty());
+        assert_eq!(d.windows(), windows);
+        for &w in &windows {
+            let open: Vec<Point> = [(0, 1), (0, -1), (1, 0), (-1, 0)].iter().map(|&(dx, dy)| w.offset(dx, dy)).filter(|&p| d.get(p).is_walkable()).collect();
+            assert_eq!(open.len(), 2);
+            assert!(open[0].x == open[1].x || open[0].y == open[1].y);
+            assert_ne!(before.node_at(open[0]), before.node_at(open[1]));
+            assert!(open.iter().all(|&p| !d.get(p).is_door()));
+        }
+        assert_eq!(d.graph().room_ids(), before.room_ids());
+    }
+}
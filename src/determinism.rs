@@ -0,0 +1,131 @@
+/*
+Checks on the seeded-generation promise: a seed gives the same level however the process
+running it got there. Each level is generated more than once under different conditions, a
+fresh thread, a heap left in another shape by earlier allocations, a map whose records were
+filled in another order before being put back, and everything the level holds is compared.
+
+What makes this hold is that nothing on a generation path iterates a hashed collection,
+whose order changes from one instance to the next. Tags and records keyed by room are kept
+in BTreeMaps and BTreeSets, and everything else in vecs in a fixed order, which the last
+test here keeps true of the whole crate.
+*/
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use config::Preset;
+use dungeon::Dungeon;
+use names::NameStyle;
+use pipeline::{ Chests, Courtyards, DeadEndRooms, Loops, MergeRooms, NameRooms, Pipeline, Purposes, SafeRoom, Torches, Windows };
+use rng::DungenRng;
+use rooms::{ Constraint, RoomPurpose };
+
+const SEEDS: u64 = 4;
+
+// everything a level holds, tiles, objects, records and tags, digested
+fn fingerprint(d: &Dungeon) -> u64 {
+    let rooms: Vec<_> = d.room_ids().map(|id| d.room(id).cloned()).collect();
+    let text = format!("{}{:?}{:?}{:?}{:?}{:?}{:?}", d.render(), d.entrances(), d.exits(), rooms, d.chests(),
+        d.corridors(), d.feature_layer());
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+// every pass that tags rooms or places things in them
+fn passes() -> Pipeline {
+    let mut pipeline = Pipeline::new();
+    pipeline.add(MergeRooms { chance: 0.3 })
+        .add(DeadEndRooms { chance: 0.5 })
+        .add(Courtyards { fraction: 0.2, grassed: true })
+        .add(Purposes(vec![
+            (RoomPurpose::Library, Constraint::MinArea(20)),
+            (RoomPurpose::Barracks, Constraint::OffMainPath),
+            (RoomPurpose::Storage, Constraint::DeadEnd),
+        ]))
+        .add(NameRooms(NameStyle::Classic))
+        .add(Chests { count: 4, tiers: 3 })
+        .add(SafeRoom)
+        .add(Torches { chance: 0.1 })
+        .add(Windows { chance: 0.1 })
+        .add(Loops { count: 2 });
+    pipeline
+}
+
+// the level's fingerprint and its generation report
+fn level(preset: Preset, seed: u64) -> (u64, String) {
+    let mut d = Dungeon::with_config(80, 50, preset.config());
+    let generated = passes().generate_preset(&mut d, preset, 60, seed).map(|(report, _)| report);
+    (fingerprint(&d), format!("{:?}", generated))
+}
+
+// leaves the heap in another shape, so anything ordered by address comes out differently
+fn churn(seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = DungenRng::new(seed);
+    let mut kept = Vec::new();
+    for i in 0..200 {
+        let block = vec![i as u8; rng.range_inclusive(1, 4096) as usize];
+        if rng.coin() {
+            kept.push(block);
+        }
+    }
+    kept
+}
+
+#[test]
+fn test_levels_repeat() {
+    for &preset in &Preset::ALL {
+        for seed in 0..SEEDS {
+            let first = level(preset, seed);
+
+            let kept = churn(seed);
+            assert_eq!(level(preset, seed), first, "{} seed {} after churn", preset.name(), seed);
+            drop(kept);
+
+            let elsewhere = thread::spawn(move || level(preset, seed)).join().unwrap();
+            assert_eq!(elsewhere, first, "{} seed {} on another thread", preset.name(), seed);
+        }
+    }
+}
+
+#[test]
+fn test_passes_repeat_on_a_reused_map() {
+    for seed in 0..SEEDS {
+        let mut fresh = Dungeon::new(80, 50);
+        fresh.generate_seeded(60, seed).unwrap();
+        let snap = fresh.snapshot();
+        passes().run(&mut fresh, &mut DungenRng::new(seed));
+
+        // the same level put back into a map whose tags and records were built up by other
+        // passes on another level, in another order
+        let mut reused = Dungeon::new(80, 50);
+        reused.generate_seeded(60, seed + 100).unwrap();
+        passes().run(&mut reused, &mut DungenRng::new(seed + 100));
+        reused.restore(&snap);
+        passes().run(&mut reused, &mut DungenRng::new(seed));
+
+        assert_eq!(fingerprint(&reused), fingerprint(&fresh), "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_no_hashed_collections() {
+    // spelled in pieces so this file doesn't trip over itself
+    let needles = [["Hash", "Map"].concat(), ["Hash", "Set"].concat()];
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut files = vec![src];
+    let mut found = Vec::new();
+
+    while let Some(path) = files.pop() {
+        if path.is_dir() {
+            files.extend(fs::read_dir(&path).unwrap().map(|entry| entry.unwrap().path()));
+            continue;
+        }
+
+        let text = fs::read_to_string(&path).unwrap();
+        for needle in &needles {
+            if text.contains(needle.as_str()) {
+                found.push(format!("{} in {}", needle, path.display()));
+            }
+        }
+    }
+    assert!(found.is_empty(), "iteration order would depend on the process: {:?}", found);
+}
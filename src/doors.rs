@@ -0,0 +1,232 @@
+/*
+What state each door is in, for games that tell more than open from closed. The tile grid
+only has ClosedDoor and OpenDoor, which is all generation and the crate's own queries need,
+so every door is on the map as the tile it behaves like and the two states beyond those are
+kept beside the grid:
+
+  Closed   ClosedDoor
+  Open     OpenDoor
+  Broken   OpenDoor, that can't be closed again and is drawn as a wreck
+  Barred   ClosedDoor, that doesn't open without forcing
+
+Anything that writes over a broken or barred door's tile, an edit, a repair or a stamp,
+drops its state with it, so a state on record is always for a door still there as the
+tile the state says. Snapshots, edits, the binary form and stamp all carry them.
+
+DoorCondition is the state of one door. config::DoorState is how generation writes doors
+open or closed in the first place; broken_doors and barred_doors in the config then give a
+share of them the other two.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+use rng::DungenRng;
+use trace::GenEvent;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DoorCondition {
+    Closed,
+    Open,
+    Broken,
+    Barred,
+}
+
+impl DoorCondition {
+    // the tile a door in this state is on the map as
+    pub fn tile(self) -> Tile {
+        match self {
+            DoorCondition::Closed | DoorCondition::Barred => Tile::ClosedDoor,
+            DoorCondition::Open | DoorCondition::Broken => Tile::OpenDoor,
+        }
+    }
+
+    // the states the tile alone doesn't say
+    pub(crate) fn is_kept(self) -> bool {
+        matches!(self, DoorCondition::Broken | DoorCondition::Barred)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Door {
+    pub pos: Point,
+    // the way through: south for a door in a wall along a row, east for one in a wall down
+    // a column
+    pub orientation: Dir,
+    pub state: DoorCondition,
+}
+
+impl Dungeon {
+    // the door at pos, or none if the tile there isn't a door
+    pub fn door(&self, pos: Point) -> Option<Door> {
+        let pos = self.wrap_point(pos);
+        let state = match self.get(pos) {
+            Tile::ClosedDoor => DoorCondition::Closed,
+            Tile::OpenDoor => DoorCondition::Open,
+            _ => return None,
+        };
+
+        let open = |dx, dy| self.get(pos.offset(dx, dy)).is_walkable();
+        let orientation = if open(0, -1) && open(0, 1) { Dir::South } else { Dir::East };
+        let state = self.door_states.get(&pos).cloned().unwrap_or(state);
+        Some(Door { pos, orientation, state })
+    }
+
+    // every door with its state, row by row, as doors lists them
+    pub fn door_details(&self) -> Vec<Door> {
+        self.doors().into_iter().filter_map(|p| self.door(p)).collect()
+    }
+
+    // puts the door at pos in state, writing the tile it's on the map as. false, changing
+    // nothing, if there's no door there.
+    pub fn set_door_state(&mut self, pos: Point, state: DoorCondition) -> bool {
+        let pos = self.wrap_point(pos);
+        if !self.get(pos).is_door() {
+            return false
+        }
+
+        self.set_tile(pos.x, pos.y, state.tile());
+        if state.is_kept() {
+            self.door_states.insert(pos, state);
+        }
+        true
+    }
+
+    // breaks each door with config().broken_doors chance, or bars it with barred_doors
+    // chance, one draw a door in row-major order. returns how many it broke or barred.
+    pub fn damage_doors(&mut self, rng: &mut DungenRng) -> usize {
+        let broken = self.config().broken_doors as f64;
+        let barred = broken + self.config().barred_doors as f64;
+        let mut damaged = 0;
+
+        for p in self.doors() {
+            let roll = rng.next_f64();
+            let state = if roll < broken {
+                DoorCondition::Broken
+            }
+
+            else if roll < barred {
+                DoorCondition::Barred
+            }
+
+            else {
+                continue;
+            };
+            self.set_door_state(p, state);
+            self.record(GenEvent::DoorDamaged { x: p.x, y: p.y, state });
+            damaged += 1;
+        }
+        damaged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::DungeonConfig;
+    use doors::*;
+    use stamp::StampMode;
+
+    fn damaged() -> DungeonConfig {
+        DungeonConfig { broken_doors: 0.2, barred_doors: 0.2, ..DungeonConfig::default() }
+    }
+
+    #[test]
+    fn test_door_states() {
+        let mut d = Dungeon::parse(&[
+            "#######",
+            "#..#..#",
+            "#..+..#",
+            "#..#..#",
+            "##-####",
+            " #.#   ",
+            " ###   ",
+        ]);
+        let (side, below) = (Point::new(3, 2), Point::new(2, 4));
+        assert_eq!(d.door(side), Some(Door { pos: side, orientation: Dir::East, state: DoorCondition::Closed }));
+        assert_eq!(d.door(below).map(|door| (door.orientation, door.state)), Some((Dir::South, DoorCondition::Open)));
+        assert_eq!(d.door(Point::new(1, 1)), None);
+        assert!(!d.set_door_state(Point::new(1, 1), DoorCondition::Barred));
+        assert_eq!(d.get((1, 1)), Tile::Floor);
+
+        // the tiles follow the states
+        assert!(d.set_door_state(side, DoorCondition::Broken));
+        assert!(d.set_door_state(below, DoorCondition::Barred));
+        assert_eq!((d.get(side), d.get(below)), (Tile::OpenDoor, Tile::ClosedDoor));
+        assert_eq!(d.door_details().iter().map(|door| door.state).collect::<Vec<_>>(), vec![DoorCondition::Broken, DoorCondition::Barred]);
+
+        // opening or closing every door leaves these two as they are
+        d.set_all_doors(false);
+        d.set_all_doors(true);
+        assert_eq!((d.door(side).unwrap().state, d.door(below).unwrap().state), (DoorCondition::Broken, DoorCondition::Barred));
+
+        // and a barred door unbarred is just closed
+        d.set_door_state(below, DoorCondition::Closed);
+        assert_eq!(d.door(below).unwrap().state, DoorCondition::Closed);
+
+        // anything written over one takes its state with it
+        d.edit().set_tile(side, Tile::Wall);
+        assert_eq!(d.door(side).map(|door| door.state), Some(DoorCondition::Broken));
+        let mut session = d.edit();
+        session.set_tile(side, Tile::Wall);
+        session.set_tile(side, Tile::OpenDoor);
+        session.commit();
+        assert_eq!(d.door(side).unwrap().state, DoorCondition::Open);
+    }
+
+    #[test]
+    fn test_states_carried() {
+        let mut d = Dungeon::with_config(60, 40, damaged());
+        d.generate_seeded(40, 5).unwrap();
+        let doors = d.door_details();
+        assert!(doors.iter().any(|door| door.state == DoorCondition::Broken));
+        assert!(doors.iter().any(|door| door.state == DoorCondition::Barred));
+        assert!(doors.iter().all(|door| d.get(door.pos) == door.state.tile()));
+
+        // snapshots
+        let snap = d.snapshot();
+        for door in &doors {
+            d.set_door_state(door.pos, DoorCondition::Open);
+        }
+        d.restore(&snap);
+        assert_eq!(d.door_details(), doors);
+
+        // the binary form
+        let back = Dungeon::from_bytes(&d.to_bytes()).unwrap();
+        assert_eq!(back.door_details(), doors);
+
+        // stamps, moved by the offset
+        let mut big = Dungeon::new(100, 60);
+        big.stamp(&d, Point::new(30, 10), StampMode::Strict).unwrap();
+        let moved: Vec<Door> = doors.iter().map(|door| Door { pos: door.pos.offset(30, 10), ..*door }).collect();
+        assert_eq!(big.door_details(), moved);
+    }
+
+    #[test]
+    fn test_generated_damage() {
+        // no damage asked for, none done and nothing drawn
+        let mut plain = Dungeon::new(70, 45);
+        plain.generate_seeded(50, 2).unwrap();
+        assert!(plain.door_details().iter().all(|door| !door.state.is_kept()));
+
+        let mut d = Dungeon::with_config(70, 45, damaged());
+        d.generate_seeded(50, 2).unwrap();
+        let states: Vec<DoorCondition> = d.door_details().iter().map(|door| door.state).collect();
+        assert_eq!(states.len(), plain.doors().len());
+        let kept = states.iter().filter(|s| s.is_kept()).count();
+        assert!(kept > 0 && kept < states.len(), "{} of {}", kept, states.len());
+
+        // only the doors changed, and the same seed damages the same doors
+        for (door, p) in d.door_details().iter().zip(plain.doors()) {
+            assert_eq!(door.pos, p);
+            if !door.state.is_kept() {
+                assert_eq!(d.get(p), plain.get(p));
+            }
+        }
+        let mut again = Dungeon::with_config(70, 45, damaged());
+        again.generate_seeded(50, 2).unwrap();
+        assert_eq!(again.door_details(), d.door_details());
+    }
+}
@@ -0,0 +1,213 @@
+/*
+Undoable manual edits, for level editors. An EditSession borrows the dungeon and routes every
+change through a tracked path that keeps the tiles each edit changed, before and after, so
+the history costs memory in proportion to what was changed rather than to the map. Room
+and corridor records are kept only for edits that changed them.
+
+Dropping a session without committing it rolls the dungeon back to where the session began.
+Generation isn't tracked; a session is for changes made after it.
+*/
+
+use dungeon::{ Dungeon, Records, Tile };
+use error::PlacementError;
+use geom::{ Point, Rect };
+
+struct Edit {
+    // every tile that changed: where, before, after
+    tiles: Vec<(Point, Tile, Tile)>,
+    // the records either side of the edit, when it changed them
+    records: Option<Box<(Records, Records)>>,
+}
+
+pub struct EditSession<'a> {
+    dungeon: &'a mut Dungeon,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl Dungeon {
+    pub fn edit(&mut self) -> EditSession<'_> {
+        EditSession::new(self)
+    }
+}
+
+impl<'a> EditSession<'a> {
+    pub fn new(dungeon: &'a mut Dungeon) -> EditSession<'a> {
+        EditSession { dungeon, undo: Vec::new(), redo: Vec::new() }
+    }
+
+    pub fn dungeon(&self) -> &Dungeon {
+        self.dungeon
+    }
+
+    // writes a single tile. an entrance or exit tile is added to the dungeon's entrances or
+    // exits, and one written over is dropped from them. points off the map are ignored.
+    pub fn set_tile<P: Into<Point>>(&mut self, p: P, tile: Tile) {
+        let p = self.dungeon.wrap_point(p.into());
+        let size = self.dungeon.size();
+        if p.x < 0 || p.y < 0 || p.x >= size.width || p.y >= size.height {
+            return
+        }
+
+        self.track(&[p], |d| {
+            d.set_object(p, tile);
+            Ok(())
+        }).unwrap()
+    }
+
+    // Dungeon::carve_rect, undoably
+    pub fn carve_rect(&mut self, rect: Rect, tile: Tile) -> Result<(), PlacementError> {
+        let mut area = Vec::new();
+        for y in rect.y - 1..rect.y + rect.height + 1 {
+            for x in rect.x - 1..rect.x + rect.width + 1 {
+                area.push(self.dungeon.wrap_point(Point::new(x, y)));
+            }
+        }
+
+        self.track(&area, |d| d.carve_rect(rect, tile))
+    }
+
+    // false if there was nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(edit) => {
+                for &(p, before, _) in &edit.tiles {
+                    self.dungeon.set_tile(p.x, p.y, before);
+                }
+                if let Some(ref records) = edit.records {
+                    self.dungeon.restore_records(&records.0);
+                }
+                self.dungeon.sync_features();
+                self.dungeon.write_orientations();
+
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // false if there was nothing to redo. any new edit clears what could be redone.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                for &(p, _, after) in &edit.tiles {
+                    self.dungeon.set_tile(p.x, p.y, after);
+                }
+                if let Some(ref records) = edit.records {
+                    self.dungeon.restore_records(&records.1);
+                }
+                self.dungeon.sync_features();
+                self.dungeon.write_orientations();
+
+                self.undo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // keeps the dungeon as it is now and ends the session
+    pub fn commit(mut self) {
+        self.undo.clear();
+    }
+
+    // runs f, which may only change tiles in area, and keeps what it changed as one edit
+    fn track<F>(&mut self, area: &[Point], f: F) -> Result<(), PlacementError>
+        where F: FnOnce(&mut Dungeon) -> Result<(), PlacementError>
+    {
+        let before: Vec<Tile> = area.iter().map(|&p| self.dungeon.get(p)).collect();
+        let records = self.dungeon.records();
+
+        f(self.dungeon)?;
+        // painting can join or split rooms
+        self.dungeon.sync_rooms();
+
+        let mut tiles = Vec::new();
+        for (&p, &old) in area.iter().zip(&before) {
+            let new = self.dungeon.get(p);
+            if new != old && !tiles.iter().any(|&(q, _, _)| q == p) {
+                tiles.push((p, old, new));
+            }
+        }
+
+        let after = self.dungeon.records();
+        let records = if after != records { Some(Box::new((records, after))) } else { None };
+        if tiles.is_empty() && records.is_none() {
+            return Ok(())
+        }
+
+        self.undo.push(Edit { tiles, records });
+        self.redo.clear();
+        Ok(())
+    }
+}
+
+impl<'a> Drop for EditSession<'a> {
+    // rolls back everything not committed
+    fn drop(&mut self) {
+        while self.undo() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Rect;
+
+    fn blank() -> Dungeon {
+        Dungeon::parse(&["                "; 10])
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut d = blank();
+        let start = d.render();
+        let mut session = d.edit();
+
+        session.carve_rect(Rect::new(2, 2, 4, 3), Tile::Floor).unwrap();
+        session.set_tile((3, 3), Tile::Entrance);
+        let carved = session.dungeon().render();
+        assert_eq!(session.dungeon().entrance(), Some((3, 3).into()));
+        assert_eq!(session.dungeon().room_ids().count(), 1);
+
+        assert!(session.undo());
+        assert_eq!(session.dungeon().entrance(), None);
+        assert!(session.undo());
+        assert!(!session.undo());
+        assert_eq!(session.dungeon().render(), start);
+        assert_eq!(session.dungeon().room_ids().count(), 0);
+
+        assert!(session.redo());
+        assert!(session.redo());
+        assert!(!session.redo());
+        assert_eq!(session.dungeon().render(), carved);
+        assert_eq!(session.dungeon().room_ids().count(), 1);
+
+        // a fresh edit drops the redo history
+        session.undo();
+        session.set_tile((4, 3), Tile::Exit);
+        assert!(!session.redo());
+        session.commit();
+
+        assert_eq!(d.get((4, 3)), Tile::Exit);
+        assert_eq!(d.get((3, 3)), Tile::Floor);
+    }
+
+    #[test]
+    fn test_dropping_rolls_back() {
+        let mut d = blank();
+        let start = d.render();
+        {
+            let mut session = d.edit();
+            session.carve_rect(Rect::new(2, 2, 4, 3), Tile::Floor).unwrap();
+            session.carve_rect(Rect::new(8, 2, 5, 1), Tile::Corridor).unwrap();
+            assert!(session.carve_rect(Rect::new(3, 3, 2, 2), Tile::Floor).is_err());
+        }
+
+        assert_eq!(d.render(), start);
+        assert!(d.corridors().is_empty());
+        assert_eq!(d.room_ids().count(), 0);
+        assert!(d.room_rects().is_empty());
+    }
+}
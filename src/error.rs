@@ -0,0 +1,503 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use geom::{ Point, Rect };
+use graph::RoomId;
+use validate::BoundaryViolation;
+
+// why a rect or object could not be placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PlacementError {
+    // the rect has no area
+    EmptyRect { rect: Rect },
+    // the rect, or the wall ring around it, would leave the map
+    OutOfBounds { rect: Rect },
+    // the rect covers a tile that is already in use
+    Overlaps { rect: Rect, at: Point },
+    // there are no rooms left to place an object in
+    NoRooms,
+    // the chosen tile is not free floor
+    TileOccupied { x: isize, y: isize },
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlacementError::EmptyRect { rect } => {
+                write!(f, "rect at ({}, {}) has no area ({}x{})", rect.x, rect.y, rect.width, rect.height)
+            }
+            PlacementError::OutOfBounds { rect } => {
+                write!(f, "{}x{} rect at ({}, {}) does not fit inside the map", rect.width, rect.height, rect.x, rect.y)
+            }
+            PlacementError::Overlaps { rect, at } => {
+                write!(f, "{}x{} rect at ({}, {}) overlaps a used tile at ({}, {})",
+                    rect.width, rect.height, rect.x, rect.y, at.x, at.y)
+            }
+            PlacementError::NoRooms => write!(f, "no rooms available"),
+            PlacementError::TileOccupied { x, y } => write!(f, "tile ({}, {}) is not free floor", x, y),
+        }
+    }
+}
+
+impl Error for PlacementError {}
+
+// why a dungeon of the requested size can't be created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DimensionError {
+    // width or height is zero or negative
+    NonPositive { width: isize, height: isize },
+    // the map can't hold the smallest configured room plus its walls and margin
+    TooSmall { width: isize, height: isize, min: isize },
+}
+
+impl fmt::Display for DimensionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DimensionError::NonPositive { width, height } => {
+                write!(f, "dungeon dimensions must be positive, got {}x{}", width, height)
+            }
+            DimensionError::TooSmall { width, height, min } => {
+                write!(f, "{}x{} dungeon is too small, both sides must be at least {}", width, height, min)
+            }
+        }
+    }
+}
+
+impl Error for DimensionError {}
+
+// why glyph rows could not be read as a map, see Dungeon::from_rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ParseError {
+    // no rows, or only empty ones
+    Empty,
+    // a character that is no tile's glyph
+    UnknownGlyph { glyph: char, at: Point },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty => write!(f, "no tiles to read"),
+            ParseError::UnknownGlyph { glyph, at } => write!(f, "{:?} at ({}, {}) is not a tile", glyph, at.x, at.y),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// why a sketch can't be read or realised, see sketch.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SketchError {
+    // no rows, or only empty ones
+    Empty,
+    // a character that is neither a cell nor a link where it stands, at its column and row
+    // in the text
+    UnknownGlyph { glyph: char, at: Point },
+    // a link that isn't between two neighbouring cells with something in them
+    BadLink { a: Point, b: Point },
+    // a second boss cell, where a level has one boss room
+    SecondBoss { cell: Point },
+    // a boss cell with other than exactly one link
+    BossLinks { cell: Point, links: usize },
+    // a boss cell whose link leads, through junctions or not, to no room the entrance could
+    // start in
+    BossCutOff { cell: Point },
+    // the map, inside its border, splits into cells smaller than the 5x5 one room needs
+    CellTooSmall { width: isize, height: isize },
+    // a cell's room couldn't be carved, the map not being blank
+    Blocked { cell: Point, error: PlacementError },
+    // the two ends of a link weren't connected once the level was realised
+    Disconnected { a: Point, b: Point },
+}
+
+impl fmt::Display for SketchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SketchError::Empty => write!(f, "no cells to read"),
+            SketchError::UnknownGlyph { glyph, at } => write!(f, "{:?} at ({}, {}) is not a cell or a link", glyph, at.x, at.y),
+            SketchError::BadLink { a, b } => write!(f, "cells ({}, {}) and ({}, {}) can't be linked", a.x, a.y, b.x, b.y),
+            SketchError::SecondBoss { cell } => write!(f, "cell ({}, {}) is a second boss room", cell.x, cell.y),
+            SketchError::BossLinks { cell, links } => {
+                write!(f, "the boss room at cell ({}, {}) needs exactly one link, it has {}", cell.x, cell.y, links)
+            }
+            SketchError::BossCutOff { cell } => write!(f, "the boss room at cell ({}, {}) is linked to no other room", cell.x, cell.y),
+            SketchError::CellTooSmall { width, height } => write!(f, "{}x{} cells are too small for a room", width, height),
+            SketchError::Blocked { cell, ref error } => write!(f, "cell ({}, {}) is blocked: {}", cell.x, cell.y, error),
+            SketchError::Disconnected { a, b } => write!(f, "cells ({}, {}) and ({}, {}) came out unconnected", a.x, a.y, b.x, b.y),
+        }
+    }
+}
+
+impl Error for SketchError {}
+
+// reasons a generation run can fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DungeonError {
+    // maxfeatures must allow at least the first room
+    InvalidMaxFeatures(isize),
+    // the first room did not fit in the map
+    FirstRoom,
+    // the first room given to generate_with_first_room can't be placed
+    FirstRoomRect(PlacementError),
+    // the exit could not be placed
+    NoExit(PlacementError),
+    // the entrance could not be placed
+    NoEntrance(PlacementError),
+    // the configured entrance room fit in no direction from the map's center
+    EntranceRoom { width: isize, height: isize },
+    // the configured entrance room ended up with fewer ways in than required
+    EntranceDoors { wanted: usize, found: usize },
+    // a cave or maze came out too small to hold both an entrance and an exit
+    NoOpenSpace,
+    // generate_with_surface was given a strip that leaves too little map below it, or a
+    // wrapping map, which has no top for the strip to sit along
+    Surface { rows: isize },
+    // the level broke the configured DungeonConfig::validation in ways repair couldn't fix
+    Validation(ValidationFailures),
+    // generate_from_sketch was given a sketch it couldn't realise
+    Sketch(SketchError),
+    // generate_rotational was given a map that isn't square
+    NotSquare { width: isize, height: isize },
+}
+
+impl fmt::Display for DungeonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DungeonError::InvalidMaxFeatures(n) => write!(f, "maxfeatures must be at least 1, got {}", n),
+            DungeonError::FirstRoom => write!(f, "unable to place first room"),
+            DungeonError::FirstRoomRect(ref e) => write!(f, "unable to place the given first room: {}", e),
+            DungeonError::NoExit(ref e) => write!(f, "unable to place exit: {}", e),
+            DungeonError::NoEntrance(ref e) => write!(f, "unable to place entrance: {}", e),
+            DungeonError::EntranceRoom { width, height } => write!(f, "a {}x{} entrance room does not fit the map", width, height),
+            DungeonError::EntranceDoors { wanted, found } => write!(f, "entrance room needs {} doors, only {} could be added", wanted, found),
+            DungeonError::NoOpenSpace => write!(f, "not enough open space for an entrance and an exit"),
+            DungeonError::Surface { rows } => write!(f, "a surface strip of {} rows doesn't fit this map", rows),
+            DungeonError::Validation(ref failures) => write!(f, "level failed validation: {}", failures),
+            DungeonError::Sketch(ref e) => write!(f, "unable to realise the sketch: {}", e),
+            DungeonError::NotSquare { width, height } => write!(f, "a {}x{} map can't be turned a quarter onto itself", width, height),
+        }
+    }
+}
+
+impl Error for DungeonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DungeonError::InvalidMaxFeatures(_) | DungeonError::FirstRoom => None,
+            DungeonError::EntranceRoom { .. } | DungeonError::EntranceDoors { .. } | DungeonError::NoOpenSpace => None,
+            DungeonError::Surface { .. } | DungeonError::Validation(_) | DungeonError::NotSquare { .. } => None,
+            DungeonError::NoExit(ref e) | DungeonError::NoEntrance(ref e) | DungeonError::FirstRoomRect(ref e) => Some(e),
+            DungeonError::Sketch(ref e) => Some(e),
+        }
+    }
+}
+
+// what a level found wrong under a validation level, see Dungeon::validate. all zeros when
+// it passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ValidationFailures {
+    // entrances and exits the config asks for that aren't on the map
+    pub missing_entrances: usize,
+    pub missing_exits: usize,
+    // open sides of walkable tiles, see Dungeon::validate_boundaries
+    pub open_boundaries: usize,
+    // see Dungeon::validate_doors
+    pub bad_doors: usize,
+    // parts of the map that can't be walked to from the rest, past the first
+    pub cut_off_regions: usize,
+    // rooms with no way in, on a map with anything else on it
+    pub sealed_rooms: usize,
+}
+
+impl ValidationFailures {
+    pub fn is_empty(&self) -> bool {
+        *self == ValidationFailures::default()
+    }
+}
+
+impl fmt::Display for ValidationFailures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            (self.missing_entrances, "missing entrances"),
+            (self.missing_exits, "missing exits"),
+            (self.open_boundaries, "open boundaries"),
+            (self.bad_doors, "bad doors"),
+            (self.cut_off_regions, "cut off regions"),
+            (self.sealed_rooms, "sealed rooms"),
+        ];
+        let found: Vec<String> = counts.iter().filter(|c| c.0 > 0).map(|&(n, what)| format!("{} {}", n, what)).collect();
+        if found.is_empty() {
+            return write!(f, "nothing")
+        }
+
+        write!(f, "{}", found.join(", "))
+    }
+}
+
+// why a dungeon stack could not be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StackError {
+    // a stack needs at least one level
+    NoLevels,
+    // the stack's size doesn't suit the config planned for the level at depth
+    Dimensions { depth: usize, error: DimensionError },
+    // generating the level at depth failed
+    Level { depth: usize, error: DungeonError },
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StackError::NoLevels => write!(f, "a dungeon stack needs at least one level"),
+            StackError::Dimensions { depth, ref error } => write!(f, "level {} has invalid dimensions: {}", depth, error),
+            StackError::Level { depth, ref error } => write!(f, "level {} could not be generated: {}", depth, error),
+        }
+    }
+}
+
+impl Error for StackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StackError::NoLevels => None,
+            StackError::Dimensions { ref error, .. } => Some(error),
+            StackError::Level { ref error, .. } => Some(error),
+        }
+    }
+}
+
+// why saved data could not be read back. not serializable, since it can hold an io error
+// kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    // the reader failed
+    Io(io::ErrorKind),
+    // the data doesn't start the way this kind of data does
+    BadMagic,
+    // written in a format version this build can't read
+    UnsupportedVersion(u16),
+    // the data stops before it should
+    Truncated,
+    // a value is out of range or doesn't agree with the rest of the data
+    Invalid(&'static str),
+    // the stack has no level at this depth
+    NoLevel(usize),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(kind) => write!(f, "read failed: {:?}", kind),
+            LoadError::BadMagic => write!(f, "not dungen save data"),
+            LoadError::UnsupportedVersion(v) => write!(f, "unsupported save format version {}", v),
+            LoadError::Truncated => write!(f, "save data ends early"),
+            LoadError::Invalid(what) => write!(f, "save data is corrupt: bad {}", what),
+            LoadError::NoLevel(depth) => write!(f, "there is no level at depth {}", depth),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+// why a bones file could not be read back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BonesError {
+    // the file was changed or cut short since it was written
+    Checksum { stored: u64, computed: u64 },
+    // the file reads as bones but what's in it doesn't, see LoadError
+    Load(LoadError),
+}
+
+impl fmt::Display for BonesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BonesError::Checksum { stored, computed } => {
+                write!(f, "bones file is corrupt: checksum {:016x} but the data sums to {:016x}", stored, computed)
+            }
+            BonesError::Load(ref e) => write!(f, "unable to read bones: {}", e),
+        }
+    }
+}
+
+impl Error for BonesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            BonesError::Checksum { .. } => None,
+            BonesError::Load(ref e) => Some(e),
+        }
+    }
+}
+
+// why a pass in a pipeline failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassError {
+    BossRoom(BossRoomError),
+    SecretArea(SecretAreaError),
+    // no room qualified as a safe room
+    NoSafeRoom,
+    // the pass left walkable tiles open to the void. only checked in debug builds.
+    Invariants(Vec<BoundaryViolation>),
+    // for passes outside the crate
+    Failed(String),
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PassError::BossRoom(ref e) => write!(f, "no boss room: {}", e),
+            PassError::SecretArea(ref e) => write!(f, "no secret area: {}", e),
+            PassError::NoSafeRoom => write!(f, "no room qualifies as a safe room"),
+            PassError::Invariants(ref v) => write!(f, "{} walkable tiles left open, first at {:?}", v.len(), v.first().map(|v| v.pos)),
+            PassError::Failed(ref why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl Error for PassError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PassError::BossRoom(ref e) => Some(e),
+            PassError::SecretArea(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// why no boss room could be designated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BossRoomError {
+    // distances are measured from the entrance, so there has to be one
+    NoEntrance,
+    // no room past the entrance reaches the minimum area
+    NoCandidate { min_area: isize },
+    // the best room kept more than one way in, since closing the others would cut off
+    // part of the map. the map is left as it was.
+    MultipleEntrances { room: RoomId, entrances: usize },
+}
+
+impl fmt::Display for BossRoomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BossRoomError::NoEntrance => write!(f, "dungeon has no entrance"),
+            BossRoomError::NoCandidate { min_area } => {
+                write!(f, "no reachable room past the entrance has an area of at least {}", min_area)
+            }
+            BossRoomError::MultipleEntrances { room, entrances } => {
+                write!(f, "room {} still has {} entrances, closing any would disconnect the map", room.0, entrances)
+            }
+        }
+    }
+}
+
+impl Error for BossRoomError {}
+
+// why no secret area could be added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SecretAreaError {
+    // the main path runs from the entrance, so there has to be one
+    NoEntrance,
+    // a map has one secret area at most
+    AlreadyPresent(RoomId),
+    // no dead-end room would do, and no wall had space behind it for one
+    NoSpace,
+}
+
+impl fmt::Display for SecretAreaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SecretAreaError::NoEntrance => write!(f, "dungeon has no entrance"),
+            SecretAreaError::AlreadyPresent(id) => write!(f, "room {} is already the secret area", id.0),
+            SecretAreaError::NoSpace => write!(f, "no room or space left for a secret area"),
+        }
+    }
+}
+
+impl Error for SecretAreaError {}
+
+// why two rooms could not be merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MergeError {
+    // the id doesn't name a room
+    NotARoom(RoomId),
+    // a room can't be merged with itself
+    SameRoom(RoomId),
+    // no single wall separates the two rooms
+    NotAdjacent { a: RoomId, b: RoomId },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::NotARoom(id) => write!(f, "{} is not a room", id.0),
+            MergeError::SameRoom(id) => write!(f, "cannot merge room {} with itself", id.0),
+            MergeError::NotAdjacent { a, b } => write!(f, "rooms {} and {} do not share a wall", a.0, b.0),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+// why one dungeon could not be stamped into another, see Dungeon::stamp. nothing is
+// changed when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StampError {
+    // a used tile of the source would land off the map here
+    OutOfBounds { at: Point },
+    // StampMode::Strict, and the tile here is already used
+    Occupied { at: Point },
+}
+
+impl fmt::Display for StampError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StampError::OutOfBounds { at } => write!(f, "stamped tile at ({}, {}) is off the map", at.x, at.y),
+            StampError::Occupied { at } => write!(f, "tile ({}, {}) is already used", at.x, at.y),
+        }
+    }
+}
+
+impl Error for StampError {}
+
+// why a trace could not be rebuilt into a dungeon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TraceError {
+    // the trace was recorded by a different generation version
+    VersionMismatch { trace: u32, current: u32 },
+    // the traced dimensions don't make a valid map
+    Dimensions(DimensionError),
+    // the event at index doesn't fit the map rebuilt from the events before it
+    InvalidEvent { index: usize },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceError::VersionMismatch { trace, current } => {
+                write!(f, "trace was recorded by generation version {}, this is version {}", trace, current)
+            }
+            TraceError::Dimensions(ref e) => write!(f, "invalid trace dimensions: {}", e),
+            TraceError::InvalidEvent { index } => write!(f, "trace event {} cannot be applied", index),
+        }
+    }
+}
+
+impl Error for TraceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TraceError::Dimensions(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
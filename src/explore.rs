@@ -0,0 +1,204 @@
+/*
+What the player has seen. An Exploration is a per-tile layer the same size as the dungeon
+it was made from, kept separately so a game can save it alongside the map. Tiles are unseen,
+remembered (seen before but out of view now) or visible.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use codec::{ self, Reader };
+use dungeon::{ Dungeon, Tile };
+use error::LoadError;
+use geom::{ Point, Size };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Visibility {
+    Unseen,
+    Remembered,
+    Visible,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Exploration {
+    size: Size,
+    wraps: bool,
+    tiles: Vec<Visibility>,
+    // tiles worth exploring: everything but solid rock
+    open: Vec<bool>,
+}
+
+impl Exploration {
+    // nothing seen yet
+    pub fn new(dungeon: &Dungeon) -> Exploration {
+        let size = dungeon.size();
+        Exploration {
+            size,
+            wraps: dungeon.wraps(),
+            tiles: vec![Visibility::Unseen; size.area() as usize],
+            open: dungeon.tile_indices(|t| (t != Tile::Unused) as u32).into_iter().map(|o| o == 1).collect(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    // off-map points are unseen
+    pub fn get<P: Into<Point>>(&self, p: P) -> Visibility {
+        match self.index(p.into()) {
+            Some(i) => self.tiles[i],
+            None => Visibility::Unseen,
+        }
+    }
+
+    // seen at some point, whether or not in view now
+    pub fn is_seen(&self, x: isize, y: isize) -> bool {
+        self.get((x, y)) != Visibility::Unseen
+    }
+
+    pub fn is_visible(&self, x: isize, y: isize) -> bool {
+        self.get((x, y)) == Visibility::Visible
+    }
+
+    // makes the tiles visible. points off the map are ignored.
+    pub fn mark_seen(&mut self, tiles: impl IntoIterator<Item = Point>) {
+        for p in tiles {
+            if let Some(i) = self.index(p) {
+                self.tiles[i] = Visibility::Visible;
+            }
+        }
+    }
+
+    // everything visible becomes remembered, e.g. before the view moves
+    pub fn fade(&mut self) {
+        for v in self.tiles.iter_mut().filter(|v| **v == Visibility::Visible) {
+            *v = Visibility::Remembered;
+        }
+    }
+
+    // fades the old view and marks the field of view from origin as visible
+    pub fn explore_from(&mut self, dungeon: &Dungeon, origin: Point, radius: isize) {
+        self.fade();
+        self.mark_seen(dungeon.field_of_view(origin, radius));
+    }
+
+    // the share of the map's tiles, solid rock aside, seen so far. 1.0 for a map with none.
+    pub fn seen_fraction(&self) -> f64 {
+        let open = self.open.iter().filter(|&&o| o).count();
+        if open == 0 {
+            return 1.0
+        }
+
+        let seen = self.tiles.iter().zip(&self.open).filter(|&(&v, &o)| o && v != Visibility::Unseen).count();
+        seen as f64 / open as f64
+    }
+
+    // the dungeon as the player knows it: seen tiles with their usual glyphs, unseen tiles blank
+    pub fn render(&self, dungeon: &Dungeon) -> String {
+        self.render_with(dungeon, |glyph, _| glyph.to_string())
+    }
+
+    // as render, with remembered tiles dimmed by ansi escapes
+    pub fn render_ansi(&self, dungeon: &Dungeon) -> String {
+        self.render_with(dungeon, |glyph, v| match v {
+            Visibility::Remembered => format!("\x1b[2m{}\x1b[0m", glyph),
+            _ => glyph.to_string(),
+        })
+    }
+
+    fn render_with<F: Fn(char, Visibility) -> String>(&self, dungeon: &Dungeon, draw: F) -> String {
+        let glyphs: Vec<char> = dungeon.render().chars().filter(|&c| c != '\n').collect();
+        let width = self.size.width as usize;
+        let mut out = String::new();
+
+        for (i, (&glyph, &v)) in glyphs.iter().zip(&self.tiles).enumerate() {
+            if v == Visibility::Unseen {
+                out.push(' ');
+            }
+
+            else {
+                out.push_str(&draw(glyph, v));
+            }
+
+            if (i + 1) % width == 0 {
+                out.push('\n');
+            }
+        }
+
+        out
+    }
+
+    // the visibility of each tile, as runs. the rest is rebuilt from the dungeon.
+    pub(crate) fn write_to(&self, out: &mut Vec<u8>) {
+        codec::put_runs(out, self.tiles.iter().map(|&v| v as u8));
+    }
+
+    pub(crate) fn read_from(dungeon: &Dungeon, r: &mut Reader) -> Result<Exploration, LoadError> {
+        let mut e = Exploration::new(dungeon);
+        let visibility = [Visibility::Unseen, Visibility::Remembered, Visibility::Visible];
+        let codes = r.runs(e.tiles.len(), visibility.len() as u8)?;
+        e.tiles = codes.into_iter().map(|c| visibility[c as usize]).collect();
+        Ok(e)
+    }
+
+    fn index(&self, p: Point) -> Option<usize> {
+        let Size { width, height } = self.size;
+        let p = if self.wraps { Point::new(p.x.rem_euclid(width), p.y.rem_euclid(height)) } else { p };
+
+        if p.x < 0 || p.y < 0 || p.x >= width || p.y >= height {
+            return None
+        }
+
+        Some((p.x + p.y * width) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use explore::*;
+    use geom::Point;
+
+    fn fixture() -> Dungeon {
+        Dungeon::parse(&[
+            "#########",
+            "#...+...#",
+            "#########",
+        ])
+    }
+
+    #[test]
+    fn test_explore_and_render() {
+        let d = fixture();
+        let mut e = Exploration::new(&d);
+
+        assert_eq!(e.seen_fraction(), 0.0);
+        assert_eq!(e.render(&d), "         \n         \n         \n");
+
+        e.explore_from(&d, Point::new(2, 1), 5);
+        assert!(e.is_visible(0, 0) && e.is_visible(4, 1));
+        assert!(!e.is_seen(5, 1));
+        assert_eq!(e.render(&d), "#####    \n#...+    \n#####    \n");
+        assert_eq!(e.seen_fraction(), 15.0 / 27.0);
+
+        e.explore_from(&d, Point::new(6, 1), 1);
+        assert_eq!(e.get((2, 1)), Visibility::Remembered);
+        assert!(e.is_visible(6, 1) && e.is_seen(2, 1));
+        assert_eq!(e.render(&d), "##### #  \n#...+... \n##### #  \n");
+    }
+
+    #[test]
+    fn test_mark_seen_and_dim() {
+        let d = fixture();
+        let mut e = Exploration::new(&d);
+
+        e.mark_seen(vec![Point::new(1, 1), Point::new(-1, 0), Point::new(40, 40)]);
+        e.fade();
+        e.mark_seen(vec![Point::new(2, 1)]);
+        assert_eq!(e.get((1, 1)), Visibility::Remembered);
+        assert_eq!(e.render_ansi(&d).lines().nth(1).unwrap(), " \x1b[2m.\x1b[0m.      ");
+        assert_eq!(e.seen_fraction(), 2.0 / 27.0);
+    }
+}
@@ -0,0 +1,232 @@
+/*
+Which room or corridor each tile belongs to, kept as a layer of ids beside the tiles so a
+lookup is a single read instead of a search through rects. A room or corridor stamps its
+tiles into the layer when it's recorded, and sync_rooms, which every pass that joins, splits
+or fills in rooms ends with, rewrites the layer from the graph. So a merged room reads as
+the room it was merged into, and a room that isn't a rect any more still has all of its
+tiles.
+
+A door or archway belongs to the room on its floor side, or failing one to the corridor it
+opens onto. In the layer, 0 is no feature, a room is its id plus one, and a corridor is its
+id plus one with the top bit set.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+use graph::{ self, CorridorId, DungeonGraph, RoomId };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FeatureRef {
+    Room(RoomId),
+    Corridor(CorridorId),
+}
+
+const CORRIDOR_BIT: u32 = 1 << 31;
+
+impl FeatureRef {
+    // the feature's value in feature_layer
+    pub fn to_u32(self) -> u32 {
+        match self {
+            FeatureRef::Room(id) => id.0 + 1,
+            FeatureRef::Corridor(id) => (id.0 + 1) | CORRIDOR_BIT,
+        }
+    }
+
+    // none for 0
+    pub fn from_u32(value: u32) -> Option<FeatureRef> {
+        match (value & !CORRIDOR_BIT, value & CORRIDOR_BIT != 0) {
+            (0, _) => None,
+            (id, false) => Some(FeatureRef::Room(RoomId(id - 1))),
+            (id, true) => Some(FeatureRef::Corridor(CorridorId(id - 1))),
+        }
+    }
+}
+
+impl Dungeon {
+    // the room or corridor the tile at p belongs to. none off the map, for walls and rock,
+    // and for corridor that no recorded corridor was carved as, like tunnels.
+    pub fn feature_at<P: Into<Point>>(&self, p: P) -> Option<FeatureRef> {
+        let p = self.wrap_point(p.into());
+        let size = self.size();
+        if p.x < 0 || p.y < 0 || p.x >= size.width || p.y >= size.height {
+            return None
+        }
+
+        // a tile filled in since the layer was last written belongs to nothing
+        let tile = self.get(p);
+        if graph::node_kind(tile).is_none() && !is_doorway(tile) {
+            return None
+        }
+
+        FeatureRef::from_u32(self.features[self.index(p)])
+    }
+
+    // the layer feature_at reads, one value per tile, x + y * width. see FeatureRef::from_u32.
+    pub fn feature_layer(&self) -> &[u32] {
+        &self.features
+    }
+
+    // marks points, wrapped onto the map, as belonging to feature. points off the map are
+    // skipped.
+    pub(crate) fn stamp_feature<I: IntoIterator<Item = Point>>(&mut self, points: I, feature: FeatureRef) {
+        let size = self.size();
+        for p in points {
+            let p = self.wrap_point(p);
+            if p.x >= 0 && p.y >= 0 && p.x < size.width && p.y < size.height {
+                let i = self.index(p);
+                self.features[i] = feature.to_u32();
+            }
+        }
+    }
+
+    // rewrites the whole layer from graph, which must be of the map as it is now
+    pub(crate) fn write_features(&mut self, graph: &DungeonGraph) {
+        let size = self.size();
+        let feature = |p: Point| {
+            graph.room_at(p).map(FeatureRef::Room).or_else(|| graph.corridor_at(p).map(FeatureRef::Corridor))
+        };
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                let mut found = feature(p);
+                if found.is_none() && is_doorway(self.get(p)) {
+                    let sides: Vec<FeatureRef> = Dir::iterator()
+                        .filter_map(|dir| {
+                            let (dx, dy) = dir.offset();
+                            feature(self.wrap_point(p.offset(dx, dy)))
+                        })
+                        .collect();
+                    found = sides.iter().cloned().find(|f| matches!(f, FeatureRef::Room(_))).or_else(|| sides.first().cloned());
+                }
+
+                let i = self.index(p);
+                self.features[i] = found.map_or(0, FeatureRef::to_u32);
+            }
+        }
+    }
+
+    // write_features with a fresh graph, for when the records were put back without a sync
+    pub(crate) fn sync_features(&mut self) {
+        let graph = self.graph();
+        self.write_features(&graph);
+    }
+}
+
+fn is_doorway(tile: Tile) -> bool {
+    tile.is_door() || tile == Tile::Archway || tile == Tile::SecretDoor
+}
+
+#[cfg(test)]
+mod tests {
+    use config::DungeonConfig;
+    use dungeon::{ Dungeon, Tile };
+    use features::*;
+    use geom::{ Point, Rect };
+
+    #[test]
+    fn test_feature_at() {
+        let d = Dungeon::parse(&[
+            "#############",
+            "#....+,,,#..#",
+            "#....#####..#",
+            "#....#...####",
+            "#.........#  ",
+            "###########  ",
+        ]);
+        let graph = d.graph();
+        let hall = FeatureRef::Room(graph.room_at(Point::new(1, 1)).unwrap());
+
+        assert_eq!(d.feature_at((2, 2)), Some(hall));
+        // the door goes with the room, not the corridor past it
+        assert_eq!(d.feature_at((5, 1)), Some(hall));
+        assert_eq!(d.feature_at((0, 0)), None);
+        assert_eq!(d.feature_at((40, 2)), None);
+        // corridor tiles never recorded as a corridor belong to nothing
+        assert_eq!(d.feature_at((7, 1)), None);
+        // a room that isn't a rect is still one room
+        assert_eq!(d.feature_at((8, 3)), Some(hall));
+        assert_ne!(d.feature_at((10, 1)), Some(hall));
+
+        for value in [0, 1, 7, CORRIDOR_BIT | 1, CORRIDOR_BIT | 30] {
+            assert_eq!(FeatureRef::from_u32(value).map_or(0, FeatureRef::to_u32), value);
+        }
+    }
+
+    #[test]
+    fn test_layer_is_kept_up_to_date() {
+        let mut d = Dungeon::parse(&["                    "; 10]);
+        d.carve_rect(Rect::new(2, 2, 4, 3), Tile::Floor).unwrap();
+        d.carve_rect(Rect::new(7, 3, 5, 1), Tile::Corridor).unwrap();
+        d.set_tile(6, 3, Tile::OpenDoor);
+
+        // stamped as carved, before any sync
+        let room = FeatureRef::Room(d.room_ids().next().unwrap());
+        let corridor = FeatureRef::Corridor(d.corridors()[0].id);
+        assert_eq!(d.feature_at((3, 3)), Some(room));
+        assert_eq!(d.feature_at((9, 3)), Some(corridor));
+
+        d.sync_rooms();
+        assert_eq!(d.feature_at((6, 3)), Some(room));
+
+        // a second room carved against the first's wall and opened into it joins it
+        d.carve_rect(Rect::new(2, 6, 4, 2), Tile::Floor).unwrap();
+        assert_ne!(d.feature_at((3, 6)), Some(room));
+        d.set_tile(3, 5, Tile::Floor);
+        d.sync_rooms();
+        assert_eq!(d.feature_at((3, 6)), Some(room));
+        assert_eq!(d.feature_at((3, 5)), Some(room));
+
+        // filled in tiles drop out straight away
+        d.set_tile(9, 3, Tile::Wall);
+        assert_eq!(d.feature_at((9, 3)), None);
+    }
+
+    #[test]
+    fn test_generated_layer_matches_graph() {
+        for (seed, ruins) in [(1, false), (2, true)] {
+            let config = DungeonConfig { ruins, ..DungeonConfig::default() };
+            let mut d = Dungeon::with_config(60, 40, config);
+            d.generate_seeded(50, seed).unwrap();
+
+            let graph = d.graph();
+            let size = d.size();
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    let p = Point::new(x, y);
+                    if let Some(id) = graph.room_at(p) {
+                        assert_eq!(d.feature_at(p), Some(FeatureRef::Room(id)), "seed {}: {:?}", seed, p);
+                    }
+
+                    else if let Some(id) = graph.corridor_at(p) {
+                        assert_eq!(d.feature_at(p), Some(FeatureRef::Corridor(id)), "seed {}: {:?}", seed, p);
+                    }
+                }
+            }
+
+            // a snapshot keeps the layer, and undoing an edit puts it back
+            let layer = d.feature_layer().to_vec();
+            let snap = d.snapshot();
+            let tiles: Vec<Point> = graph.room_tiles(d.room_ids().next().unwrap()).to_vec();
+            {
+                let mut session = d.edit();
+                for &p in &tiles {
+                    session.set_tile(p, Tile::Wall);
+                }
+                assert_eq!(session.dungeon().feature_at(tiles[0]), None);
+            }
+            assert_eq!(d.feature_layer(), &layer[..]);
+
+            for &p in &tiles {
+                d.set_tile(p.x, p.y, Tile::Wall);
+            }
+            d.sync_rooms();
+            assert_ne!(d.feature_layer(), &layer[..]);
+            d.restore(&snap);
+            assert_eq!(d.feature_layer(), &layer[..]);
+        }
+    }
+}
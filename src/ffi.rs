@@ -0,0 +1,195 @@
+/*
+C interface, behind the "ffi" feature. Declarations are in include/dungen.h.
+
+A dungeon is an opaque pointer from dungen_new that must go back to dungen_free. Calls
+return error codes instead of Results, and panics are caught at the boundary and reported
+as DUNGEN_ERR_PANIC rather than unwinding into the caller. Tiles cross as one byte each,
+numbered in Tile's declaration order.
+*/
+
+// the safety requirements are the header's: pointers must come from dungen_new and not
+// have been freed, and out buffers must hold len bytes
+#![allow(clippy::missing_safety_doc)]
+
+use std::panic::{ self, AssertUnwindSafe };
+use std::ptr;
+use std::slice;
+use dungeon::{ Dungeon, Tile };
+
+pub const DUNGEN_OK: i32 = 0;
+pub const DUNGEN_ERR_NULL: i32 = -1;
+pub const DUNGEN_ERR_GENERATION: i32 = -2;
+pub const DUNGEN_ERR_BUFFER: i32 = -3;
+pub const DUNGEN_ERR_PANIC: i32 = -4;
+
+// dungen_tile_at's answer for a null dungeon, outside the range of real tiles
+pub const DUNGEN_TILE_INVALID: u8 = 255;
+
+pub fn tile_code(tile: Tile) -> u8 {
+    match tile {
+        Tile::Unused => 0,
+        Tile::Floor => 1,
+        Tile::Corridor => 2,
+        Tile::Wall => 3,
+        Tile::ClosedDoor => 4,
+        Tile::OpenDoor => 5,
+        Tile::Exit => 6,
+        Tile::Entrance => 7,
+        Tile::Npc => 8,
+        Tile::Torch => 9,
+        Tile::Grass => 10,
+        Tile::Pit => 11,
+        Tile::Archway => 12,
+        Tile::Window => 13,
+        Tile::Altar => 14,
+        Tile::Pillar => 15,
+        Tile::Water => 16,
+        Tile::SecretDoor => 17,
+    }
+}
+
+// null if the dimensions are unusable
+#[no_mangle]
+pub extern "C" fn dungen_new(width: i32, height: i32) -> *mut Dungeon {
+    let made = panic::catch_unwind(|| Dungeon::try_new(width as isize, height as isize));
+    match made {
+        Ok(Ok(d)) => Box::into_raw(Box::new(d)),
+        _ => ptr::null_mut(),
+    }
+}
+
+// replaces whatever the dungeon held with a fresh map from the seed
+#[no_mangle]
+pub unsafe extern "C" fn dungen_generate(d: *mut Dungeon, maxfeatures: i32, seed: u64) -> i32 {
+    let d = match d.as_mut() {
+        Some(d) => d,
+        None => return DUNGEN_ERR_NULL,
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let size = d.size();
+        *d = Dungeon::new(size.width, size.height);
+        d.generate_seeded(maxfeatures as isize, seed)
+    }));
+
+    match result {
+        Ok(Ok(_)) => DUNGEN_OK,
+        Ok(Err(_)) => DUNGEN_ERR_GENERATION,
+        Err(_) => DUNGEN_ERR_PANIC,
+    }
+}
+
+// 0 for a null dungeon
+#[no_mangle]
+pub unsafe extern "C" fn dungen_width(d: *const Dungeon) -> i32 {
+    d.as_ref().map_or(0, |d| d.size().width as i32)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dungen_height(d: *const Dungeon) -> i32 {
+    d.as_ref().map_or(0, |d| d.size().height as i32)
+}
+
+// tiles outside the map read as unused
+#[no_mangle]
+pub unsafe extern "C" fn dungen_tile_at(d: *const Dungeon, x: i32, y: i32) -> u8 {
+    match d.as_ref() {
+        Some(d) => panic::catch_unwind(|| tile_code(d.get((x as isize, y as isize)))).unwrap_or(DUNGEN_TILE_INVALID),
+        None => DUNGEN_TILE_INVALID,
+    }
+}
+
+// writes width * height tile bytes, row-major, into out. nothing is written if len is short.
+#[no_mangle]
+pub unsafe extern "C" fn dungen_copy_tiles(d: *const Dungeon, out: *mut u8, len: usize) -> i32 {
+    let d = match d.as_ref() {
+        Some(d) => d,
+        None => return DUNGEN_ERR_NULL,
+    };
+    if out.is_null() {
+        return DUNGEN_ERR_NULL
+    }
+
+    let size = d.size();
+    let needed = (size.width * size.height) as usize;
+    if len < needed {
+        return DUNGEN_ERR_BUFFER
+    }
+
+    let out = slice::from_raw_parts_mut(out, needed);
+    let copied = panic::catch_unwind(AssertUnwindSafe(|| {
+        for (o, code) in out.iter_mut().zip(d.tile_indices(|t| tile_code(t) as u32)) {
+            *o = code as u8;
+        }
+    }));
+
+    match copied {
+        Ok(()) => DUNGEN_OK,
+        Err(_) => DUNGEN_ERR_PANIC,
+    }
+}
+
+// null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn dungen_free(d: *mut Dungeon) {
+    if !d.is_null() {
+        drop(Box::from_raw(d));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use dungeon::{ Dungeon, Tile };
+    use ffi::*;
+
+    #[test]
+    fn test_round_trip() {
+        unsafe {
+            let d = dungen_new(60, 40);
+            assert!(!d.is_null());
+            assert_eq!((dungen_width(d), dungen_height(d)), (60, 40));
+            assert_eq!(dungen_generate(d, 40, 99), DUNGEN_OK);
+
+            let mut buf = vec![0u8; 60 * 40];
+            assert_eq!(dungen_copy_tiles(d, buf.as_mut_ptr(), buf.len()), DUNGEN_OK);
+
+            let mut expected = Dungeon::new(60, 40);
+            expected.generate_seeded(40, 99).unwrap();
+            assert_eq!(buf, expected.tile_indices(|t| tile_code(t) as u32).into_iter().map(|c| c as u8).collect::<Vec<_>>());
+            assert_eq!(dungen_tile_at(d, 3, 4), tile_code(expected.get((3, 4))));
+            assert_eq!(buf.iter().filter(|&&c| c == tile_code(Tile::Entrance)).count(), 1);
+
+            // generating again starts over rather than building on the old map
+            assert_eq!(dungen_generate(d, 40, 99), DUNGEN_OK);
+            let mut again = vec![0u8; 60 * 40];
+            dungen_copy_tiles(d, again.as_mut_ptr(), again.len());
+            assert_eq!(again, buf);
+
+            dungen_free(d);
+        }
+    }
+
+    #[test]
+    fn test_error_codes() {
+        unsafe {
+            assert!(dungen_new(0, 10).is_null());
+            assert!(dungen_new(2, 2).is_null());
+            assert_eq!(dungen_generate(ptr::null_mut(), 10, 1), DUNGEN_ERR_NULL);
+            assert_eq!(dungen_width(ptr::null()), 0);
+            assert_eq!(dungen_tile_at(ptr::null(), 0, 0), DUNGEN_TILE_INVALID);
+            dungen_free(ptr::null_mut());
+
+            let d = dungen_new(30, 30);
+            assert_eq!(dungen_generate(d, 0, 1), DUNGEN_ERR_GENERATION);
+            assert_eq!(dungen_tile_at(d, -5, 100), tile_code(Tile::Unused));
+
+            let mut small = vec![7u8; 10];
+            assert_eq!(dungen_copy_tiles(d, small.as_mut_ptr(), small.len()), DUNGEN_ERR_BUFFER);
+            assert_eq!(small, vec![7u8; 10]);
+            assert_eq!(dungen_copy_tiles(d, ptr::null_mut(), 900), DUNGEN_ERR_NULL);
+
+            dungen_free(d);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+/*
+Line of sight and field of view. A tile is seen when the straight line to it from the viewer
+crosses no tile that blocks sight. Lines are Bresenham lines, which are not quite symmetric:
+a seeing b doesn't always mean b sees a. A wall is seen when a line reaches it, and also
+when it touches a seen open tile, otherwise the corners of a narrow corridor stay dark.
+*/
+
+use dungeon::Dungeon;
+use geom::Point;
+
+impl Dungeon {
+    // whether nothing between a and b blocks sight. the end tiles themselves don't count.
+    pub fn line_of_sight(&self, a: Point, b: Point) -> bool {
+        line(a, b).iter().skip(1).take_while(|&&p| p != b).all(|&p| !self.get(p).blocks_sight())
+    }
+
+    // every tile within radius of origin that it can see, origin included, each once. points
+    // are on the map; on a wrapping map the view carries across the edges.
+    pub fn field_of_view(&self, origin: Point, radius: isize) -> Vec<Point> {
+        let size = self.size();
+        let mut seen = vec![false; (size.width * size.height) as usize];
+        let mut out = Vec::new();
+        let on_map = |p: Point| p.x >= 0 && p.y >= 0 && p.x < size.width && p.y < size.height;
+        let in_range: Vec<Point> = (-radius..=radius)
+            .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx * dx + dy * dy <= radius * radius)
+            .map(|(dx, dy)| origin.offset(dx, dy))
+            .collect();
+
+        for &target in &in_range {
+            let p = self.wrap_point(target);
+            if on_map(p) && !seen[self.index(p)] && self.line_of_sight(origin, target) {
+                seen[self.index(p)] = true;
+                out.push(p);
+            }
+        }
+
+        for &target in &in_range {
+            let p = self.wrap_point(target);
+            if !on_map(p) || seen[self.index(p)] || !self.get(p).blocks_sight() {
+                continue;
+            }
+
+            let lit = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))).any(|(dx, dy)| {
+                let n = self.wrap_point(p.offset(dx, dy));
+                on_map(n) && seen[self.index(n)] && !self.get(n).blocks_sight()
+            });
+            if lit {
+                seen[self.index(p)] = true;
+                out.push(p);
+            }
+        }
+
+        out
+    }
+}
+
+// the tiles from a to b inclusive
+fn line(a: Point, b: Point) -> Vec<Point> {
+    let (dx, dy) = ((b.x - a.x).abs(), -(b.y - a.y).abs());
+    let (sx, sy) = ((b.x - a.x).signum(), (b.y - a.y).signum());
+    let mut err = dx + dy;
+    let mut p = a;
+    let mut out = vec![a];
+
+    while p != b {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            p.x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            p.y += sy;
+        }
+        out.push(p);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Point;
+
+    #[test]
+    fn test_walls_block_sight() {
+        let d = Dungeon::parse(&[
+            "#########",
+            "#...#...#",
+            "#...+...#",
+            "#...#...#",
+            "#########",
+        ]);
+        let eye = Point::new(2, 2);
+
+        assert!(d.line_of_sight(eye, Point::new(3, 1)));
+        // the door itself is seen, not what's behind it
+        assert!(d.line_of_sight(eye, Point::new(4, 2)));
+        assert!(!d.line_of_sight(eye, Point::new(6, 2)));
+
+        let view = d.field_of_view(eye, 10);
+        assert!(view.contains(&eye));
+        assert!(view.contains(&Point::new(0, 0)));
+        assert!(view.contains(&Point::new(4, 2)));
+        assert!(view.iter().all(|p| p.x <= 4));
+        assert_eq!(view.len(), 25);
+    }
+
+    #[test]
+    fn test_radius_and_open_doors() {
+        let mut d = Dungeon::parse(&[
+            "#########",
+            "#...#...#",
+            "#...-...#",
+            "#...#...#",
+            "#########",
+        ]);
+
+        assert!(d.line_of_sight(Point::new(2, 2), Point::new(7, 2)));
+        assert_eq!(d.field_of_view(Point::new(2, 2), 0), vec![Point::new(2, 2)]);
+        assert_eq!(d.field_of_view(Point::new(2, 2), 1).len(), 5);
+
+        d.set_tile(4, 2, Tile::ClosedDoor);
+        assert!(!d.line_of_sight(Point::new(2, 2), Point::new(7, 2)));
+    }
+}
@@ -0,0 +1,172 @@
+/*
+Coverage for FrontierStrategy::FillSparse. The map is cut into DENSITY_CELL squares, each
+counting the tiles carved in it: counted afresh when growth starts, then added to as
+place_rect and meandering corridors carve more. has_exits then grows from the face that
+looks out on the least carved region, judged a little way past the face, where what it
+grows will go: the square there and those round it, out to RADIUS. Growth so reaches into
+the parts of the map it has left alone instead of piling up round the first room. Squares
+cut short by the map's edge are weighed by the tiles they have, and ties go to the face
+queued first.
+
+Nothing is drawn for the pick, so the rng is spent only on what's grown there.
+
+The faces themselves are the growth frontier, which frontier() lets callers watch. A face
+is queued only if growth from it would start somewhere in bounds, and leaves the queue
+once something grows from it or, after a round where nothing did, once nothing ever can:
+every tile it could open onto is taken or out of bounds.
+*/
+
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::{ Point, Rect };
+
+// the side of a square in the density grid
+const DENSITY_CELL: isize = 8;
+// how far past a face the middle of the region it's judged by lies, and how many squares
+// that region reaches out each way from the one there
+const REACH: isize = DENSITY_CELL * 2;
+const RADIUS: isize = 3;
+
+impl Dungeon {
+    // the sides of rooms and corridors growth can still go on from, in the order they were
+    // queued. see the module comment.
+    pub fn frontier(&self) -> &[Rect] {
+        &self.faces
+    }
+
+    // counts every square's carved tiles from the map as it is
+    pub(crate) fn count_density(&mut self) {
+        let (columns, rows) = self.density_size();
+        self.density.clear();
+        self.density.resize((columns * rows) as usize, 0);
+
+        let size = self.size();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let tile = self.get((x, y));
+                if tile != Tile::Unused && !tile.is_wall() {
+                    let i = self.density_index(Point::new(x, y));
+                    self.density[i] += 1;
+                }
+            }
+        }
+    }
+
+    // adds rect's tiles to the squares they're in. nothing while there's no grid, when
+    // growth isn't keeping one.
+    pub(crate) fn note_carved(&mut self, rect: &Rect) {
+        let (columns, rows) = self.density_size();
+        if self.density.len() != (columns * rows) as usize {
+            return
+        }
+
+        let size = self.size();
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                let p = self.wrap_point(Point::new(x, y));
+                if p.x >= 0 && p.y >= 0 && p.x < size.width && p.y < size.height {
+                    let i = self.density_index(p);
+                    self.density[i] += 1;
+                }
+            }
+        }
+    }
+
+    // the index in faces of the face in the least carved part of the map, of those not
+    // marked in tried
+    pub(crate) fn sparsest_face(&self, tried: &[bool]) -> usize {
+        self.faces.iter()
+            .enumerate()
+            .filter(|&(i, _)| !tried[i])
+            .map(|(i, face)| {
+                let (carved, tiles) = self.density_around(self.ahead_of(face));
+                (i, carved, tiles)
+            })
+            .min_by(|a, b| (a.1 * b.2).cmp(&(b.1 * a.2)).then(a.0.cmp(&b.0)))
+            .map_or(0, |(i, _, _)| i)
+    }
+
+    // REACH tiles out from the middle of face, away from what it's the side of
+    fn ahead_of(&self, face: &Rect) -> Point {
+        let mid = Point::new(face.x + face.width / 2, face.y + face.height / 2);
+        let out = Dir::iterator()
+            .find(|dir| {
+                let (dx, dy) = dir.opposite().offset();
+                self.get(mid.offset(dx, dy)).is_walkable()
+            })
+            .map_or((0, 0), |dir| dir.offset());
+        mid.offset(out.0 * REACH, out.1 * REACH)
+    }
+
+    // carved tiles and all tiles in p's square and those within RADIUS of it
+    fn density_around(&self, p: Point) -> (u64, u64) {
+        let size = self.size();
+        let (columns, rows) = self.density_size();
+        let p = self.wrap_point(p);
+        let (cx, cy) = (p.x.clamp(0, size.width - 1) / DENSITY_CELL, p.y.clamp(0, size.height - 1) / DENSITY_CELL);
+
+        let (mut carved, mut tiles) = (0, 0);
+        for y in (cy - RADIUS).max(0)..=(cy + RADIUS).min(rows - 1) {
+            for x in (cx - RADIUS).max(0)..=(cx + RADIUS).min(columns - 1) {
+                carved += self.density.get((x + y * columns) as usize).cloned().unwrap_or(0) as u64;
+                let width = DENSITY_CELL.min(size.width - x * DENSITY_CELL);
+                let height = DENSITY_CELL.min(size.height - y * DENSITY_CELL);
+                tiles += (width * height) as u64;
+            }
+        }
+        (carved, tiles)
+    }
+
+    fn density_size(&self) -> (isize, isize) {
+        let size = self.size();
+        ((size.width + DENSITY_CELL - 1) / DENSITY_CELL, (size.height + DENSITY_CELL - 1) / DENSITY_CELL)
+    }
+
+    fn density_index(&self, p: Point) -> usize {
+        let (columns, _) = self.density_size();
+        (p.x / DENSITY_CELL + p.y / DENSITY_CELL * columns) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, FrontierStrategy };
+    use dungeon::Dungeon;
+    use testing::check_invariants;
+
+    // the variance of the walkable tiles in each quarter of the map
+    fn quarter_variance(d: &Dungeon) -> f64 {
+        let size = d.size();
+        let mut quarters = [0.0; 4];
+        for y in 0..size.height {
+            for x in 0..size.width {
+                if d.get((x, y)).is_walkable() {
+                    quarters[(x * 2 / size.width + y * 2 / size.height * 2) as usize] += 1.0;
+                }
+            }
+        }
+
+        let mean = quarters.iter().sum::<f64>() / 4.0;
+        quarters.iter().map(|q| (q - mean) * (q - mean)).sum::<f64>() / 4.0
+    }
+
+    const SEEDS: u64 = 24;
+
+    #[test]
+    fn test_fill_sparse_spreads_growth() {
+        let mean_variance = |frontier| {
+            let config = DungeonConfig { frontier, ..DungeonConfig::default() };
+            let mut total = 0.0;
+            for seed in 0..SEEDS {
+                let mut d = Dungeon::with_config(160, 90, config.clone());
+                d.generate_seeded(40, seed).unwrap();
+                assert!(check_invariants(&d).is_empty(), "{:?} seed {}", frontier, seed);
+                total += quarter_variance(&d);
+            }
+            total / SEEDS as f64
+        };
+
+        let uniform = mean_variance(FrontierStrategy::Uniform);
+        let sparse = mean_variance(FrontierStrategy::FillSparse);
+        assert!(sparse < uniform / 2.0, "uniform {} fill sparse {}", uniform, sparse);
+    }
+}
@@ -0,0 +1,198 @@
+/*
+Coordinate vocabulary shared by the public API. Points and sizes are named structs so x/y
+and width/height can't be swapped silently; tuple conversions are provided so existing
+(isize, isize) call sites keep working.
+*/
+
+use std::collections::BTreeSet;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+
+// a tile position. x grows east, y grows south, (0, 0) is the top-left tile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Point {
+    pub x: isize,
+    pub y: isize,
+}
+
+impl Point {
+    pub fn new(x: isize, y: isize) -> Point {
+        Point { x, y }
+    }
+
+    pub fn offset(&self, dx: isize, dy: isize) -> Point {
+        Point { x: self.x + dx, y: self.y + dy }
+    }
+}
+
+impl From<(isize, isize)> for Point {
+    fn from((x, y): (isize, isize)) -> Point {
+        Point { x, y }
+    }
+}
+
+impl From<Point> for (isize, isize) {
+    fn from(p: Point) -> (isize, isize) {
+        (p.x, p.y)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Size {
+    pub width: isize,
+    pub height: isize,
+}
+
+impl Size {
+    pub fn new(width: isize, height: isize) -> Size {
+        Size { width, height }
+    }
+
+    pub fn area(&self) -> isize {
+        self.width * self.height
+    }
+}
+
+impl From<(isize, isize)> for Size {
+    fn from((width, height): (isize, isize)) -> Size {
+        Size { width, height }
+    }
+}
+
+impl From<Size> for (isize, isize) {
+    fn from(s: Size) -> (isize, isize) {
+        (s.width, s.height)
+    }
+}
+
+// an axis-aligned block of tiles, not including the wall ring carved around it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    pub x: isize,
+    pub y: isize,
+    pub width: isize,
+    pub height: isize,
+}
+
+impl Rect {
+    pub fn new(x: isize, y: isize, width: isize, height: isize) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn from_parts(origin: Point, size: Size) -> Rect {
+        Rect::new(origin.x, origin.y, size.width, size.height)
+    }
+
+    // top-left tile
+    pub fn origin(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.x && p.x < self.x + self.width && p.y >= self.y && p.y < self.y + self.height
+    }
+
+    // true if the rects share at least one tile
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width && other.x < self.x + self.width &&
+            self.y < other.y + other.height && other.y < self.y + self.height
+    }
+
+    // the tiles both rects cover. zero wide or high where they don't meet.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        Rect::new(x, y, (right - x).max(0), (bottom - y).max(0))
+    }
+
+    // middle tile, rounding toward the origin on even sides
+    pub fn center(&self) -> Point {
+        Point::new(self.x + (self.width - 1) / 2, self.y + (self.height - 1) / 2)
+    }
+
+    // every tile, row by row
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let (x, width) = (self.x, self.width);
+        (self.y..self.y + self.height).flat_map(move |y| (x..x + width).map(move |x| Point::new(x, y)))
+    }
+}
+
+// boxes covering tiles exactly, never overlapping: grown greedily from the first tile not
+// yet covered along its row and then down, or the same along columns, whichever takes
+// fewer. sorted by top left tile in row-major order.
+pub(crate) fn cover(tiles: &[Point]) -> Vec<Rect> {
+    let rows = grow_boxes(tiles, false);
+    let columns = grow_boxes(tiles, true);
+    let mut boxes = if columns.len() < rows.len() { columns } else { rows };
+    boxes.sort_by_key(|rect| (rect.y, rect.x));
+    boxes
+}
+
+// the greedy cover, along rows, or along columns if transposed
+fn grow_boxes(tiles: &[Point], transposed: bool) -> Vec<Rect> {
+    // (row, column), or (column, row) when transposed
+    let mut free: BTreeSet<(isize, isize)> = tiles.iter().map(|p| if transposed { (p.x, p.y) } else { (p.y, p.x) }).collect();
+    let mut boxes = Vec::new();
+
+    while let Some(&(r, c)) = free.iter().next() {
+        let mut length = 1;
+        while free.contains(&(r, c + length)) {
+            length += 1;
+        }
+
+        let mut depth = 1;
+        while (c..c + length).all(|i| free.contains(&(r + depth, i))) {
+            depth += 1;
+        }
+
+        for j in r..r + depth {
+            for i in c..c + length {
+                free.remove(&(j, i));
+            }
+        }
+        boxes.push(if transposed { Rect::new(r, c, depth, length) } else { Rect::new(c, r, length, depth) });
+    }
+
+    boxes
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::*;
+
+    #[test]
+    fn test_rect_queries() {
+        let r = Rect::from_parts(Point::new(2, 3), Size::new(4, 3));
+
+        assert_eq!(r, Rect::new(2, 3, 4, 3));
+        assert!(r.contains(Point::new(2, 3)));
+        assert!(r.contains(Point::new(5, 5)));
+        assert!(!r.contains(Point::new(6, 5)));
+        assert!(!r.contains(Point::new(5, 6)));
+        assert_eq!(r.center(), Point::new(3, 4));
+
+        assert!(r.intersects(&Rect::new(5, 5, 2, 2)));
+        assert!(!r.intersects(&Rect::new(6, 3, 2, 2)));
+        assert!(!r.intersects(&Rect::new(2, 6, 4, 1)));
+        assert_eq!(r.intersection(&Rect::new(5, 1, 4, 3)), Rect::new(5, 3, 1, 1));
+        assert_eq!(r.intersection(&Rect::new(6, 3, 2, 2)).width, 0);
+    }
+
+    #[test]
+    fn test_tuple_conversions() {
+        let p: Point = (4, -1).into();
+        let back: (isize, isize) = p.into();
+
+        assert_eq!(p, Point::new(4, -1));
+        assert_eq!(back, (4, -1));
+        assert_eq!(Size::from((3, 5)).area(), 15);
+    }
+}
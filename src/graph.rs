@@ -0,0 +1,424 @@
+/*
+The connectivity graph: rooms and corridors as nodes, the doors and openings between them
+as edges. Rooms are 4-connected areas of floor (the entrance, exit and npcs count as floor).
+Corridors are the recorded corridors, or 4-connected areas of corridor tiles where none were
+recorded. Every door tile is its own edge, so two doors between the same pair of spaces form
+a loop; spaces that touch without a door are joined by a single edge.
+*/
+
+use std::collections::{ BTreeSet, VecDeque };
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+
+// a room, named by an id handed out when it's carved and never reused
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RoomId(pub(crate) u32);
+
+// a recorded corridor, numbered in placement order
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CorridorId(pub(crate) u32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NodeKind {
+    Room,
+    Corridor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphNode {
+    pub kind: NodeKind,
+    // tiles in row-major order
+    pub tiles: Vec<Point>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphEdge {
+    pub a: usize,
+    pub b: usize,
+    // the door tile, or for doorless contacts the tile on the a side
+    pub via: Point,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DungeonGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    width: isize,
+    labels: Vec<Option<usize>>,
+    // the id of each room node
+    rooms: Vec<Option<RoomId>>,
+    // the recorded corridor of each corridor node, if any
+    corridors: Vec<Option<CorridorId>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphMetrics {
+    // rooms plus corridors
+    pub nodes: usize,
+    // doors and doorless openings
+    pub edges: usize,
+    // independent cycles: edges - nodes + components
+    pub cycles: usize,
+    pub components: usize,
+    pub average_room_degree: f32,
+    // most edges on any shortest route between two rooms
+    pub diameter: usize,
+}
+
+pub(crate) fn node_kind(tile: Tile) -> Option<NodeKind> {
+    match tile {
+        Tile::Floor | Tile::Grass | Tile::Entrance | Tile::Exit | Tile::Npc | Tile::Pit | Tile::Altar | Tile::Pillar | Tile::Water => Some(NodeKind::Room),
+        Tile::Corridor => Some(NodeKind::Corridor),
+        _ => None,
+    }
+}
+
+impl DungeonGraph {
+    // node a tile belongs to. door and wall tiles belong to none.
+    pub fn node_at(&self, p: Point) -> Option<usize> {
+        if p.x < 0 || p.y < 0 || p.x >= self.width {
+            return None
+        }
+
+        self.labels.get((p.x + p.y * self.width) as usize).cloned().unwrap_or(None)
+    }
+
+    pub fn degree(&self, node: usize) -> usize {
+        self.edges.iter().filter(|e| e.a == node || e.b == node).count()
+    }
+
+    // neighbouring nodes, one entry per edge
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adj = vec![Vec::new(); self.nodes.len()];
+        for e in &self.edges {
+            adj[e.a].push(e.b);
+            adj[e.b].push(e.a);
+        }
+
+        adj
+    }
+
+    // edges crossed to reach every node from start
+    pub fn hops_from(&self, start: usize) -> Vec<Option<usize>> {
+        let adj = self.adjacency();
+        let mut hops = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        hops[start] = Some(0);
+        queue.push_back(start);
+
+        while let Some(n) = queue.pop_front() {
+            let next = hops[n].unwrap() + 1;
+            for &m in &adj[n] {
+                if hops[m].is_none() {
+                    hops[m] = Some(next);
+                    queue.push_back(m);
+                }
+            }
+        }
+
+        hops
+    }
+
+    pub fn components(&self) -> usize {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut count = 0;
+
+        for start in 0..self.nodes.len() {
+            if seen[start] {
+                continue;
+            }
+
+            count += 1;
+            for (n, h) in self.hops_from(start).into_iter().enumerate() {
+                if h.is_some() {
+                    seen[n] = true;
+                }
+            }
+        }
+
+        count
+    }
+
+    pub fn room_nodes(&self) -> Vec<usize> {
+        (0..self.nodes.len()).filter(|&n| self.nodes[n].kind == NodeKind::Room).collect()
+    }
+
+    // in id order
+    pub fn room_ids(&self) -> Vec<RoomId> {
+        let mut ids: Vec<RoomId> = self.rooms.iter().filter_map(|&id| id).collect();
+        ids.sort();
+        ids
+    }
+
+    // room containing a tile, if the tile is room floor
+    pub fn room_at(&self, p: Point) -> Option<RoomId> {
+        self.node_at(p).and_then(|n| self.rooms[n])
+    }
+
+    // node of a room, none if the room isn't on the map
+    pub fn room_node(&self, id: RoomId) -> Option<usize> {
+        self.rooms.iter().position(|&r| r == Some(id))
+    }
+
+    // empty if the room isn't on the map
+    pub fn room_tiles(&self, id: RoomId) -> &[Point] {
+        match self.room_node(id) {
+            Some(n) => &self.nodes[n].tiles,
+            None => &[],
+        }
+    }
+
+    pub fn room_degree(&self, id: RoomId) -> usize {
+        self.room_node(id).map_or(0, |n| self.degree(n))
+    }
+
+    // recorded corridor containing a tile
+    pub fn corridor_at(&self, p: Point) -> Option<CorridorId> {
+        self.node_at(p).and_then(|n| self.corridors[n])
+    }
+
+    // node of a recorded corridor, none if it's no longer on the map
+    pub fn corridor_node(&self, id: CorridorId) -> Option<usize> {
+        self.corridors.iter().position(|&c| c == Some(id))
+    }
+
+    pub fn metrics(&self) -> GraphMetrics {
+        let components = self.components();
+        let rooms = self.room_nodes();
+
+        let average_room_degree = if rooms.is_empty() {
+            0.0
+        }
+
+        else {
+            rooms.iter().map(|&r| self.degree(r)).sum::<usize>() as f32 / rooms.len() as f32
+        };
+
+        let mut diameter = 0;
+        for &r in &rooms {
+            let hops = self.hops_from(r);
+            for &other in &rooms {
+                if let Some(h) = hops[other] {
+                    diameter = diameter.max(h);
+                }
+            }
+        }
+
+        GraphMetrics {
+            nodes: self.nodes.len(),
+            edges: self.edges.len(),
+            cycles: (self.edges.len() + components).saturating_sub(self.nodes.len()),
+            components,
+            average_room_degree,
+            diameter,
+        }
+    }
+}
+
+impl Dungeon {
+    pub fn graph(&self) -> DungeonGraph {
+        let size = self.size();
+        let mut labels: Vec<Option<usize>> = vec![None; (size.width * size.height) as usize];
+        let mut nodes = Vec::new();
+        let index = |p: Point| (p.x + p.y * size.width) as usize;
+
+        // flood fill each space of a single kind into a node. recorded corridors stay apart
+        // even where they meet.
+        let owners = self.corridor_owners();
+
+        let mut corridors = Vec::new();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let start = Point::new(x, y);
+                let kind = match node_kind(self.get(start)) {
+                    Some(kind) if labels[index(start)].is_none() => kind,
+                    _ => continue,
+                };
+
+                let id = nodes.len();
+                let mut tiles = Vec::new();
+                let mut queue = VecDeque::new();
+                labels[index(start)] = Some(id);
+                queue.push_back(start);
+
+                while let Some(p) = queue.pop_front() {
+                    tiles.push(p);
+                    for dir in Dir::iterator() {
+                        let (dx, dy) = dir.offset();
+                        let n = self.wrap_point(p.offset(dx, dy));
+                        if node_kind(self.get(n)) == Some(kind) && labels[index(n)].is_none() && owners[index(n)] == owners[index(start)] {
+                            labels[index(n)] = Some(id);
+                            queue.push_back(n);
+                        }
+                    }
+                }
+
+                tiles.sort_by_key(|p| (p.y, p.x));
+                nodes.push(GraphNode { kind, tiles });
+                corridors.push(owners[index(start)]);
+            }
+        }
+
+        let label = |p: Point| {
+            let p = self.wrap_point(p);
+            if p.x < 0 || p.y < 0 || p.x >= size.width || p.y >= size.height {
+                return None
+            }
+            labels[index(p)]
+        };
+
+        let mut edges = Vec::new();
+        let mut contacts = BTreeSet::new();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                match self.get(p) {
+                    Tile::ClosedDoor | Tile::OpenDoor | Tile::Archway | Tile::SecretDoor => {
+                        // a door joins every distinct space it touches to the first one
+                        let mut sides: Vec<usize> = Vec::new();
+                        for dir in Dir::iterator() {
+                            let (dx, dy) = dir.offset();
+                            if let Some(n) = label(p.offset(dx, dy)) {
+                                if !sides.contains(&n) {
+                                    sides.push(n);
+                                }
+                            }
+                        }
+
+                        for &other in sides.iter().skip(1) {
+                            edges.push(GraphEdge { a: sides[0].min(other), b: sides[0].max(other), via: p });
+                        }
+                    }
+                    _ => {
+                        // spaces of different kinds touching directly
+                        if let Some(a) = label(p) {
+                            for &(dx, dy) in [(1, 0), (0, 1)].iter() {
+                                if let Some(b) = label(p.offset(dx, dy)) {
+                                    if a != b && contacts.insert((a.min(b), a.max(b))) {
+                                        edges.push(GraphEdge { a: a.min(b), b: a.max(b), via: p });
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // a room keeps the id whose anchor it holds, the oldest if it holds several. rooms
+        // holding none get the ids the next sync_rooms will hand out.
+        let mut rooms = vec![None; nodes.len()];
+        for (id, anchor) in self.room_anchors() {
+            if let Some(n) = label(anchor) {
+                if nodes[n].kind == NodeKind::Room && rooms[n].is_none() {
+                    rooms[n] = Some(id);
+                }
+            }
+        }
+
+        let mut next = self.room_slots.len() as u32;
+        for (n, node) in nodes.iter().enumerate() {
+            if node.kind == NodeKind::Room && rooms[n].is_none() {
+                rooms[n] = Some(RoomId(next));
+                next += 1;
+            }
+        }
+
+        DungeonGraph { nodes, edges, width: size.width, labels, rooms, corridors }
+    }
+
+    pub fn graph_metrics(&self) -> GraphMetrics {
+        self.graph().metrics()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use geom::Point;
+    use graph::*;
+
+    #[test]
+    fn test_single_loop() {
+        let d = Dungeon::parse(&[
+            "###########",
+            "#...+,,,+.#",
+            "#...#####.#",
+            "#...+,,,+.#",
+            "###########",
+        ]);
+        let graph = d.graph();
+        let m = graph.metrics();
+
+        assert_eq!(m.nodes, 4);
+        assert_eq!(m.edges, 4);
+        assert_eq!(m.components, 1);
+        assert_eq!(m.cycles, 1);
+        assert_eq!(m.average_room_degree, 2.0);
+        assert_eq!(m.diameter, 2);
+
+        assert_eq!(graph.node_at(Point::new(1, 1)), graph.node_at(Point::new(3, 3)));
+        assert_eq!(graph.node_at(Point::new(4, 1)), None);
+        assert_eq!(graph.nodes[graph.node_at(Point::new(6, 1)).unwrap()].kind, NodeKind::Corridor);
+        assert_eq!(graph.room_ids().len(), 2);
+        assert_eq!(graph.room_tiles(graph.room_at(Point::new(9, 2)).unwrap()).len(), 3);
+        assert_eq!(graph.room_at(Point::new(6, 1)), None);
+    }
+
+    #[test]
+    fn test_chain_of_rooms() {
+        let d = Dungeon::parse(&[
+            "#################",
+            "#..+,,+..+,,,+..#",
+            "#################",
+        ]);
+        let m = d.graph_metrics();
+
+        assert_eq!(m.nodes, 5);
+        assert_eq!(m.edges, 4);
+        assert_eq!(m.cycles, 0);
+        assert!((m.average_room_degree - 4.0 / 3.0).abs() < 1e-6);
+        assert_eq!(m.diameter, 4);
+    }
+
+    #[test]
+    fn test_disconnected_spaces() {
+        let d = Dungeon::parse(&[
+            "##########",
+            "#..+,,#..#",
+            "##########",
+        ]);
+        let m = d.graph_metrics();
+
+        assert_eq!(m.nodes, 3);
+        assert_eq!(m.edges, 1);
+        assert_eq!(m.components, 2);
+        assert_eq!(m.cycles, 0);
+        assert_eq!(m.average_room_degree, 0.5);
+        // rooms in different components don't count toward the diameter
+        assert_eq!(m.diameter, 0);
+    }
+
+    #[test]
+    fn test_doorless_contact_is_one_edge() {
+        let d = Dungeon::parse(&[
+            "#######",
+            "#..,,,#",
+            "#..,,,#",
+            "#######",
+        ]);
+        let m = d.graph_metrics();
+
+        assert_eq!(m.nodes, 2);
+        assert_eq!(m.edges, 1);
+        assert_eq!(m.cycles, 0);
+    }
+}
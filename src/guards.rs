@@ -0,0 +1,216 @@
+/*
+Choke points worth guarding. A choke is a walkable tile that, if it were blocked, would cut
+the map it belongs to in two or more pieces: every door into a dead-end room, every tile
+of a corridor that is the only way somewhere. What a choke guards is the part of the map
+lying beyond it, taken as every piece but the largest.
+
+Cut tiles that touch each other make one choke, since a corridor that is the only way on is
+a cut at every tile along it, and a guard at one end holds the same ground as at the other.
+Each choke gets one post, on its door if it has one.
+
+This is read-only analysis over 4-connected walkable tiles, found in one depth-first pass
+that scans the map row by row, so results are deterministic.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+use geom::Point;
+use nav::Connectivity;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GuardPost {
+    pub pos: Point,
+    // walkable tiles cut off from the rest of the map if pos were blocked
+    pub beyond: usize,
+    pub on_main_path: bool,
+}
+
+impl Dungeon {
+    // up to max posts, one per choke. posts on the main path come first, then the rest, each
+    // by how much lies beyond them, largest first. the entrance and exit are never posts.
+    pub fn guard_posts(&self, max: usize) -> Vec<GuardPost> {
+        let size = self.size();
+        let beyond = self.cut_sizes();
+        let path = self.main_path().map(|path| path.tiles).unwrap_or_default();
+        let mut taken = vec![false; beyond.len()];
+        let mut posts = Vec::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if beyond[self.index(p)] == 0 || taken[self.index(p)] {
+                    continue;
+                }
+
+                // gather the whole choke, then pick its post
+                let mut choke = vec![p];
+                taken[self.index(p)] = true;
+                let mut i = 0;
+                while i < choke.len() {
+                    for n in self.neighbors(choke[i], Connectivity::Four) {
+                        if beyond[self.index(n)] > 0 && !taken[self.index(n)] {
+                            taken[self.index(n)] = true;
+                            choke.push(n);
+                        }
+                    }
+                    i += 1;
+                }
+
+                let post = choke.into_iter()
+                    .filter(|&p| !matches!(self.get(p), Tile::Entrance | Tile::Exit))
+                    .min_by_key(|&p| (!is_doorway(self.get(p)), std::cmp::Reverse(beyond[self.index(p)]), p.y, p.x));
+                if let Some(pos) = post {
+                    posts.push(GuardPost { pos, beyond: beyond[self.index(pos)], on_main_path: path.contains(&pos) });
+                }
+            }
+        }
+
+        posts.sort_by_key(|post| (!post.on_main_path, std::cmp::Reverse(post.beyond), post.pos.y, post.pos.x));
+        posts.truncate(max);
+        posts
+    }
+
+    // for every tile, indexed x + y * width, how many walkable tiles blocking it would cut
+    // off. zero for tiles that aren't cuts, and for everything that isn't walkable.
+    fn cut_sizes(&self) -> Vec<usize> {
+        let size = self.size();
+        let len = (size.width * size.height) as usize;
+        // discovery order from 1, lowest discovery reachable, tiles below in the search tree
+        let mut order = vec![0; len];
+        let mut low = vec![0; len];
+        let mut below = vec![0; len];
+        // per tile, the sizes of the pieces under it that it alone joins on: their total and
+        // the largest
+        let mut cut = vec![(0, 0); len];
+        let mut out = vec![0; len];
+        let mut next = 1;
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let root = Point::new(x, y);
+                if !self.get(root).is_walkable() || order[self.index(root)] != 0 {
+                    continue;
+                }
+
+                let mut component = vec![root];
+                order[self.index(root)] = next;
+                low[self.index(root)] = next;
+                next += 1;
+                let mut stack = vec![(root, self.neighbors(root, Connectivity::Four), 0)];
+
+                while let Some(&mut (p, ref neighbors, ref mut at)) = stack.last_mut() {
+                    let i = self.index(p);
+                    if *at < neighbors.len() {
+                        let n = neighbors[*at];
+                        *at += 1;
+                        let j = self.index(n);
+                        if order[j] == 0 {
+                            order[j] = next;
+                            low[j] = next;
+                            next += 1;
+                            component.push(n);
+                            stack.push((n, self.neighbors(n, Connectivity::Four), 0));
+                        }
+
+                        else {
+                            low[i] = low[i].min(order[j]);
+                        }
+                        continue;
+                    }
+
+                    stack.pop();
+                    below[i] += 1;
+                    if let Some(&(parent, _, _)) = stack.last() {
+                        let k = self.index(parent);
+                        below[k] += below[i];
+                        low[k] = low[k].min(low[i]);
+                        if low[i] >= order[k] {
+                            cut[k] = (cut[k].0 + below[i], cut[k].1.max(below[i]));
+                        }
+                    }
+                }
+
+                // the root is joined to nothing above it, so it is a cut only when more than
+                // one piece hangs off it
+                let total = component.len();
+                for p in component {
+                    let i = self.index(p);
+                    let (pieces, largest) = cut[i];
+                    let rest = total - 1 - pieces;
+                    if pieces > 0 && (p != root || largest < pieces) {
+                        out[i] = pieces + rest - largest.max(rest);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn is_doorway(tile: Tile) -> bool {
+    tile.is_door() || tile == Tile::Archway || tile == Tile::SecretDoor
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use geom::Point;
+    use nav::Connectivity;
+
+    #[test]
+    fn test_ranking() {
+        let d = Dungeon::parse(&[
+            "#################",
+            "#...#......#....#",
+            "#.<.+......+..>.#",
+            "#...#......#....#",
+            "#######.#########",
+            "  #####+#####    ",
+            "  #.........#    ",
+            "  #.........#    ",
+            "  ###########    ",
+        ]);
+
+        // the door to the side room guards the most, but the two on the main path come
+        // first
+        let posts = d.guard_posts(10);
+        let summary: Vec<_> = posts.iter().map(|post| (post.pos, post.beyond, post.on_main_path)).collect();
+        assert_eq!(summary, vec![
+            (Point::new(11, 2), 12, true),
+            (Point::new(4, 2), 9, true),
+            (Point::new(7, 5), 18, false),
+        ]);
+        assert_eq!(d.guard_posts(2), posts[..2].to_vec());
+        assert!(d.guard_posts(0).is_empty());
+
+        // a ring has no chokes
+        let ring = Dungeon::parse(&["#######", "#.....#", "#.###.#", "#.....#", "#######"]);
+        assert!(ring.guard_posts(10).is_empty());
+    }
+
+    #[test]
+    fn test_posts_cut_what_they_claim() {
+        for seed in 0..6 {
+            let mut d = Dungeon::new(60, 40);
+            if d.generate_seeded(30, seed).is_err() {
+                continue;
+            }
+            let entrance = d.entrance().unwrap();
+            let reachable = d.distance_map(entrance, Connectivity::Four).iter().filter(|t| t.is_some()).count();
+
+            let posts = d.guard_posts(usize::MAX);
+            assert!(!posts.is_empty(), "seed {}", seed);
+            for post in posts {
+                let blocked = d.distance_map_where(entrance, Connectivity::Four, |p| p != post.pos);
+                let cut_off = reachable - 1 - blocked.iter().filter(|t| t.is_some()).count();
+                // seen from the entrance, the cut off part is either what lies beyond the
+                // post or everything else
+                assert!(cut_off == post.beyond || cut_off == reachable - 1 - post.beyond, "seed {}: {:?} cuts {}", seed, post, cut_off);
+                assert_eq!(post.on_main_path, d.main_path().unwrap().tiles.contains(&post.pos));
+            }
+        }
+    }
+}
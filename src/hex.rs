@@ -0,0 +1,472 @@
+/*
+Hex-grid dungeons. Positions are axial coordinates (q, r); tiles are stored as a rectangle
+of "odd-r" offset rows, where every odd row is shifted half a hex to the right, which is
+also how render() draws them.
+
+Generation is a first cut of the square algorithm: rooms are hexagonal blobs of radius 1
+to 3, and every new room hangs off an existing one by a straight corridor with a door at
+each end, so the map is connected by construction.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use std::collections::VecDeque;
+use dungeon::{ GenerationAttempts, GenerationReport, Tile };
+use error::{ DimensionError, DungeonError, PlacementError };
+use rng::{ self, DungenRng };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Hex {
+    pub q: isize,
+    pub r: isize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HexDir {
+    East,
+    NorthEast,
+    NorthWest,
+    West,
+    SouthWest,
+    SouthEast,
+}
+
+static HEX_DIRS: [HexDir; 6] = [HexDir::East, HexDir::NorthEast, HexDir::NorthWest,
+                                HexDir::West, HexDir::SouthWest, HexDir::SouthEast];
+
+impl HexDir {
+    pub fn all() -> &'static [HexDir; 6] {
+        &HEX_DIRS
+    }
+
+    // change in (q, r) for one step. north is toward r = 0.
+    pub fn offset(&self) -> (isize, isize) {
+        match *self {
+            HexDir::East => (1, 0),
+            HexDir::NorthEast => (1, -1),
+            HexDir::NorthWest => (0, -1),
+            HexDir::West => (-1, 0),
+            HexDir::SouthWest => (-1, 1),
+            HexDir::SouthEast => (0, 1),
+        }
+    }
+}
+
+impl Hex {
+    pub fn new(q: isize, r: isize) -> Hex {
+        Hex { q, r }
+    }
+
+    // n steps toward dir
+    pub fn step(&self, dir: HexDir, n: isize) -> Hex {
+        let (dq, dr) = dir.offset();
+        Hex::new(self.q + dq * n, self.r + dr * n)
+    }
+
+    pub fn distance(&self, other: Hex) -> isize {
+        let (dq, dr) = (self.q - other.q, self.r - other.r);
+        (dq.abs() + dr.abs() + (dq + dr).abs()) / 2
+    }
+
+    // column and row in the stored rectangle
+    pub fn to_offset(&self) -> (isize, isize) {
+        (self.q + (self.r - (self.r & 1)) / 2, self.r)
+    }
+
+    pub fn from_offset(col: isize, row: isize) -> Hex {
+        Hex::new(col - (row - (row & 1)) / 2, row)
+    }
+
+    // every hex within radius of this one
+    pub fn spiral(&self, radius: isize) -> Vec<Hex> {
+        let mut out = Vec::new();
+        for dq in -radius..=radius {
+            for dr in (-radius).max(-dq - radius)..=radius.min(-dq + radius) {
+                out.push(Hex::new(self.q + dq, self.r + dr));
+            }
+        }
+
+        out
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HexRoom {
+    pub center: Hex,
+    pub radius: isize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HexDungeon {
+    width: isize,
+    height: isize,
+    tiles: Vec<Tile>,
+    rooms: Vec<HexRoom>,
+    entrance: Option<Hex>,
+    exit: Option<Hex>,
+}
+
+impl HexDungeon {
+    // smallest side that fits a radius 1 room, its walls and a margin
+    pub const MIN_DIMENSION: isize = 7;
+
+    // panics if the dimensions are unusable, see try_new
+    pub fn new(width: isize, height: isize) -> HexDungeon {
+        match HexDungeon::try_new(width, height) {
+            Ok(d) => d,
+            Err(e) => panic!("invalid dungeon dimensions: {}", e),
+        }
+    }
+
+    pub fn try_new(width: isize, height: isize) -> Result<HexDungeon, DimensionError> {
+        if width <= 0 || height <= 0 {
+            return Err(DimensionError::NonPositive { width, height })
+        }
+
+        let min = HexDungeon::MIN_DIMENSION;
+        if width < min || height < min {
+            return Err(DimensionError::TooSmall { width, height, min })
+        }
+
+        Ok(HexDungeon { width, height, tiles: vec![Tile::Unused; (width * height) as usize],
+                        rooms: Vec::new(), entrance: None, exit: None })
+    }
+
+    // columns and rows of the stored rectangle
+    pub fn size(&self) -> (isize, isize) {
+        (self.width, self.height)
+    }
+
+    pub fn contains(&self, h: Hex) -> bool {
+        let (col, row) = h.to_offset();
+        col >= 0 && row >= 0 && col < self.width && row < self.height
+    }
+
+    // tile at h. anything outside the map reads as Unused.
+    pub fn get(&self, h: Hex) -> Tile {
+        match self.index(h) {
+            Some(i) => self.tiles[i],
+            None => Tile::Unused,
+        }
+    }
+
+    pub fn rooms(&self) -> &[HexRoom] {
+        &self.rooms
+    }
+
+    pub fn entrance(&self) -> Option<Hex> {
+        self.entrance
+    }
+
+    pub fn exit(&self) -> Option<Hex> {
+        self.exit
+    }
+
+    pub fn generate_seeded(&mut self, maxfeatures: isize, seed: u64) -> Result<GenerationReport, DungeonError> {
+        if maxfeatures < 1 {
+            return Err(DungeonError::InvalidMaxFeatures(maxfeatures))
+        }
+
+        let mut streams = rng::Streams::new(seed);
+        let rng = &mut streams.layout;
+
+        let center = Hex::from_offset(self.width / 2, self.height / 2);
+        let radius = rng.range_inclusive(1, 3);
+        if !self.place_room(center, radius) {
+            // a smaller first room always fits the minimum map size
+            if !self.place_room(center, 1) {
+                return Err(DungeonError::FirstRoom)
+            }
+        }
+
+        let mut features = 1;
+        for _ in 1..maxfeatures {
+            if !self.grow(rng) {
+                break;
+            }
+            features += 1;
+        }
+
+        self.place_objects(&mut streams.objects)?;
+
+        let exit_distance = match (self.entrance, self.exit) {
+            (Some(from), Some(to)) => self.index(to).and_then(|i| self.distance_map(from)[i]),
+            _ => None,
+        };
+
+        // hex maps have a single entrance and exit, and don't count attempts
+        let exit_distances = if self.entrance.is_some() && self.exit.is_some() { vec![vec![exit_distance]] } else { Vec::new() };
+        Ok(GenerationReport { features, rooms: self.rooms.len(), exit_distance, exit_distances, attempts: GenerationAttempts::default() })
+    }
+
+    // walkable neighbours of h
+    pub fn neighbors(&self, h: Hex) -> Vec<Hex> {
+        HexDir::all().iter()
+            .map(|&dir| h.step(dir, 1))
+            .filter(|&n| self.get(n).is_walkable())
+            .collect()
+    }
+
+    // steps from `from` to every tile, indexed col + row * width. None means unreachable.
+    pub fn distance_map(&self, from: Hex) -> Vec<Option<u32>> {
+        let mut dist = vec![None; self.tiles.len()];
+        let start = match self.index(from) {
+            Some(i) if self.tiles[i].is_walkable() => i,
+            _ => return dist,
+        };
+
+        let mut queue = VecDeque::new();
+        dist[start] = Some(0);
+        queue.push_back(from);
+
+        while let Some(h) = queue.pop_front() {
+            let next = dist[self.index(h).unwrap()].unwrap() + 1;
+            for n in self.neighbors(h) {
+                let i = self.index(n).unwrap();
+                if dist[i].is_none() {
+                    dist[i] = Some(next);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        dist
+    }
+
+    // true if every walkable tile can be reached from every other
+    pub fn is_connected(&self) -> bool {
+        let first = match self.tiles.iter().position(|t| t.is_walkable()) {
+            Some(i) => i as isize,
+            None => return true,
+        };
+
+        let dist = self.distance_map(Hex::from_offset(first % self.width, first / self.width));
+        self.tiles.iter().zip(&dist).all(|(t, d)| !t.is_walkable() || d.is_some())
+    }
+
+    // glyph rows, odd rows shifted half a hex right
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.height {
+            if row & 1 == 1 {
+                out.push(' ');
+            }
+
+            let glyphs: Vec<String> = (0..self.width)
+                .map(|col| glyph(self.get(Hex::from_offset(col, row))).to_string())
+                .collect();
+            out.push_str(glyphs.join(" ").trim_end());
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn index(&self, h: Hex) -> Option<usize> {
+        if !self.contains(h) {
+            return None
+        }
+
+        let (col, row) = h.to_offset();
+        Some((col + row * self.width) as usize)
+    }
+
+    fn set(&mut self, h: Hex, tile: Tile) {
+        if let Some(i) = self.index(h) {
+            self.tiles[i] = tile;
+        }
+    }
+
+    // open hexes need a wall inside the map on every side, and must not touch anything walkable
+    fn can_open(&self, hexes: &[Hex]) -> bool {
+        hexes.iter().all(|&h| {
+            self.get(h) == Tile::Unused &&
+                HexDir::all().iter().all(|&dir| self.contains(h.step(dir, 1)) && !self.get(h.step(dir, 1)).is_walkable())
+        })
+    }
+
+    fn open(&mut self, hexes: &[Hex], tile: Tile) {
+        for &h in hexes {
+            self.set(h, tile);
+        }
+
+        for &h in hexes {
+            for &dir in HexDir::all().iter() {
+                if self.get(h.step(dir, 1)) == Tile::Unused {
+                    self.set(h.step(dir, 1), Tile::Wall);
+                }
+            }
+        }
+    }
+
+    fn place_room(&mut self, center: Hex, radius: isize) -> bool {
+        let blob = center.spiral(radius);
+        if !self.can_open(&blob) {
+            return false
+        }
+
+        self.open(&blob, Tile::Floor);
+        self.rooms.push(HexRoom { center, radius });
+        true
+    }
+
+    // a corridor straight out of a random room's wall, with a new room at its far end
+    fn grow(&mut self, rng: &mut DungenRng) -> bool {
+        for _ in 0..1000 {
+            let from = self.rooms[rng.range_exclusive(self.rooms.len() as isize) as usize];
+            let dir = HexDir::all()[rng.range_exclusive(6) as usize];
+            let length = rng.range_inclusive(2, 6);
+            let radius = rng.range_inclusive(1, 3);
+
+            let door = from.center.step(dir, from.radius + 1);
+            let far_door = door.step(dir, length);
+            let center = far_door.step(dir, radius + 1);
+            let corridor: Vec<Hex> = (1..length).map(|i| door.step(dir, i)).collect();
+            let blob = center.spiral(radius);
+
+            // the far door is still open space, so the corridor and room can't touch through it
+            let mut hexes = corridor.clone();
+            hexes.push(far_door);
+            if self.get(door) != Tile::Wall || !self.can_open(&hexes) || !self.can_open(&blob) {
+                continue;
+            }
+            if blob.iter().any(|b| hexes.iter().any(|h| h.distance(*b) <= 1 && *h != far_door)) {
+                continue;
+            }
+
+            self.open(&blob, Tile::Floor);
+            self.open(&corridor, Tile::Corridor);
+            self.set(door, Tile::ClosedDoor);
+            self.set(far_door, Tile::ClosedDoor);
+            self.rooms.push(HexRoom { center, radius });
+            return true
+        }
+
+        false
+    }
+
+    // the exit in a random room, the entrance in a different one
+    fn place_objects(&mut self, rng: &mut DungenRng) -> Result<(), DungeonError> {
+        if self.rooms.len() < 2 {
+            return Err(DungeonError::NoExit(PlacementError::NoRooms))
+        }
+
+        let exit_room = rng.range_exclusive(self.rooms.len() as isize) as usize;
+        let mut entrance_room = rng.range_exclusive(self.rooms.len() as isize - 1) as usize;
+        if entrance_room >= exit_room {
+            entrance_room += 1;
+        }
+
+        for &(room, tile) in [(exit_room, Tile::Exit), (entrance_room, Tile::Entrance)].iter() {
+            let room = self.rooms[room];
+            let spots = room.center.spiral(room.radius - 1);
+            let h = spots[rng.range_exclusive(spots.len() as isize) as usize];
+            self.set(h, tile);
+
+            if tile == Tile::Exit {
+                self.exit = Some(h);
+            }
+
+            else {
+                self.entrance = Some(h);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn glyph(tile: Tile) -> char {
+    match tile {
+        Tile::Floor => '.',
+        Tile::Corridor => ',',
+        Tile::Wall => '#',
+        Tile::ClosedDoor => '+',
+        Tile::OpenDoor => '-',
+        Tile::Exit => '>',
+        Tile::Entrance => '<',
+        Tile::Npc => '&',
+        Tile::Torch => '!',
+        Tile::Grass => '"',
+        Tile::Pit => '^',
+        Tile::Archway => '\'',
+        Tile::Window => '=',
+        Tile::Altar => '_',
+        Tile::Pillar => 'O',
+        Tile::Water => '~',
+        Tile::SecretDoor => '%',
+        Tile::Unused => ' ',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Tile;
+    use error::{ DimensionError, DungeonError };
+    use hex::*;
+
+    #[test]
+    fn test_hex_coordinates() {
+        let h = Hex::new(2, 3);
+
+        assert_eq!(Hex::from_offset(h.to_offset().0, h.to_offset().1), h);
+        assert_eq!(h.distance(h.step(HexDir::NorthEast, 4)), 4);
+        assert_eq!(h.distance(Hex::new(0, 0)), 5);
+        assert_eq!(h.spiral(0), vec![h]);
+        assert_eq!(h.spiral(1).len(), 7);
+        assert_eq!(h.spiral(3).len(), 37);
+        assert!(h.spiral(2).iter().all(|&n| n.distance(h) <= 2));
+
+        for &dir in HexDir::all().iter() {
+            assert_eq!(h.step(dir, 1).distance(h), 1);
+        }
+    }
+
+    #[test]
+    fn test_hex_generation_is_connected() {
+        for seed in 0..20 {
+            let mut d = HexDungeon::new(40, 30);
+            let report = d.generate_seeded(15, seed).unwrap();
+            let (entrance, exit) = (d.entrance().unwrap(), d.exit().unwrap());
+
+            assert!(d.is_connected(), "seed {}\n{}", seed, d.render());
+            assert_eq!(d.get(entrance), Tile::Entrance);
+            assert_eq!(d.get(exit), Tile::Exit);
+            assert!(report.rooms >= 2);
+            assert!(report.exit_distance.unwrap() > 0);
+
+            // same seed, same map
+            let mut again = HexDungeon::new(40, 30);
+            again.generate_seeded(15, seed).unwrap();
+            assert_eq!(again, d);
+        }
+    }
+
+    #[test]
+    fn test_hex_render_offsets_odd_rows() {
+        let mut d = HexDungeon::new(7, 7);
+        assert!(d.place_room(Hex::from_offset(3, 3), 1));
+
+        // odd rows sit half a hex to the right, so the blob draws as a hexagon
+        assert_eq!(d.render().lines().collect::<Vec<_>>(), vec![
+            "",
+            "     # # #",
+            "    # . . #",
+            "   # . . . #",
+            "    # . . #",
+            "     # # #",
+            "",
+        ]);
+    }
+
+    #[test]
+    fn test_hex_dimensions() {
+        assert_eq!(HexDungeon::try_new(0, 5), Err(DimensionError::NonPositive { width: 0, height: 5 }));
+        assert_eq!(HexDungeon::try_new(6, 9), Err(DimensionError::TooSmall { width: 6, height: 9, min: 7 }));
+        assert_eq!(HexDungeon::new(9, 9).generate_seeded(0, 1), Err(DungeonError::InvalidMaxFeatures(0)));
+    }
+}
@@ -1,4 +1,77 @@
+#[cfg(feature = "rand")]
 extern crate rand;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "bracket")]
+extern crate bracket_pathfinding;
+#[cfg(test)]
+extern crate serde_json;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "ndarray")]
+extern crate ndarray;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
 mod rng;
+pub mod analysis;
+pub mod annotate;
+pub mod audio;
+pub mod blockers;
+pub mod bones;
+#[cfg(feature = "bracket")]
+pub mod bracket;
+pub mod caves;
+pub mod chunks;
+pub mod codec;
+pub mod config;
+pub mod corridors;
+pub mod decor;
+#[cfg(test)]
+mod determinism;
+pub mod doors;
 pub mod dungeon;
+pub mod edit;
+pub mod error;
+pub mod explore;
+pub mod features;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fov;
+pub mod frontier;
+pub mod geom;
+pub mod graph;
+pub mod guards;
+pub mod hex;
+pub mod maze;
+pub mod minimap;
+pub mod names;
+pub mod nav;
+pub mod noise;
+pub mod orientation;
+pub mod pipeline;
+pub mod prefab;
+pub mod progression;
+pub mod projection;
+pub mod repair;
+pub mod reshape;
+pub mod rooms;
+pub mod rotational;
+pub mod secret;
+pub mod sketch;
+pub mod smooth;
+pub mod spawn;
+pub mod stack;
+pub mod stamp;
+pub mod stats;
+pub mod surface;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod validate;
+pub mod walls;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod water;
+
+pub use rng::DungenRng;
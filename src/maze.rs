@@ -0,0 +1,93 @@
+/*
+Maze levels: a perfect maze of one-tile corridors, dug by a randomized depth-first search, so
+there is exactly one path between any two tiles. Cells sit on every other tile starting just
+inside the border, and the tiles between two cells are dug when the search passes from one
+to the other.
+
+The entrance goes in the cell the search starts from and the exit on the tile furthest
+from it, which is always the end of a dead end.
+*/
+
+use dungeon::{ Dir, Dungeon, GenerationReport, Tile };
+use error::DungeonError;
+use geom::Point;
+use rng::{ self, DungenRng };
+
+impl Dungeon {
+    // lays a maze out over a blank map. the same seed gives the same maze.
+    pub fn generate_maze_seeded(&mut self, seed: u64) -> Result<GenerationReport, DungeonError> {
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "maze"));
+        let size = self.size();
+        let inset = self.config().border.max(0) + 1;
+        let (columns, rows) = ((size.width - 2 * inset + 1) / 2, (size.height - 2 * inset + 1) / 2);
+        if columns < 1 || rows < 1 || columns * rows < 2 {
+            return Err(DungeonError::NoOpenSpace)
+        }
+
+        let cell = |c: isize, r: isize| Point::new(inset + 2 * c, inset + 2 * r);
+        let mut visited = vec![false; (columns * rows) as usize];
+        let start = (rng.range_exclusive(columns), rng.range_exclusive(rows));
+        let mut stack = vec![start];
+        visited[(start.0 + start.1 * columns) as usize] = true;
+        self.set_tile(cell(start.0, start.1).x, cell(start.0, start.1).y, Tile::Corridor);
+
+        while let Some(&(c, r)) = stack.last() {
+            let unvisited: Vec<(isize, isize)> = Dir::iterator()
+                .map(|dir| {
+                    let (dx, dy) = dir.offset();
+                    (c + dx, r + dy)
+                })
+                .filter(|&(nc, nr)| nc >= 0 && nr >= 0 && nc < columns && nr < rows && !visited[(nc + nr * columns) as usize])
+                .collect();
+
+            if unvisited.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (nc, nr) = unvisited[rng.range_exclusive(unvisited.len() as isize) as usize];
+            visited[(nc + nr * columns) as usize] = true;
+            let (from, to) = (cell(c, r), cell(nc, nr));
+            self.set_tile((from.x + to.x) / 2, (from.y + to.y) / 2, Tile::Corridor);
+            self.set_tile(to.x, to.y, Tile::Corridor);
+            stack.push((nc, nr));
+        }
+
+        self.wall_in();
+        self.place_stairs(cell(start.0, start.1), Tile::Corridor);
+        self.sync_rooms();
+
+        self.finish(1, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, RoomSizeDistribution };
+    use dungeon::{ Dungeon, Tile };
+    use error::DungeonError;
+    use nav::Connectivity;
+
+    #[test]
+    fn test_maze() {
+        for seed in 0..5 {
+            let mut d = Dungeon::new(41, 21);
+            let report = d.generate_maze_seeded(seed).unwrap();
+
+            assert!(d.validate_boundaries().is_empty(), "seed {}", seed);
+            assert!(report.exit_distance.unwrap() > 0);
+            // 19x9 cells inside the border joined by 170 passages, so every cell is dug
+            // and there are no loops
+            let open = d.render().matches(&[',', '<', '>'][..]).count();
+            assert_eq!(open, 171 + 170);
+            let reached = d.distance_map(d.entrance().unwrap(), Connectivity::Four).iter().filter(|t| t.is_some()).count();
+            assert_eq!(reached, open);
+            assert_eq!(d.get((1, 1)), Tile::Wall);
+        }
+
+        // room for a single cell only
+        let config = DungeonConfig { room_size: RoomSizeDistribution::Uniform { min: 1, max: 1 }, ..DungeonConfig::default() };
+        let mut tiny = Dungeon::with_config(5, 5, config);
+        assert_eq!(tiny.generate_maze_seeded(1).err(), Some(DungeonError::NoOpenSpace));
+    }
+}
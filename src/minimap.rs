@@ -0,0 +1,233 @@
+/*
+Downsampled views of the map. Each minimap cell summarizes a factor x factor block of
+tiles; blocks on the right and bottom edges are cut short where the map ends. The braille
+render packs 2x4 tiles into each character.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MinimapKind {
+    // nothing but unused tiles
+    Empty,
+    // walls and other tiles nobody walks on, pits, pillars and the like, and maybe unused
+    // tiles. nothing walkable.
+    Wall,
+    // every tile walkable
+    Open,
+    // walkable tiles alongside walls or unused ones
+    Mixed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MinimapCell {
+    pub kind: MinimapKind,
+    pub entrance: bool,
+    pub exit: bool,
+    pub door: bool,
+}
+
+impl Dungeon {
+    // columns and rows of the minimap at this factor. a factor of 0 is treated as 1.
+    pub fn minimap_size(&self, factor: usize) -> (usize, usize) {
+        let factor = factor.max(1);
+        let size = self.size();
+        let ceil = |n: isize| (n as usize).div_ceil(factor);
+
+        (ceil(size.width), ceil(size.height))
+    }
+
+    // one cell per block, row-major, minimap_size(factor) cells in all
+    pub fn minimap(&self, factor: usize) -> Vec<MinimapCell> {
+        let factor = factor.max(1);
+        let size = self.size();
+        let (columns, rows) = self.minimap_size(factor);
+        let mut cells = Vec::with_capacity(columns * rows);
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x0, y0) = ((column * factor) as isize, (row * factor) as isize);
+                let (x1, y1) = ((x0 + factor as isize).min(size.width), (y0 + factor as isize).min(size.height));
+                let mut cell = MinimapCell { kind: MinimapKind::Empty, entrance: false, exit: false, door: false };
+                let (mut open, mut walls, mut unused) = (0, 0, 0);
+
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let tile = self.get((x, y));
+                        match tile {
+                            Tile::Unused => unused += 1,
+                            // an npc stands on floor
+                            Tile::Npc => open += 1,
+                            _ if tile.is_walkable() => open += 1,
+                            _ => walls += 1,
+                        }
+
+                        cell.entrance |= tile == Tile::Entrance;
+                        cell.exit |= tile == Tile::Exit;
+                        cell.door |= tile.is_door();
+                    }
+                }
+
+                cell.kind = if open == 0 && walls == 0 {
+                    MinimapKind::Empty
+                }
+
+                else if open == 0 {
+                    MinimapKind::Wall
+                }
+
+                else if walls + unused == 0 {
+                    MinimapKind::Open
+                }
+
+                else {
+                    MinimapKind::Mixed
+                };
+
+                cells.push(cell);
+            }
+        }
+
+        cells
+    }
+
+    // the minimap as glyph rows: '<' and '>' for blocks holding the entrance or exit,
+    // otherwise '.' open, ':' mixed, '#' wall and ' ' empty
+    pub fn render_minimap(&self, factor: usize) -> String {
+        let (columns, _) = self.minimap_size(factor);
+        let mut out = String::new();
+
+        for row in self.minimap(factor).chunks(columns) {
+            for cell in row {
+                out.push(if cell.entrance {
+                    '<'
+                }
+
+                else if cell.exit {
+                    '>'
+                }
+
+                else {
+                    match cell.kind {
+                        MinimapKind::Empty => ' ',
+                        MinimapKind::Wall => '#',
+                        MinimapKind::Open => '.',
+                        MinimapKind::Mixed => ':',
+                    }
+                });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+// bit for the braille dot at column x, row y of a 2x4 cell
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+impl Dungeon {
+    // walkable tiles as raised dots, 2 tiles across and 4 down per character. the entrance
+    // and exit are walkable like any floor. past the edges of the map the dots stay off.
+    pub fn render_braille(&self) -> String {
+        let size = self.size();
+        let mut out = String::new();
+
+        for row in 0..(size.height + 3) / 4 {
+            for column in 0..(size.width + 1) / 2 {
+                let mut bits = 0;
+                for (dx, dots) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dy, &bit) in dots.iter().enumerate() {
+                        if self.get((column * 2 + dx as isize, row * 4 + dy as isize)).is_walkable() {
+                            bits |= bit;
+                        }
+                    }
+                }
+
+                out.push(::std::char::from_u32(0x2800 + bits).unwrap());
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use minimap::*;
+
+    #[test]
+    fn test_minimap_blocks() {
+        let d = Dungeon::parse(&[
+            "######   ",
+            "#....#   ",
+            "#.<..+,,,",
+            "#....#   ",
+            "######   ",
+        ]);
+
+        assert_eq!(d.minimap_size(2), (5, 3));
+        assert_eq!(d.render_minimap(2), ":::  \n:<:::\n###  \n");
+
+        let cells = d.minimap(2);
+        assert_eq!(cells[5 + 1].kind, MinimapKind::Open);
+        assert!(cells[5 + 1].entrance);
+        assert!(cells[5 + 2].door);
+        assert!(!cells[5 + 3].door);
+        // the bottom row of blocks is only one tile high
+        assert_eq!(cells[10].kind, MinimapKind::Wall);
+        assert_eq!(cells[14].kind, MinimapKind::Empty);
+
+        // pits, pillars and altars block the way like walls, an npc doesn't
+        let d = Dungeon::parse(&[
+            "..^O",
+            "._.&",
+        ]);
+        let kinds: Vec<MinimapKind> = d.minimap(2).iter().map(|cell| cell.kind).collect();
+        assert_eq!(kinds, vec![MinimapKind::Mixed, MinimapKind::Mixed]);
+        assert_eq!(d.minimap(1)[2].kind, MinimapKind::Wall);
+        assert_eq!(d.minimap(1)[7].kind, MinimapKind::Open);
+    }
+
+    #[test]
+    fn test_minimap_factor_edges() {
+        let d = Dungeon::parse(&[
+            "#####",
+            "#.>.#",
+            "#####",
+        ]);
+
+        // one block covers the whole map
+        assert_eq!(d.minimap_size(10), (1, 1));
+        assert_eq!(d.minimap(10), vec![MinimapCell { kind: MinimapKind::Mixed, entrance: false, exit: true, door: false }]);
+        assert_eq!(d.render_minimap(10), ">\n");
+
+        // factor 1 and factor 0 give one cell per tile
+        assert_eq!(d.minimap_size(0), (5, 3));
+        assert_eq!(d.render_minimap(1), "#####\n#.>.#\n#####\n");
+        assert_eq!(d.minimap(0), d.minimap(1));
+    }
+
+    #[test]
+    fn test_braille() {
+        let d = Dungeon::parse(&[
+            "#####",
+            "#.<.#",
+            "#..,,",
+            "#####",
+            "#.#  ",
+        ]);
+
+        // columns 0-1: dots at (1,1), (1,2), (1,4). columns 2-3: (0,1), (1,1), (0,2), (1,2).
+        // column 4 with padding: (4,2).
+        assert_eq!(d.render_braille(), "\u{2830}\u{2836}\u{2804}\n\u{2808}\u{2800}\u{2800}\n");
+        assert_eq!(Dungeon::new(200, 200).render_braille().lines().count(), 50);
+        assert!(Dungeon::new(200, 200).render_braille().lines().all(|l| l.chars().count() == 100));
+    }
+}
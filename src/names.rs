@@ -0,0 +1,229 @@
+/*
+Flavour names for rooms and levels, built from word lists compiled into the crate or
+supplied by the caller. Names depend only on the rng they are drawn from, so the same
+seed always gives the same names.
+*/
+
+use std::collections::BTreeSet;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::Dungeon;
+use graph::RoomId;
+use rng::DungenRng;
+use rooms::RoomPurpose;
+
+// word lists for room names. a room is called "The <adjective> <noun>": the adjective comes
+// from the list for its most notable tag, the noun from its purpose or else its size.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NameWords {
+    // nouns for rooms under 20 tiles, under 60 tiles, and larger
+    pub small: Vec<String>,
+    pub medium: Vec<String>,
+    pub large: Vec<String>,
+    // adjectives, by tag. boss outranks treasure, then safe, then dead end.
+    pub boss: Vec<String>,
+    pub treasure: Vec<String>,
+    pub safe: Vec<String>,
+    pub dead_end: Vec<String>,
+    pub plain: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NameStyle {
+    Classic,
+    Grim,
+    Custom(NameWords),
+}
+
+fn words(list: &[&str]) -> Vec<String> {
+    list.iter().map(|w| w.to_string()).collect()
+}
+
+impl NameStyle {
+    pub fn words(&self) -> NameWords {
+        let (boss, plain): (&[&str], &[&str]) = match *self {
+            NameStyle::Custom(ref words) => return words.clone(),
+            NameStyle::Classic => (&["Throne", "Champion's", "Warlord's", "Great"],
+                                   &["Quiet", "Old", "Stone", "Dusty", "Narrow", "Forgotten"]),
+            NameStyle::Grim => (&["Butcher's", "Blackened", "Dread", "Hollow King's"],
+                                &["Flooded", "Rotting", "Silent", "Ashen", "Weeping", "Crumbling"]),
+        };
+
+        NameWords {
+            small: words(&["Alcove", "Cell", "Niche", "Closet", "Cubby"]),
+            medium: words(&["Chamber", "Antechamber", "Gallery", "Study", "Vestibule"]),
+            large: words(&["Hall", "Vault", "Cavern", "Atrium", "Crypt"]),
+            boss: words(boss),
+            treasure: words(&["Gilded", "Hoarder's", "Glittering", "Jeweled"]),
+            safe: words(&["Lantern-lit", "Sheltered", "Hearth", "Peaceful"]),
+            dead_end: words(&["Lonely", "Walled", "Last", "Hidden"]),
+            plain: words(plain),
+        }
+    }
+}
+
+fn purpose_noun(purpose: RoomPurpose) -> Option<&'static str> {
+    match purpose {
+        RoomPurpose::Barracks => Some("Barracks"),
+        RoomPurpose::Library => Some("Library"),
+        RoomPurpose::Shrine => Some("Shrine"),
+        RoomPurpose::Storage => Some("Storeroom"),
+        RoomPurpose::Prison => Some("Prison"),
+        RoomPurpose::Start => Some("Hall"),
+        RoomPurpose::Secret => Some("Cache"),
+        RoomPurpose::Custom(_) => None,
+    }
+}
+
+// one word from the first non-empty list
+fn pick<'a>(lists: &[&'a [String]], rng: &mut DungenRng) -> Option<&'a str> {
+    let list = lists.iter().find(|l| !l.is_empty())?;
+    Some(&list[rng.range_exclusive(list.len() as isize) as usize])
+}
+
+impl Dungeon {
+    // names every room, replacing earlier names. rooms are named in id order and a name
+    // already taken is redrawn a few times before it's allowed to repeat.
+    pub fn name_rooms(&mut self, style: NameStyle, rng: &mut DungenRng) {
+        let words = style.words();
+        let mut taken = BTreeSet::new();
+        self.names.clear();
+
+        for id in self.graph().room_ids() {
+            let info = self.room_info(id).unwrap();
+            let treasure = self.chests.iter().any(|c| c.room == id);
+
+            let tagged: &[String] = if info.boss {
+                &words.boss
+            }
+
+            else if treasure {
+                &words.treasure
+            }
+
+            else if info.safe {
+                &words.safe
+            }
+
+            else if info.connections == 1 {
+                &words.dead_end
+            }
+
+            else {
+                &words.plain
+            };
+
+            let sized: &[String] = match info.area {
+                0..=19 => &words.small,
+                20..=59 => &words.medium,
+                _ => &words.large,
+            };
+
+            let mut name = String::new();
+            for _ in 0..8 {
+                let adjective = pick(&[tagged, &words.plain], rng);
+                let noun = match info.purpose.and_then(purpose_noun) {
+                    Some(noun) => noun,
+                    None => pick(&[sized, &words.medium], rng).unwrap_or("Room"),
+                };
+
+                name = match adjective {
+                    Some(adjective) => format!("The {} {}", adjective, noun),
+                    None => format!("The {}", noun),
+                };
+                if !taken.contains(&name) {
+                    break;
+                }
+            }
+
+            taken.insert(name.clone());
+            self.names.insert(id, name);
+        }
+
+        self.sync_rooms();
+    }
+
+    pub fn room_name(&self, id: RoomId) -> Option<&str> {
+        self.names.get(&id).map(|n| n.as_str())
+    }
+}
+
+static LEVEL_ADJECTIVES: [&str; 8] = ["Sunken", "Drowned", "Burning", "Forsaken", "Whispering", "Shattered", "Frozen", "Endless"];
+static LEVEL_PLACES: [&str; 8] = ["Halls", "Catacombs", "Depths", "Warrens", "Vaults", "Galleries", "Cisterns", "Mines"];
+static LEVEL_THINGS: [&str; 8] = ["Ash", "Mourning", "the Lost King", "Bones", "Echoes", "the Serpent", "Rust", "Embers"];
+
+// a name for a whole level, such as "The Sunken Halls of Ash". the same seed and depth
+// always give the same name.
+pub fn level_name(seed: u64, depth: usize) -> String {
+    let mut rng = DungenRng::new(seed ^ (depth as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    let mut draw = |list: &[&'static str]| list[rng.range_exclusive(list.len() as isize) as usize];
+
+    format!("The {} {} of {}", draw(&LEVEL_ADJECTIVES), draw(&LEVEL_PLACES), draw(&LEVEL_THINGS))
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use names::*;
+    use rng::DungenRng;
+    use rooms::RoomPurpose;
+
+    #[test]
+    fn test_names_are_deterministic() {
+        let mut a = Dungeon::new(80, 50);
+        a.generate_seeded(50, 140).unwrap();
+        let mut b = Dungeon::new(80, 50);
+        b.generate_seeded(50, 140).unwrap();
+
+        a.name_rooms(NameStyle::Classic, &mut DungenRng::new(140));
+        b.name_rooms(NameStyle::Classic, &mut DungenRng::new(140));
+
+        let ids = a.graph().room_ids();
+        let names: Vec<&str> = ids.iter().map(|&id| a.room_name(id).unwrap()).collect();
+        assert_eq!(names, ids.iter().map(|&id| b.room_name(id).unwrap()).collect::<Vec<_>>());
+        assert!(names.iter().all(|n| n.starts_with("The ")));
+        assert_eq!(a.room_info(ids[0]).unwrap().name.as_deref(), Some(names[0]));
+
+        assert_eq!(level_name(7, 3), level_name(7, 3));
+        assert_ne!(level_name(7, 3), level_name(7, 4));
+        assert!(level_name(7, 3).starts_with("The "));
+    }
+
+    #[test]
+    fn test_tags_and_purposes_pick_words() {
+        // a hall with a dead end off either side and one below
+        let mut d = Dungeon::parse(&[
+            "###########",
+            "#<.+...+.>#",
+            "#..#...#..#",
+            "#####+#####",
+            "    #.#    ",
+            "    #.#    ",
+            "    ###    ",
+        ]);
+        let graph = d.graph();
+        let (middle, bottom) = (graph.room_at((5, 1).into()).unwrap(), graph.room_at((5, 4).into()).unwrap());
+        d.purposes.insert(bottom, RoomPurpose::Library);
+        d.boss_room = Some(middle);
+        d.sync_rooms();
+
+        let words = NameWords {
+            small: vec!["Nook".to_string()],
+            medium: vec!["Room".to_string()],
+            large: vec![],
+            boss: vec!["Boss".to_string()],
+            treasure: vec![],
+            safe: vec![],
+            dead_end: vec!["Dead".to_string()],
+            plain: vec!["Plain".to_string()],
+        };
+        d.name_rooms(NameStyle::Custom(words), &mut DungenRng::new(1));
+
+        assert_eq!(d.room_name(middle), Some("The Boss Nook"));
+        // the library has one door, so it's a dead end, but its purpose names it
+        assert_eq!(d.room_name(bottom), Some("The Dead Library"));
+        assert_eq!(d.room_name(graph.room_at((1, 1).into()).unwrap()), Some("The Dead Nook"));
+    }
+}
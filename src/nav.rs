@@ -0,0 +1,295 @@
+/*
+Movement queries over the tile grid: neighbours, connected regions, distance maps and
+shortest paths. Generation itself is strictly 4-directional; these queries can also
+treat diagonal steps as moves for cave maps and field of view.
+
+walkable_rects gives the walkable ground as rects instead, for physics engines and navmeshes
+that want a few static regions rather than a grid. It's the greedy cover the audio zones use,
+so it's deterministic and exact but not always the fewest rects there could be.
+*/
+
+use std::collections::VecDeque;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::Dungeon;
+use geom::{ self, Point, Rect };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Connectivity {
+    // orthogonal steps only
+    Four,
+    // orthogonal and diagonal steps. without corner cutting, a diagonal step is
+    // refused when both orthogonal tiles it squeezes between are blocked.
+    Eight { cut_corners: bool },
+}
+
+const ORTHOGONAL: [(isize, isize); 4] = [(0, -1), (0, 1), (1, 0), (-1, 0)];
+const DIAGONAL: [(isize, isize); 4] = [(1, -1), (-1, -1), (1, 1), (-1, 1)];
+
+impl Dungeon {
+    // walkable tiles one step away from p
+    pub fn neighbors(&self, p: Point, conn: Connectivity) -> Vec<Point> {
+        let mut out = Vec::with_capacity(8);
+
+        for &(dx, dy) in ORTHOGONAL.iter() {
+            if self.get(p.offset(dx, dy)).is_walkable() {
+                out.push(self.wrap_point(p.offset(dx, dy)));
+            }
+        }
+
+        if let Connectivity::Eight { cut_corners } = conn {
+            for &(dx, dy) in DIAGONAL.iter() {
+                if !self.get(p.offset(dx, dy)).is_walkable() {
+                    continue;
+                }
+
+                let squeezed = !self.get(p.offset(dx, 0)).is_walkable() && !self.get(p.offset(0, dy)).is_walkable();
+                if cut_corners || !squeezed {
+                    out.push(self.wrap_point(p.offset(dx, dy)));
+                }
+            }
+        }
+
+        out
+    }
+
+    // rects covering every walkable tile, doors and objects included, and nothing else, no
+    // two overlapping. ordered by top left tile in row-major order.
+    pub fn walkable_rects(&self) -> Vec<Rect> {
+        let size = self.size();
+        let tiles: Vec<Point> = (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|&p| self.get(p).is_walkable())
+            .collect();
+        geom::cover(&tiles)
+    }
+
+    // connected groups of walkable tiles, each in discovery order, scanning row by row
+    pub fn regions(&self, conn: Connectivity) -> Vec<Vec<Point>> {
+        let size = self.size();
+        let mut seen = vec![false; (size.width * size.height) as usize];
+        let mut regions = Vec::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let start = Point::new(x, y);
+                if seen[self.index(start)] || !self.get(start).is_walkable() {
+                    continue;
+                }
+
+                let mut region = Vec::new();
+                let mut queue = VecDeque::new();
+                seen[self.index(start)] = true;
+                queue.push_back(start);
+
+                while let Some(p) = queue.pop_front() {
+                    region.push(p);
+                    for n in self.neighbors(p, conn) {
+                        if !seen[self.index(n)] {
+                            seen[self.index(n)] = true;
+                            queue.push_back(n);
+                        }
+                    }
+                }
+
+                regions.push(region);
+            }
+        }
+
+        regions
+    }
+
+    // steps from `from` to every tile, indexed x + y * width. None means unreachable.
+    pub fn distance_map(&self, from: Point, conn: Connectivity) -> Vec<Option<u32>> {
+        self.distance_map_where(from, conn, |_| true)
+    }
+
+    // as distance_map, stepping only onto tiles `open` accepts. `from` itself is exempt.
+    pub(crate) fn distance_map_where<F: Fn(Point) -> bool>(&self, from: Point, conn: Connectivity, open: F) -> Vec<Option<u32>> {
+        let size = self.size();
+        let from = self.wrap_point(from);
+        let mut dist = vec![None; (size.width * size.height) as usize];
+
+        if !self.get(from).is_walkable() {
+            return dist
+        }
+
+        let mut queue = VecDeque::new();
+        dist[self.index(from)] = Some(0);
+        queue.push_back(from);
+
+        while let Some(p) = queue.pop_front() {
+            let next = dist[self.index(p)].unwrap() + 1;
+            for n in self.neighbors(p, conn) {
+                if dist[self.index(n)].is_none() && open(n) {
+                    dist[self.index(n)] = Some(next);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        dist
+    }
+
+    // a shortest walkable path, both ends included
+    pub fn find_path(&self, from: Point, to: Point, conn: Connectivity) -> Option<Vec<Point>> {
+        self.find_path_where(from, to, conn, |_| true)
+    }
+
+    // as find_path, through tiles `open` accepts. `from` itself is exempt.
+    pub(crate) fn find_path_where<F: Fn(Point) -> bool>(&self, from: Point, to: Point, conn: Connectivity, open: F) -> Option<Vec<Point>> {
+        if !self.get(from).is_walkable() || !self.get(to).is_walkable() {
+            return None
+        }
+        let (from, to) = (self.wrap_point(from), self.wrap_point(to));
+        if !open(to) {
+            return None
+        }
+
+        // search backwards so the path can be read off front to back. the path starts at
+        // from, so from may be closed and is let in as the last step.
+        let dist = self.distance_map_where(to, conn, |p| p == from || open(p));
+        dist[self.index(from)]?;
+
+        let mut path = vec![from];
+        let mut p = from;
+        while p != to {
+            let d = dist[self.index(p)].unwrap();
+            p = self.neighbors(p, conn).into_iter()
+                .find(|&n| dist[self.index(n)] == Some(d - 1))
+                .unwrap();
+            path.push(p);
+        }
+
+        Some(path)
+    }
+
+    pub(crate) fn index(&self, p: Point) -> usize {
+        (p.x + p.y * self.size().width) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use geom::{ Point, Rect };
+    use nav::Connectivity;
+
+    const EIGHT: Connectivity = Connectivity::Eight { cut_corners: true };
+    const EIGHT_STRICT: Connectivity = Connectivity::Eight { cut_corners: false };
+
+    #[test]
+    fn test_diagonal_gap_between_walls() {
+        let d = Dungeon::parse(&[
+            "#####",
+            "#.###",
+            "##.##",
+            "#####",
+        ]);
+        let (a, b) = (Point::new(1, 1), Point::new(2, 2));
+
+        assert_eq!(d.find_path(a, b, Connectivity::Four), None);
+        assert_eq!(d.find_path(a, b, EIGHT), Some(vec![a, b]));
+        assert_eq!(d.find_path(a, b, EIGHT_STRICT), None);
+        assert_eq!(d.regions(EIGHT_STRICT).len(), 2);
+        assert_eq!(d.regions(EIGHT).len(), 1);
+    }
+
+    #[test]
+    fn test_corner_rule_allows_one_open_side() {
+        let d = Dungeon::parse(&[
+            "#####",
+            "#..##",
+            "##.##",
+            "#####",
+        ]);
+
+        let n = d.neighbors(Point::new(1, 1), EIGHT_STRICT);
+        assert!(n.contains(&Point::new(2, 2)));
+        assert!(n.contains(&Point::new(2, 1)));
+        assert_eq!(n.len(), 2);
+    }
+
+    #[test]
+    fn test_distance_map_and_path() {
+        let d = Dungeon::parse(&[
+            "#######",
+            "#.....#",
+            "#.###.#",
+            "#.....#",
+            "#######",
+        ]);
+        let four = d.distance_map(Point::new(1, 1), Connectivity::Four);
+        let eight = d.distance_map(Point::new(1, 1), EIGHT);
+
+        assert_eq!(four[5 + 3 * 7], Some(6));
+        assert_eq!(eight[5 + 3 * 7], Some(5));
+        assert_eq!(four[0], None);
+
+        let path = d.find_path(Point::new(1, 1), Point::new(5, 3), Connectivity::Four).unwrap();
+        assert_eq!(path.len(), 7);
+        assert_eq!(path[0], Point::new(1, 1));
+        assert_eq!(path[6], Point::new(5, 3));
+        for pair in path.windows(2) {
+            assert_eq!((pair[0].x - pair[1].x).abs() + (pair[0].y - pair[1].y).abs(), 1);
+        }
+    }
+
+    #[test]
+    fn test_entrance_reaches_exit() {
+        let mut d = Dungeon::new(60, 60);
+        d.generate_seeded(40, 3).unwrap();
+
+        let path = d.find_path(d.entrance().unwrap(), d.exit().unwrap(), Connectivity::Four);
+        assert!(path.is_some());
+    }
+
+    #[test]
+    fn test_walkable_rects() {
+        let d = Dungeon::parse(&[
+            "#########",
+            "#...#...#",
+            "#...-...#",
+            "#...#####",
+            "##,##    ",
+            " #,#     ",
+            " ###     ",
+        ]);
+        assert_eq!(d.walkable_rects(), vec![Rect::new(1, 1, 3, 3), Rect::new(5, 1, 3, 2), Rect::new(4, 2, 1, 1), Rect::new(2, 4, 1, 2)]);
+
+        for seed in 0..6 {
+            let mut d = Dungeon::new(80, 50);
+            match seed % 3 {
+                0 => d.generate_seeded(60, seed).unwrap(),
+                1 => d.generate_caves_seeded(seed).unwrap(),
+                _ => d.generate_hybrid_seeded(60, seed).unwrap(),
+            };
+            let rects = d.walkable_rects();
+
+            // every walkable tile is covered once, and nothing else
+            let size = d.size();
+            let mut covered = vec![0; (size.width * size.height) as usize];
+            for rect in &rects {
+                for p in rect.points() {
+                    covered[(p.x + p.y * size.width) as usize] += 1;
+                }
+            }
+            let mut walkable = 0;
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    let open = d.get((x, y)).is_walkable();
+                    walkable += open as usize;
+                    assert_eq!(covered[(x + y * size.width) as usize], open as usize, "seed {}: ({}, {})", seed, x, y);
+                }
+            }
+
+            // far fewer rects than tiles
+            assert!(rects.len() * 4 < walkable, "seed {}: {} rects for {} tiles", seed, rects.len(), walkable);
+            let mut sorted = rects.clone();
+            sorted.sort_by_key(|r| (r.y, r.x));
+            assert_eq!(sorted, rects);
+            assert_eq!(d.walkable_rects(), rects);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/*
+Smooth random variation over the map, for styling that shouldn't look placed by hand:
+cracked floor, patches of moss. This is value noise: every point of an integer lattice gets
+a random value hashed from the seed and its coordinates, and points between are blended
+from the four lattice points around them with a smoothstep curve, so there are no creases
+along the lattice lines. A second octave at twice the frequency and half the weight breaks
+up the blobs.
+
+Nothing here draws from an rng, so the same seed gives the same values at the same
+coordinates whatever else has been asked for.
+*/
+
+use dungeon::Dungeon;
+use geom::Point;
+use rng;
+
+const OCTAVES: u32 = 2;
+
+// noise in [0, 1] at x, y, where one lattice cell is one unit
+pub fn value_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let mut total = 0.0;
+    let mut weight = 1.0;
+    let mut weights = 0.0;
+    for octave in 0..OCTAVES {
+        let frequency = (1 << octave) as f64;
+        total += weight * lattice_noise(rng::stream_seed(seed, &format!("octave {}", octave)), x * frequency, y * frequency);
+        weights += weight;
+        weight /= 2.0;
+    }
+
+    total / weights
+}
+
+fn lattice_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (ix, iy) = (x0 as i64, y0 as i64);
+
+    let top = lerp(lattice_value(seed, ix, iy), lattice_value(seed, ix + 1, iy), tx);
+    let bottom = lerp(lattice_value(seed, ix, iy + 1), lattice_value(seed, ix + 1, iy + 1), tx);
+    lerp(top, bottom, ty)
+}
+
+// a value in [0, 1) for a lattice point
+fn lattice_value(seed: u64, x: i64, y: i64) -> f64 {
+    let h = rng::mix(seed ^ rng::mix((x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (y as u64)));
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+impl Dungeon {
+    // a variation index per tile, row-major, from noise with features about scale tiles
+    // across. walkable tiles get 0 to 255, everything else 0. a scale under 1 is taken
+    // as 1.
+    pub fn floor_variation(&self, seed: u64, scale: f64) -> Vec<u8> {
+        let size = self.size();
+        let scale = if scale >= 1.0 { scale } else { 1.0 };
+        let mut out = vec![0; size.area() as usize];
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p).is_walkable() {
+                    out[self.index(p)] = (value_noise(seed, x as f64 / scale, y as f64 / scale) * 255.0).round() as u8;
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use noise::*;
+
+    #[test]
+    fn test_value_noise_is_smooth_and_in_range() {
+        for i in 0..400 {
+            let (x, y) = (i as f64 * 0.37 - 50.0, i as f64 * 0.91 - 100.0);
+            let v = value_noise(3, x, y);
+            assert!((0.0..=1.0).contains(&v));
+            // a small step moves the value a little
+            assert!((value_noise(3, x + 0.01, y) - v).abs() < 0.05);
+        }
+
+        // lattice points aren't all alike, and other seeds give other values
+        let values: Vec<f64> = (0..20).map(|i| value_noise(3, i as f64, 0.0)).collect();
+        assert!(values.iter().any(|&v| (v - values[0]).abs() > 0.1));
+        assert!((0..20).any(|i| value_noise(4, i as f64, 0.0) != values[i]));
+    }
+
+    #[test]
+    fn test_floor_variation() {
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(30, 2).unwrap();
+
+        let a = d.floor_variation(9, 6.0);
+        assert_eq!(a, d.floor_variation(9, 6.0));
+        assert_ne!(a, d.floor_variation(10, 6.0));
+        assert_eq!(a.len(), 60 * 40);
+
+        let size = d.size();
+        let mut spread = (255, 0);
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let v = a[(x + y * size.width) as usize];
+                if d.get((x, y)).is_walkable() {
+                    spread = (spread.0.min(v), spread.1.max(v));
+                }
+
+                else {
+                    assert_eq!(v, 0);
+                }
+            }
+        }
+        assert!(spread.1 - spread.0 > 64, "{:?}", spread);
+
+        // below one tile per feature is the same as one
+        assert_eq!(d.floor_variation(9, 0.25), d.floor_variation(9, 1.0));
+        assert_eq!(d.floor_variation(9, f64::NAN), d.floor_variation(9, 1.0));
+    }
+}
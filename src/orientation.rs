@@ -0,0 +1,268 @@
+/*
+Which way doors and corridors run, for tilesets with a sprite for each. Worked out from a
+tile's neighbours on the client this goes wrong around junctions and doors, so it's kept
+as a layer beside the tiles instead, one byte a tile, written in one pass over the map
+by sync_rooms, which every generator and every pass that digs or fills corridors ends
+with, and by edits and their undo. Snapshots carry it, and stamps and the binary form
+write it afresh.
+
+A door, archway or secret door is Horizontal in a wall along a row, with the way through
+running north and south, and Vertical in a wall down a column. A corridor tile is classed
+by the sides it opens onto anything walkable, rooms and doors as well as more corridor:
+straight, a corner, a T, a cross, a dead end, or nothing at all.
+
+In the layer, 0 is anything else, 1 and 2 are Horizontal and Vertical, and a corridor is 16
+plus a bit for each open side, 1 << dir.index() for its Dir.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+
+const HORIZONTAL: u8 = 1;
+const VERTICAL: u8 = 2;
+const CORRIDOR: u8 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Orientation {
+    // doors, archways and secret doors, by the wall they're in
+    Horizontal,
+    Vertical,
+    // corridors
+    NorthSouth,
+    EastWest,
+    NorthEast,
+    SouthEast,
+    SouthWest,
+    NorthWest,
+    // a T, by the one side that's closed
+    Tee(Dir),
+    Cross,
+    // a dead end, by the one side that's open
+    End(Dir),
+    // open on no side
+    Isolated,
+}
+
+impl Orientation {
+    // none for 0, and for values no tile is given
+    pub fn from_u8(value: u8) -> Option<Orientation> {
+        match value {
+            HORIZONTAL => Some(Orientation::Horizontal),
+            VERTICAL => Some(Orientation::Vertical),
+            CORRIDOR..=31 => {
+                let open: Vec<Dir> = Dir::iterator().cloned().filter(|dir| value & (1 << dir.index()) != 0).collect();
+                Some(match open.as_slice() {
+                    [] => Orientation::Isolated,
+                    &[dir] => Orientation::End(dir),
+                    [Dir::North, Dir::South] => Orientation::NorthSouth,
+                    [Dir::East, Dir::West] => Orientation::EastWest,
+                    [Dir::North, Dir::East] => Orientation::NorthEast,
+                    [Dir::South, Dir::East] => Orientation::SouthEast,
+                    [Dir::South, Dir::West] => Orientation::SouthWest,
+                    [Dir::North, Dir::West] => Orientation::NorthWest,
+                    [_, _, _] => {
+                        let closed = Dir::iterator().cloned().find(|dir| !open.contains(dir)).unwrap();
+                        Orientation::Tee(closed)
+                    }
+                    _ => Orientation::Cross,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Dungeon {
+    // which way the door, archway, secret door or corridor at (x, y) runs, as of the last
+    // sync. none off the map, for every other tile, and for a tile changed into or out of
+    // one since.
+    pub fn tile_orientation(&self, x: isize, y: isize) -> Option<Orientation> {
+        let p = self.wrap_point(Point::new(x, y));
+        let size = self.size();
+        if p.x < 0 || p.y < 0 || p.x >= size.width || p.y >= size.height {
+            return None
+        }
+
+        let value = self.orientations[self.index(p)];
+        let fits = match self.get(p) {
+            Tile::Corridor => value >= CORRIDOR,
+            tile if tile.is_door() || matches!(tile, Tile::Archway | Tile::SecretDoor) => value == HORIZONTAL || value == VERTICAL,
+            _ => false,
+        };
+        if fits { Orientation::from_u8(value) } else { None }
+    }
+
+    // the layer tile_orientation reads, one byte per tile, x + y * width. see the module
+    // comment.
+    pub fn orientation_layer(&self) -> &[u8] {
+        &self.orientations
+    }
+
+    // rewrites the whole layer from the tiles
+    pub(crate) fn write_orientations(&mut self) {
+        let size = self.size();
+        self.orientations.resize((size.width * size.height) as usize, 0);
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                let i = self.index(p);
+                self.orientations[i] = self.orientation_value(p);
+            }
+        }
+    }
+
+    // the layer's value for p as the map is now
+    pub(crate) fn orientation_value(&self, p: Point) -> u8 {
+        let size = self.size();
+        let open = |dir: &Dir| {
+            let (dx, dy) = dir.offset();
+            let n = self.wrap_point(p.offset(dx, dy));
+            n.x >= 0 && n.y >= 0 && n.x < size.width && n.y < size.height && self.get(n).is_walkable()
+        };
+
+        match self.get(p) {
+            Tile::Corridor => Dir::iterator().filter(|dir| open(dir)).fold(CORRIDOR, |value, dir| value | 1 << dir.index()),
+            tile if tile.is_door() || matches!(tile, Tile::Archway | Tile::SecretDoor) => {
+                if open(&Dir::North) && open(&Dir::South) { HORIZONTAL } else { VERTICAL }
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dir, Dungeon, Tile };
+    use geom::Point;
+    use orientation::*;
+    use rng::DungenRng;
+
+    // the map with every oriented tile drawn as which way it runs
+    fn arrows(d: &Dungeon) -> String {
+        let size = d.size();
+        let mut out = String::new();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                out.push(match d.tile_orientation(x, y) {
+                    Some(Orientation::Horizontal) => '↕',
+                    Some(Orientation::Vertical) => '↔',
+                    Some(Orientation::NorthSouth) => '│',
+                    Some(Orientation::EastWest) => '─',
+                    Some(Orientation::NorthEast) => '└',
+                    Some(Orientation::SouthEast) => '┌',
+                    Some(Orientation::SouthWest) => '┐',
+                    Some(Orientation::NorthWest) => '┘',
+                    Some(Orientation::Tee(Dir::North)) => '┬',
+                    Some(Orientation::Tee(Dir::East)) => '┤',
+                    Some(Orientation::Tee(Dir::South)) => '┴',
+                    Some(Orientation::Tee(Dir::West)) => '├',
+                    Some(Orientation::Cross) => '┼',
+                    Some(Orientation::End(Dir::North)) => '╵',
+                    Some(Orientation::End(Dir::East)) => '╶',
+                    Some(Orientation::End(Dir::South)) => '╷',
+                    Some(Orientation::End(Dir::West)) => '╴',
+                    Some(Orientation::Isolated) => '·',
+                    None => d.get((x, y)).glyph(),
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn fresh(d: &Dungeon) -> bool {
+        let size = d.size();
+        (0..size.height).all(|y| (0..size.width).all(|x| {
+            let p = Point::new(x, y);
+            d.orientation_layer()[d.index(p)] == d.orientation_value(p)
+        }))
+    }
+
+    #[test]
+    fn test_golden_orientations() {
+        let mut d = Dungeon::new(60, 30);
+        d.generate_seeded(30, 3).unwrap();
+        let drawn = arrows(&d);
+        assert_eq!(drawn, include_str!("../tests/golden/orientations.txt"), "\n{}", drawn);
+    }
+
+    #[test]
+    fn test_orientations() {
+        let mut d = Dungeon::parse(&[
+            "############",
+            "#...+,,,,###",
+            "#...####,###",
+            "#...#,,,,,,#",
+            "##-##,######",
+            " #,,,,,,#   ",
+            " ####,###   ",
+            "    #,#     ",
+            "    ###     ",
+        ]);
+        let at = |d: &Dungeon, x, y| d.tile_orientation(x, y);
+        assert_eq!(at(&d, 4, 1), Some(Orientation::Vertical));
+        assert_eq!(at(&d, 2, 4), Some(Orientation::Horizontal));
+        assert_eq!(at(&d, 6, 1), Some(Orientation::EastWest));
+        assert_eq!(at(&d, 8, 1), Some(Orientation::SouthWest));
+        assert_eq!(at(&d, 8, 2), Some(Orientation::NorthSouth));
+        assert_eq!(at(&d, 8, 3), Some(Orientation::Tee(Dir::South)));
+        assert_eq!(at(&d, 5, 3), Some(Orientation::SouthEast));
+        assert_eq!(at(&d, 10, 3), Some(Orientation::End(Dir::West)));
+        assert_eq!(at(&d, 2, 5), Some(Orientation::NorthEast));
+        assert_eq!(at(&d, 5, 5), Some(Orientation::Cross));
+        assert_eq!(at(&d, 5, 7), Some(Orientation::End(Dir::North)));
+        assert_eq!(at(&d, 2, 2), None);
+        assert_eq!(at(&d, 0, 0), None);
+        assert_eq!(at(&d, -1, 3), None);
+        assert_eq!(Orientation::from_u8(CORRIDOR | 1 << Dir::West.index()), Some(Orientation::End(Dir::West)));
+        assert_eq!(Orientation::from_u8(CORRIDOR), Some(Orientation::Isolated));
+        assert_eq!(Orientation::from_u8(3), None);
+        assert_eq!(Orientation::from_u8(32), None);
+
+        // written over and not synced, a tile has none rather than a wrong one, and edits
+        // write the layer again, undo too
+        d.set_tile(4, 1, Tile::Wall);
+        assert_eq!(at(&d, 4, 1), None);
+        d.set_tile(4, 1, Tile::ClosedDoor);
+        let mut session = d.edit();
+        session.set_tile((6, 2), Tile::Corridor);
+        assert!(fresh(session.dungeon()));
+        assert_eq!(session.dungeon().tile_orientation(6, 1), Some(Orientation::Tee(Dir::North)));
+        assert_eq!(session.dungeon().tile_orientation(6, 3), Some(Orientation::Tee(Dir::South)));
+        session.undo();
+        assert!(fresh(session.dungeon()));
+        session.commit();
+        assert_eq!(at(&d, 6, 1), Some(Orientation::EastWest));
+    }
+
+    #[test]
+    fn test_orientations_kept() {
+        for seed in 0..6 {
+            let mut d = Dungeon::new(70, 45);
+            d.generate_seeded(50, seed).unwrap();
+            assert!(fresh(&d), "seed {}", seed);
+
+            // doors agree with door_details
+            for door in d.door_details() {
+                let expected = if door.orientation == Dir::South { Orientation::Horizontal } else { Orientation::Vertical };
+                assert_eq!(d.tile_orientation(door.pos.x, door.pos.y), Some(expected));
+            }
+
+            // passes that dig or fill corridors write it again
+            d.cap_dead_ends_with_rooms(1.0, &mut DungenRng::new(seed));
+            assert!(fresh(&d), "seed {}", seed);
+
+            // and snapshots carry it
+            let snap = d.snapshot();
+            let layer = d.orientation_layer().to_vec();
+            let mut other = Dungeon::new(20, 20);
+            other.restore(&snap);
+            assert_eq!(other.orientation_layer(), &layer[..]);
+            let back = Dungeon::from_bytes(&d.to_bytes()).unwrap();
+            assert_eq!(back.orientation_layer(), &layer[..]);
+        }
+    }
+}
@@ -0,0 +1,461 @@
+/*
+Post-generation passes, composed. A Pass is one step run on a finished map, such as
+smoothing walls or hanging torches, and a Pipeline runs a list of them in order and collects
+what each did. The passes the crate has are all here as Pass types, each a thin wrapper over
+the Dungeon method of the same name, so a generator can be assembled as one list instead of
+a sequence of calls that has to be kept in the right order by hand.
+
+On an error the pipeline stops, unless told to carry on, in which case the failed pass is
+recorded and the rest still run. Debug builds check the map after every pass and treat a
+pass that leaves walkable tiles open to the void as failed.
+*/
+
+use config::Preset;
+use dungeon::{ Dungeon, GenerationReport };
+use error::{ DungeonError, PassError };
+use names::NameStyle;
+use rng::{ self, DungenRng };
+use rooms::{ Constraint, RoomPurpose };
+use stack::LevelAlgorithm;
+
+// what a pass changed: tiles, rooms or objects, whichever the pass deals in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct PassReport {
+    pub changed: usize,
+}
+
+impl PassReport {
+    pub fn changed(changed: usize) -> PassReport {
+        PassReport { changed }
+    }
+}
+
+pub trait Pass {
+    // for reports
+    fn name(&self) -> &str;
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OnError {
+    #[default]
+    Stop,
+    Continue,
+}
+
+// one pass's name and what came of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassOutcome {
+    pub name: String,
+    pub result: Result<PassReport, PassError>,
+}
+
+// an outcome for every pass that ran, in order. after a stop, the passes that didn't run
+// have none.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PipelineReport {
+    pub outcomes: Vec<PassOutcome>,
+}
+
+impl PipelineReport {
+    pub fn is_ok(&self) -> bool {
+        self.outcomes.iter().all(|o| o.result.is_ok())
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &PassOutcome> {
+        self.outcomes.iter().filter(|o| o.result.is_err())
+    }
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+    pub on_error: OnError,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    // adds a pass after the ones already added
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) -> &mut Pipeline {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> PipelineReport {
+        let mut report = PipelineReport::default();
+        for pass in &self.passes {
+            let result = pass.run(d, rng).and_then(|done| {
+                if cfg!(debug_assertions) {
+                    let open = d.validate_boundaries();
+                    if !open.is_empty() {
+                        return Err(PassError::Invariants(open))
+                    }
+                }
+                Ok(done)
+            });
+
+            let failed = result.is_err();
+            report.outcomes.push(PassOutcome { name: pass.name().to_string(), result });
+            if failed && self.on_error == OnError::Stop {
+                break;
+            }
+        }
+        report
+    }
+
+    // lays d out with algorithm, then runs the passes on the seed's decor stream, so how
+    // much randomness they take never changes the layout
+    pub fn generate(&self, d: &mut Dungeon, algorithm: LevelAlgorithm, maxfeatures: isize, seed: u64) -> Result<(GenerationReport, PipelineReport), DungeonError> {
+        let generated = algorithm.generate(d, maxfeatures, seed)?;
+        let passes = self.run(d, &mut rng::Streams::new(seed).decor);
+        Ok((generated, passes))
+    }
+
+    // generate with the preset's layout. d should have been made with the preset's config.
+    pub fn generate_preset(&self, d: &mut Dungeon, preset: Preset, maxfeatures: isize, seed: u64) -> Result<(GenerationReport, PipelineReport), DungeonError> {
+        self.generate(d, preset.algorithm(), maxfeatures, seed)
+    }
+}
+
+// Dungeon::smooth_walls
+pub struct SmoothWalls {
+    pub aggressive: bool,
+}
+
+impl Pass for SmoothWalls {
+    fn name(&self) -> &str {
+        "smooth walls"
+    }
+
+    fn run(&self, d: &mut Dungeon, _: &mut DungenRng) -> Result<PassReport, PassError> {
+        Ok(PassReport::changed(d.smooth_walls(self.aggressive)))
+    }
+}
+
+// Dungeon::decorate
+pub struct Torches {
+    pub chance: f32,
+}
+
+impl Pass for Torches {
+    fn name(&self) -> &str {
+        "torches"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        Ok(PassReport::changed(d.decorate(self.chance, rng).len()))
+    }
+}
+
+// Dungeon::add_windows
+pub struct Windows {
+    pub chance: f32,
+}
+
+impl Pass for Windows {
+    fn name(&self) -> &str {
+        "windows"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        Ok(PassReport::changed(d.add_windows(self.chance, rng).len()))
+    }
+}
+
+// Dungeon::cap_dead_ends_with_rooms
+pub struct DeadEndRooms {
+    pub chance: f32,
+}
+
+impl Pass for DeadEndRooms {
+    fn name(&self) -> &str {
+        "dead-end rooms"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        Ok(PassReport::changed(d.cap_dead_ends_with_rooms(self.chance, rng)))
+    }
+}
+
+// Dungeon::assign_courtyards, then grass_courtyards if grassed
+pub struct Courtyards {
+    pub fraction: f32,
+    pub grassed: bool,
+}
+
+impl Pass for Courtyards {
+    fn name(&self) -> &str {
+        "courtyards"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        let courtyards = d.assign_courtyards(self.fraction, rng);
+        if self.grassed {
+            d.grass_courtyards();
+        }
+        Ok(PassReport::changed(courtyards.len()))
+    }
+}
+
+// Dungeon::name_rooms
+pub struct NameRooms(pub NameStyle);
+
+impl Pass for NameRooms {
+    fn name(&self) -> &str {
+        "room names"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        d.name_rooms(self.0.clone(), rng);
+        Ok(PassReport::changed(d.room_ids().count()))
+    }
+}
+
+// Dungeon::place_chests
+pub struct Chests {
+    pub count: usize,
+    pub tiers: u8,
+}
+
+impl Pass for Chests {
+    fn name(&self) -> &str {
+        "chests"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        Ok(PassReport::changed(d.place_chests(self.count, self.tiers, rng).len()))
+    }
+}
+
+// Dungeon::designate_boss_room
+pub struct BossRoom {
+    pub min_area: isize,
+}
+
+impl Pass for BossRoom {
+    fn name(&self) -> &str {
+        "boss room"
+    }
+
+    fn run(&self, d: &mut Dungeon, _: &mut DungenRng) -> Result<PassReport, PassError> {
+        d.designate_boss_room(self.min_area).map_err(PassError::BossRoom)?;
+        Ok(PassReport::changed(1))
+    }
+}
+
+// Dungeon::place_safe_room
+pub struct SafeRoom;
+
+impl Pass for SafeRoom {
+    fn name(&self) -> &str {
+        "safe room"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        d.place_safe_room(rng).ok_or(PassError::NoSafeRoom)?;
+        Ok(PassReport::changed(1))
+    }
+}
+
+// Dungeon::add_secret_area
+pub struct Secret;
+
+impl Pass for Secret {
+    fn name(&self) -> &str {
+        "secret area"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        d.add_secret_area(rng).map_err(PassError::SecretArea)?;
+        Ok(PassReport::changed(1))
+    }
+}
+
+// Dungeon::assign_purposes
+pub struct Purposes(pub Vec<(RoomPurpose, Constraint)>);
+
+impl Pass for Purposes {
+    fn name(&self) -> &str {
+        "room purposes"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        d.assign_purposes(&self.0, rng);
+        Ok(PassReport::changed(d.purposes.values().filter(|&&p| p != RoomPurpose::Start).count()))
+    }
+}
+
+// Dungeon::auto_merge
+pub struct MergeRooms {
+    pub chance: f32,
+}
+
+impl Pass for MergeRooms {
+    fn name(&self) -> &str {
+        "room merging"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        let before = d.room_ids().count();
+        d.auto_merge(self.chance, rng);
+        Ok(PassReport::changed(before - d.room_ids().count()))
+    }
+}
+
+// doors knocked through to make loops, as DungeonConfig::loops does during generation
+pub struct Loops {
+    pub count: usize,
+}
+
+impl Pass for Loops {
+    fn name(&self) -> &str {
+        "loops"
+    }
+
+    fn run(&self, d: &mut Dungeon, rng: &mut DungenRng) -> Result<PassReport, PassError> {
+        let added = d.add_loops(self.count, rng);
+        d.sync_rooms();
+        Ok(PassReport::changed(added))
+    }
+}
+
+// Dungeon::seal
+pub struct Seal;
+
+impl Pass for Seal {
+    fn name(&self) -> &str {
+        "seal"
+    }
+
+    fn run(&self, d: &mut Dungeon, _: &mut DungenRng) -> Result<PassReport, PassError> {
+        d.seal();
+        Ok(PassReport::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use error::PassError;
+    use pipeline::*;
+    use rng::DungenRng;
+
+    // fails, or when told to, knocks the top-left wall out
+    struct Breaker {
+        fail: bool,
+    }
+
+    impl Pass for Breaker {
+        fn name(&self) -> &str {
+            "breaker"
+        }
+
+        fn run(&self, d: &mut Dungeon, _: &mut DungenRng) -> Result<PassReport, PassError> {
+            if self.fail {
+                return Err(PassError::Failed("broken".to_string()))
+            }
+            d.set_tile(0, 1, Tile::Floor);
+            Ok(PassReport::changed(1))
+        }
+    }
+
+    fn generated() -> Dungeon {
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(40, 3).unwrap();
+        d
+    }
+
+    #[test]
+    fn test_passes_run_in_order() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add(SmoothWalls { aggressive: false }).add(Torches { chance: 1.0 }).add(Seal);
+        assert_eq!(pipeline.len(), 3);
+
+        let mut d = generated();
+        let report = pipeline.run(&mut d, &mut DungenRng::new(1));
+        assert!(report.is_ok());
+        let names: Vec<&str> = report.outcomes.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, ["smooth walls", "torches", "seal"]);
+
+        // the same as calling the methods by hand
+        let mut by_hand = generated();
+        let mut rng = DungenRng::new(1);
+        let smoothed = by_hand.smooth_walls(false);
+        let torches = by_hand.decorate(1.0, &mut rng);
+        by_hand.seal();
+        assert_eq!(d.render(), by_hand.render());
+        assert_eq!(report.outcomes[0].result, Ok(PassReport::changed(smoothed)));
+        assert_eq!(report.outcomes[1].result, Ok(PassReport::changed(torches.len())));
+    }
+
+    #[test]
+    fn test_stop_or_continue_on_error() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add(Breaker { fail: true }).add(Seal);
+
+        let report = pipeline.run(&mut generated(), &mut DungenRng::new(1));
+        assert!(!report.is_ok());
+        assert_eq!(report.outcomes.len(), 1);
+        assert_eq!(report.outcomes[0].result, Err(PassError::Failed("broken".to_string())));
+
+        pipeline.on_error = OnError::Continue;
+        let report = pipeline.run(&mut generated(), &mut DungenRng::new(1));
+        assert_eq!(report.outcomes.len(), 2);
+        assert_eq!(report.errors().count(), 1);
+        assert!(report.outcomes[1].result.is_ok());
+    }
+
+    #[test]
+    fn test_failed_pass_is_reported() {
+        let mut d = generated();
+        let mut pipeline = Pipeline::new();
+        pipeline.add(BossRoom { min_area: 100_000 }).add(SafeRoom);
+        pipeline.on_error = OnError::Continue;
+
+        let report = pipeline.run(&mut d, &mut DungenRng::new(1));
+        match report.outcomes[0].result {
+            Err(PassError::BossRoom(_)) => {}
+            ref other => panic!("{:?}", other),
+        }
+        assert_eq!(report.outcomes[1].result, Ok(PassReport::changed(1)));
+        assert!(d.safe_room().is_some());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_open_map_fails_in_debug() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add(Breaker { fail: false });
+
+        let report = pipeline.run(&mut generated(), &mut DungenRng::new(1));
+        match report.outcomes[0].result {
+            Err(PassError::Invariants(ref open)) => assert!(!open.is_empty()),
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add(Courtyards { fraction: 0.5, grassed: true }).add(Chests { count: 3, tiers: 2 });
+
+        let build = || {
+            let mut d = Dungeon::new(60, 40);
+            let (_, report) = pipeline.generate(&mut d, LevelAlgorithm::Rooms, 40, 9).unwrap();
+            assert!(report.is_ok());
+            d.render()
+        };
+        assert_eq!(build(), build());
+    }
+}
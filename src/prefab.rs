@@ -0,0 +1,223 @@
+/*
+Small fixed patterns stamped into rooms once a map is generated. A prefab is a block of
+tiles drawn as glyph rows, with spaces for tiles it leaves as they are. It goes where the
+whole block and a ring of tiles around it lie on the room's plain floor, as near the
+room's middle as that allows. The ring stays floor, so any way across the room that the
+block cuts can go round it instead, and the room can still be walked through.
+
+Shrines are the first prefab: an altar, sometimes with a pillar at each corner, in a
+dead-end room off the main path.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+use geom::{ Point, Rect, Size };
+use graph::RoomId;
+use rng::DungenRng;
+use rooms::RoomPurpose;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefab {
+    size: Size,
+    // row by row. none leaves the tile as it is.
+    tiles: Vec<Option<Tile>>,
+}
+
+impl Prefab {
+    // rows of glyphs as Dungeon::render draws them. short rows are padded with spaces.
+    pub fn from_rows(rows: &[&str]) -> Prefab {
+        let height = rows.len() as isize;
+        let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0) as isize;
+        let mut tiles = vec![None; (width * height) as usize];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c != ' ' {
+                    tiles[x + y * width as usize] = Some(Tile::from_glyph(c).unwrap_or(Tile::Unused));
+                }
+            }
+        }
+
+        Prefab { size: Size::new(width, height), tiles }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    // none for a tile the prefab leaves alone, or one outside it
+    pub fn get(&self, x: isize, y: isize) -> Option<Tile> {
+        if x < 0 || y < 0 || x >= self.size.width || y >= self.size.height {
+            return None
+        }
+
+        self.tiles[(x + y * self.size.width) as usize]
+    }
+}
+
+// where place_shrine put the altar, and in which room
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ShrinePlacement {
+    pub room: RoomId,
+    pub altar: Point,
+}
+
+const SHRINE: [&str; 3] = [" . ", "._.", " . "];
+const PILLARED_SHRINE: [&str; 3] = ["O.O", "._.", "O.O"];
+// a shrine carved past a dead end is just big enough for the pattern and its ring
+const SHRINE_ROOM: (isize, isize) = (5, 5);
+
+impl Dungeon {
+    // stamps prefab into room at the spot nearest its middle where the prefab and a ring of
+    // one tile around it all lie on the room's floor or grass. returns the rect it took up.
+    // none if the room has no such spot.
+    pub fn stamp_prefab(&mut self, room: RoomId, prefab: &Prefab) -> Option<Rect> {
+        let graph = self.graph();
+        let tiles = graph.room_tiles(room);
+        if tiles.is_empty() {
+            return None
+        }
+
+        let size = prefab.size();
+        let plain = |p: Point| matches!(self.get(p), Tile::Floor | Tile::Grass) && graph.room_at(p) == Some(room);
+        let fits = |at: Point| (-1..=size.height).all(|dy| (-1..=size.width).all(|dx| plain(at.offset(dx, dy))));
+
+        // in doubled coordinates, so a block's middle needn't be a whole tile
+        let n = tiles.len() as isize;
+        let mid = Point::new(tiles.iter().map(|p| p.x).sum::<isize>() * 2 / n, tiles.iter().map(|p| p.y).sum::<isize>() * 2 / n);
+        let at = tiles.iter()
+            .cloned()
+            .filter(|&p| fits(p))
+            .min_by_key(|p| ((p.x * 2 + size.width - 1 - mid.x).abs() + (p.y * 2 + size.height - 1 - mid.y).abs(), p.y, p.x))?;
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                if let Some(tile) = prefab.get(x, y) {
+                    let p = self.wrap_point(at.offset(x, y));
+                    self.set_tile(p.x, p.y, tile);
+                }
+            }
+        }
+
+        Some(Rect::from_parts(at, size))
+    }
+
+    // puts a shrine in a dead-end room off the main path that has no purpose yet. failing
+    // that it carves a room for one past a dead-end corridor, and failing that it takes any
+    // other such room off the main path. the room is tagged a shrine. the entrance, exit,
+    // boss and safe rooms are never chosen. none if no room will take it.
+    pub fn place_shrine(&mut self, rng: &mut DungenRng) -> Option<ShrinePlacement> {
+        let prefab = Prefab::from_rows(if rng.coin() { &PILLARED_SHRINE } else { &SHRINE });
+        let graph = self.graph();
+        let taken: Vec<RoomId> = self.entrance().into_iter().chain(self.exit())
+            .filter_map(|p| graph.room_at(p))
+            .chain(self.boss_room)
+            .chain(self.safe_room)
+            .collect();
+        let (mut dead_ends, mut others): (Vec<RoomId>, Vec<RoomId>) = self.off_path_rooms().into_iter()
+            .filter(|id| !taken.contains(id) && !self.purposes.contains_key(id))
+            .partition(|&id| graph.room_degree(id) == 1);
+        shuffle(&mut dead_ends, rng);
+        shuffle(&mut others, rng);
+
+        let mut stamped = dead_ends.into_iter().find_map(|id| self.stamp_prefab(id, &prefab).map(|rect| (id, rect)));
+        if stamped.is_none() {
+            let mut ends = self.dead_ends();
+            shuffle(&mut ends, rng);
+            for (end, dir) in ends {
+                if let Some(id) = self.cap_dead_end(end, dir, &[SHRINE_ROOM], rng) {
+                    self.sync_rooms();
+                    stamped = self.stamp_prefab(id, &prefab).map(|rect| (id, rect));
+                    break;
+                }
+            }
+        }
+        if stamped.is_none() {
+            stamped = others.into_iter().find_map(|id| self.stamp_prefab(id, &prefab).map(|rect| (id, rect)));
+        }
+
+        let (room, rect) = stamped?;
+        self.purposes.insert(room, RoomPurpose::Shrine);
+        self.sync_rooms();
+        Some(ShrinePlacement { room, altar: rect.center() })
+    }
+}
+
+pub(crate) fn shuffle<T>(items: &mut [T], rng: &mut DungenRng) {
+    for i in (1..items.len()).rev() {
+        let j = rng.range_inclusive(0, i as isize) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::{ Point, Rect };
+    use nav::Connectivity;
+    use prefab::*;
+    use rng::DungenRng;
+    use rooms::RoomPurpose;
+
+    #[test]
+    fn test_stamp_recentres_in_small_rooms() {
+        let mut d = Dungeon::parse(&[
+            "###########",
+            "#.........#",
+            "#.....#####",
+            "#.....#    ",
+            "#.....#    ",
+            "#.....#    ",
+            "#######    ",
+        ]);
+        let room = d.graph().room_at(Point::new(1, 1)).unwrap();
+        let prefab = Prefab::from_rows(&["O.O", "._.", "O.O"]);
+
+        // the middle is off to the right, but only the left part has room for the block
+        // and its ring
+        assert_eq!(d.stamp_prefab(room, &prefab), Some(Rect::new(2, 2, 3, 3)));
+        assert_eq!(d.get((3, 3)), Tile::Altar);
+        assert_eq!(d.get((2, 2)), Tile::Pillar);
+        assert_eq!(d.stamp_prefab(room, &prefab), None);
+
+        let mut small = Dungeon::parse(&["######", "#....#", "#....#", "#....#", "#....#", "######"]);
+        let room = small.graph().room_at(Point::new(1, 1)).unwrap();
+        assert_eq!(small.stamp_prefab(room, &prefab), None);
+    }
+
+    #[test]
+    fn test_place_shrine() {
+        let mut placed = 0;
+        for seed in 0..10 {
+            let mut d = Dungeon::new(70, 45);
+            if d.generate_seeded(40, seed).is_err() {
+                continue;
+            }
+            let before: usize = d.distance_map(d.entrance().unwrap(), Connectivity::Four).iter().filter(|t| t.is_some()).count();
+
+            let shrine = match d.place_shrine(&mut DungenRng::new(seed)) {
+                Some(shrine) => shrine,
+                None => continue,
+            };
+            placed += 1;
+
+            assert_eq!(d.get(shrine.altar), Tile::Altar);
+            assert_eq!(d.graph().room_at(shrine.altar), Some(shrine.room));
+            assert_eq!(d.room(shrine.room).unwrap().purpose, Some(RoomPurpose::Shrine));
+            assert!(!d.main_path().unwrap().rooms.contains(&shrine.room));
+
+            // everything still reachable, bar the tiles the shrine now stands on
+            let after = d.distance_map(d.entrance().unwrap(), Connectivity::Four).iter().filter(|t| t.is_some()).count();
+            let blocked = d.render().matches(['_', 'O']).count();
+            assert!(after + blocked >= before, "seed {}", seed);
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let p = shrine.altar.offset(dx * 2, dy * 2);
+                    assert!(p == shrine.altar || d.get(p).is_walkable(), "seed {}: ring broken at {:?}", seed, p);
+                }
+            }
+        }
+        assert!(placed >= 9, "only {} shrines", placed);
+    }
+}
@@ -0,0 +1,239 @@
+/*
+How far into the dungeon each part of the map lies, measured as walking distance in tiles
+from the entrance. Games use this to scale encounters, so everything here is normalized to
+0.0 at the entrance and 1.0 at the deepest point. The main path is the shortest walk from
+the entrance to the exit, where the key encounters belong; rooms off it are optional.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, Tile };
+use geom::Point;
+use graph::RoomId;
+use nav::Connectivity;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MainPath {
+    // every tile from the entrance to the exit, both included
+    pub tiles: Vec<Point>,
+    // rooms in the order the path enters them
+    pub rooms: Vec<RoomId>,
+    // door tiles the path walks through, in order
+    pub doors: Vec<Point>,
+}
+
+impl Dungeon {
+    // difficulty of every room reachable from the entrance, from the tile distance to its
+    // nearest floor tile. the exit and boss rooms are always 1.0. unreachable rooms are omitted, and
+    // without an entrance the list is empty.
+    pub fn difficulty_map(&self) -> Vec<(RoomId, f32)> {
+        let entrance = match self.entrance() {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+
+        let size = self.size();
+        let dist = self.distance_map(entrance, Connectivity::Four);
+        let graph = self.graph();
+
+        let mut rooms = Vec::new();
+        for id in graph.room_ids() {
+            let nearest = graph.room_tiles(id).iter()
+                .filter_map(|p| dist[(p.x + p.y * size.width) as usize])
+                .min();
+            if let Some(d) = nearest {
+                rooms.push((id, d));
+            }
+        }
+
+        let deepest = rooms.iter().map(|&(_, d)| d).max().unwrap_or(0);
+        let exit_room = self.exit().and_then(|p| graph.room_at(p));
+
+        rooms.into_iter()
+            .map(|(id, d)| {
+                if Some(id) == exit_room || Some(id) == self.boss_room {
+                    (id, 1.0)
+                }
+
+                else {
+                    (id, d as f32 / deepest.max(1) as f32)
+                }
+            })
+            .collect()
+    }
+
+    // per-tile difficulty indexed x + y * width. tiles that can't be reached from the
+    // entrance, walls included, are NAN, as is everything when there is no entrance.
+    pub fn tile_difficulty(&self) -> Vec<f32> {
+        let size = self.size();
+        let dist = match self.entrance() {
+            Some(p) => self.distance_map(p, Connectivity::Four),
+            None => vec![None; (size.width * size.height) as usize],
+        };
+
+        let deepest = dist.iter().filter_map(|&d| d).max().unwrap_or(0).max(1);
+        dist.into_iter()
+            .map(|d| match d {
+                Some(d) => d as f32 / deepest as f32,
+                None => f32::NAN,
+            })
+            .collect()
+    }
+
+    // none without both an entrance and an exit, or if the exit can't be reached
+    pub fn main_path(&self) -> Option<MainPath> {
+        let tiles = self.find_path(self.entrance()?, self.exit()?, Connectivity::Four)?;
+        let graph = self.graph();
+
+        let mut rooms: Vec<RoomId> = Vec::new();
+        let mut doors = Vec::new();
+        for &p in &tiles {
+            if let Some(id) = graph.room_at(p) {
+                if rooms.last() != Some(&id) {
+                    rooms.push(id);
+                }
+            }
+
+            match self.get(p) {
+                Tile::ClosedDoor | Tile::OpenDoor => doors.push(p),
+                _ => {}
+            }
+        }
+
+        Some(MainPath { tiles, rooms, doors })
+    }
+
+    // rooms the main path never enters. every room when there is no main path.
+    pub fn off_path_rooms(&self) -> Vec<RoomId> {
+        let on_path = self.main_path().map(|path| path.rooms).unwrap_or_default();
+        self.graph().room_ids().into_iter().filter(|id| !on_path.contains(id)).collect()
+    }
+
+    // the map as glyph rows with the main path drawn over it in '*'. the entrance and exit
+    // keep their own glyphs.
+    pub fn render_with_path(&self) -> String {
+        let size = self.size();
+        let path = self.main_path().map(|path| path.tiles).unwrap_or_default();
+        let mut out = String::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let tile = self.get((x, y));
+                let on_path = path.contains(&Point::new(x, y)) && tile != Tile::Entrance && tile != Tile::Exit;
+                out.push(if on_path { '*' } else { tile.glyph() });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use geom::Point;
+
+    #[test]
+    fn test_room_difficulty_grows_with_distance() {
+        let d = Dungeon::parse(&[
+            "####################",
+            "#<.+,,+..+,,,,+..#.#",
+            "####################",
+        ]);
+        let graph = d.graph();
+        let map = d.difficulty_map();
+        let of = |x| map.iter().find(|&&(id, _)| Some(id) == graph.room_at(Point::new(x, 1))).map(|&(_, v)| v);
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(of(1), Some(0.0));
+        assert!((of(7).unwrap() - 6.0 / 14.0).abs() < 1e-6);
+        assert_eq!(of(15), Some(1.0));
+        // walled off from the entrance
+        assert_eq!(of(18), None);
+    }
+
+    #[test]
+    fn test_exit_room_is_always_hardest() {
+        let d = Dungeon::parse(&[
+            "##############",
+            "#<.+,,+>.+,,.#",
+            "##############",
+        ]);
+        let graph = d.graph();
+        let map = d.difficulty_map();
+        let exit_room = graph.room_at(Point::new(7, 1)).unwrap();
+
+        assert!(map.contains(&(exit_room, 1.0)));
+        assert!(map.iter().all(|&(_, v)| v <= 1.0));
+    }
+
+    #[test]
+    fn test_tile_difficulty() {
+        let d = Dungeon::parse(&[
+            "######",
+            "#<.,.#",
+            "######",
+        ]);
+        let tiles = d.tile_difficulty();
+
+        assert_eq!(tiles[1 + 6], 0.0);
+        assert_eq!(tiles[2 + 6], 1.0 / 3.0);
+        assert_eq!(tiles[4 + 6], 1.0);
+        assert!(tiles[0].is_nan());
+
+        assert!(Dungeon::parse(&["..."]).difficulty_map().is_empty());
+        assert!(Dungeon::parse(&["..."]).tile_difficulty().iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn test_main_path_through_rooms_and_doors() {
+        let d = Dungeon::parse(&[
+            "###############",
+            "#<.+,,+..+,,+>#",
+            "#..####..######",
+            "#####,+..#     ",
+            "    #,#..#     ",
+            "    #,####     ",
+            "    ###        ",
+        ]);
+        let graph = d.graph();
+        let path = d.main_path().unwrap();
+
+        assert_eq!(path.tiles.first(), Some(&Point::new(1, 1)));
+        assert_eq!(path.tiles.last(), Some(&Point::new(13, 1)));
+        assert_eq!(path.tiles.len(), 13);
+        assert_eq!(path.rooms, vec![
+            graph.room_at(Point::new(1, 1)).unwrap(),
+            graph.room_at(Point::new(7, 1)).unwrap(),
+            graph.room_at(Point::new(13, 1)).unwrap(),
+        ]);
+        assert_eq!(path.doors, vec![Point::new(3, 1), Point::new(6, 1), Point::new(9, 1), Point::new(12, 1)]);
+        assert!(d.off_path_rooms().is_empty());
+
+        assert_eq!(d.render_with_path().lines().nth(1), Some("#<***********>#"));
+    }
+
+    #[test]
+    fn test_off_path_rooms() {
+        let d = Dungeon::parse(&[
+            "###########",
+            "#<.+,,+..>#",
+            "###,#######",
+            "  #,#      ",
+            "###+###    ",
+            "#.....#    ",
+            "#######    ",
+        ]);
+        let graph = d.graph();
+
+        assert_eq!(d.off_path_rooms(), vec![graph.room_at(Point::new(1, 5)).unwrap()]);
+        assert_eq!(d.render_with_path().lines().nth(1), Some("#<*******>#"));
+
+        let unreachable = Dungeon::parse(&["#<.#.>#"]);
+        assert!(unreachable.main_path().is_none());
+        assert_eq!(unreachable.off_path_rooms().len(), 2);
+        assert_eq!(unreachable.render_with_path(), "#<.#.>#\n");
+    }
+}
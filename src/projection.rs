@@ -0,0 +1,112 @@
+/*
+Isometric projection of the tile grid, for renderers that draw each tile as a diamond
+tile_w pixels across and tile_h pixels down. The grid keeps its own conventions: x grows
+east and y grows south from the top-left tile (0, 0). On screen, east runs down and to the
+right and south runs down and to the left, so tile (0, 0) is the diamond at the top and
+its top corner is the screen origin.
+
+to_iso gives the screen position of a tile's top corner, and from_iso undoes it for any
+screen position, giving fractional tile coordinates. The diamond of tile (x, y) holds the
+screen positions whose coordinates come back between x and x + 1 and between y and y + 1,
+so iso_tile, which rounds them down, is the tile under a screen position.
+
+Tiles further back are drawn first. A tile can only be covered by tiles with a larger
+x + y, so painting in order of x + y works, and tiles on the same diagonal never overlap.
+depth_key adds x as a tie-break so the order is fixed, and a layer so things standing on a
+tile sort after it and before anything in front of it.
+*/
+
+use dungeon::Dungeon;
+use geom::Point;
+
+// the screen position of p's top corner
+pub fn to_iso(p: Point, tile_w: f32, tile_h: f32) -> (f32, f32) {
+    let (x, y) = (p.x as f32, p.y as f32);
+    ((x - y) * tile_w / 2.0, (x + y) * tile_h / 2.0)
+}
+
+// the tile coordinates, fractional, of a screen position. the inverse of to_iso.
+pub fn from_iso(sx: f32, sy: f32, tile_w: f32, tile_h: f32) -> (f32, f32) {
+    let (across, down) = (sx / (tile_w / 2.0), sy / (tile_h / 2.0));
+    ((down + across) / 2.0, (down - across) / 2.0)
+}
+
+// the tile whose diamond holds a screen position. it may be off the map.
+pub fn iso_tile(sx: f32, sy: f32, tile_w: f32, tile_h: f32) -> Point {
+    let (x, y) = from_iso(sx, sy, tile_w, tile_h);
+    Point::new(x.floor() as isize, y.floor() as isize)
+}
+
+// sorts back to front. tiles are layer 0; something standing on a tile takes the tile's
+// position and a higher layer.
+pub fn depth_key(p: Point, layer: u8) -> (isize, isize, u8) {
+    (p.x + p.y, p.x, layer)
+}
+
+impl Dungeon {
+    // every tile on the map in painter's order, back to front: by depth_key, one diagonal
+    // of x + y at a time, west to east along it
+    pub fn render_order(&self) -> impl Iterator<Item = Point> + '_ {
+        let size = self.size();
+        (0..size.width + size.height - 1).flat_map(move |sum| {
+            let first = (sum - size.height + 1).max(0);
+            let last = sum.min(size.width - 1);
+            (first..=last).map(move |x| Point::new(x, sum - x))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use geom::Point;
+    use projection::*;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(to_iso(Point::new(0, 0), 64.0, 32.0), (0.0, 0.0));
+        assert_eq!(to_iso(Point::new(1, 0), 64.0, 32.0), (32.0, 16.0));
+        assert_eq!(to_iso(Point::new(0, 1), 64.0, 32.0), (-32.0, 16.0));
+        assert_eq!(to_iso(Point::new(3, 2), 64.0, 32.0), (32.0, 80.0));
+
+        for &(w, h) in &[(64.0, 32.0), (32.0, 16.0), (40.0, 30.0)] {
+            for y in -3..12 {
+                for x in -3..12 {
+                    let p = Point::new(x, y);
+                    let (sx, sy) = to_iso(p, w, h);
+                    let (fx, fy) = from_iso(sx, sy, w, h);
+                    assert!((fx - x as f32).abs() < 1e-4 && (fy - y as f32).abs() < 1e-4, "{:?} at {}x{}", p, w, h);
+
+                    // the middle of the diamond, and just inside each of its corners
+                    assert_eq!(iso_tile(sx, sy + h / 2.0, w, h), p);
+                    assert_eq!(iso_tile(sx, sy + 0.5, w, h), p);
+                    assert_eq!(iso_tile(sx, sy + h - 0.5, w, h), p);
+                    assert_eq!(iso_tile(sx - w / 2.0 + 1.0, sy + h / 2.0, w, h), p);
+                    assert_eq!(iso_tile(sx + w / 2.0 - 1.0, sy + h / 2.0, w, h), p);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_order() {
+        let d = Dungeon::parse(&["   "; 2]);
+        let order: Vec<(isize, isize)> = d.render_order().map(|p| (p.x, p.y)).collect();
+        assert_eq!(order, vec![(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1)]);
+
+        let d = Dungeon::new(13, 7);
+        let order: Vec<Point> = d.render_order().collect();
+        assert_eq!(order.len(), 13 * 7);
+        let mut sorted = order.clone();
+        sorted.sort_by_key(|&p| depth_key(p, 0));
+        assert_eq!(order, sorted);
+        sorted.dedup();
+        assert_eq!(sorted.len(), order.len());
+
+        // whatever stands on a tile comes after it and before the tiles in front
+        let p = Point::new(4, 2);
+        assert!(depth_key(p, 0) < depth_key(p, 1));
+        assert!(depth_key(p, 1) < depth_key(Point::new(4, 3), 0));
+        assert!(depth_key(p, 1) < depth_key(Point::new(5, 2), 0));
+    }
+}
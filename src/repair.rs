@@ -0,0 +1,274 @@
+/*
+Validation levels, set by DungeonConfig::validation, and the repairs that go with them.
+Every generator runs the level on what it made before handing it back:
+
+  None    nothing is checked, and nothing costs anything
+  Basic   the entrances and exits the config asks for are on the map, and no walkable
+          tile is open to the void or to the edge of the map
+  Strict  Basic, and every walkable tile can be walked to from every other, every door
+          passes validate_doors, and no room is sealed off from the rest
+
+A level that fails is repaired as far as it can be, in this order, and checked again:
+
+  doors     a door that doesn't fit its wall, or touches another, gives way to the floor
+            or corridor it opens onto, or to wall if it opens onto less than two sides.
+            doors are visited in row-major order, so of two touching doors the first goes.
+  edges     walkable tiles on the edge of the map become wall, and tiles open to the void
+            are walled in.
+  objects   entrances and exits on record whose tiles were lost are forgotten, and as many
+            are placed again as the config asks for, as generation places them.
+  regions   parts of the map cut off from the entrance are tunnelled on to the nearest
+            walkable ground, as reshape_region does, and walled in. a sealed room is cut
+            off, so it's opened the same way.
+
+A repair can break what an earlier one fixed, a tunnel dug beside a door say, so the round
+is run again while it still finds work, a few times at most. What is still wrong after that
+comes back as DungeonError::Validation, with a count of each kind of failure. Placing
+objects draws from a stream of its own, so a level that needs no repairs is the level the
+seed always gave.
+*/
+
+use config::Validation;
+use dungeon::{ Dir, Dungeon, Tile };
+use error::{ DungeonError, ValidationFailures };
+use geom::Point;
+use nav::Connectivity;
+use rng::{ self, DungenRng };
+
+// rounds of repairs before giving up on a level
+const REPAIR_ROUNDS: usize = 3;
+
+impl Dungeon {
+    // what the map breaks of level's rules, never changing it. empty for Validation::None.
+    pub fn validate(&self, level: Validation) -> ValidationFailures {
+        let mut out = ValidationFailures::default();
+        if level == Validation::None {
+            return out
+        }
+
+        let placed = |list: &[Point], tile: Tile| list.iter().filter(|&&p| self.get(p) == tile).count();
+        out.missing_entrances = self.config().entrances.saturating_sub(placed(&self.entrances, Tile::Entrance));
+        out.missing_exits = self.config().exits.saturating_sub(placed(&self.exits, Tile::Exit));
+        out.open_boundaries = self.validate_boundaries().len();
+        if level == Validation::Basic {
+            return out
+        }
+
+        out.bad_doors = self.validate_doors().len();
+        out.cut_off_regions = self.regions(Connectivity::Four).len().saturating_sub(1);
+        let graph = self.graph();
+        if graph.adjacency().len() > 1 {
+            out.sealed_rooms = graph.room_ids().into_iter().filter(|&id| graph.room_degree(id) == 0).count();
+        }
+        out
+    }
+
+    // repairs what it can of the map's failures under level, see the module comment, and
+    // returns how many repairs it made, or what it couldn't put right.
+    pub fn repair(&mut self, level: Validation, rng: &mut DungenRng) -> Result<usize, ValidationFailures> {
+        let mut repairs = 0;
+        for _ in 0..REPAIR_ROUNDS {
+            if self.validate(level).is_empty() {
+                break;
+            }
+
+            let mut round = 0;
+            if level == Validation::Strict {
+                round += self.repair_doors();
+            }
+            round += self.repair_edges();
+            round += self.repair_objects(rng);
+            if level == Validation::Strict {
+                round += self.join_regions();
+            }
+
+            if round == 0 {
+                break;
+            }
+            self.sync_rooms();
+            repairs += round;
+        }
+
+        let left = self.validate(level);
+        if left.is_empty() { Ok(repairs) } else { Err(left) }
+    }
+
+    // the config's validation level, run on a level just generated from seed
+    pub(crate) fn enforce_validation(&mut self, seed: u64) -> Result<(), DungeonError> {
+        let level = self.config().validation;
+        if level == Validation::None {
+            return Ok(())
+        }
+
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "repair"));
+        self.repair(level, &mut rng).map(|_| ()).map_err(DungeonError::Validation)
+    }
+
+    // every door breaking a rule gives way to an opening or a wall. returns how many did.
+    fn repair_doors(&mut self) -> usize {
+        let mut doors: Vec<Point> = self.validate_doors().into_iter().map(|v| v.pos).collect();
+        doors.dedup();
+
+        let mut fixed = 0;
+        for p in doors {
+            // an earlier fix may have put this one right, or made it worse
+            if !self.get(p).is_door() || (self.door_fits(p) && !self.touches_door(p)) {
+                continue;
+            }
+
+            let sides: Vec<Tile> = Dir::iterator().map(|dir| {
+                let (dx, dy) = dir.offset();
+                self.get(p.offset(dx, dy))
+            }).collect();
+            let tile = if sides.iter().filter(|t| t.is_walkable() && !t.is_door()).count() < 2 {
+                Tile::Wall
+            }
+
+            else if sides.iter().any(|&t| matches!(t, Tile::Floor | Tile::Grass | Tile::Entrance | Tile::Exit)) {
+                Tile::Floor
+            }
+
+            else {
+                Tile::Corridor
+            };
+            self.set_tile(p.x, p.y, tile);
+            fixed += 1;
+        }
+        fixed
+    }
+
+    // walls over walkable tiles on the map's edge and around tiles open to the void.
+    // returns how many open sides there were.
+    fn repair_edges(&mut self) -> usize {
+        let open = self.validate_boundaries();
+        for v in &open {
+            if v.neighbor.is_none() && self.get(v.pos).is_walkable() {
+                self.set_object(v.pos, Tile::Wall);
+            }
+        }
+        if !open.is_empty() {
+            self.wall_in();
+        }
+        open.len()
+    }
+
+    // forgets entrances and exits that aren't on the map any more and places the missing
+    // ones. returns how many were placed.
+    fn repair_objects(&mut self, rng: &mut DungenRng) -> usize {
+        let entrances: Vec<Point> = self.entrances.iter().cloned().filter(|&p| self.get(p) == Tile::Entrance).collect();
+        let exits: Vec<Point> = self.exits.iter().cloned().filter(|&p| self.get(p) == Tile::Exit).collect();
+        self.entrances = entrances;
+        self.exits = exits;
+
+        let mut placed = 0;
+        while self.entrances.len() < self.config().entrances {
+            if self.place_object(Tile::Entrance, rng).is_err() {
+                break;
+            }
+            placed += 1;
+        }
+        while self.exits.len() < self.config().exits {
+            let others = self.entrances.clone();
+            if self.place_object_apart(Tile::Exit, &others, rng).is_err() {
+                break;
+            }
+            placed += 1;
+        }
+        placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, Validation };
+    use dungeon::{ Dungeon, Tile };
+    use error::{ DungeonError, ValidationFailures };
+    use geom::Point;
+    use nav::Connectivity;
+    use rng::DungenRng;
+
+    fn strict() -> DungeonConfig {
+        DungeonConfig { validation: Validation::Strict, ..DungeonConfig::default() }
+    }
+
+    #[test]
+    fn test_generated_levels_pass() {
+        for seed in 0..8 {
+            let mut plain = Dungeon::new(70, 45);
+            let mut checked = Dungeon::with_config(70, 45, strict());
+            plain.generate_seeded(50, seed).unwrap();
+            checked.generate_seeded(50, seed).unwrap();
+
+            // generation already holds to Strict, so checking changes nothing
+            assert!(plain.validate(Validation::Strict).is_empty(), "seed {}", seed);
+            assert_eq!(checked.render(), plain.render(), "seed {}", seed);
+        }
+
+        let mut cave = Dungeon::with_config(60, 40, strict());
+        cave.generate_caves_seeded(2).unwrap();
+        assert!(cave.validate(Validation::Strict).is_empty());
+    }
+
+    #[test]
+    fn test_repair_restores_strict_invariants() {
+        for seed in 0..6 {
+            let mut d = Dungeon::new(70, 45);
+            d.generate_seeded(50, seed).unwrap();
+            assert!(d.validate(Validation::None).is_empty());
+            let size = d.size();
+
+            // wreck it: the entrance and exit gone, a room walled off, a door left hanging
+            // in the open, and floor carved out to the edge of the map
+            let (entrance, exit) = (d.entrance().unwrap(), d.exit().unwrap());
+            d.set_tile(entrance.x, entrance.y, Tile::Floor);
+            d.set_tile(exit.x, exit.y, Tile::Wall);
+
+            let graph = d.graph();
+            let room = graph.room_ids().into_iter()
+                .find(|&id| !graph.room_tiles(id).contains(&entrance) && !graph.room_tiles(id).contains(&exit))
+                .unwrap();
+            let ring: Vec<Point> = d.room_perimeter(room).collect();
+            for &p in &ring {
+                d.set_tile(p.x, p.y, Tile::Wall);
+            }
+
+            let corner = graph.room_tiles(room)[0];
+            d.set_tile(corner.x, corner.y, Tile::ClosedDoor);
+            for x in 0..3 {
+                d.set_tile(x, size.height / 2, Tile::Floor);
+            }
+            d.sync_rooms();
+
+            let broken = d.validate(Validation::Strict);
+            assert_eq!((broken.missing_entrances, broken.missing_exits), (1, 1), "seed {}", seed);
+            assert!(broken.open_boundaries > 0 && broken.bad_doors > 0 && broken.cut_off_regions > 0, "seed {}: {:?}", seed, broken);
+            assert_eq!(d.validate(Validation::Basic).bad_doors, 0);
+
+            let repairs = d.repair(Validation::Strict, &mut DungenRng::new(seed)).unwrap();
+            assert!(repairs >= 4, "seed {}: {}", seed, repairs);
+            assert!(d.validate(Validation::Strict).is_empty(), "seed {}\n{}", seed, d.render());
+            assert_eq!(d.regions(Connectivity::Four).len(), 1);
+            assert_eq!(d.get(d.entrance().unwrap()), Tile::Entrance);
+            assert_eq!(d.get(d.exit().unwrap()), Tile::Exit);
+            assert!(d.find_path(d.entrance().unwrap(), d.exit().unwrap(), Connectivity::Four).is_some());
+            assert!(d.validate_doors().is_empty() && d.validate_boundaries().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_what_cant_be_repaired() {
+        // a maze has no rooms to put an entrance in once it's lost
+        let config = DungeonConfig { validation: Validation::Basic, ..DungeonConfig::default() };
+        let mut d = Dungeon::with_config(41, 31, config);
+        d.generate_maze_seeded(4).unwrap();
+        let entrance = d.entrance().unwrap();
+        d.set_tile(entrance.x, entrance.y, Tile::Corridor);
+
+        let failures = d.repair(Validation::Basic, &mut DungenRng::new(1)).unwrap_err();
+        assert_eq!(failures, ValidationFailures { missing_entrances: 1, ..ValidationFailures::default() });
+        assert_eq!(DungeonError::Validation(failures).to_string(), "level failed validation: 1 missing entrances");
+
+        // levels asking for no validation are never looked at
+        assert_eq!(d.repair(Validation::None, &mut DungenRng::new(1)), Ok(0));
+    }
+}
@@ -0,0 +1,318 @@
+/*
+Reshaping part of a level after it was generated, for events like an earthquake that bring
+down a stretch of the map mid-run. The region is cleared back to rock and dug out afresh
+by the same feature growth that generation uses, started from the openings the rest of the
+map had into the region, so the new layout joins onto the old one where the old one led in.
+
+Clearing takes the region out of the records too. Rooms that lose tiles are brought up to
+date by sync_rooms: a room wholly inside is retired with its tags, and what is left of a
+room cut in two keeps its id on the side holding its anchor. Chests inside are dropped.
+Corridor records keep their ids, and one whose tiles are all gone just covers nothing. An
+entrance or exit inside the region is moved onto the nearest floor outside it first.
+
+Doors and archways facing into the region are left with nothing behind them, so they're
+turned into plain openings of the tile on their other side, and the new feature puts its
+door one tile further in. Once the region is dug, any part of the map that can't be walked
+to from the rest is tunnelled to the nearest walkable ground.
+*/
+
+use std::mem;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use config::FrontierStrategy;
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::{ Point, Rect };
+use nav::Connectivity;
+use rng::DungenRng;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReshapeReport {
+    // the region as reshaped, kept inside the map's border
+    pub region: Rect,
+    // ways in from the rest of the map that new features could grow from
+    pub openings: usize,
+    // rooms and corridors dug in the region
+    pub features: usize,
+    // entrances and exits moved out of the region, from and to
+    pub relocated: Vec<(Point, Point)>,
+    // tunnels dug to join parts of the map that were cut off
+    pub tunnels: usize,
+}
+
+impl Dungeon {
+    // clears region and digs it out again from its openings onto the rest of the map, then
+    // makes sure every walkable tile can be reached from every other. on a wrapping map the
+    // region may run across the seam.
+    pub fn reshape_region(&mut self, region: Rect, rng: &mut DungenRng) -> ReshapeReport {
+        let region = self.clip_region(region);
+        let mut report = ReshapeReport { region, openings: 0, features: 0, relocated: Vec::new(), tunnels: 0 };
+        if region.width <= 0 || region.height <= 0 {
+            return report
+        }
+
+        let mut cells: Vec<Point> = (region.y..region.y + region.height)
+            .flat_map(|y| (region.x..region.x + region.width).map(move |x| Point::new(x, y)))
+            .map(|p| self.wrap_point(p))
+            .collect();
+        cells.sort_by_key(|p| (p.y, p.x));
+        cells.dedup();
+
+        // walkable tiles outside with walkable ground inside, and the way in from each
+        let mut openings: Vec<(Point, Dir)> = Vec::new();
+        for &p in &cells {
+            if !self.get(p).is_walkable() {
+                continue;
+            }
+            for dir in Dir::iterator() {
+                let (dx, dy) = dir.opposite().offset();
+                let o = self.wrap_point(p.offset(dx, dy));
+                if !self.region_contains(&region, o) && self.get(o).is_walkable() {
+                    openings.push((o, *dir));
+                }
+            }
+        }
+
+        // out of the way before the ground goes
+        let objects: Vec<Point> = self.entrances().iter().chain(self.exits()).cloned()
+            .filter(|&p| self.region_contains(&region, p))
+            .collect();
+        let mut stranded = Vec::new();
+        for p in objects {
+            match self.nearest_floor(p, |q| !self.region_contains(&region, q)) {
+                Some(q) => {
+                    self.move_object(p, q);
+                    report.relocated.push((p, q));
+                }
+                None => stranded.push(p),
+            }
+        }
+
+        let chests = mem::take(&mut self.chests);
+        self.chests = chests.into_iter().filter(|chest| !self.region_contains(&region, chest.pos)).collect();
+        let touched = |d: &Dungeon, rect: &Rect| {
+            (rect.y..rect.y + rect.height).any(|y| (rect.x..rect.x + rect.width).any(|x| d.region_contains(&region, Point::new(x, y))))
+        };
+        let rooms = mem::take(&mut self.rooms);
+        self.rooms = rooms.into_iter().filter(|rect| !touched(self, rect)).collect();
+        let faces = mem::take(&mut self.faces);
+        let kept_faces: Vec<Rect> = faces.into_iter().filter(|rect| !touched(self, rect)).collect();
+
+        let rock = if self.is_sealed() { Tile::Wall } else { Tile::Unused };
+        for &p in &cells {
+            self.set_tile(p.x, p.y, rock);
+        }
+
+        for &(o, dir) in &openings {
+            if self.get(o).is_door() || matches!(self.get(o), Tile::Archway | Tile::SecretDoor) {
+                let (dx, dy) = dir.opposite().offset();
+                let behind = self.get(o.offset(dx, dy));
+                if behind == Tile::Floor || behind == Tile::Corridor {
+                    self.set_tile(o.x, o.y, behind);
+                }
+            }
+        }
+        openings.retain(|&(o, _)| matches!(self.get(o), Tile::Floor | Tile::Corridor));
+        report.openings = openings.len();
+        self.wall_in();
+
+        // grown from the openings alone, and kept inside the region
+        self.faces = openings.iter()
+            .map(|&(o, dir)| {
+                let (dx, dy) = dir.offset();
+                Rect::new(o.x + dx, o.y + dy, 1, 1)
+            })
+            .collect();
+        self.bounds = Some(region);
+        if self.config().frontier == FrontierStrategy::FillSparse {
+            self.count_density();
+        }
+        let most = (region.width * region.height / 4).max(1) as usize;
+        while report.features < most && self.has_exits(rng) {
+            report.features += 1;
+        }
+        self.bounds = None;
+        let grown = mem::take(&mut self.faces);
+        self.faces = kept_faces;
+        self.faces.extend(grown);
+        self.wall_in();
+
+        // only when there was no floor left outside the region to move them to
+        for p in stranded {
+            match self.nearest_floor(p, |_| true) {
+                Some(q) => {
+                    self.move_object(p, q);
+                    report.relocated.push((p, q));
+                }
+                None => self.set_object(p, rock),
+            }
+        }
+
+        report.tunnels = self.join_regions();
+        self.sync_rooms();
+        report
+    }
+
+    // whether the on-map point p lies in region, which on a wrapping map may run off the
+    // edges and around
+    pub(crate) fn region_contains(&self, region: &Rect, p: Point) -> bool {
+        let size = self.size();
+        let p = self.wrap_point(p);
+        let shifts: &[isize] = if self.wraps() { &[-1, 0, 1] } else { &[0] };
+
+        shifts.iter().any(|&i| shifts.iter().any(|&j| region.contains(p.offset(i * size.width, j * size.height))))
+    }
+
+    // inside the border, or no bigger than the map when it wraps
+    fn clip_region(&self, region: Rect) -> Rect {
+        let size = self.size();
+        if self.wraps() {
+            return Rect::new(region.x, region.y, region.width.min(size.width), region.height.min(size.height))
+        }
+
+        let border = self.config().border.max(0);
+        let (x, y) = (region.x.max(border), region.y.max(border));
+        let right = (region.x + region.width).min(size.width - border);
+        let bottom = (region.y + region.height).min(size.height - border);
+        Rect::new(x, y, (right - x).max(0), (bottom - y).max(0))
+    }
+
+    // the floor tile nearest p that ok accepts, by steps along the axes and then row-major
+    fn nearest_floor<F: Fn(Point) -> bool>(&self, p: Point, ok: F) -> Option<Point> {
+        let size = self.size();
+        (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|&q| self.get(q) == Tile::Floor && ok(q))
+            .min_by_key(|q| ((q.x - p.x).abs() + (q.y - p.y).abs(), q.y, q.x))
+    }
+
+    // tunnels the smallest part of the map cut off from the entrance, or from the largest
+    // part without one, on to the nearest walkable ground until nothing is cut off. returns
+    // the number of tunnels dug.
+    pub(crate) fn join_regions(&mut self) -> usize {
+        let inset = self.config().border.max(1);
+        let mut tunnels = 0;
+
+        loop {
+            let mut regions = self.regions(Connectivity::Four);
+            if regions.len() <= 1 {
+                break;
+            }
+
+            let main = match self.entrance() {
+                Some(entrance) => regions.iter().position(|r| r.contains(&entrance)),
+                None => None,
+            };
+            let main = main.unwrap_or_else(|| (0..regions.len()).max_by_key(|&i| regions[i].len()).unwrap());
+            regions.remove(main);
+            let smallest = regions.iter().min_by_key(|r| r.len()).unwrap();
+
+            if !self.tunnel_from(smallest, Tile::Corridor, inset) {
+                break;
+            }
+            tunnels += 1;
+        }
+
+        if tunnels > 0 {
+            self.wall_in();
+        }
+        tunnels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::{ Point, Rect };
+    use nav::Connectivity;
+    use rng::DungenRng;
+
+    fn level(seed: u64) -> Dungeon {
+        let mut d = Dungeon::new(70, 45);
+        d.generate_seeded(50, seed).unwrap();
+        d.sync_rooms();
+        d
+    }
+
+    fn check_sound(d: &Dungeon, seed: u64) {
+        assert!(d.validate_boundaries().is_empty(), "seed {}", seed);
+        assert_eq!(d.regions(Connectivity::Four).len(), 1, "seed {}\n{}", seed, d.render());
+        assert!(d.main_path().is_some(), "seed {}", seed);
+        for &p in d.entrances() {
+            assert_eq!(d.get(p), Tile::Entrance);
+        }
+        for &p in d.exits() {
+            assert_eq!(d.get(p), Tile::Exit);
+        }
+
+        // every room on record is on the map, and its info agrees with the graph
+        let graph = d.graph();
+        for id in d.room_ids() {
+            assert!(!graph.room_tiles(id).is_empty(), "seed {}: {:?}", seed, id);
+            assert_eq!(d.room(id).unwrap().area, graph.room_tiles(id).len());
+        }
+        assert_eq!(graph.room_ids().len(), d.room_ids().count());
+    }
+
+    #[test]
+    fn test_reshape_keeps_the_level_sound() {
+        for seed in 0..8 {
+            let mut d = level(seed);
+            let before: Vec<Tile> = d.rows().flatten().cloned().collect();
+            let region = Rect::new(20, 12, 24, 18);
+            let report = d.reshape_region(region, &mut DungenRng::new(seed));
+
+            assert_eq!(report.region, region);
+            assert!(report.openings > 0 && report.features > 0, "seed {}: {:?}", seed, report);
+            check_sound(&d, seed);
+
+            // nothing changes away from the region, bar the tunnels
+            if report.tunnels == 0 {
+                let size = d.size();
+                for y in 0..size.height {
+                    for x in 0..size.width {
+                        let near = Rect::new(region.x - 1, region.y - 1, region.width + 2, region.height + 2).contains(Point::new(x, y));
+                        if !near && !report.relocated.iter().any(|&(_, to)| to == Point::new(x, y)) {
+                            assert_eq!(d.get((x, y)), before[(x + y * size.width) as usize], "seed {}: ({}, {})", seed, x, y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reshape_moves_objects_and_retires_rooms() {
+        let mut d = level(3);
+        let entrance = d.entrance().unwrap();
+        let region = Rect::new(entrance.x - 4, entrance.y - 4, 9, 9);
+        let graph = d.graph();
+        let inside: Vec<_> = graph.room_ids().into_iter()
+            .filter(|&id| graph.room_tiles(id).iter().all(|&p| region.contains(p)))
+            .collect();
+        let newest = d.room_ids().last().unwrap();
+
+        let report = d.reshape_region(region, &mut DungenRng::new(1));
+        check_sound(&d, 3);
+        assert_eq!(report.relocated[0].0, entrance);
+        assert_eq!(d.entrance(), Some(report.relocated[0].1));
+        assert!(!region.contains(d.entrance().unwrap()));
+
+        for id in inside {
+            assert!(d.room(id).is_none(), "{:?}", id);
+        }
+        // rooms dug in the region get new ids
+        let graph = d.graph();
+        for id in d.room_ids() {
+            if graph.room_tiles(id).iter().all(|&p| region.contains(p)) {
+                assert!(id > newest);
+            }
+        }
+
+        // a region off the map changes nothing
+        let before = d.render();
+        let report = d.reshape_region(Rect::new(-20, -20, 10, 10), &mut DungenRng::new(1));
+        assert_eq!((report.region.width, report.features), (0, 0));
+        assert_eq!(d.render(), before);
+    }
+}
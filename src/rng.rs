@@ -1,17 +1,189 @@
-use rand::thread_rng;
-use rand::distributions::{ IndependentSample, Range };
-
-// grabs a random sample from a range, max inclusive
-pub fn inclusive_random(min: isize, max: isize) -> isize {
-    let mut rng = thread_rng();
-    let range = Range::new(min, max+1);
-    
-    range.ind_sample(&mut rng)
-}
-
-pub fn exclusive_random(max: isize) -> isize {
-    let mut rng = thread_rng();
-    let range = Range::new(0, max);
-    
-    range.ind_sample(&mut rng)
-}
\ No newline at end of file
+/*
+Self-contained random number generation. All randomness used during generation comes
+from DungenRng, a xoshiro256++ generator seeded through splitmix64, so a seed maps to the
+same dungeon on every platform and with every version of the rand crate.
+
+Range mapping is specified as follows: a draw in min..=max takes one 64-bit output x and
+returns min + floor(x * span / 2^64), where span = max - min + 1. No rejection sampling
+is done, so every range draw consumes exactly one output.
+
+Changing anything here that alters the output sequence changes generated dungeons and
+must come with a bump of dungeon::GENERATION_VERSION.
+*/
+
+#[cfg(feature = "rand")]
+use rand::Rng;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DungenRng {
+    s: [u64; 4],
+}
+
+impl DungenRng {
+    pub fn new(seed: u64) -> DungenRng {
+        let mut z = seed;
+        let mut s = [0u64; 4];
+        for word in s.iter_mut() {
+            z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            *word = mix(z);
+        }
+
+        DungenRng { s }
+    }
+
+    // seeds a generator from any rand generator, for callers who don't need reproducibility
+    #[cfg(feature = "rand")]
+    pub fn from_rng<R: Rng>(rng: &mut R) -> DungenRng {
+        DungenRng::new(rng.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s[0].wrapping_add(self.s[3]).rotate_left(23).wrapping_add(self.s[0]);
+        let t = self.s[1] << 17;
+
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+
+        result
+    }
+
+    // uniform float in [0, 1) built from the top 53 bits of one output
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    // fair coin flip from the top bit of one output
+    pub fn coin(&mut self) -> bool {
+        self.next_u64() >> 63 == 1
+    }
+
+    // grabs a random sample from a range, max inclusive
+    pub fn range_inclusive(&mut self, min: isize, max: isize) -> isize {
+        let span = (max as i128 - min as i128 + 1) as u128;
+        let offset = (self.next_u64() as u128 * span) >> 64;
+
+        (min as i128 + offset as i128) as isize
+    }
+
+    // grabs a random sample from 0..max
+    pub fn range_exclusive(&mut self, max: isize) -> isize {
+        self.range_inclusive(0, max - 1)
+    }
+
+    // picks an index into weights with probability proportional to its weight
+    pub fn weighted_index(&mut self, weights: &[u32]) -> usize {
+        let total: u64 = weights.iter().map(|&w| w as u64).sum();
+        let mut roll = self.range_exclusive(total as isize) as u64;
+
+        for (i, &w) in weights.iter().enumerate() {
+            if roll < w as u64 {
+                return i
+            }
+            roll -= w as u64;
+        }
+
+        weights.len() - 1
+    }
+
+    // approximately standard normal sample (sum of twelve uniforms). uses plain
+    // arithmetic only, so unlike Box-Muller it doesn't depend on the platform's libm.
+    pub fn standard_normal(&mut self) -> f64 {
+        let mut sum = 0.0;
+        for _ in 0..12 {
+            sum += self.next_f64();
+        }
+
+        sum - 6.0
+    }
+}
+
+#[cfg(feature = "rand")]
+impl Rng for DungenRng {
+    fn next_u32(&mut self) -> u32 {
+        (DungenRng::next_u64(self) >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        DungenRng::next_u64(self)
+    }
+}
+
+// mixes a 64-bit value so nearby inputs give unrelated outputs (splitmix64 finalizer)
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// derives the seed of a named stream from the master seed. this is a fixed
+// fnv-1a hash rather than std's hasher so seeds stay stable across toolchains.
+pub fn stream_seed(seed: u64, name: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in seed.to_le_bytes().iter().chain(name.as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    mix(hash)
+}
+
+// independent generators for each phase of generation, so the amount of
+// randomness one phase consumes can never shift the results of another
+pub struct Streams {
+    pub layout: DungenRng,
+    pub objects: DungenRng,
+    // the passes run on a finished map: torches, windows, chests and the like
+    pub decor: DungenRng,
+}
+
+impl Streams {
+    pub fn new(seed: u64) -> Streams {
+        Streams {
+            layout: DungenRng::new(stream_seed(seed, "layout")),
+            objects: DungenRng::new(stream_seed(seed, "objects")),
+            decor: DungenRng::new(stream_seed(seed, "decor")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rng::*;
+
+    #[test]
+    fn test_xoshiro_reference_output() {
+        // state and first output from the reference implementation's test vectors
+        let mut rng = DungenRng { s: [1, 2, 3, 4] };
+        assert_eq!(rng.next_u64(), 41943041);
+    }
+
+    #[test]
+    fn test_seeded_sequence_is_pinned() {
+        let mut rng = DungenRng::new(12345);
+        let seq: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+
+        assert_eq!(seq, vec![10201931350592234856, 3780764549115216544, 1570246627180645737]);
+    }
+
+    #[test]
+    fn test_range_mapping() {
+        let mut rng = DungenRng::new(7);
+        for _ in 0..10000 {
+            let v = rng.range_inclusive(-3, 3);
+            assert!((-3..=3).contains(&v));
+
+            let v = rng.range_exclusive(5);
+            assert!((0..5).contains(&v));
+        }
+
+        // the top of the output range maps onto max, the bottom onto min
+        let mut high = DungenRng { s: [u64::MAX, 0, 0, 0] };
+        assert_eq!(high.range_inclusive(0, 9), 9);
+        let mut low = DungenRng { s: [0, 0, 0, 0] };
+        assert_eq!(low.range_inclusive(0, 9), 0);
+    }
+}
@@ -0,0 +1,1051 @@
+/*
+Per-room metadata and the passes that fill it in. A room gets at most one purpose, so two
+purposes can never end up on the same room. Assignment is greedy: purposes are handled in
+the order they first appear in the table, and each one takes the rooms that satisfy all of
+its constraints, visited in an order shuffled from the caller's rng.
+
+The boss room is picked separately: the room furthest from the entrance in door-steps
+(largest on ties), with every way in but one walled up. The safe room is an optional
+dead end off the main path, where nothing spawns. Dead-end rooms are small rooms carved past
+the ends of dead-end corridors to reward exploring them; chest placement visits them first.
+Courtyards are roomy, open-air rooms off the main path. Caves are the caverns the cave
+layouts grow, tagged as they're dug.
+
+Room ids are handed out as rooms are carved and never reused, so they survive edits to the
+map. Each id keeps a slot holding a tile of its room, the anchor, which is how a freshly
+built graph tells which room is which. Edits that join, split or fill in rooms call
+sync_rooms, which gives new rooms ids and retires the ids of rooms that are gone; a room
+merged into another hands its tags over to the older of the two.
+*/
+
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use error::{ BossRoomError, MergeError };
+use features::FeatureRef;
+use geom::{ Point, Rect };
+use graph::{ DungeonGraph, GraphEdge, RoomId };
+use nav::Connectivity;
+use rng::DungenRng;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RoomPurpose {
+    Barracks,
+    Library,
+    Shrine,
+    Storage,
+    Prison,
+    // the entrance room built from DungeonConfig::entrance_room
+    Start,
+    // the room behind the secret door, see Dungeon::add_secret_area
+    Secret,
+    // for purposes the game defines itself
+    Custom(u16),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Constraint {
+    // at least this many floor tiles
+    MinArea(usize),
+    // a single way in
+    DeadEnd,
+    OnMainPath,
+    OffMainPath,
+    // no more than this many rooms with the purpose
+    MaxPerLevel(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RoomEnvironment {
+    Interior,
+    // open to the sky
+    Courtyard,
+    // grown by one of the cave layouts
+    Cave,
+}
+
+// courtyards are at least this many floor tiles
+pub const COURTYARD_MIN_AREA: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RoomInfo {
+    pub id: RoomId,
+    // floor tiles, including an entrance or exit inside the room
+    pub area: usize,
+    // doors and openings leading out of the room
+    pub connections: usize,
+    pub purpose: Option<RoomPurpose>,
+    pub boss: bool,
+    pub safe: bool,
+    // carved by cap_dead_ends_with_rooms
+    pub dead_end: bool,
+    // set by assign_courtyards and the cave layouts
+    pub environment: RoomEnvironment,
+    // set by name_rooms
+    pub name: Option<String>,
+}
+
+// one per room id ever handed out, none once the room is gone
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RoomSlot {
+    // a tile the room has held since it got its id
+    anchor: Point,
+    info: RoomInfo,
+}
+
+impl RoomSlot {
+    // untagged and unconnected until the next sync_rooms
+    fn new(id: RoomId, anchor: Point, area: usize) -> RoomSlot {
+        let info = RoomInfo {
+            id,
+            area,
+            connections: 0,
+            purpose: None,
+            boss: false,
+            safe: false,
+            dead_end: false,
+            environment: RoomEnvironment::Interior,
+            name: None,
+        };
+
+        RoomSlot { anchor, info }
+    }
+}
+
+impl Dungeon {
+    // none if id doesn't name a room on the map
+    pub fn room(&self, id: RoomId) -> Option<&RoomInfo> {
+        self.room_slots.get(id.0 as usize).and_then(|slot| slot.as_ref()).map(|slot| &slot.info)
+    }
+
+    // rooms on the map, oldest first
+    pub fn room_ids(&self) -> impl Iterator<Item = RoomId> + '_ {
+        self.room_slots.iter().filter_map(|slot| slot.as_ref().map(|slot| slot.info.id))
+    }
+
+    pub fn room_info(&self, id: RoomId) -> Option<RoomInfo> {
+        self.room(id).cloned()
+    }
+
+    // the room's open floor in row-major order: its Floor and Grass tiles with no chest on
+    // them. entrances, exits, npcs, pits, altars and pillars are left out. the room is
+    // walked tile by tile rather than by rect, so one that isn't a rect, or was merged from
+    // two, gives every tile it has and none past its walls. empty if the room isn't on the map.
+    pub fn room_interior(&self, id: RoomId) -> impl Iterator<Item = Point> + '_ {
+        let mut tiles = self.graph().room_tiles(id).to_vec();
+        tiles.sort_by_key(|p| (p.y, p.x));
+        tiles.into_iter().filter(move |&p| {
+            matches!(self.get(p), Tile::Floor | Tile::Grass) && !self.chests.iter().any(|chest| chest.pos == p)
+        })
+    }
+
+    // the ring around the room in row-major order: every tile beside one of its tiles,
+    // diagonals included, that isn't part of it. that's its walls and the doors and
+    // archways in them, wherever a room that isn't a rect turns a corner. a wall knocked out
+    // by merge_rooms is floor of the merged room, so it isn't in the ring.
+    pub fn room_perimeter(&self, id: RoomId) -> impl Iterator<Item = Point> + '_ {
+        let graph = self.graph();
+        let size = self.size();
+        let map = Rect::new(0, 0, size.width, size.height);
+        let mut ring = BTreeSet::new();
+
+        for &p in graph.room_tiles(id) {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let q = self.wrap_point(p.offset(dx, dy));
+                    if map.contains(q) && graph.room_at(q) != Some(id) {
+                        ring.insert((q.y, q.x));
+                    }
+                }
+            }
+        }
+
+        ring.into_iter().map(|(y, x)| Point::new(x, y))
+    }
+
+    pub fn safe_room(&self) -> Option<RoomId> {
+        self.safe_room
+    }
+
+    // picks a random dead-end room off the main path, or failing that the smallest room off
+    // it, and stands an npc on the floor tile nearest its middle. the entrance, exit and
+    // boss rooms are never chosen. none if no room qualifies.
+    pub fn place_safe_room(&mut self, rng: &mut DungenRng) -> Option<RoomId> {
+        let graph = self.graph();
+        let taken: Vec<RoomId> = self.entrance().into_iter().chain(self.exit())
+            .filter_map(|p| graph.room_at(p))
+            .chain(self.boss_room)
+            .collect();
+        let off_path: Vec<RoomId> = self.off_path_rooms().into_iter()
+            .filter(|id| !taken.contains(id) && graph.room_tiles(*id).iter().any(|&p| self.get(p) == Tile::Floor))
+            .collect();
+
+        let dead_ends: Vec<RoomId> = off_path.iter().cloned().filter(|id| graph.room_degree(*id) == 1).collect();
+        let room = if !dead_ends.is_empty() {
+            dead_ends[rng.range_exclusive(dead_ends.len() as isize) as usize]
+        }
+
+        else {
+            *off_path.iter().min_by_key(|id| graph.room_tiles(**id).len())?
+        };
+
+        // the floor tile nearest the average of the room's tiles, in doubled coordinates
+        let tiles = graph.room_tiles(room);
+        let n = tiles.len() as isize;
+        let mid = Point::new(tiles.iter().map(|p| p.x).sum::<isize>() * 2 / n, tiles.iter().map(|p| p.y).sum::<isize>() * 2 / n);
+        let npc = tiles.iter()
+            .cloned()
+            .filter(|&p| self.get(p) == Tile::Floor)
+            .min_by_key(|p| (p.x * 2 - mid.x).abs() + (p.y * 2 - mid.y).abs())?;
+
+        self.set_tile(npc.x, npc.y, Tile::Npc);
+        self.safe_room = Some(room);
+        self.sync_rooms();
+        Some(room)
+    }
+
+    pub fn environment(&self, id: RoomId) -> RoomEnvironment {
+        if self.courtyards.contains(&id) {
+            return RoomEnvironment::Courtyard
+        }
+
+        if self.caves.contains(&id) { RoomEnvironment::Cave } else { RoomEnvironment::Interior }
+    }
+
+    pub fn caves(&self) -> Vec<RoomId> {
+        self.caves.iter().cloned().collect()
+    }
+
+    pub fn courtyards(&self) -> Vec<RoomId> {
+        self.courtyards.iter().cloned().collect()
+    }
+
+    // makes courtyards of that fraction, rounded, of the rooms off the main path with at
+    // least COURTYARD_MIN_AREA floor tiles. replaces any earlier assignment. returns the
+    // courtyards in id order.
+    pub fn assign_courtyards(&mut self, fraction: f32, rng: &mut DungenRng) -> Vec<RoomId> {
+        let graph = self.graph();
+        let main_path = self.main_path().map(|path| path.rooms).unwrap_or_default();
+        let mut eligible: Vec<RoomId> = graph.room_ids().into_iter()
+            .filter(|id| !main_path.contains(id) && graph.room_tiles(*id).len() >= COURTYARD_MIN_AREA)
+            .collect();
+
+        for i in (1..eligible.len()).rev() {
+            let j = rng.range_inclusive(0, i as isize) as usize;
+            eligible.swap(i, j);
+        }
+
+        let count = (eligible.len() as f32 * fraction.clamp(0.0, 1.0)).round() as usize;
+        self.courtyards = eligible.into_iter().take(count).collect();
+        self.sync_rooms();
+        self.courtyards()
+    }
+
+    // lays grass over the plain floor of every courtyard, so it renders differently
+    pub fn grass_courtyards(&mut self) {
+        let graph = self.graph();
+        for &id in &self.courtyards.clone() {
+            for &p in graph.room_tiles(id) {
+                if self.get(p) == Tile::Floor {
+                    self.set_tile(p.x, p.y, Tile::Grass);
+                }
+            }
+        }
+    }
+
+    pub fn dead_end_rooms(&self) -> Vec<RoomId> {
+        self.dead_end_rooms.iter().cloned().collect()
+    }
+
+    // with the given chance for each corridor tile with a single way out, carves a room of
+    // 3x3 to 5x5 past its end, behind a door. a room that doesn't fit is retried at 3x3,
+    // then given up on. returns how many dead ends became rooms.
+    pub fn cap_dead_ends_with_rooms(&mut self, chance: f32, rng: &mut DungenRng) -> usize {
+        let mut carved = 0;
+        for (end, dir) in self.dead_ends() {
+            if rng.next_f64() >= chance as f64 {
+                continue;
+            }
+
+            let (dx, dy) = dir.offset();
+            if self.get(end.offset(dx, dy)) != Tile::Wall {
+                continue;
+            }
+
+            let rolled = (rng.range_inclusive(3, 5), rng.range_inclusive(3, 5));
+            if self.cap_dead_end(end, dir, &[rolled, (3, 3)], rng).is_some() {
+                carved += 1;
+            }
+        }
+
+        self.sync_rooms();
+        carved
+    }
+
+    // corridor tiles with a single way out, row by row, each with the way a room past it
+    // would face: away from the way out
+    pub(crate) fn dead_ends(&self) -> Vec<(Point, Dir)> {
+        let size = self.size();
+        let mut ends = Vec::new();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                let open: Vec<&Dir> = Dir::iterator()
+                    .filter(|dir| {
+                        let (dx, dy) = dir.offset();
+                        self.get(p.offset(dx, dy)).is_walkable()
+                    })
+                    .collect();
+
+                if self.get(p) == Tile::Corridor && open.len() == 1 {
+                    ends.push((p, open[0].opposite()));
+                }
+            }
+        }
+        ends
+    }
+
+    // carves a room past the dead end at the first of sizes that fits, behind a door in the
+    // wall at the corridor's end, and marks it a dead-end room. none if the wall isn't there
+    // or no size fits. the room's info is stale until the next sync_rooms.
+    pub(crate) fn cap_dead_end(&mut self, end: Point, dir: Dir, sizes: &[(isize, isize)], rng: &mut DungenRng) -> Option<RoomId> {
+        let (dx, dy) = dir.offset();
+        let junction = end.offset(dx, dy);
+        if self.get(junction) != Tile::Wall {
+            return None
+        }
+
+        let fits = sizes.iter().any(|&(width, height)| {
+            self.carve_rect(Dungeon::room_rect(junction.x, junction.y, &dir, width, height), Tile::Floor).is_ok()
+        });
+        if !fits {
+            return None
+        }
+
+        self.write_door(junction.x, junction.y, Tile::Corridor, rng);
+        // carve_rect registered it last
+        let id = RoomId(self.room_slots.len() as u32 - 1);
+        self.dead_end_rooms.insert(id);
+        Some(id)
+    }
+
+    pub fn boss_room(&self) -> Option<RoomId> {
+        self.boss_room
+    }
+
+    // tags the furthest room with at least min_area floor tiles as the boss room, turning
+    // every door but the one nearest the entrance into wall. doors whose removal would cut
+    // off any other part of the map are kept, and if that leaves more than one way in the
+    // map is restored and an error returned. doorless openings can't be closed this way.
+    pub fn designate_boss_room(&mut self, min_area: isize) -> Result<RoomId, BossRoomError> {
+        let entrance = self.entrance().ok_or(BossRoomError::NoEntrance)?;
+        let graph = self.graph();
+        let start = graph.room_at(entrance).and_then(|id| graph.room_node(id));
+        let hops = match start {
+            Some(n) => graph.hops_from(n),
+            None => return Err(BossRoomError::NoEntrance),
+        };
+
+        let (room, node) = graph.room_ids().into_iter()
+            .filter_map(|id| graph.room_node(id).map(|n| (id, n)))
+            .filter(|&(id, n)| Some(n) != start && graph.room_tiles(id).len() as isize >= min_area)
+            .filter_map(|(id, n)| hops[n].map(|h| (h, graph.room_tiles(id).len(), id, n)))
+            .max_by_key(|&(h, area, id, _)| (h, area, Reverse(id)))
+            .map(|(_, _, id, n)| (id, n))
+            .ok_or(BossRoomError::NoCandidate { min_area })?;
+
+        // keep the way in that leads back toward the entrance
+        let mut ways: Vec<GraphEdge> = graph.edges.iter().cloned().filter(|e| e.a == node || e.b == node).collect();
+        ways.sort_by_key(|e| hops[if e.a == node { e.b } else { e.a }]);
+
+        let reachable = |d: &Dungeon| d.distance_map(entrance, Connectivity::Four).iter().filter(|t| t.is_some()).count();
+        let mut walled = Vec::new();
+        for e in ways.iter().skip(1) {
+            let tile = self.get(e.via);
+            if !tile.is_door() && tile != Tile::Archway {
+                continue;
+            }
+
+            let before = reachable(self);
+            self.set_tile(e.via.x, e.via.y, Tile::Wall);
+            if reachable(self) + 1 == before {
+                walled.push((e.via, tile));
+            }
+
+            else {
+                self.set_tile(e.via.x, e.via.y, tile);
+            }
+        }
+
+        let entrances = ways.len() - walled.len();
+        if entrances > 1 {
+            for (p, tile) in walled {
+                self.set_tile(p.x, p.y, tile);
+            }
+            return Err(BossRoomError::MultipleEntrances { room, entrances })
+        }
+
+        self.boss_room = Some(room);
+        self.sync_rooms();
+        Ok(room)
+    }
+
+    // replaces any earlier assignment but Start. the rooms holding the entrance and exit are
+    // left without a purpose.
+    pub fn assign_purposes(&mut self, table: &[(RoomPurpose, Constraint)], rng: &mut DungenRng) {
+        let graph = self.graph();
+        let main_path = self.main_path().map(|path| path.rooms).unwrap_or_default();
+        let reserved: Vec<RoomId> = self.entrance().into_iter().chain(self.exit())
+            .filter_map(|p| graph.room_at(p))
+            .collect();
+
+        let mut order: Vec<RoomId> = graph.room_ids().into_iter().filter(|id| !reserved.contains(id)).collect();
+        for i in (1..order.len()).rev() {
+            let j = rng.range_inclusive(0, i as isize) as usize;
+            order.swap(i, j);
+        }
+
+        let mut purposes: Vec<RoomPurpose> = Vec::new();
+        for &(purpose, _) in table {
+            if !purposes.contains(&purpose) {
+                purposes.push(purpose);
+            }
+        }
+
+        self.purposes.retain(|_, p| *p == RoomPurpose::Start);
+        for purpose in purposes {
+            let constraints: Vec<Constraint> = table.iter().filter(|e| e.0 == purpose).map(|e| e.1).collect();
+            let max = constraints.iter()
+                .filter_map(|c| match *c {
+                    Constraint::MaxPerLevel(n) => Some(n),
+                    _ => None,
+                })
+                .min();
+            let mut count = 0;
+
+            for &id in &order {
+                if max.is_some_and(|max| count >= max) {
+                    break;
+                }
+
+                if self.purposes.contains_key(&id) {
+                    continue;
+                }
+
+                let fits = constraints.iter().all(|c| match *c {
+                    Constraint::MinArea(n) => graph.room_tiles(id).len() >= n,
+                    Constraint::DeadEnd => graph.room_degree(id) == 1,
+                    Constraint::OnMainPath => main_path.contains(&id),
+                    Constraint::OffMainPath => !main_path.contains(&id),
+                    Constraint::MaxPerLevel(_) => true,
+                });
+                if fits {
+                    self.purposes.insert(id, purpose);
+                    count += 1;
+                }
+            }
+        }
+
+        self.sync_rooms();
+    }
+
+    // knocks through the wall between two rooms, door included, leaving one room. the
+    // wall tiles taken out are those with a on one side and b directly opposite.
+    pub fn merge_rooms(&mut self, a: RoomId, b: RoomId) -> Result<RoomId, MergeError> {
+        let graph = self.graph();
+        for &id in [a, b].iter() {
+            if !graph.room_ids().contains(&id) {
+                return Err(MergeError::NotARoom(id))
+            }
+        }
+
+        if a == b {
+            return Err(MergeError::SameRoom(a))
+        }
+
+        let wall = self.shared_wall(&graph, a, b);
+        if wall.is_empty() {
+            return Err(MergeError::NotAdjacent { a, b })
+        }
+
+        for p in wall {
+            self.set_tile(p.x, p.y, Tile::Floor);
+        }
+        self.sync_rooms();
+
+        Ok(a.min(b))
+    }
+
+    // merges each pair of rooms sharing a wall with the given chance. pairs are visited in
+    // a fixed order, and a pair already joined through an earlier merge is skipped.
+    pub fn auto_merge(&mut self, chance: f32, rng: &mut DungenRng) {
+        let graph = self.graph();
+        let rooms = graph.room_ids();
+        let mut pairs = Vec::new();
+        for (i, &a) in rooms.iter().enumerate() {
+            for &b in &rooms[i + 1..] {
+                if !self.shared_wall(&graph, a, b).is_empty() {
+                    pairs.push((graph.room_tiles(a)[0], graph.room_tiles(b)[0]));
+                }
+            }
+        }
+
+        for (pa, pb) in pairs {
+            if rng.next_f64() >= chance as f64 {
+                continue;
+            }
+
+            let current = self.graph();
+            if let (Some(a), Some(b)) = (current.room_at(pa), current.room_at(pb)) {
+                if a != b {
+                    let _ = self.merge_rooms(a, b);
+                }
+            }
+        }
+    }
+
+    fn shared_wall(&self, graph: &DungeonGraph, a: RoomId, b: RoomId) -> Vec<Point> {
+        let mut wall = Vec::new();
+        for &p in graph.room_tiles(a) {
+            for &(dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+                let between = p.offset(dx, dy);
+                let separates = match self.get(between) {
+                    Tile::Wall | Tile::Torch | Tile::Window | Tile::ClosedDoor | Tile::OpenDoor | Tile::Archway | Tile::SecretDoor => graph.room_at(between.offset(dx, dy)) == Some(b),
+                    _ => false,
+                };
+                if separates && !wall.contains(&between) {
+                    wall.push(between);
+                }
+            }
+        }
+
+        wall
+    }
+
+    // (id, anchor) for every room with a slot, oldest first
+    pub(crate) fn room_anchors(&self) -> Vec<(RoomId, Point)> {
+        self.room_slots.iter().filter_map(|slot| slot.as_ref().map(|slot| (slot.info.id, slot.anchor))).collect()
+    }
+
+    // moves the anchor of a room held at one of points onto the room's first tile, in
+    // graph's row-major order, that isn't, so the room keeps its id once those tiles are
+    // filled in. graph must be of the map from before.
+    pub(crate) fn reanchor_rooms(&mut self, points: &[Point], graph: &DungeonGraph) {
+        for slot in self.room_slots.iter_mut().flatten() {
+            if points.contains(&slot.anchor) {
+                if let Some(&p) = graph.room_tiles(slot.info.id).iter().find(|p| !points.contains(p)) {
+                    slot.anchor = p;
+                }
+            }
+        }
+    }
+
+    // gives a freshly carved room its id, anchored at its top left tile
+    pub(crate) fn register_room(&mut self, rect: &Rect) -> RoomId {
+        let id = RoomId(self.room_slots.len() as u32);
+        let anchor = self.wrap_point(Point::new(rect.x, rect.y));
+        self.room_slots.push(Some(RoomSlot::new(id, anchor, (rect.width * rect.height) as usize)));
+        self.stamp_feature(rect.points(), FeatureRef::Room(id));
+        id
+    }
+
+    // an id for a room brought in from another map, anchored at anchor. its info is filled
+    // in by the next sync_rooms.
+    pub(crate) fn register_room_at(&mut self, anchor: Point) -> RoomId {
+        let id = RoomId(self.room_slots.len() as u32);
+        self.room_slots.push(Some(RoomSlot::new(id, self.wrap_point(anchor), 0)));
+        id
+    }
+
+    // brings the slots in line with the map after rooms were joined, split or filled in,
+    // and refreshes the info of every room
+    pub(crate) fn sync_rooms(&mut self) {
+        let graph = self.graph();
+        for n in graph.room_nodes() {
+            let id = graph.room_at(graph.nodes[n].tiles[0]).unwrap();
+            if id.0 as usize >= self.room_slots.len() {
+                let anchor = graph.nodes[n].tiles[0];
+                self.room_slots.push(Some(RoomSlot::new(id, anchor, graph.room_tiles(id).len())));
+            }
+        }
+
+        for (id, anchor) in self.room_anchors() {
+            let owner = graph.room_at(anchor);
+            if owner != Some(id) {
+                self.retire_room(id, owner);
+            }
+        }
+
+        for chest in self.chests.iter_mut() {
+            if let Some(id) = graph.room_at(chest.pos) {
+                chest.room = id;
+            }
+        }
+
+        let infos: Vec<RoomInfo> = self.room_ids()
+            .map(|id| RoomInfo {
+                id,
+                area: graph.room_tiles(id).len(),
+                connections: graph.room_degree(id),
+                purpose: self.purposes.get(&id).cloned(),
+                boss: self.boss_room == Some(id),
+                safe: self.safe_room == Some(id),
+                dead_end: self.dead_end_rooms.contains(&id),
+                environment: self.environment(id),
+                name: self.names.get(&id).cloned(),
+            })
+            .collect();
+        for info in infos {
+            if let Some(slot) = self.room_slots[info.id.0 as usize].as_mut() {
+                slot.info = info;
+            }
+        }
+        self.write_features(&graph);
+        self.write_orientations();
+    }
+
+    // tombstones a room's slot. its tags go to the room it was merged into, if any, unless
+    // that room already has its own.
+    fn retire_room(&mut self, id: RoomId, into: Option<RoomId>) {
+        self.room_slots[id.0 as usize] = None;
+
+        if let Some(purpose) = self.purposes.remove(&id) {
+            if let Some(into) = into {
+                self.purposes.entry(into).or_insert(purpose);
+            }
+        }
+
+        if let Some(name) = self.names.remove(&id) {
+            if let Some(into) = into {
+                self.names.entry(into).or_insert(name);
+            }
+        }
+
+        if self.dead_end_rooms.remove(&id) {
+            self.dead_end_rooms.extend(into);
+        }
+        if self.courtyards.remove(&id) {
+            self.courtyards.extend(into);
+        }
+        if self.caves.remove(&id) {
+            self.caves.extend(into);
+        }
+
+        if self.boss_room == Some(id) {
+            self.boss_room = into;
+        }
+        if self.safe_room == Some(id) {
+            self.safe_room = into;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::Dungeon;
+    use dungeon::Tile;
+    use error::{ BossRoomError, MergeError };
+    use geom::Point;
+    use rng::DungenRng;
+    use rooms::*;
+    use spawn::{ ChestPlacement, SpawnTable };
+
+    fn fixture() -> Dungeon {
+        Dungeon::parse(&[
+            "####################",
+            "#<.+,,+....+,,+..>.#",
+            "#..#  #....#  #....#",
+            "####  ##+###  ######",
+            "       #,#          ",
+            "     ###+###        ",
+            "     #.....#        ",
+            "     #.....#        ",
+            "     #######        ",
+        ])
+    }
+
+    #[test]
+    fn test_room_info() {
+        let d = fixture();
+        let graph = d.graph();
+        let hall = graph.room_at(Point::new(7, 1)).unwrap();
+        let info = d.room_info(hall).unwrap();
+
+        assert_eq!(info.area, 8);
+        assert_eq!(info.connections, 3);
+        assert_eq!(info.purpose, None);
+
+        assert_eq!(d.room_info(RoomId(99)), None);
+    }
+
+    #[test]
+    fn test_assign_purposes_respects_constraints() {
+        let mut d = fixture();
+        let graph = d.graph();
+        let hall = graph.room_at(Point::new(7, 1)).unwrap();
+        let cellar = graph.room_at(Point::new(6, 6)).unwrap();
+
+        let table = [
+            (RoomPurpose::Prison, Constraint::DeadEnd),
+            (RoomPurpose::Prison, Constraint::OffMainPath),
+            (RoomPurpose::Library, Constraint::MinArea(6)),
+            (RoomPurpose::Shrine, Constraint::OnMainPath),
+            (RoomPurpose::Shrine, Constraint::MaxPerLevel(0)),
+        ];
+        d.assign_purposes(&table, &mut DungenRng::new(1));
+
+        assert_eq!(d.room_info(cellar).unwrap().purpose, Some(RoomPurpose::Prison));
+        assert_eq!(d.room_info(hall).unwrap().purpose, Some(RoomPurpose::Library));
+        // entrance and exit rooms are reserved
+        assert_eq!(d.room_info(graph.room_at(Point::new(1, 1)).unwrap()).unwrap().purpose, None);
+        assert_eq!(d.room_info(graph.room_at(Point::new(17, 1)).unwrap()).unwrap().purpose, None);
+    }
+
+    #[test]
+    fn test_assign_purposes_is_deterministic_and_capped() {
+        let table = [
+            (RoomPurpose::Custom(7), Constraint::MaxPerLevel(1)),
+            (RoomPurpose::Storage, Constraint::MinArea(1)),
+        ];
+        let assigned = |seed| {
+            let mut d = fixture();
+            d.assign_purposes(&table, &mut DungenRng::new(seed));
+            d.graph().room_ids().into_iter().map(|id| d.room_info(id).unwrap().purpose).collect::<Vec<_>>()
+        };
+
+        for seed in 0..8 {
+            let purposes = assigned(seed);
+            assert_eq!(purposes, assigned(seed));
+            assert_eq!(purposes.iter().filter(|&&p| p == Some(RoomPurpose::Custom(7))).count(), 1);
+            assert_eq!(purposes.iter().filter(|&&p| p == Some(RoomPurpose::Storage)).count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_boss_room_is_far_large_and_single_entrance() {
+        let mut d = Dungeon::parse(&[
+            "###################",
+            "#<.+,,+...+,,,+...#",
+            "#..#  #...#   #...#",
+            "####  #...#   ##+##",
+            "      ##+##    #,# ",
+            "       #,,,,,,,,,# ",
+            "       ###########  ",
+        ]);
+        let graph = d.graph();
+        let far = graph.room_at(Point::new(16, 1)).unwrap();
+
+        assert_eq!(d.designate_boss_room(6), Ok(far));
+        assert_eq!(d.boss_room(), Some(far));
+        assert!(d.room_info(far).unwrap().boss);
+        assert_eq!(d.graph().room_degree(far), 1);
+        // one of the two doors into the far room is gone, and nothing else
+        let doors = |d: &Dungeon| [(14, 1), (16, 3)].iter().filter(|&&p| d.get(p) == Tile::ClosedDoor).count();
+        assert_eq!(doors(&d), 1);
+        assert_eq!(d.get((3, 1)), Tile::ClosedDoor);
+        assert!(d.difficulty_map().contains(&(far, 1.0)));
+    }
+
+    #[test]
+    fn test_boss_room_errors() {
+        let mut d = fixture();
+        assert_eq!(d.designate_boss_room(100), Err(BossRoomError::NoCandidate { min_area: 100 }));
+        assert_eq!(Dungeon::parse(&["#..#"]).designate_boss_room(1), Err(BossRoomError::NoEntrance));
+
+        // the only large room is also the only way to the closet behind it
+        let mut d = Dungeon::parse(&[
+            "#############",
+            "#<.+,,+...+.#",
+            "#############",
+        ]);
+        let room = d.graph().room_at(Point::new(8, 1)).unwrap();
+        assert_eq!(d.designate_boss_room(3), Err(BossRoomError::MultipleEntrances { room, entrances: 2 }));
+        assert_eq!(d.get((10, 1)), Tile::ClosedDoor);
+        assert_eq!(d.boss_room(), None);
+    }
+
+    #[test]
+    fn test_safe_room_is_an_off_path_dead_end() {
+        let mut d = Dungeon::parse(&[
+            "################",
+            "#<.+,,+...+,,+>#",
+            "####  #...#  ###",
+            "      ##+##     ",
+            "      #...#     ",
+            "      #...#     ",
+            "      #...#     ",
+            "      #####     ",
+        ]);
+        let graph = d.graph();
+        let closet = graph.room_at(Point::new(8, 5)).unwrap();
+
+        assert_eq!(d.place_safe_room(&mut DungenRng::new(2)), Some(closet));
+        assert_eq!(d.safe_room(), Some(closet));
+        assert_eq!(d.get((8, 5)), Tile::Npc);
+        assert!(d.room_info(closet).unwrap().safe);
+        assert!(d.spawn_points(closet).is_empty());
+
+        let mut table = SpawnTable::new();
+        table.add(1, 1, 0.0, 1.0);
+        assert!(d.populate(&table, 5..=5, &mut DungenRng::new(4)).iter().all(|&(p, _)| d.graph().room_at(p) != Some(closet)));
+        assert!(d.place_chests(20, 2, &mut DungenRng::new(4)).iter().all(|c| c.room != closet));
+    }
+
+    #[test]
+    fn test_safe_room_falls_back_to_smallest_off_path_room() {
+        // both side rooms have two ways in, so neither is a dead end
+        let mut d = Dungeon::parse(&[
+            "#########",
+            "#<.+,+.>#",
+            "####,####",
+            "#.+,,,+.#",
+            "#.#,,,#.#",
+            "#.+,,,+.#",
+            "#######.#",
+            "#########",
+        ]);
+        let graph = d.graph();
+        let small = graph.room_at(Point::new(1, 3)).unwrap();
+
+        assert_eq!(d.place_safe_room(&mut DungenRng::new(0)), Some(small));
+        assert_eq!(Dungeon::parse(&["#<+>#"]).place_safe_room(&mut DungenRng::new(0)), None);
+    }
+
+    #[test]
+    fn test_merge_rooms_knocks_out_the_whole_wall() {
+        let mut d = Dungeon::parse(&[
+            "###########",
+            "#<..#.....#",
+            "#...+.....#",
+            "#...#.....#",
+            "###########",
+        ]);
+        let graph = d.graph();
+        let left = graph.room_at(Point::new(1, 1)).unwrap();
+        let right = graph.room_at(Point::new(6, 1)).unwrap();
+        d.designate_boss_room(1).unwrap();
+
+        let merged = d.merge_rooms(left, right).unwrap();
+        for y in 1..4 {
+            assert_eq!(d.get((4, y)), Tile::Floor);
+        }
+        let graph = d.graph();
+        assert_eq!(graph.room_ids(), vec![merged]);
+        assert_eq!(graph.room_at(Point::new(9, 3)), Some(merged));
+        assert_eq!(d.room_info(merged).unwrap().area, 27);
+        assert_eq!(d.boss_room(), Some(merged));
+        assert_eq!(d.render_with_path().lines().nth(2), Some("#.........#"));
+    }
+
+    #[test]
+    fn test_room_interior_and_perimeter() {
+        // a plain rect with things in it, one that isn't a rect, and two about to be merged
+        let mut d = Dungeon::parse(&[
+            "################",
+            "#<.O#....#..#..#",
+            "#...+..###..+..#",
+            "#..>#..#.#..#..#",
+            "#####..#.#######",
+            "    #..+.#      ",
+            "    ######      ",
+        ]);
+        let graph = d.graph();
+        let (plain, bent) = (graph.room_at(Point::new(1, 1)).unwrap(), graph.room_at(Point::new(5, 1)).unwrap());
+        let (left, right) = (graph.room_at(Point::new(10, 1)).unwrap(), graph.room_at(Point::new(13, 1)).unwrap());
+        d.chests.push(ChestPlacement { pos: Point::new(2, 2), tier: 0, room: plain });
+
+        let points = |v: &[(isize, isize)]| v.iter().map(|&(x, y)| Point::new(x, y)).collect::<Vec<_>>();
+        assert_eq!(d.room_interior(plain).collect::<Vec<_>>(), points(&[(2, 1), (1, 2), (3, 2), (1, 3), (2, 3)]));
+        assert_eq!(d.room_perimeter(plain).count(), 16);
+        assert!(d.room_perimeter(plain).any(|p| p == Point::new(4, 2)));
+
+        // the bent room, right down to the tiles round its inner corner, and not the room
+        // through the door off its foot
+        let interior: Vec<Point> = d.room_interior(bent).collect();
+        assert_eq!(interior.len(), 4 + 2 * 4);
+        assert!(!interior.contains(&Point::new(7, 2)) && !interior.contains(&Point::new(8, 5)));
+        let ring: Vec<Point> = d.room_perimeter(bent).collect();
+        assert!(!ring.contains(&Point::new(8, 5)));
+        for p in [(7, 2), (8, 2), (7, 3), (7, 4), (4, 2), (7, 5), (9, 0)] {
+            assert!(ring.contains(&Point::new(p.0, p.1)), "{:?}", p);
+        }
+        assert!(ring.iter().all(|&p| !d.get(p).is_walkable() || d.get(p).is_door()));
+        assert!(ring.windows(2).all(|w| (w[0].y, w[0].x) < (w[1].y, w[1].x)));
+
+        // merging drops the wall between them out of the ring
+        let merged = d.merge_rooms(left, right).unwrap();
+        let interior: Vec<Point> = d.room_interior(merged).collect();
+        assert_eq!(interior.len(), 6 + 6 + 3);
+        assert!(interior.contains(&Point::new(12, 2)));
+        let ring: Vec<Point> = d.room_perimeter(merged).collect();
+        assert!(!ring.contains(&Point::new(12, 1)) && ring.contains(&Point::new(12, 0)));
+        assert_eq!(ring.len(), 7 * 5 - 15);
+
+        assert_eq!(d.room_interior(RoomId(99)).count(), 0);
+        assert_eq!(d.room_perimeter(RoomId(99)).count(), 0);
+    }
+
+    #[test]
+    fn test_merge_errors() {
+        let mut d = Dungeon::parse(&[
+            "##########",
+            "#..+,,+..#",
+            "##########",
+        ]);
+        let graph = d.graph();
+        let a = graph.room_at(Point::new(1, 1)).unwrap();
+        let b = graph.room_at(Point::new(8, 1)).unwrap();
+        let missing = RoomId(99);
+
+        assert_eq!(d.merge_rooms(a, b), Err(MergeError::NotAdjacent { a, b }));
+        assert_eq!(d.merge_rooms(a, a), Err(MergeError::SameRoom(a)));
+        assert_eq!(d.merge_rooms(a, missing), Err(MergeError::NotARoom(missing)));
+        assert_eq!(d.get((3, 1)), Tile::ClosedDoor);
+    }
+
+    #[test]
+    fn test_auto_merge() {
+        let rows = [
+            "#############",
+            "#...#...#...#",
+            "#...+...+...#",
+            "#############",
+        ];
+        let mut all = Dungeon::parse(&rows);
+        all.auto_merge(1.0, &mut DungenRng::new(1));
+        assert_eq!(all.graph().room_ids().len(), 1);
+
+        let mut none = Dungeon::parse(&rows);
+        none.auto_merge(0.0, &mut DungenRng::new(1));
+        assert_eq!(none.graph().room_ids().len(), 3);
+    }
+
+    #[test]
+    fn test_cap_dead_ends_with_rooms() {
+        let mut rows = vec![
+            "################",
+            "#....+,,,,######",
+            "#....####,######",
+            "#....####,######",
+            "################",
+        ];
+        rows.extend(vec!["                "; 7]);
+        let mut d = Dungeon::parse(&rows);
+        let mut rng = DungenRng::new(4);
+
+        assert_eq!(d.cap_dead_ends_with_rooms(1.0, &mut rng), 1);
+        assert_eq!(d.get((9, 4)), Tile::ClosedDoor);
+        let room = d.graph().room_at((9, 5).into()).unwrap();
+        assert_eq!(d.dead_end_rooms(), vec![room]);
+
+        let info = d.room_info(room).unwrap();
+        assert!(info.dead_end && info.connections == 1);
+        assert!(info.area >= 9 && info.area <= 25);
+        assert!(::testing::check_invariants(&d).iter().all(|v| !matches!(v, ::testing::InvariantViolation::RoomTile { .. })));
+
+        // the dead end is gone, so a second pass finds nothing
+        assert_eq!(d.cap_dead_ends_with_rooms(1.0, &mut rng), 0);
+    }
+
+    #[test]
+    fn test_dead_end_rooms_get_chests_first() {
+        let mut d = Dungeon::new(80, 50);
+        d.generate_seeded(40, 11).unwrap();
+        let capped = d.cap_dead_ends_with_rooms(1.0, &mut DungenRng::new(2));
+        assert!(capped > 0);
+        assert_eq!(d.dead_end_rooms().len(), capped);
+
+        let chests = d.place_chests(capped, 1, &mut DungenRng::new(3));
+        let rooms: Vec<RoomId> = chests.iter().map(|c| c.room).collect();
+        let reachable: Vec<RoomId> = d.dead_end_rooms().into_iter().filter(|&r| !d.spawn_points(r).is_empty()).collect();
+        assert!(reachable.iter().all(|r| rooms.contains(r)));
+    }
+
+    #[test]
+    fn test_courtyards() {
+        let mut d = Dungeon::new(80, 50);
+        d.generate_seeded(50, 21).unwrap();
+        let main_path = d.main_path().unwrap().rooms;
+        let graph = d.graph();
+        let eligible = graph.room_ids().into_iter()
+            .filter(|id| !main_path.contains(id) && graph.room_tiles(*id).len() >= COURTYARD_MIN_AREA)
+            .count();
+        assert!(eligible >= 2);
+
+        let courtyards = d.assign_courtyards(0.5, &mut DungenRng::new(6));
+        assert_eq!(courtyards.len(), (eligible as f32 * 0.5).round() as usize);
+        assert_eq!(d.courtyards(), courtyards);
+        for &id in &courtyards {
+            assert_eq!(d.room_info(id).unwrap().environment, RoomEnvironment::Courtyard);
+            assert!(!main_path.contains(&id));
+        }
+        let inside = graph.room_ids().into_iter().find(|id| !courtyards.contains(id)).unwrap();
+        assert_eq!(d.environment(inside), RoomEnvironment::Interior);
+
+        // grass keeps the rooms, and their ids, as they were
+        d.grass_courtyards();
+        assert_eq!(d.graph().room_ids(), graph.room_ids());
+        assert!(graph.room_tiles(courtyards[0]).iter().any(|&p| d.get(p) == Tile::Grass));
+        assert!(graph.room_tiles(inside).iter().all(|&p| d.get(p) != Tile::Grass));
+        assert!(!d.spawn_points(courtyards[0]).is_empty());
+
+        assert_eq!(d.assign_courtyards(0.0, &mut DungenRng::new(6)), vec![]);
+    }
+
+    #[test]
+    fn test_room_ids_survive_merges() {
+        let mut d = Dungeon::parse(&[
+            "#############",
+            "#...#...#...#",
+            "#...+...+...#",
+            "#############",
+        ]);
+        let ids: Vec<RoomId> = d.room_ids().collect();
+        assert_eq!(ids, vec![RoomId(0), RoomId(1), RoomId(2)]);
+
+        d.names.insert(ids[2], "The Far Room".to_string());
+        assert_eq!(d.merge_rooms(ids[2], ids[1]), Ok(ids[1]));
+
+        assert_eq!(d.room_ids().collect::<Vec<_>>(), vec![ids[0], ids[1]]);
+        assert_eq!(d.room(ids[2]), None);
+        assert_eq!(d.room(ids[0]).unwrap().area, 6);
+        let merged = d.room(ids[1]).unwrap();
+        assert_eq!(merged.area, 14);
+        assert_eq!(merged.name.as_deref(), Some("The Far Room"));
+        assert_eq!(d.graph().room_at(Point::new(10, 1)), Some(ids[1]));
+    }
+
+    #[test]
+    fn test_generated_room_ids_match_the_graph() {
+        let mut d = Dungeon::new(60, 40);
+        let (_, trace) = d.generate_debug(40, 9);
+        let graph = d.graph();
+
+        let ids: Vec<RoomId> = d.room_ids().collect();
+        assert_eq!(ids, graph.room_ids());
+        for &id in &ids {
+            assert_eq!(d.room(id).unwrap().area, graph.room_tiles(id).len());
+        }
+
+        let replayed = Dungeon::from_trace(&trace).unwrap();
+        assert_eq!(replayed.room_ids().collect::<Vec<_>>(), ids);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_room_ids_round_trip() {
+        use serde_json;
+
+        let d = fixture();
+        let info = d.room(RoomId(2)).unwrap();
+        let back: RoomInfo = serde_json::from_str(&serde_json::to_string(info).unwrap()).unwrap();
+        assert_eq!(&back, info);
+        assert_eq!(back.id, RoomId(2));
+    }
+}
@@ -0,0 +1,333 @@
+/*
+Arenas for four players, each quarter of the map a quarter turn of the one before. A square
+central chamber is carved first and kept clear of everything else. One quadrant, the top
+left, is then laid out as generate_seeded would inside it, growing from a first room in the
+middle of the square above and left of the chamber. It's joined to the chamber through a
+door in the chamber's top wall, and given its entrance. The quadrant is turned a quarter
+clockwise about the middle of the map three times over for the other three, so every room,
+corridor and door has its counterparts, and the four entrances are where the players start,
+each as far from the chamber as the others.
+
+Turning a quarter clockwise takes (x, y) to (n - 1 - y, x) on an n by n map. The chamber has
+the same parity as the map, so it turns onto itself. On a map with an odd side the middle
+row and column lie in no quadrant, and only the chamber is on them. The exit, if the config
+asks for one, goes in the middle of the chamber, the one part of the map left out of the
+symmetry. Broken and barred doors are rolled, and the validation level's repairs made,
+before the turn, while the quadrant and the chamber are all there is, so they turn with
+the rest and the finished arena is as symmetric as the quadrant was.
+*/
+
+use doors::DoorCondition;
+use dungeon::{ Dungeon, GenerationReport, Tile };
+use error::DungeonError;
+use geom::{ Point, Rect };
+use graph::RoomId;
+use rng::{ self, DungenRng };
+
+// the chamber's side is a room's longer one, at least this, less one on a map with an even
+// side, so that a door in the top wall has a tile either side of it in the quadrant
+const MIN_CHAMBER: isize = 5;
+// and at most this share of the map's
+const CHAMBER_SHARE: isize = 3;
+
+impl Dungeon {
+    // lays out a four-way rotational arena over a blank square map, maxfeatures in each
+    // quadrant. fails with DungeonError::NotSquare on any other map. the report counts the
+    // features of all four quadrants and the chamber.
+    pub fn generate_rotational_seeded(&mut self, maxfeatures: isize, seed: u64) -> Result<GenerationReport, DungeonError> {
+        let size = self.size();
+        if size.width != size.height {
+            return Err(DungeonError::NotSquare { width: size.width, height: size.height })
+        }
+        if maxfeatures < 1 {
+            return Err(DungeonError::InvalidMaxFeatures(maxfeatures))
+        }
+
+        let n = size.width;
+        let half = n / 2;
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "rotational"));
+        let mut streams = rng::Streams::new(seed);
+
+        // the chamber first, so the quadrant grows around it. it's held out of the rooms,
+        // so that growth doesn't open it anywhere but the quadrant's door.
+        let (w, h) = self.roll_room_size(&mut rng);
+        let mut side = w.max(h).clamp(MIN_CHAMBER, (n / CHAMBER_SHARE).max(MIN_CHAMBER));
+        if (n - side) % 2 != 0 {
+            side -= 1;
+        }
+        let corner = (n - side) / 2;
+        let chamber = Rect::new(corner, corner, side, side);
+        self.carve_rect(chamber, Tile::Floor).map_err(|_| DungeonError::FirstRoom)?;
+        let chamber_id = self.graph().room_at(chamber.origin());
+        let held = self.rooms.remove(0);
+
+        // the quadrant's rooms and corridors keep their walls inside it. its first room is
+        // the middle of the square above and left of the chamber, shrunk to fit if need be.
+        let inset = self.config().border.max(0) + 1;
+        let span = corner - inset - 3;
+        if span < 1 {
+            return Err(DungeonError::FirstRoom)
+        }
+        let (w, h) = self.roll_room_size(&mut rng);
+        let (w, h) = (w.min(span), h.min(span));
+        let first = Rect::new(inset + 1 + (span - w) / 2, inset + 1 + (span - h) / 2, w, h);
+        self.bounds = Some(Rect::new(0, 0, half - 1, half - 1));
+        let features = self.generate_layout(maxfeatures, Some(first), &mut streams.layout);
+        self.bounds = None;
+        let features = features?;
+
+        // a door in the chamber's top wall, where it lies in the quadrant, and a tunnel from
+        // it to the nearest of the quadrant's rooms and corridors
+        let door = Point::new(rng.range_inclusive(corner, (corner + side - 1).min(half - 2)), corner - 1);
+        let start = door.offset(0, -1);
+        if !self.get(start).is_walkable() {
+            self.set_tile(start.x, start.y, Tile::Corridor);
+        }
+        let ring = Rect::new(corner - 1, corner - 1, side + 2, side + 2);
+        self.tunnel_within(&[start], Tile::Corridor, |p| {
+            p.x >= inset && p.y >= inset && p.x <= half - 2 && p.y <= half - 2 && !ring.contains(p)
+        });
+        self.write_door(door.x, door.y, Tile::Corridor, &mut rng);
+        self.wall_in();
+
+        // the quadrant's entrance
+        if self.config().entrance_room.is_some() {
+            self.place_entrance_in_first_room();
+        }
+
+        else {
+            self.place_object(Tile::Entrance, &mut streams.objects).map_err(DungeonError::NoEntrance)?;
+        }
+
+        // the exit, off the quadrant, and everything finish would do that isn't symmetric
+        // by itself
+        if self.config().exits > 0 {
+            self.set_object(chamber.center(), Tile::Exit);
+        }
+        self.sync_rooms();
+        self.damage_doors_from(seed);
+        self.enforce_validation(seed)?;
+
+        self.turn_quadrant(half, chamber_id);
+        if self.config().exits == 0 {
+            self.rooms.insert(0, held);
+        }
+        self.sync_rooms();
+
+        let report = self.finish_damaged(features * 4 + 1, seed);
+        // fuzzing with debug builds catches any quadrant that didn't turn true
+        debug_assert!(report.is_err() || self.is_rotationally_symmetric(chamber), "not symmetric:\n{}", self.render());
+        report
+    }
+
+    // generate_rotational_seeded, seeded from rng
+    pub fn generate_rotational(&mut self, maxfeatures: isize, rng: &mut DungenRng) -> Result<GenerationReport, DungeonError> {
+        let seed = rng.next_u64();
+        self.generate_rotational_seeded(maxfeatures, seed)
+    }
+
+    // whether every tile outside center, and every broken or barred door, is the same as a
+    // quarter turn clockwise from it. a map that isn't square never is.
+    pub fn is_rotationally_symmetric(&self, center: Rect) -> bool {
+        let size = self.size();
+        if size.width != size.height {
+            return false
+        }
+
+        let n = size.width;
+        (0..n).all(|y| (0..n).all(|x| {
+            let p = Point::new(x, y);
+            center.contains(p) || (self.get(p) == self.get(turn(p, n)) && self.door_states.get(&p) == self.door_states.get(&turn(p, n)))
+        }))
+    }
+
+    // copies the top-left quadrant, half tiles across, with its records and door states, onto
+    // the other three a quarter turn at a time. the chamber, whose id is given, turns onto itself and
+    // gets no copies.
+    fn turn_quadrant(&mut self, half: isize, chamber: Option<RoomId>) {
+        let n = self.size().width;
+        let quadrant = Rect::new(0, 0, half, half);
+        let tiles: Vec<(Point, Tile)> = quadrant.points().map(|p| (p, self.get(p))).collect();
+        let states: Vec<(Point, DoorCondition)> = self.door_states.iter().filter(|&(&p, _)| quadrant.contains(p)).map(|(&p, &s)| (p, s)).collect();
+        let anchors: Vec<(RoomId, Point)> = self.room_anchors().into_iter().filter(|&(id, _)| Some(id) != chamber).collect();
+        let rooms = self.rooms.clone();
+        let corridors = self.corridors.clone();
+
+        for turns in 1..4 {
+            let moved = |p: Point| (0..turns).fold(p, |p, _| turn(p, n));
+            for &(p, tile) in &tiles {
+                let to = moved(p);
+                if tile == Tile::Entrance {
+                    self.set_object(to, tile);
+                }
+
+                else {
+                    self.set_tile(to.x, to.y, tile);
+                }
+            }
+            for &(p, state) in &states {
+                self.set_door_state(moved(p), state);
+            }
+
+            for &(id, anchor) in &anchors {
+                let new = self.register_room_at(moved(anchor));
+                if let Some(&purpose) = self.purposes.get(&id) {
+                    self.purposes.insert(new, purpose);
+                }
+            }
+            self.rooms.extend(rooms.iter().map(|&r| (0..turns).fold(r, |r, _| turn_rect(r, n))));
+            for corridor in &corridors {
+                if corridor.path.is_empty() {
+                    self.record_corridor((0..turns).fold(corridor.rect, |r, _| turn_rect(r, n)));
+                }
+
+                else {
+                    let path: Vec<Point> = corridor.path.iter().map(|&p| moved(p)).collect();
+                    self.record_path(&path);
+                }
+            }
+        }
+    }
+}
+
+// p a quarter turn clockwise about the middle of an n by n map
+fn turn(p: Point, n: isize) -> Point {
+    Point::new(n - 1 - p.y, p.x)
+}
+
+fn turn_rect(r: Rect, n: isize) -> Rect {
+    Rect::new(n - r.y - r.height, r.x, r.height, r.width)
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, Preset, Validation };
+    use doors::{ Door, DoorCondition };
+    use dungeon::{ Dungeon, Tile };
+    use error::DungeonError;
+    use geom::{ Point, Rect };
+    use nav::Connectivity;
+    use rng::DungenRng;
+    use rotational::*;
+    use testing::check_invariants;
+
+    fn strict() -> DungeonConfig {
+        DungeonConfig { validation: Validation::Strict, ..DungeonConfig::default() }
+    }
+
+    // the chamber, the room in the middle of the map
+    fn chamber(d: &Dungeon) -> Rect {
+        let graph = d.graph();
+        let middle = Point::new(d.size().width / 2, d.size().height / 2);
+        let tiles = graph.room_tiles(graph.room_at(middle).unwrap());
+        let (x, y) = (tiles[0].x, tiles[0].y);
+        let last = tiles[tiles.len() - 1];
+        Rect::new(x, y, last.x - x + 1, last.y - y + 1)
+    }
+
+    #[test]
+    fn test_turns() {
+        assert_eq!(turn(Point::new(0, 0), 5), Point::new(4, 0));
+        assert_eq!(turn(Point::new(4, 0), 5), Point::new(4, 4));
+        assert_eq!(turn(Point::new(1, 3), 6), Point::new(2, 1));
+        assert_eq!(turn(Point::new(2, 2), 5), Point::new(2, 2));
+        let r = Rect::new(1, 2, 4, 3);
+        assert_eq!(turn_rect(r, 10), Rect::new(5, 1, 3, 4));
+        assert!(r.points().all(|p| turn_rect(r, 10).contains(turn(p, 10))));
+    }
+
+    #[test]
+    fn test_rotational_arenas() {
+        for &(n, seed) in &[(61, 1), (60, 2), (71, 3), (64, 4), (45, 5), (80, 6)] {
+            let mut d = Dungeon::with_config(n, n, strict());
+            let report = d.generate_rotational_seeded(12, seed).unwrap();
+            assert!(check_invariants(&d).is_empty(), "{} {}: {:?}", n, seed, check_invariants(&d));
+            assert_eq!(report.features % 4, 1);
+
+            // every tile but the chamber's floor has its three counterparts
+            let middle = chamber(&d);
+            assert!(d.is_rotationally_symmetric(middle), "{} {}\n{}", n, seed, d.render());
+            assert_eq!(middle.width, middle.height);
+            assert_eq!((n - middle.width) % 2, 0);
+            assert_eq!(turn_rect(middle, n), middle);
+            assert_eq!(d.exit(), Some(middle.center()));
+
+            // four entrances, each a turn of the last, with a door into the chamber for
+            // each quadrant
+            let entrances = d.entrances().to_vec();
+            assert_eq!(entrances.len(), 4);
+            for i in 0..4 {
+                assert_eq!(turn(entrances[i], n), entrances[(i + 1) % 4]);
+            }
+
+            // an odd map's middle row is the chamber's alone, and its exit is the same walk
+            // from every entrance
+            if n % 2 == 1 {
+                assert_eq!(middle.center(), Point::new(n / 2, n / 2));
+                assert!((0..n).all(|i| middle.contains(Point::new(i, n / 2)) || !d.get((i, n / 2)).is_walkable()));
+                let exit = d.exit().unwrap();
+                let lengths: Vec<usize> = entrances.iter().map(|&e| d.find_path(e, exit, Connectivity::Four).unwrap().len()).collect();
+                assert!(lengths.iter().all(|&l| l == lengths[0]), "{:?}", lengths);
+            }
+            let degree = d.graph().room_degree(d.graph().room_at(middle.origin()).unwrap());
+            assert!(degree >= 4 && degree.is_multiple_of(4), "{}", degree);
+            assert_eq!(d.regions(Connectivity::Four).len(), 1);
+
+            // the same seed, the same arena
+            let mut again = Dungeon::with_config(n, n, strict());
+            again.generate_rotational(12, &mut DungenRng::new(seed)).unwrap();
+            let mut same = Dungeon::with_config(n, n, strict());
+            same.generate_rotational_seeded(12, DungenRng::new(seed).next_u64()).unwrap();
+            assert_eq!(again.render(), same.render());
+        }
+    }
+
+    #[test]
+    fn test_rotational_presets() {
+        for preset in Preset::ALL.iter().cloned() {
+            for seed in 0..4 {
+                let config = DungeonConfig { validation: Validation::Strict, ..DungeonConfig::preset(preset) };
+                let mut d = Dungeon::with_config(71, 71, config);
+                d.generate_rotational_seeded(15, seed).unwrap();
+                assert!(d.is_rotationally_symmetric(chamber(&d)), "{:?} {}\n{}", preset, seed, d.render());
+                assert_eq!(d.entrances().len(), 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotational_damaged_doors() {
+        // broken and barred doors, and the validation level's repairs, turn with the rest
+        let config = DungeonConfig { broken_doors: 0.3, barred_doors: 0.3, ..strict() };
+        for &(n, seed) in &[(61, 1), (60, 2), (71, 3), (64, 4)] {
+            let mut d = Dungeon::with_config(n, n, config.clone());
+            d.generate_rotational_seeded(12, seed).unwrap();
+            assert!(d.is_rotationally_symmetric(chamber(&d)), "{} {}\n{}", n, seed, d.render());
+
+            let damaged: Vec<Door> = d.door_details().into_iter().filter(|door| door.state.is_kept()).collect();
+            assert!(damaged.iter().any(|door| door.state == DoorCondition::Broken), "{} {}", n, seed);
+            assert!(damaged.iter().any(|door| door.state == DoorCondition::Barred), "{} {}", n, seed);
+            for door in &damaged {
+                assert_eq!(d.door(turn(door.pos, n)).map(|turned| turned.state), Some(door.state));
+            }
+        }
+
+        // a door out of step with its counterparts is caught
+        let mut d = Dungeon::with_config(61, 61, config);
+        d.generate_rotational_seeded(12, 1).unwrap();
+        let door = d.door_details().into_iter().find(|door| !door.state.is_kept() && !chamber(&d).contains(door.pos)).unwrap();
+        d.set_door_state(door.pos, DoorCondition::Barred);
+        assert!(!d.is_rotationally_symmetric(chamber(&d)));
+    }
+
+    #[test]
+    fn test_rotational_errors() {
+        let mut d = Dungeon::new(60, 40);
+        let err = d.generate_rotational_seeded(10, 1).unwrap_err();
+        assert_eq!(err, DungeonError::NotSquare { width: 60, height: 40 });
+        assert_eq!(err.to_string(), "a 60x40 map can't be turned a quarter onto itself");
+        assert!(!d.is_rotationally_symmetric(Rect::new(0, 0, 60, 40)));
+        assert_eq!(d.get((30, 20)), Tile::Unused);
+        assert_eq!(Dungeon::new(50, 50).generate_rotational_seeded(0, 1).err(), Some(DungeonError::InvalidMaxFeatures(0)));
+    }
+}
@@ -0,0 +1,269 @@
+/*
+One secret area a level: a small room whose only way in is a secret door, with a chest
+inside, off the main path. add_secret_area first looks for a dead-end room that would do,
+between 3x3 and 5x5, off the main path and with nothing else in it, whose one door it turns
+secret. Failing that it carves a room of that size into rock behind a straight stretch of
+some room's or corridor's wall and punches the secret door through.
+
+Either way the rest of the map is joined up as it was without the secret door, so a
+player who never finds it misses nothing but the room. The room is tagged
+RoomPurpose::Secret, which is how the map keeps track of it, and the chest is added to
+chests(), in the highest tier already on the map. place_chests replaces every chest, this
+one included, so chests go down before the secret area rather than after.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use error::SecretAreaError;
+use geom::{ Point, Rect };
+use graph::RoomId;
+use prefab::shuffle;
+use rng::DungenRng;
+use rooms::RoomPurpose;
+use spawn::ChestPlacement;
+
+// the smallest and largest side a secret room has
+const SMALLEST: isize = 3;
+const LARGEST: isize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SecretArea {
+    pub room: RoomId,
+    pub door: Point,
+    pub chest: Point,
+}
+
+impl Dungeon {
+    // gives the map its secret area, see the module comment. the map is left as it was if
+    // it already has one, has no entrance, or has neither a room nor space to spare.
+    pub fn add_secret_area(&mut self, rng: &mut DungenRng) -> Result<SecretArea, SecretAreaError> {
+        if let Some(&room) = self.purposes.iter().find(|&(_, &p)| p == RoomPurpose::Secret).map(|(id, _)| id) {
+            return Err(SecretAreaError::AlreadyPresent(room))
+        }
+        if self.entrance().is_none() {
+            return Err(SecretAreaError::NoEntrance)
+        }
+
+        // everything is worked out before anything is written, so a map without space for
+        // the area is left as it was
+        let (room, door, carve, tiles) = match self.hide_dead_end(rng) {
+            Some((room, door)) => (room, door, None, self.graph().room_tiles(room).to_vec()),
+            None => {
+                let (rect, door) = self.find_secret_room(rng).ok_or(SecretAreaError::NoSpace)?;
+                // carve_rect hands out the next id
+                let room = RoomId(self.room_slots.len() as u32);
+                (room, door, Some(rect), rect.points().map(|p| self.wrap_point(p)).collect())
+            }
+        };
+        let placed = self.chests.iter().find(|chest| chest.room == room).map(|chest| chest.pos);
+        let chest = match placed {
+            Some(pos) => pos,
+            None => {
+                let n = tiles.len() as isize;
+                if n == 0 {
+                    return Err(SecretAreaError::NoSpace)
+                }
+                let mid = Point::new(tiles.iter().map(|p| p.x).sum::<isize>() / n, tiles.iter().map(|p| p.y).sum::<isize>() / n);
+                // a carved room is all floor once carve_rect has been
+                tiles.iter()
+                    .cloned()
+                    .filter(|&p| carve.is_some() || self.get(p) == Tile::Floor)
+                    .min_by_key(|p| ((p.x - mid.x).abs() + (p.y - mid.y).abs(), p.y, p.x))
+                    .ok_or(SecretAreaError::NoSpace)?
+            }
+        };
+
+        if let Some(rect) = carve {
+            self.carve_rect(rect, Tile::Floor).map_err(|_| SecretAreaError::NoSpace)?;
+        }
+        self.set_tile(door.x, door.y, Tile::SecretDoor);
+        self.purposes.insert(room, RoomPurpose::Secret);
+        self.sync_rooms();
+        if placed.is_none() {
+            let tier = self.chests.iter().map(|chest| chest.tier).max().unwrap_or(0);
+            self.chests.push(ChestPlacement { pos: chest, tier, room });
+        }
+
+        Ok(SecretArea { room, door, chest })
+    }
+
+    // the secret area add_secret_area made. none if there isn't one, or if its door or
+    // chest has gone since.
+    pub fn secret_area(&self) -> Option<SecretArea> {
+        let room = self.purposes.iter().find(|&(_, &p)| p == RoomPurpose::Secret).map(|(&id, _)| id)?;
+        let graph = self.graph();
+        let door = graph.room_tiles(room).iter()
+            .flat_map(|&p| Dir::iterator().map(move |dir| {
+                let (dx, dy) = dir.offset();
+                p.offset(dx, dy)
+            }))
+            .map(|p| self.wrap_point(p))
+            .find(|&p| self.get(p) == Tile::SecretDoor)?;
+        let chest = self.chests.iter().find(|chest| chest.room == room)?.pos;
+        Some(SecretArea { room, door, chest })
+    }
+
+    // a dead-end room off the main path, of a secret room's size and with nothing in it or
+    // planned for it, whose one way in is a door or archway in a straight wall. returns the
+    // room and that door.
+    fn hide_dead_end(&self, rng: &mut DungenRng) -> Option<(RoomId, Point)> {
+        let graph = self.graph();
+        let taken: Vec<RoomId> = self.entrance().into_iter().chain(self.exit())
+            .filter_map(|p| graph.room_at(p))
+            .chain(self.boss_room)
+            .chain(self.safe_room)
+            .collect();
+        let mut rooms: Vec<RoomId> = self.off_path_rooms().into_iter()
+            .filter(|id| !taken.contains(id) && !self.purposes.contains_key(id) && graph.room_degree(*id) == 1)
+            .collect();
+        shuffle(&mut rooms, rng);
+
+        rooms.into_iter().find_map(|id| {
+            let tiles = graph.room_tiles(id);
+            let width = tiles.iter().map(|p| p.x).max()? - tiles.iter().map(|p| p.x).min()? + 1;
+            let height = tiles.iter().map(|p| p.y).max()? - tiles.iter().map(|p| p.y).min()? + 1;
+            let fits = |side| (SMALLEST..=LARGEST).contains(&side);
+            if !fits(width) || !fits(height) || tiles.iter().any(|&p| self.get(p) != Tile::Floor) {
+                return None
+            }
+
+            let node = graph.room_node(id)?;
+            let door = graph.edges.iter().find(|e| e.a == node || e.b == node)?.via;
+            let doorway = self.get(door).is_door() || self.get(door) == Tile::Archway;
+            if doorway && self.door_fits(door) && !self.touches_door(door) { Some((id, door)) } else { None }
+        })
+    }
+
+    // space for a new room behind a wall of some room or corridor, in rock with nothing
+    // walkable round it, and the tile of that wall the door goes in. nothing is written.
+    fn find_secret_room(&self, rng: &mut DungenRng) -> Option<(Rect, Point)> {
+        let size = self.size();
+        let mut walls = Vec::new();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) != Tile::Wall {
+                    continue;
+                }
+
+                for dir in Dir::iterator() {
+                    let (dx, dy) = dir.offset();
+                    let behind = matches!(self.get(p.offset(-dx, -dy)), Tile::Floor | Tile::Corridor);
+                    let straight = self.get(p.offset(dy, dx)) == Tile::Wall && self.get(p.offset(-dy, -dx)) == Tile::Wall;
+                    if behind && straight {
+                        walls.push((p, *dir));
+                    }
+                }
+            }
+        }
+        shuffle(&mut walls, rng);
+
+        let rolled = (rng.range_inclusive(SMALLEST, LARGEST), rng.range_inclusive(SMALLEST, LARGEST));
+        for (p, dir) in walls {
+            for &(width, height) in &[rolled, (SMALLEST, SMALLEST)] {
+                let rect = Dungeon::room_rect(p.x, p.y, &dir, width, height);
+                if self.ring_is_rock(&rect) && self.check_rect(&rect).is_ok() {
+                    return Some((rect, p))
+                }
+            }
+        }
+        None
+    }
+
+    // whether the walls carving rect would put round it only cover wall and rock
+    fn ring_is_rock(&self, rect: &Rect) -> bool {
+        (rect.y - 1..=rect.y + rect.height).all(|y| (rect.x - 1..=rect.x + rect.width).all(|x| {
+            let inside = x >= rect.x && y >= rect.y && x < rect.x + rect.width && y < rect.y + rect.height;
+            inside || matches!(self.get((x, y)), Tile::Wall | Tile::Unused)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use error::SecretAreaError;
+    use geom::Point;
+    use nav::Connectivity;
+    use rng::DungenRng;
+    use rooms::RoomPurpose;
+    use secret::*;
+    use testing::check_invariants;
+
+    // every walkable tile the entrance reaches
+    fn reached(d: &Dungeon) -> Vec<Point> {
+        let size = d.size();
+        let dist = d.distance_map(d.entrance().unwrap(), Connectivity::Four);
+        (0..size.height).flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|p| dist[(p.x + p.y * size.width) as usize].is_some())
+            .collect()
+    }
+
+    #[test]
+    fn test_add_secret_area() {
+        for seed in 0..12 {
+            let mut d = Dungeon::new(70, 45);
+            d.generate_seeded(40, seed).unwrap();
+            let area = d.add_secret_area(&mut DungenRng::new(seed)).unwrap();
+            assert!(check_invariants(&d).is_empty(), "seed {}: {:?}", seed, check_invariants(&d));
+            assert_eq!(d.get(area.door), Tile::SecretDoor);
+            assert_eq!(d.secret_area(), Some(area));
+            assert_eq!(d.room(area.room).unwrap().purpose, Some(RoomPurpose::Secret));
+            assert!(d.chests().iter().any(|chest| chest.room == area.room && chest.pos == area.chest));
+            assert!(!d.main_path().unwrap().rooms.contains(&area.room));
+
+            let graph = d.graph();
+            let tiles = graph.room_tiles(area.room).to_vec();
+            let (xs, ys) = (tiles.iter().map(|p| p.x), tiles.iter().map(|p| p.y));
+            let width = xs.clone().max().unwrap() - xs.min().unwrap() + 1;
+            let height = ys.clone().max().unwrap() - ys.min().unwrap() + 1;
+            assert!((3..=5).contains(&width) && (3..=5).contains(&height), "seed {}: {}x{}", seed, width, height);
+
+            // without the door the secret room can't be reached at all, and everything else
+            // still can
+            let before = reached(&d);
+            d.set_tile(area.door.x, area.door.y, Tile::Wall);
+            let after = reached(&d);
+            assert!(tiles.iter().all(|p| before.contains(p) && !after.contains(p)), "seed {}", seed);
+            let rest: Vec<Point> = before.into_iter().filter(|p| *p != area.door && !tiles.contains(p)).collect();
+            assert_eq!(after, rest, "seed {}", seed);
+
+            // and there's only ever the one
+            d.set_tile(area.door.x, area.door.y, Tile::SecretDoor);
+            let rendered = d.render();
+            assert_eq!(d.add_secret_area(&mut DungenRng::new(seed)), Err(SecretAreaError::AlreadyPresent(area.room)));
+            assert_eq!(d.render(), rendered);
+            assert_eq!(d.room_ids().filter(|&id| d.room(id).unwrap().purpose == Some(RoomPurpose::Secret)).count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_secret_dead_end() {
+        let mut d = Dungeon::parse(&[
+            "###########",
+            "#<.......>#",
+            "#.........#",
+            "####-######",
+            "   #...#   ",
+            "   #...#   ",
+            "   #...#   ",
+            "   #####   ",
+        ]);
+        let area = d.add_secret_area(&mut DungenRng::new(1)).unwrap();
+        assert_eq!(area, SecretArea { room: area.room, door: Point::new(4, 3), chest: Point::new(5, 5) });
+        assert_eq!(d.get((4, 3)), Tile::SecretDoor);
+        assert_eq!(d.chests().len(), 1);
+
+        // no room to take and no rock to carve one from, or no entrance to measure from
+        let mut d = Dungeon::parse(&["#####", "#<.>#", "#####"]);
+        let rendered = d.render();
+        assert_eq!(d.add_secret_area(&mut DungenRng::new(1)), Err(SecretAreaError::NoSpace));
+        assert_eq!(d.secret_area(), None);
+        assert_eq!(d.render(), rendered);
+        assert_eq!(d.room_ids().count(), 1);
+        let mut d = Dungeon::parse(&["#####", "#...#", "#####"]);
+        assert_eq!(d.add_secret_area(&mut DungenRng::new(1)), Err(SecretAreaError::NoEntrance));
+    }
+}
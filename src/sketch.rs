@@ -0,0 +1,540 @@
+/*
+Levels realised from a coarse sketch, for designers who want to say roughly what goes where
+and leave the details to the seed. A sketch is a small grid of cells, each empty, a room, a
+corridor junction or the boss room, with links between neighbouring cells. The map inside
+its border is cut into as many equal areas as the sketch has cells, and each cell is
+realised in its own:
+
+  Room       a room of the config's room size, shrunk where it must be to leave two tiles
+             of its area around it, placed at random in the area
+  Boss       a room as for Room but as large as the config's room size goes, which
+             becomes the boss room
+  Corridor   a corridor tile in the middle of the area, where its links meet
+  Empty      nothing
+
+Each link becomes a corridor from a door in the side of one room facing the other, or from
+a junction, to the other end, in three straight legs turning at a random row or column
+between them. Doors are written as generation writes them, so door_chance, door_state and
+archway_tile hold. The boss cell follows designate_boss_room's rules: there's at most one,
+it has exactly one link, so one way in, and it's large and far from the entrance. The
+first entrance goes in the middle of the room the most hops from it, which is tagged Start,
+so the boss cell's link has to lead to a room, directly or through junctions. The other
+entrances and the exits go in the other rooms as generate_seeded places them, never in the
+boss room.
+
+Written out, cells stand on even columns and rows with the links between them:
+
+  R-R-C-R
+  |   | |
+  R . R B
+
+R, C and B are rooms, junctions and the boss room, '.' or a space an empty cell, '-' and '|'
+links, and a space between two cells no link.
+
+Once realised, the two ends of every link are checked to be connected before the level goes
+through the config's validation like any other.
+*/
+
+use std::cmp::Reverse;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dungeon, GenerationReport, Tile };
+use error::{ DungeonError, SketchError };
+use geom::{ Point, Rect, Size };
+use nav::Connectivity;
+use rng::{ self, DungenRng };
+
+// the smallest area a cell is realised in: a room one tile across, its walls, and a tile
+// either side for the corridors around it
+const MIN_CELL: isize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SketchCell {
+    #[default]
+    Empty,
+    Room,
+    Corridor,
+    Boss,
+}
+
+impl SketchCell {
+    fn from_glyph(c: char) -> Option<SketchCell> {
+        match c {
+            '.' | ' ' => Some(SketchCell::Empty),
+            'R' => Some(SketchCell::Room),
+            'C' => Some(SketchCell::Corridor),
+            'B' => Some(SketchCell::Boss),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sketch {
+    size: Size,
+    cells: Vec<SketchCell>,
+    // each link once, the cell to the north or west first, in row-major order of that cell
+    // and then east before south
+    links: Vec<(Point, Point)>,
+}
+
+// where a cell was realised
+#[derive(Copy, Clone)]
+enum Realised {
+    Nothing,
+    Room(Rect),
+    Junction(Point),
+}
+
+impl Sketch {
+    // columns by rows empty cells, with no links
+    pub fn new(columns: isize, rows: isize) -> Sketch {
+        let size = Size::new(columns.max(0), rows.max(0));
+        Sketch { size, cells: vec![SketchCell::Empty; size.area() as usize], links: Vec::new() }
+    }
+
+    // reads a sketch written out as in the module comment. short rows are empty past their
+    // end. fails on anything Sketch::check refuses.
+    pub fn from_rows(rows: &[&str]) -> Result<Sketch, SketchError> {
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as isize;
+        if width == 0 {
+            return Err(SketchError::Empty)
+        }
+
+        let mut sketch = Sketch::new((width + 1) / 2, (rows.len() as isize + 1) / 2);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let (x, y) = (x as isize, y as isize);
+                let cell = Point::new(x / 2, y / 2);
+                let next = match (x % 2, y % 2, c) {
+                    (0, 0, _) => {
+                        let kind = SketchCell::from_glyph(c).ok_or(SketchError::UnknownGlyph { glyph: c, at: Point::new(x, y) })?;
+                        sketch.set(cell, kind);
+                        continue;
+                    }
+                    (_, _, ' ') => continue,
+                    (1, 0, '-') => cell.offset(1, 0),
+                    (0, 1, '|') => cell.offset(0, 1),
+                    _ => return Err(SketchError::UnknownGlyph { glyph: c, at: Point::new(x, y) }),
+                };
+                if !sketch.link(cell, next) {
+                    return Err(SketchError::BadLink { a: cell, b: next })
+                }
+            }
+        }
+
+        sketch.check()?;
+        Ok(sketch)
+    }
+
+    // columns and rows of cells
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    // the cell at column and row cell, empty off the grid
+    pub fn get(&self, cell: Point) -> SketchCell {
+        if self.contains(cell) { self.cells[self.index(cell)] } else { SketchCell::Empty }
+    }
+
+    // does nothing off the grid
+    pub fn set(&mut self, cell: Point, kind: SketchCell) {
+        if self.contains(cell) {
+            let i = self.index(cell);
+            self.cells[i] = kind;
+        }
+    }
+
+    // links two cells side by side on the grid, either way round. false, linking nothing,
+    // for any other pair. a link between cells left empty is refused by check.
+    pub fn link(&mut self, a: Point, b: Point) -> bool {
+        let (a, b) = if (b.y, b.x) < (a.y, a.x) { (b, a) } else { (a, b) };
+        if !self.contains(a) || !self.contains(b) || !matches!((b.x - a.x, b.y - a.y), (1, 0) | (0, 1)) {
+            return false
+        }
+
+        let key = |&(a, b): &(Point, Point)| (a.y, a.x, b.y, b.x);
+        if let Err(i) = self.links.binary_search_by_key(&key(&(a, b)), key) {
+            self.links.insert(i, (a, b));
+        }
+        true
+    }
+
+    pub fn links(&self) -> &[(Point, Point)] {
+        &self.links
+    }
+
+    // what keeps the sketch from being realised on any map: a link to an empty cell, a
+    // second boss cell, or a boss cell without exactly one link or with no room at the far
+    // end of it
+    pub fn check(&self) -> Result<(), SketchError> {
+        if let Some(&(a, b)) = self.links.iter().find(|&&(a, b)| self.get(a) == SketchCell::Empty || self.get(b) == SketchCell::Empty) {
+            return Err(SketchError::BadLink { a, b })
+        }
+
+        let mut bosses = self.cells().filter(|&cell| self.get(cell) == SketchCell::Boss);
+        if let Some(cell) = bosses.next() {
+            if let Some(second) = bosses.next() {
+                return Err(SketchError::SecondBoss { cell: second })
+            }
+
+            let links = self.links.iter().filter(|&&(a, b)| a == cell || b == cell).count();
+            if links != 1 {
+                return Err(SketchError::BossLinks { cell, links })
+            }
+
+            // out along the link and on through any junctions, for a room
+            let mut seen = vec![cell];
+            let mut next = vec![cell];
+            while let Some(at) = next.pop() {
+                for &(a, b) in &self.links {
+                    let other = if a == at { b } else if b == at { a } else { continue };
+                    if seen.contains(&other) {
+                        continue;
+                    }
+                    seen.push(other);
+                    match self.get(other) {
+                        SketchCell::Room => return Ok(()),
+                        SketchCell::Corridor => next.push(other),
+                        _ => {}
+                    }
+                }
+            }
+            return Err(SketchError::BossCutOff { cell })
+        }
+        Ok(())
+    }
+
+    // every cell's position, row-major
+    fn cells(&self) -> impl Iterator<Item = Point> {
+        let size = self.size;
+        (0..size.height).flat_map(move |y| (0..size.width).map(move |x| Point::new(x, y)))
+    }
+
+    fn contains(&self, cell: Point) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.size.width && cell.y < self.size.height
+    }
+
+    fn index(&self, cell: Point) -> usize {
+        (cell.x + cell.y * self.size.width) as usize
+    }
+}
+
+impl Dungeon {
+    // lays a sketch out over a blank map, see the module comment. the same sketch and seed
+    // give the same level.
+    pub fn generate_from_sketch_seeded(&mut self, sketch: &Sketch, seed: u64) -> Result<GenerationReport, DungeonError> {
+        sketch.check().map_err(DungeonError::Sketch)?;
+        let cells = sketch.size();
+        if cells.area() == 0 {
+            return Err(DungeonError::Sketch(SketchError::Empty))
+        }
+
+        let border = self.config().border.max(0);
+        let size = self.size();
+        let (width, height) = ((size.width - 2 * border) / cells.width, (size.height - 2 * border) / cells.height);
+        if width < MIN_CELL || height < MIN_CELL {
+            return Err(DungeonError::Sketch(SketchError::CellTooSmall { width, height }))
+        }
+
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "sketch"));
+        let mut realised = Vec::new();
+        let mut boss = None;
+        for cell in sketch.cells() {
+            let area = Rect::new(border + cell.x * width, border + cell.y * height, width, height);
+            let kind = sketch.get(cell);
+            realised.push(match kind {
+                SketchCell::Empty => Realised::Nothing,
+                SketchCell::Corridor => {
+                    let p = area.center();
+                    self.set_tile(p.x, p.y, Tile::Corridor);
+                    self.record_path(&[p]);
+                    Realised::Junction(p)
+                }
+                SketchCell::Room | SketchCell::Boss => {
+                    let (w, h) = if kind == SketchCell::Boss {
+                        let max = self.config().room_size.max_size();
+                        (max, max)
+                    }
+
+                    else {
+                        self.roll_room_size(&mut rng)
+                    };
+                    let (w, h) = (w.clamp(1, width - 4), h.clamp(1, height - 4));
+                    let x = rng.range_inclusive(area.x + 2, area.x + width - 2 - w);
+                    let y = rng.range_inclusive(area.y + 2, area.y + height - 2 - h);
+                    let room = Rect::new(x, y, w, h);
+                    self.carve_rect(room, Tile::Floor).map_err(|error| DungeonError::Sketch(SketchError::Blocked { cell, error }))?;
+                    if kind == SketchCell::Boss {
+                        boss = Some(room);
+                    }
+                    Realised::Room(room)
+                }
+            });
+        }
+
+        let at = |cell: Point| realised[(cell.x + cell.y * cells.width) as usize];
+        for &(a, b) in sketch.links() {
+            self.carve_link(at(a), at(b), b.x > a.x, &mut rng);
+        }
+        self.wall_in();
+        self.sync_rooms();
+
+        // every link's ends in one walkable region
+        let mut region = vec![usize::MAX; size.area() as usize];
+        for (i, tiles) in self.regions(Connectivity::Four).iter().enumerate() {
+            for &p in tiles {
+                region[self.index(p)] = i;
+            }
+        }
+        let anchor = |r: Realised| match r {
+            Realised::Room(rect) => rect.origin(),
+            Realised::Junction(p) => p,
+            Realised::Nothing => unreachable!("check refuses links to empty cells"),
+        };
+        if let Some(&(a, b)) = sketch.links().iter().find(|&&(a, b)| region[self.index(anchor(at(a)))] != region[self.index(anchor(at(b)))]) {
+            return Err(DungeonError::Sketch(SketchError::Disconnected { a, b }))
+        }
+
+        // the boss room is held out of the rooms while the entrances and exits are placed,
+        // the first entrance in the room furthest from it
+        let held = boss.and_then(|rect| self.rooms.iter().position(|&r| r == rect).map(|i| (i, self.rooms.remove(i))));
+        let start = held.and_then(|(_, rect)| self.furthest_room_from(rect));
+        let objects = self.place_objects_from(start, &mut DungenRng::new(rng::stream_seed(seed, "objects")));
+        if let Some((i, rect)) = held {
+            self.rooms.insert(i.min(self.rooms.len()), rect);
+            self.boss_room = self.graph().room_at(rect.origin());
+        }
+        self.sync_rooms();
+        objects?;
+
+        let features = realised.iter().filter(|r| !matches!(r, Realised::Nothing)).count() + sketch.links().len();
+        self.finish(features as isize, seed)
+    }
+
+    // the index in rooms of the room the most hops from the one at rect, the largest of
+    // those, and then the first. none if no room can be walked to from it.
+    fn furthest_room_from(&self, rect: Rect) -> Option<usize> {
+        let graph = self.graph();
+        let hops = graph.hops_from(graph.room_at(rect.origin()).and_then(|id| graph.room_node(id))?);
+        self.rooms.iter().enumerate()
+            .filter_map(|(i, r)| {
+                let node = graph.room_at(r.origin()).and_then(|id| graph.room_node(id))?;
+                hops[node].map(|h| (h, r.width * r.height, Reverse(i)))
+            })
+            .max()
+            .map(|(_, _, Reverse(i))| i)
+    }
+
+    // generate_from_sketch_seeded with a seed drawn from rng
+    pub fn generate_from_sketch(&mut self, sketch: &Sketch, rng: &mut DungenRng) -> Result<GenerationReport, DungeonError> {
+        let seed = rng.next_u64();
+        self.generate_from_sketch_seeded(sketch, seed)
+    }
+
+    // the corridor between two realised cells, from a to b, b lying east of a or else
+    // south of it, with the doors of any rooms at its ends
+    fn carve_link(&mut self, a: Realised, b: Realised, east: bool, rng: &mut DungenRng) {
+        let (from, door_a) = link_end(a, east, true, rng);
+        let (to, door_b) = link_end(b, east, false, rng);
+
+        // out along the first leg to the turn, across, and on along the last
+        let (first, second) = if east {
+            let x = rng.range_inclusive(from.x, to.x);
+            (Point::new(x, from.y), Point::new(x, to.y))
+        }
+
+        else {
+            let y = rng.range_inclusive(from.y, to.y);
+            (Point::new(from.x, y), Point::new(to.x, y))
+        };
+        let mut path = Vec::new();
+        for &(start, end) in &[(from, first), (first, second), (second, to)] {
+            for p in line(start, end) {
+                if path.last() != Some(&p) && self.get(p) == Tile::Unused {
+                    self.set_tile(p.x, p.y, Tile::Corridor);
+                    path.push(p);
+                }
+            }
+        }
+        if !path.is_empty() {
+            self.record_path(&path);
+        }
+
+        for door in door_a.into_iter().chain(door_b) {
+            self.write_door(door.x, door.y, Tile::Corridor, rng);
+        }
+    }
+}
+
+// where a link's corridor starts at a realised cell, and the door it goes through if the
+// cell is a room. leaving says whether the corridor leaves the cell eastward or southward,
+// or arrives at it from the west or north.
+fn link_end(cell: Realised, east: bool, leaving: bool, rng: &mut DungenRng) -> (Point, Option<Point>) {
+    let room = match cell {
+        Realised::Room(room) => room,
+        Realised::Junction(p) => return (p, None),
+        Realised::Nothing => unreachable!("check refuses links to empty cells"),
+    };
+
+    let step = if leaving { 1 } else { -1 };
+    let door = if east {
+        let x = if leaving { room.x + room.width } else { room.x - 1 };
+        Point::new(x, rng.range_inclusive(room.y, room.y + room.height - 1))
+    }
+
+    else {
+        let y = if leaving { room.y + room.height } else { room.y - 1 };
+        Point::new(rng.range_inclusive(room.x, room.x + room.width - 1), y)
+    };
+    let start = if east { door.offset(step, 0) } else { door.offset(0, step) };
+    (start, Some(door))
+}
+
+// the tiles from a to b in a straight line along a row or a column, both ends included
+fn line(a: Point, b: Point) -> Vec<Point> {
+    let steps = (b.x - a.x).abs().max((b.y - a.y).abs());
+    let (dx, dy) = ((b.x - a.x).signum(), (b.y - a.y).signum());
+    (0..=steps).map(|i| a.offset(dx * i, dy * i)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, Validation };
+    use dungeon::{ Dungeon, Tile };
+    use error::{ DungeonError, SketchError };
+    use geom::Point;
+    use nav::Connectivity;
+    use rng::DungenRng;
+    use rooms::RoomPurpose;
+    use sketch::*;
+    use testing::check_invariants;
+
+    fn strict() -> DungeonConfig {
+        DungeonConfig { validation: Validation::Strict, ..DungeonConfig::default() }
+    }
+
+    fn level() -> Sketch {
+        Sketch::from_rows(&[
+            "R-R-C-R",
+            "|   | |",
+            "R . R B",
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_read_sketch() {
+        let sketch = level();
+        assert_eq!(sketch.size(), Size::new(4, 2));
+        assert_eq!(sketch.get(Point::new(2, 0)), SketchCell::Corridor);
+        assert_eq!(sketch.get(Point::new(1, 1)), SketchCell::Empty);
+        assert_eq!(sketch.get(Point::new(3, 1)), SketchCell::Boss);
+        assert_eq!(sketch.get(Point::new(9, 9)), SketchCell::Empty);
+        let links: Vec<(isize, isize, isize, isize)> = sketch.links().iter().map(|&(a, b)| (a.x, a.y, b.x, b.y)).collect();
+        assert_eq!(links, vec![(0, 0, 1, 0), (0, 0, 0, 1), (1, 0, 2, 0), (2, 0, 3, 0), (2, 0, 2, 1), (3, 0, 3, 1)]);
+
+        // built by hand, linking either way round, it's the same sketch
+        let mut built = Sketch::new(4, 2);
+        for &(x, y, kind) in &[(0, 0, SketchCell::Room), (1, 0, SketchCell::Room), (2, 0, SketchCell::Corridor), (3, 0, SketchCell::Room),
+                               (0, 1, SketchCell::Room), (2, 1, SketchCell::Room), (3, 1, SketchCell::Boss)] {
+            built.set(Point::new(x, y), kind);
+        }
+        for &(a, b) in sketch.links().iter().rev() {
+            assert!(built.link(b, a));
+        }
+        assert!(built.link(Point::new(0, 0), Point::new(1, 0)));
+        assert_eq!(built, sketch);
+        assert!(!built.link(Point::new(0, 0), Point::new(1, 1)));
+        assert!(!built.link(Point::new(3, 0), Point::new(4, 0)));
+        assert!(!built.link(Point::new(2, 0), Point::new(2, 0)));
+    }
+
+    #[test]
+    fn test_sketch_errors() {
+        assert_eq!(Sketch::from_rows(&[]), Err(SketchError::Empty));
+        assert_eq!(Sketch::from_rows(&["R-X"]), Err(SketchError::UnknownGlyph { glyph: 'X', at: Point::new(2, 0) }));
+        assert_eq!(Sketch::from_rows(&["R|R"]), Err(SketchError::UnknownGlyph { glyph: '|', at: Point::new(1, 0) }));
+        assert_eq!(Sketch::from_rows(&["R-"]), Err(SketchError::BadLink { a: Point::new(0, 0), b: Point::new(1, 0) }));
+        assert_eq!(Sketch::from_rows(&["R-.", "|", "R"]), Err(SketchError::BadLink { a: Point::new(0, 0), b: Point::new(1, 0) }));
+        assert_eq!(Sketch::from_rows(&["B-R-B"]), Err(SketchError::SecondBoss { cell: Point::new(2, 0) }));
+        assert_eq!(Sketch::from_rows(&["R-B-R"]), Err(SketchError::BossLinks { cell: Point::new(1, 0), links: 2 }));
+        assert_eq!(Sketch::from_rows(&["R B"]).unwrap_err().to_string(), "the boss room at cell (1, 0) needs exactly one link, it has 0");
+        assert_eq!(Sketch::from_rows(&["R C-C-B"]), Err(SketchError::BossCutOff { cell: Point::new(3, 0) }));
+        assert!(Sketch::from_rows(&["R-C-C-B"]).is_ok());
+
+        // too many cells for the map, or a map already in use
+        let mut d = Dungeon::new(18, 20);
+        let err = d.generate_from_sketch_seeded(&level(), 1).unwrap_err();
+        assert_eq!(err, DungeonError::Sketch(SketchError::CellTooSmall { width: 4, height: 9 }));
+        let mut d = Dungeon::new(60, 30);
+        for y in 0..15 {
+            for x in 0..15 {
+                d.set_tile(x, y, Tile::Floor);
+            }
+        }
+        assert!(matches!(d.generate_from_sketch_seeded(&level(), 1), Err(DungeonError::Sketch(SketchError::Blocked { cell, .. })) if cell == Point::new(0, 0)));
+    }
+
+    #[test]
+    fn test_realised_sketch() {
+        let sketch = level();
+        for seed in 0..8 {
+            let mut d = Dungeon::with_config(66, 38, strict());
+            let report = d.generate_from_sketch_seeded(&sketch, seed).unwrap();
+            assert!(check_invariants(&d).is_empty(), "seed {}: {:?}", seed, check_invariants(&d));
+            assert_eq!(report.features, 7 + 6);
+            assert_eq!(d.regions(Connectivity::Four).len(), 1, "seed {}\n{}", seed, d.render());
+
+            // a room in each room cell, in its own area, with the junction in the middle
+            // of its own
+            let graph = d.graph();
+            assert_eq!(graph.room_ids().len(), 6, "seed {}\n{}", seed, d.render());
+            for id in graph.room_ids() {
+                let tiles = graph.room_tiles(id);
+                let cell = |p: Point| ((p.x - 1) / 16, (p.y - 1) / 18);
+                assert!(tiles.iter().all(|&p| cell(p) == cell(tiles[0])));
+            }
+            assert_eq!(d.get((40, 9)), Tile::Corridor);
+
+            // the boss room in the bottom right, one way in and nothing placed in it
+            let boss = d.boss_room().unwrap();
+            let tiles = graph.room_tiles(boss);
+            assert!(tiles.iter().all(|p| p.x >= 49 && p.y >= 19));
+            assert_eq!(graph.room_degree(boss), 1);
+            assert!(tiles.iter().all(|&p| d.get(p) == Tile::Floor));
+            assert!(d.find_path(d.entrance().unwrap(), d.exit().unwrap(), Connectivity::Four).is_some());
+            assert!(d.find_path(d.entrance().unwrap(), tiles[0], Connectivity::Four).is_some());
+
+            // as large as any room, with the entrance in the room furthest from it
+            assert!(graph.room_ids().iter().all(|&id| graph.room_tiles(id).len() <= tiles.len()));
+            let hops = graph.hops_from(graph.room_node(boss).unwrap());
+            let start = graph.room_at(d.entrance().unwrap()).unwrap();
+            let furthest = graph.room_ids().iter().filter_map(|&id| hops[graph.room_node(id).unwrap()]).max();
+            assert_eq!(hops[graph.room_node(start).unwrap()], furthest, "seed {}\n{}", seed, d.render());
+            assert_eq!(d.room_info(start).unwrap().purpose, Some(RoomPurpose::Start));
+
+            // the same seed, the same level, whichever way it's asked for
+            let mut again = Dungeon::with_config(66, 38, strict());
+            again.generate_from_sketch(&sketch, &mut DungenRng::new(seed)).unwrap();
+            let mut same = Dungeon::with_config(66, 38, strict());
+            same.generate_from_sketch_seeded(&sketch, DungenRng::new(seed).next_u64()).unwrap();
+            assert_eq!(again.render(), same.render());
+        }
+    }
+
+    #[test]
+    fn test_unlinked_cells() {
+        // cells the sketch doesn't link aren't joined
+        let sketch = Sketch::from_rows(&["R-R", "", "R-R"]).unwrap();
+        let mut d = Dungeon::new(40, 30);
+        d.generate_from_sketch_seeded(&sketch, 3).unwrap();
+        assert_eq!(d.regions(Connectivity::Four).len(), 2);
+
+        // unless the validation level joins them up itself
+        let mut joined = Dungeon::with_config(40, 30, strict());
+        joined.generate_from_sketch_seeded(&sketch, 3).unwrap();
+        assert_eq!(joined.regions(Connectivity::Four).len(), 1);
+    }
+}
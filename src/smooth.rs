@@ -0,0 +1,173 @@
+/*
+Cleanup passes over finished maps. Where two placements meet they can leave a one tile
+wall nub poking into open space, and walls buried under other walls serve no purpose.
+Every change keeps walkable tiles fully ringed: no walkable tile ever ends up next to
+Unused space or the map edge, diagonals included.
+*/
+
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+
+const AROUND: [(isize, isize); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+impl Dungeon {
+    // turns wall nubs with walkable tiles on three or four sides into floor, and walls with
+    // nothing walkable around them into Unused, unless the map is sealed. walls beside a door are left so the door
+    // stays in a straight wall. a single pass can expose new nubs; aggressive repeats until
+    // nothing changes. returns the number of tiles changed.
+    pub fn smooth_walls(&mut self, aggressive: bool) -> usize {
+        let mut changed = 0;
+        loop {
+            let pass = self.smooth_pass();
+            changed += pass;
+            if !aggressive || pass == 0 {
+                break;
+            }
+        }
+
+        // opening a nub can join two rooms into one
+        if changed > 0 {
+            self.sync_rooms();
+        }
+        changed
+    }
+
+    fn smooth_pass(&mut self) -> usize {
+        let size = self.size();
+        let mut changed = 0;
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) != Tile::Wall {
+                    continue;
+                }
+
+                let mut open = 0;
+                let mut beside_door = false;
+                for dir in Dir::iterator() {
+                    let (dx, dy) = dir.offset();
+                    let t = self.get(p.offset(dx, dy));
+                    if t.is_walkable() {
+                        open += 1;
+                    }
+                    beside_door |= t.is_door() || t == Tile::Archway || t == Tile::SecretDoor;
+                }
+
+                if open >= 3 && !beside_door && self.is_ringed(p) {
+                    self.set_tile(x, y, Tile::Floor);
+                    changed += 1;
+                }
+
+                else if !self.is_sealed() && AROUND.iter().all(|&(dx, dy)| !self.get(p.offset(dx, dy)).is_walkable()) {
+                    self.set_tile(x, y, Tile::Unused);
+                    changed += 1;
+                }
+            }
+        }
+
+        changed
+    }
+
+    // turns every Unused tile beside a walkable one, diagonals included, into wall. used by
+    // the layouts that carve open space straight out of Unused.
+    pub(crate) fn wall_in(&mut self) {
+        let size = self.size();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) == Tile::Unused && AROUND.iter().any(|&(dx, dy)| self.get(self.wrap_point(p.offset(dx, dy))).is_walkable()) {
+                    self.set_tile(x, y, Tile::Wall);
+                }
+            }
+        }
+    }
+
+    // whether a walkable tile at p would have no Unused space or map edge around it
+    fn is_ringed(&self, p: Point) -> bool {
+        let size = self.size();
+        AROUND.iter().all(|&(dx, dy)| {
+            let n = self.wrap_point(p.offset(dx, dy));
+            n.x >= 0 && n.y >= 0 && n.x < size.width && n.y < size.height && self.get(n) != Tile::Unused
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Point;
+    use nav::Connectivity;
+
+    #[test]
+    fn test_smooth_walls_before_and_after() {
+        let mut d = Dungeon::parse(&[
+            "###########",
+            "#.........#",
+            "#...#.....#",
+            "#.........#",
+            "######+####",
+            "######,####",
+            "######,####",
+            "###########",
+        ]);
+
+        // the nub becomes floor, and the walls buried beside the corridor go
+        assert_eq!(d.smooth_walls(false), 25);
+        assert_eq!(d.get((4, 2)), Tile::Floor);
+        assert_eq!(d.render_with_path(), [
+            "###########",
+            "#.........#",
+            "#.........#",
+            "#.........#",
+            "######+####",
+            "     #,#   ",
+            "     #,#   ",
+            "     ###   ",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_smoothing_keeps_generated_maps_ringed() {
+        for seed in 0..6 {
+            let mut d = Dungeon::new(80, 50);
+            d.generate_seeded(60, seed).unwrap();
+            let before = d.render_with_path();
+            let walkable = |d: &Dungeon| d.regions(Connectivity::Four).iter().map(|r| r.len()).sum::<usize>();
+            let (tiles, regions) = (walkable(&d), d.regions(Connectivity::Four).len());
+
+            d.smooth_walls(true);
+            assert!(walkable(&d) >= tiles, "seed {}", seed);
+            assert!(d.regions(Connectivity::Four).len() <= regions, "seed {}", seed);
+            assert_eq!(d.validate_doors(), vec![], "seed {}", seed);
+            assert_eq!(d.smooth_walls(true), 0);
+
+            let size = d.size();
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    let p = Point::new(x, y);
+                    if d.get(p).is_walkable() {
+                        assert!(d.is_ringed(p), "seed {} at {:?}\\nbefore:\\n{}\\nafter:\\n{}", seed, p, before, d.render_with_path());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sealed_maps_stay_sealed() {
+        let mut d = Dungeon::parse(&[
+            "#######",
+            "#######",
+            "##...##",
+            "#######",
+            "#######",
+        ]);
+        d.seal();
+
+        d.smooth_walls(true);
+        assert_eq!(d.get((0, 0)), Tile::Wall);
+        assert!(!d.render().contains(' '));
+    }
+}
@@ -0,0 +1,263 @@
+/*
+Weighted spawn tables and the rules for where spawns may legally go. A spawn tile must be
+plain floor or grass inside a room reachable from the entrance, must not be next to a door
+or to the entrance or exit (so nothing blocks a doorway or camps the stairs), and nothing
+spawns in the entrance room or the safe room.
+
+Chest tiers come from the rank of each chest's walking distance from the entrance among
+all the chests placed, not from fixed distance thresholds, so every tier gets its share of
+chests however small the map is. Rooms carved by cap_dead_ends_with_rooms are each given a
+chest before chests go anywhere else.
+*/
+
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+use graph::RoomId;
+use nav::Connectivity;
+use rng::DungenRng;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnEntry<T> {
+    pub item: T,
+    pub weight: u32,
+    // difficulty band, both ends inclusive
+    pub min_difficulty: f32,
+    pub max_difficulty: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnTable<T> {
+    pub entries: Vec<SpawnEntry<T>>,
+}
+
+impl<T> SpawnTable<T> {
+    pub fn new() -> SpawnTable<T> {
+        SpawnTable { entries: Vec::new() }
+    }
+
+    pub fn add(&mut self, item: T, weight: u32, min_difficulty: f32, max_difficulty: f32) -> &mut SpawnTable<T> {
+        self.entries.push(SpawnEntry { item, weight, min_difficulty, max_difficulty });
+        self
+    }
+
+    // weighted pick among entries whose band holds difficulty. none if nothing fits.
+    pub fn roll(&self, difficulty: f32, rng: &mut DungenRng) -> Option<&T> {
+        let fits: Vec<&SpawnEntry<T>> = self.entries.iter()
+            .filter(|e| e.weight > 0 && e.min_difficulty <= difficulty && difficulty <= e.max_difficulty)
+            .collect();
+        if fits.is_empty() {
+            return None
+        }
+
+        let weights: Vec<u32> = fits.iter().map(|e| e.weight).collect();
+        Some(&fits[rng.weighted_index(&weights)].item)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChestPlacement {
+    pub pos: Point,
+    // 0 nearest the entrance, up to tiers - 1
+    pub tier: u8,
+    pub room: RoomId,
+}
+
+impl<T> Default for SpawnTable<T> {
+    fn default() -> SpawnTable<T> {
+        SpawnTable::new()
+    }
+}
+
+impl Dungeon {
+    // floor tiles of a room where something may spawn, in row-major order. always empty
+    // for the safe room.
+    pub fn spawn_points(&self, room: RoomId) -> Vec<Point> {
+        let graph = self.graph();
+        if !graph.room_ids().contains(&room) || self.safe_room == Some(room) {
+            return Vec::new()
+        }
+
+        graph.room_tiles(room).iter()
+            .cloned()
+            .filter(|&p| matches!(self.get(p), Tile::Floor | Tile::Grass))
+            .filter(|&p| Dir::iterator().all(|dir| {
+                let (dx, dy) = dir.offset();
+                !matches!(self.get(p.offset(dx, dy)), Tile::ClosedDoor | Tile::OpenDoor | Tile::Entrance | Tile::Exit)
+            }))
+            .collect()
+    }
+
+    // rolls per_room spawns in every room past the entrance, at the room's difficulty.
+    // rooms with fewer legal tiles get fewer spawns, and rolls that fit no entry are dropped.
+    pub fn populate<'t, T>(&self, table: &'t SpawnTable<T>, per_room: RangeInclusive<usize>, rng: &mut DungenRng) -> Vec<(Point, &'t T)> {
+        let graph = self.graph();
+        let start_room = self.entrance().and_then(|p| graph.room_at(p));
+        let mut out = Vec::new();
+
+        for (room, difficulty) in self.difficulty_map() {
+            if Some(room) == start_room {
+                continue;
+            }
+
+            let mut tiles = self.spawn_points(room);
+            let wanted = rng.range_inclusive(*per_room.start() as isize, *per_room.end() as isize) as usize;
+            let count = wanted.min(tiles.len());
+
+            // partial shuffle: the first count tiles end up a uniform random pick
+            for i in 0..count {
+                let j = rng.range_inclusive(i as isize, tiles.len() as isize - 1) as usize;
+                tiles.swap(i, j);
+                if let Some(item) = table.roll(difficulty, rng) {
+                    out.push((tiles[i], item));
+                }
+            }
+        }
+
+        out
+    }
+
+    // puts up to count chests on spawn points past the entrance room and grades them into
+    // `tiers` loot tiers. replaces any earlier chests; the result is also kept for chests().
+    pub fn place_chests(&mut self, count: usize, tiers: u8, rng: &mut DungenRng) -> Vec<ChestPlacement> {
+        self.chests.clear();
+        let entrance = match self.entrance() {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+
+        let graph = self.graph();
+        let start_room = graph.room_at(entrance);
+        let mut tiles: Vec<(Point, RoomId)> = Vec::new();
+        for (room, _) in self.difficulty_map() {
+            if Some(room) != start_room {
+                tiles.extend(self.spawn_points(room).into_iter().map(|p| (p, room)));
+            }
+        }
+
+        // a chest in each dead-end room first, then the rest anywhere
+        let count = count.min(tiles.len());
+        let mut first = 0;
+        for &room in self.dead_end_rooms.iter() {
+            let spots: Vec<usize> = (first..tiles.len()).filter(|&i| tiles[i].1 == room).collect();
+            if first < count && !spots.is_empty() {
+                tiles.swap(first, spots[rng.range_exclusive(spots.len() as isize) as usize]);
+                first += 1;
+            }
+        }
+
+        for i in first..count {
+            let j = rng.range_inclusive(i as isize, tiles.len() as isize - 1) as usize;
+            tiles.swap(i, j);
+        }
+        tiles.truncate(count);
+
+        // chests at the same distance always share a tier
+        let width = self.size().width;
+        let dist = self.distance_map(entrance, Connectivity::Four);
+        let depth: Vec<u32> = tiles.iter().map(|&(p, _)| dist[(p.x + p.y * width) as usize].unwrap_or(0)).collect();
+        let tiers = tiers.max(1) as usize;
+
+        self.chests = tiles.iter().zip(&depth)
+            .map(|(&(pos, room), &d)| {
+                let nearer = depth.iter().filter(|&&other| other < d).count();
+                ChestPlacement { pos, tier: (nearer * tiers / count) as u8, room }
+            })
+            .collect();
+
+        self.chests.clone()
+    }
+
+    pub fn chests(&self) -> &[ChestPlacement] {
+        &self.chests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Point;
+    use rng::DungenRng;
+    use spawn::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Monster {
+        Rat,
+        Ogre,
+    }
+
+    #[test]
+    fn test_roll_respects_difficulty_band() {
+        let mut table = SpawnTable::new();
+        table.add(Monster::Rat, 3, 0.0, 0.5).add(Monster::Ogre, 1, 0.5, 1.0);
+        let mut rng = DungenRng::new(3);
+
+        for _ in 0..100 {
+            assert_eq!(table.roll(0.2, &mut rng), Some(&Monster::Rat));
+            assert_eq!(table.roll(0.9, &mut rng), Some(&Monster::Ogre));
+            assert!(table.roll(0.5, &mut rng).is_some());
+        }
+        assert_eq!(table.roll(1.5, &mut rng), None);
+        assert_eq!(SpawnTable::<Monster>::new().roll(0.0, &mut rng), None);
+    }
+
+    #[test]
+    fn test_populate_uses_legal_tiles() {
+        let d = Dungeon::parse(&[
+            "#####################",
+            "#<..+,,+.....+,,+...#",
+            "#...#  #.....#  #..>#",
+            "#...#  #.....#  #...#",
+            "#####  #######  #####",
+        ]);
+        let mut table = SpawnTable::new();
+        table.add(Monster::Rat, 1, 0.0, 0.6).add(Monster::Ogre, 1, 0.6, 1.0);
+        let graph = d.graph();
+        let start = graph.room_at(Point::new(1, 1));
+
+        let spawns = d.populate(&table, 2..=3, &mut DungenRng::new(9));
+        assert!(spawns.len() >= 4 && spawns.len() <= 6);
+        for &(p, monster) in &spawns {
+            assert_eq!(d.get(p), Tile::Floor);
+            assert!(graph.room_at(p) != start);
+            assert!(p != Point::new(8, 1) && p != Point::new(12, 1) && p != Point::new(19, 1));
+            // the middle room sits at difficulty 7/16, the exit room at 1.0
+            let expected = if p.x < 13 { &Monster::Rat } else { &Monster::Ogre };
+            assert_eq!(monster, expected);
+        }
+
+        assert_eq!(spawns, d.populate(&table, 2..=3, &mut DungenRng::new(9)));
+    }
+
+    #[test]
+    fn test_chest_tiers_follow_distance() {
+        let mut d = Dungeon::parse(&[
+            "##################################",
+            "#<+..........+..........+.......>#",
+            "#.#..........#..........#........#",
+            "##################################",
+        ]);
+        let chests = d.place_chests(12, 4, &mut DungenRng::new(5));
+        assert_eq!(chests.len(), 12);
+        assert_eq!(d.chests(), &chests[..]);
+
+        let mut along = chests.clone();
+        along.sort_by_key(|c| (c.pos.x, c.pos.y));
+        for pair in along.windows(2) {
+            if pair[0].pos.x < pair[1].pos.x {
+                assert!(pair[0].tier <= pair[1].tier);
+            }
+        }
+
+        // quantiles keep every tier populated
+        for tier in 0..4 {
+            assert!(chests.iter().any(|c| c.tier == tier));
+        }
+        let graph = d.graph();
+        assert!(chests.iter().all(|c| graph.room_at(c.pos) == Some(c.room)));
+        assert!(chests.iter().all(|c| c.pos.x > 2));
+    }
+}
@@ -0,0 +1,1017 @@
+/*
+Multi-level dungeons. A stack is a run of levels of the same size, one per depth, where the
+exit of each level sits on the same tile as the entrance of the level below, so the stairs
+line up. Every depth is planned on its own: a LevelSpec picks the layout, the config and the
+decoration passes for it, so deeper floors can be laid out differently from shallow ones.
+
+Levels are generated independently, each from its own seed, and the entrance of each level
+below the first is then moved onto the stairs coming down. Where they land in rock, as when
+a cave level follows a rooms level, a pocket of floor is dug around them and tunnelled to
+the nearest open ground. Where they land on the level's own exit, the exit moves to the
+tile furthest from them. The passes run last, on the finished level.
+
+A stack is fully determined by its StackSpec, the per-level specs included, and that is what
+gets serialized; from_spec builds the levels back.
+
+A campaign in progress is kept with save instead, which writes every level's map and
+exploration layer and the depth the player is on, so levels changed since they were
+generated come back as they were left. The container starts with the level count, the
+current depth and where each level's bytes lie; load decodes only the current level and
+keeps the bytes of the rest until load_level asks for them. A loaded stack has no spec.
+
+Pits are a second way down, added by add_pits once the levels are built: a Pit tile on one
+level over open ground on the next. They go only inside rooms, away from their walls and
+off the main path, so nobody has to cross one, and never over the level below's stairs.
+
+Cross-level locks hold a door on one level shut until the player has picked up its key on
+the level above. The door is one that parts the level's entrance from its exit, the one
+nearest the entrance, and the key lies in a room off the main path. can_reach_final_exit
+plays the stack through, collecting keys as it walks and taking stairs both ways and pits
+down, and add_cross_level_locks keeps only locks that leave it true.
+*/
+
+use std::io::{ self, Read, Write };
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use codec::{ self, Reader };
+use config::{ DungeonConfig, Preset };
+use dungeon::{ Dungeon, GenerationReport, Point, Tile };
+use error::{ DungeonError, LoadError, StackError };
+use explore::Exploration;
+use nav::Connectivity;
+use names::NameStyle;
+use pipeline::{ self, OnError, Pipeline };
+use rng::{ self, DungenRng };
+
+// how a level is laid out
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LevelAlgorithm {
+    // rooms and corridors, see Dungeon::generate_seeded
+    Rooms,
+    // one open cavern, see Dungeon::generate_caves_seeded
+    Caves,
+    // see Dungeon::generate_maze_seeded
+    Maze,
+    // rooms with caves between them, see Dungeon::generate_hybrid_seeded
+    Hybrid,
+}
+
+impl LevelAlgorithm {
+    // lays out d this way. maxfeatures is ignored by the layouts that don't place features.
+    pub fn generate(self, d: &mut Dungeon, maxfeatures: isize, seed: u64) -> Result<GenerationReport, DungeonError> {
+        match self {
+            LevelAlgorithm::Rooms => d.generate_seeded(maxfeatures, seed),
+            LevelAlgorithm::Caves => d.generate_caves_seeded(seed),
+            LevelAlgorithm::Maze => d.generate_maze_seeded(seed),
+            LevelAlgorithm::Hybrid => d.generate_hybrid_seeded(maxfeatures, seed),
+        }
+    }
+
+    // the tile the layout walks on, which is put back where the entrance moves from
+    fn ground(&self) -> Tile {
+        match *self {
+            LevelAlgorithm::Maze => Tile::Corridor,
+            _ => Tile::Floor,
+        }
+    }
+}
+
+// a decoration pass run on a finished level
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LevelPass {
+    SmoothWalls { aggressive: bool },
+    // decorate, with this torch chance
+    Torches(f32),
+    // cap_dead_ends_with_rooms, with this chance per dead end
+    DeadEndRooms(f32),
+    // assign_courtyards with this fraction of rooms, grassed over
+    Courtyards(f32),
+    Names(NameStyle),
+}
+
+// everything that decides one level of a stack
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LevelSpec {
+    pub algorithm: LevelAlgorithm,
+    pub config: DungeonConfig,
+    // for the layouts that place features, ignored by caves and mazes
+    pub maxfeatures: isize,
+    // in the order they run
+    pub passes: Vec<LevelPass>,
+}
+
+impl Default for LevelSpec {
+    fn default() -> LevelSpec {
+        LevelSpec { algorithm: LevelAlgorithm::Rooms, config: DungeonConfig::default(), maxfeatures: 50, passes: Vec::new() }
+    }
+}
+
+impl LevelPass {
+    fn add_to(&self, pipeline: &mut Pipeline) {
+        match *self {
+            LevelPass::SmoothWalls { aggressive } => pipeline.add(pipeline::SmoothWalls { aggressive }),
+            LevelPass::Torches(chance) => pipeline.add(pipeline::Torches { chance }),
+            LevelPass::DeadEndRooms(chance) => pipeline.add(pipeline::DeadEndRooms { chance }),
+            LevelPass::Courtyards(fraction) => pipeline.add(pipeline::Courtyards { fraction, grassed: true }),
+            LevelPass::Names(ref style) => pipeline.add(pipeline::NameRooms(style.clone())),
+        };
+    }
+}
+
+impl LevelSpec {
+    // the preset's config and layout, with no passes
+    pub fn preset(preset: Preset) -> LevelSpec {
+        LevelSpec { algorithm: preset.algorithm(), config: preset.config(), ..LevelSpec::default() }
+    }
+}
+
+// everything that decides a stack, shallowest level first
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StackSpec {
+    pub width: isize,
+    pub height: isize,
+    pub seed: u64,
+    pub levels: Vec<LevelSpec>,
+}
+
+const MAGIC: &[u8; 4] = b"DGNS";
+// version 2 added the pit table and version 3 the lock table. older saves still load,
+// without them.
+const VERSION: u16 = 3;
+// how far off open ground, in tiles, a pit may be dug a landing below
+const LANDING_REACH: isize = 6;
+
+// a pit at pos on the level at depth, falling to pos on the level below
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PitLink {
+    pub depth: usize,
+    pub pos: Point,
+}
+
+// a locked door at door on the level at depth + 1, opened by the key at key on the level
+// at depth
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CrossLevelLock {
+    pub depth: usize,
+    pub key: Point,
+    pub door: Point,
+}
+
+enum Level {
+    Loaded(Box<Dungeon>, Exploration),
+    // a level's encoding, as it was read from a save
+    Saved(Vec<u8>),
+}
+
+pub struct DungeonStack {
+    spec: Option<StackSpec>,
+    levels: Vec<Level>,
+    // the depth the player is on
+    current: usize,
+    pits: Vec<PitLink>,
+    locks: Vec<CrossLevelLock>,
+}
+
+impl DungeonStack {
+    // depth levels, each laid out as plan says for its depth
+    pub fn generate<F>(width: isize, height: isize, depth: usize, seed: u64, plan: F) -> Result<DungeonStack, StackError>
+        where F: Fn(usize) -> LevelSpec
+    {
+        DungeonStack::from_spec(StackSpec { width, height, seed, levels: (0..depth).map(plan).collect() })
+    }
+
+    pub fn from_spec(spec: StackSpec) -> Result<DungeonStack, StackError> {
+        if spec.levels.is_empty() {
+            return Err(StackError::NoLevels)
+        }
+
+        let mut levels: Vec<Dungeon> = Vec::with_capacity(spec.levels.len());
+        for (depth, level) in spec.levels.iter().enumerate() {
+            let stairs = levels.last().and_then(|above| above.exit());
+            levels.push(build_level(&spec, depth, level, stairs)?);
+        }
+
+        let levels = levels.into_iter().map(|d| {
+            let explored = Exploration::new(&d);
+            Level::Loaded(Box::new(d), explored)
+        }).collect();
+        Ok(DungeonStack { spec: Some(spec), levels, current: 0, pits: Vec::new(), locks: Vec::new() })
+    }
+
+    // none for a stack that was loaded from a save
+    pub fn spec(&self) -> Option<&StackSpec> {
+        self.spec.as_ref()
+    }
+
+    // none if there's no such level or it hasn't been loaded yet, see load_level
+    pub fn level(&self, depth: usize) -> Option<&Dungeon> {
+        match self.levels.get(depth) {
+            Some(Level::Loaded(d, _)) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn level_mut(&mut self, depth: usize) -> Option<&mut Dungeon> {
+        match self.levels.get_mut(depth) {
+            Some(Level::Loaded(d, _)) => Some(d),
+            _ => None,
+        }
+    }
+
+    // what the player has seen of a level, none under the same conditions as level
+    pub fn exploration(&self, depth: usize) -> Option<&Exploration> {
+        match self.levels.get(depth) {
+            Some(Level::Loaded(_, e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    pub fn exploration_mut(&mut self, depth: usize) -> Option<&mut Exploration> {
+        match self.levels.get_mut(depth) {
+            Some(Level::Loaded(_, e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    pub fn is_loaded(&self, depth: usize) -> bool {
+        self.level(depth).is_some()
+    }
+
+    // decodes a level kept back by load. a level that is already loaded is just returned.
+    pub fn load_level(&mut self, depth: usize) -> Result<&Dungeon, LoadError> {
+        let decoded = match self.levels.get(depth) {
+            None => return Err(LoadError::NoLevel(depth)),
+            Some(Level::Saved(bytes)) => Some(decode_level(bytes)?),
+            Some(Level::Loaded(..)) => None,
+        };
+
+        if let Some((d, explored)) = decoded {
+            let size = d.size();
+            let on_map = |p: Point| p.x < size.width && p.y < size.height;
+            for pit in self.pits.iter().filter(|pit| pit.depth == depth) {
+                if !on_map(pit.pos) || d.get(pit.pos) != Tile::Pit {
+                    return Err(LoadError::Invalid("pit"))
+                }
+            }
+            for lock in &self.locks {
+                if (lock.depth == depth && !on_map(lock.key)) || (lock.depth + 1 == depth && !(on_map(lock.door) && d.get(lock.door).is_door())) {
+                    return Err(LoadError::Invalid("lock"))
+                }
+            }
+            self.levels[depth] = Level::Loaded(Box::new(d), explored);
+        }
+        Ok(self.level(depth).unwrap())
+    }
+
+    // the depth the player is on, 0 for a new stack
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // moves the player to depth, loading the level if need be
+    pub fn set_current(&mut self, depth: usize) -> Result<(), LoadError> {
+        self.load_level(depth)?;
+        self.current = depth;
+        Ok(())
+    }
+
+    // in the order they were added
+    pub fn pits(&self) -> &[PitLink] {
+        &self.pits
+    }
+
+    // adds up to per_level pits to every level but the last, each falling onto floor or
+    // corridor below. where the level below is solid under every spot it would do, a
+    // landing is dug out under one within LANDING_REACH of its open ground. loads every
+    // level. returns how many pits were added.
+    pub fn add_pits(&mut self, per_level: usize, rng: &mut DungenRng) -> Result<usize, LoadError> {
+        self.load_all()?;
+
+        let mut added = 0;
+        for depth in 0..self.len().saturating_sub(1) {
+            let main_path = self.level(depth).unwrap().main_path().map(|path| path.tiles).unwrap_or_default();
+
+            for _ in 0..per_level {
+                let (upper, lower) = (self.level(depth).unwrap(), self.level(depth + 1).unwrap());
+                // pits already falling onto this level keep their landings
+                let landings: Vec<Point> = self.pits.iter().filter(|pit| pit.depth + 1 == depth).map(|pit| pit.pos).collect();
+                let spots: Vec<Point> = upper.pit_spots().into_iter()
+                    .filter(|p| !main_path.contains(p) && !landings.contains(p))
+                    .collect();
+
+                let onto_ground: Vec<Point> = spots.iter().cloned().filter(|&p| matches!(lower.get(p), Tile::Floor | Tile::Grass | Tile::Corridor)).collect();
+                let (pos, dig) = if !onto_ground.is_empty() {
+                    (onto_ground[rng.range_exclusive(onto_ground.len() as isize) as usize], false)
+                }
+
+                else {
+                    let near: Vec<Point> = spots.into_iter().filter(|&p| lower.near_open_ground(p)).collect();
+                    if near.is_empty() {
+                        break;
+                    }
+                    (near[rng.range_exclusive(near.len() as isize) as usize], true)
+                };
+
+                if dig {
+                    self.level_mut(depth + 1).unwrap().dig_landing(pos, Tile::Floor);
+                }
+                self.level_mut(depth).unwrap().set_tile(pos.x, pos.y, Tile::Pit);
+                self.pits.push(PitLink { depth, pos });
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+
+    // in the order they were added
+    pub fn locks(&self) -> &[CrossLevelLock] {
+        &self.locks
+    }
+
+    // decodes every level not loaded yet
+    pub fn load_all(&mut self) -> Result<(), LoadError> {
+        for depth in 0..self.len() {
+            self.load_level(depth)?;
+        }
+        Ok(())
+    }
+
+    // locks a door on each level below the first, with its key on the level above. a pair
+    // of levels with no door between the lower one's stairs, no room off the main path above
+    // to hide the key in, or where the lock would leave the final exit out of reach, is
+    // skipped. loads every level. returns how many locks were added.
+    pub fn add_cross_level_locks(&mut self, rng: &mut DungenRng) -> Result<usize, LoadError> {
+        self.load_all()?;
+
+        let mut added = 0;
+        for depth in 0..self.len().saturating_sub(1) {
+            let (upper, lower) = (self.level(depth).unwrap(), self.level(depth + 1).unwrap());
+            let door = match self.lockable_door(lower) {
+                Some(door) => door,
+                None => continue,
+            };
+            let key = match self.key_spot(upper, rng) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let old = lower.get(door);
+            self.level_mut(depth + 1).unwrap().set_tile(door.x, door.y, Tile::ClosedDoor);
+            self.locks.push(CrossLevelLock { depth, key, door });
+            if !self.can_reach_final_exit() {
+                self.locks.pop();
+                self.level_mut(depth + 1).unwrap().set_tile(door.x, door.y, old);
+                continue;
+            }
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    // the door nearest the level's entrance that every way from the entrance to the exit
+    // goes through. none if the level has no such door, or no way through at all.
+    fn lockable_door(&self, d: &Dungeon) -> Option<Point> {
+        let (entrance, exit) = (d.entrance()?, d.exit()?);
+        let dist = d.distance_map(entrance, Connectivity::Four);
+        dist[d.index(exit)]?;
+
+        d.main_path()?.tiles.into_iter()
+            .filter(|&p| d.get(p).is_door() && !self.locks.iter().any(|lock| lock.door == p))
+            .filter(|&door| d.find_path_where(entrance, exit, Connectivity::Four, |p| p != door).is_none())
+            .min_by_key(|&p| dist[d.index(p)])
+    }
+
+    // a floor tile, picked at random, in a room off the main path that can be walked to from
+    // the entrance. doors locked on the level don't count against it, since their keys lie
+    // further up.
+    fn key_spot(&self, d: &Dungeon, rng: &mut DungenRng) -> Option<Point> {
+        let dist = d.distance_map(d.entrance()?, Connectivity::Four);
+        let graph = d.graph();
+        let taken: Vec<Point> = self.locks.iter().map(|lock| lock.key).collect();
+
+        let rooms: Vec<Vec<Point>> = d.off_path_rooms().into_iter()
+            .map(|id| graph.room_tiles(id).iter().cloned()
+                .filter(|&p| d.get(p) == Tile::Floor && dist[d.index(p)].is_some() && !taken.contains(&p))
+                .collect::<Vec<Point>>())
+            .filter(|tiles| !tiles.is_empty())
+            .collect();
+        if rooms.is_empty() {
+            return None
+        }
+
+        let tiles = &rooms[rng.range_exclusive(rooms.len() as isize) as usize];
+        Some(tiles[rng.range_exclusive(tiles.len() as isize) as usize])
+    }
+
+    // whether a player starting on the first level's entrance can get to the last level's
+    // exit. they walk through doors whose keys they have picked up, and pick up keys by
+    // walking over them; they take stairs up and down and may fall down pits. false if any
+    // level isn't loaded.
+    pub fn can_reach_final_exit(&self) -> bool {
+        let levels: Vec<&Dungeon> = match (0..self.len()).map(|depth| self.level(depth)).collect() {
+            Some(levels) => levels,
+            None => return false,
+        };
+        let (start, goal) = match (levels[0].entrance(), levels[levels.len() - 1].exit()) {
+            (Some(start), Some(goal)) => (start, goal),
+            _ => return false,
+        };
+
+        // tiles the player has stepped onto each level from, and keys picked up
+        let mut entries: Vec<Vec<Point>> = vec![Vec::new(); levels.len()];
+        entries[0].push(start);
+        let mut held = vec![false; self.locks.len()];
+
+        loop {
+            let reached: Vec<Vec<bool>> = levels.iter().enumerate().map(|(depth, d)| {
+                let locked: Vec<Point> = self.locks.iter().zip(&held)
+                    .filter(|&(lock, &held)| lock.depth + 1 == depth && !held)
+                    .map(|(lock, _)| lock.door)
+                    .collect();
+
+                let mut reached = vec![false; d.size().area() as usize];
+                for &p in entries[depth].iter().filter(|p| !locked.contains(p)) {
+                    let dist = d.distance_map_where(p, Connectivity::Four, |q| !locked.contains(&q));
+                    for (r, step) in reached.iter_mut().zip(dist) {
+                        *r |= step.is_some();
+                    }
+                }
+                reached
+            }).collect();
+            let at = |depth: usize, p: Point| reached[depth][levels[depth].index(p)];
+
+            let mut progress = false;
+            for (lock, held) in self.locks.iter().zip(held.iter_mut()) {
+                if !*held && at(lock.depth, lock.key) {
+                    *held = true;
+                    progress = true;
+                }
+            }
+
+            // (from depth, from tile, to depth, to tile)
+            let mut ways = Vec::new();
+            for depth in 0..levels.len() - 1 {
+                if let (Some(exit), Some(entrance)) = (levels[depth].exit(), levels[depth + 1].entrance()) {
+                    ways.push((depth, exit, depth + 1, entrance));
+                    ways.push((depth + 1, entrance, depth, exit));
+                }
+            }
+            for pit in self.pits.iter().filter(|pit| pit.depth + 1 < levels.len()) {
+                // a pit is fallen into from beside it
+                if let Some(&edge) = levels[pit.depth].neighbors(pit.pos, Connectivity::Four).iter().find(|&&p| at(pit.depth, p)) {
+                    ways.push((pit.depth, edge, pit.depth + 1, pit.pos));
+                }
+            }
+
+            for (from_depth, from, to_depth, to) in ways {
+                if at(from_depth, from) && levels[to_depth].get(to).is_walkable() && !entries[to_depth].contains(&to) {
+                    entries[to_depth].push(to);
+                    progress = true;
+                }
+            }
+
+            if at(levels.len() - 1, goal) {
+                return true
+            }
+            if !progress {
+                return false
+            }
+        }
+    }
+
+    // writes every level, loaded or not, the current depth, the pits and the locks
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut body = Vec::new();
+        let mut spans = Vec::with_capacity(self.levels.len());
+        for level in &self.levels {
+            let start = body.len();
+            match *level {
+                Level::Loaded(ref d, ref explored) => {
+                    d.write_to(&mut body);
+                    explored.write_to(&mut body);
+                }
+                Level::Saved(ref bytes) => body.extend_from_slice(bytes),
+            }
+            spans.push((start, body.len() - start));
+        }
+
+        if body.len() > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "stack too large to save"))
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        codec::put_u16(&mut header, VERSION);
+        codec::put_u32(&mut header, self.levels.len() as u32);
+        codec::put_u32(&mut header, self.current as u32);
+        for &(offset, len) in &spans {
+            codec::put_u32(&mut header, offset as u32);
+            codec::put_u32(&mut header, len as u32);
+        }
+        codec::put_u32(&mut header, self.pits.len() as u32);
+        for pit in &self.pits {
+            codec::put_u32(&mut header, pit.depth as u32);
+            codec::put_u32(&mut header, pit.pos.x as u32);
+            codec::put_u32(&mut header, pit.pos.y as u32);
+        }
+        codec::put_u32(&mut header, self.locks.len() as u32);
+        for lock in &self.locks {
+            codec::put_u32(&mut header, lock.depth as u32);
+            for p in &[lock.key, lock.door] {
+                codec::put_u32(&mut header, p.x as u32);
+                codec::put_u32(&mut header, p.y as u32);
+            }
+        }
+
+        writer.write_all(&header)?;
+        writer.write_all(&body)
+    }
+
+    // reads a stack written by save, decoding only the current level
+    pub fn load<R: Read>(mut reader: R) -> Result<DungeonStack, LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|e| LoadError::Io(e.kind()))?;
+
+        let mut r = Reader::new(&bytes);
+        r.magic(MAGIC)?;
+        let version = r.u16()?;
+        if version == 0 || version > VERSION {
+            return Err(LoadError::UnsupportedVersion(version))
+        }
+
+        let count = r.u32()? as usize;
+        let current = r.u32()? as usize;
+        if count == 0 {
+            return Err(LoadError::Invalid("level count"))
+        }
+        if current >= count {
+            return Err(LoadError::Invalid("current depth"))
+        }
+
+        // the count isn't trusted for an allocation until the table is known to be there
+        let mut spans = Vec::new();
+        for _ in 0..count {
+            spans.push((r.u32()? as usize, r.u32()? as usize));
+        }
+
+        let mut pits = Vec::new();
+        let pit_count = if version >= 2 { r.u32()? } else { 0 };
+        for _ in 0..pit_count {
+            let depth = r.u32()? as usize;
+            // the tiles are checked when the level is loaded
+            let pos = read_point(&mut r)?;
+            if depth + 1 >= count {
+                return Err(LoadError::Invalid("pit"))
+            }
+            pits.push(PitLink { depth, pos });
+        }
+
+        let mut locks = Vec::new();
+        let lock_count = if version >= 3 { r.u32()? } else { 0 };
+        for _ in 0..lock_count {
+            let depth = r.u32()? as usize;
+            let (key, door) = (read_point(&mut r)?, read_point(&mut r)?);
+            if depth + 1 >= count {
+                return Err(LoadError::Invalid("lock"))
+            }
+            locks.push(CrossLevelLock { depth, key, door });
+        }
+
+        let body = r.rest();
+        let mut levels = Vec::with_capacity(count);
+        for (offset, len) in spans {
+            match offset.checked_add(len) {
+                Some(end) if end <= body.len() => levels.push(Level::Saved(body[offset..end].to_vec())),
+                _ => return Err(LoadError::Invalid("level offset")),
+            }
+        }
+
+        let mut stack = DungeonStack { spec: None, levels, current, pits, locks };
+        stack.load_level(current)?;
+        Ok(stack)
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    // never true, a stack has at least one level
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
+
+// an x, y pair that fits a map
+fn read_point(r: &mut Reader) -> Result<Point, LoadError> {
+    let (x, y) = (r.u32()?, r.u32()?);
+    if x > i32::MAX as u32 || y > i32::MAX as u32 {
+        return Err(LoadError::Invalid("point"))
+    }
+    Ok(Point::new(x as isize, y as isize))
+}
+
+fn decode_level(bytes: &[u8]) -> Result<(Dungeon, Exploration), LoadError> {
+    let mut r = Reader::new(bytes);
+    let d = Dungeon::read_from(&mut r)?;
+    let explored = Exploration::read_from(&d, &mut r)?;
+    r.finish()?;
+    Ok((d, explored))
+}
+
+// lays out the level at depth, lands its entrance on stairs, if the level above has an
+// exit, then runs its passes
+fn build_level(stack: &StackSpec, depth: usize, spec: &LevelSpec, stairs: Option<Point>) -> Result<Dungeon, StackError> {
+    let seed = rng::stream_seed(stack.seed, &format!("level {}", depth));
+    let mut d = Dungeon::try_with_config(stack.width, stack.height, spec.config.clone())
+        .map_err(|error| StackError::Dimensions { depth, error })?;
+
+    spec.algorithm.generate(&mut d, spec.maxfeatures, seed).map_err(|error| StackError::Level { depth, error })?;
+
+    if let Some(p) = stairs {
+        d.land_stairs(p, spec.algorithm.ground());
+    }
+
+    // none of these passes can fail, short of leaving the map open, which is a bug
+    let mut pipeline = Pipeline::new();
+    pipeline.on_error = OnError::Continue;
+    for pass in &spec.passes {
+        pass.add_to(&mut pipeline);
+    }
+    let report = pipeline.run(&mut d, &mut rng::Streams::new(seed).decor);
+    debug_assert!(report.is_ok(), "level {}: {:?}", depth, report.errors().collect::<Vec<_>>());
+
+    Ok(d)
+}
+
+impl Dungeon {
+    // moves the first entrance onto p, putting ground back where it was
+    fn land_stairs(&mut self, p: Point, ground: Tile) {
+        if let Some(old) = self.entrance() {
+            self.set_object(old, ground);
+        }
+
+        match self.get(p) {
+            Tile::Exit => {
+                // the exit moves out of the way, as far off as it can
+                self.place_stairs(p, ground);
+                return
+            }
+            Tile::Floor | Tile::Corridor | Tile::Grass => {}
+            _ => self.dig_landing(p, ground),
+        }
+        self.set_object(p, Tile::Entrance);
+    }
+
+    // floor and grass inside a room, with room floor all round, where a pit can't cut one
+    // part of the room off from another
+    fn pit_spots(&self) -> Vec<Point> {
+        let size = self.size();
+        let floor = |p: Point| matches!(self.get(p), Tile::Floor | Tile::Grass);
+        let mut out = Vec::new();
+        for y in 1..size.height - 1 {
+            for x in 1..size.width - 1 {
+                let p = Point::new(x, y);
+                if (-1..=1).all(|dy| (-1..=1).all(|dx| floor(p.offset(dx, dy)))) {
+                    out.push(p);
+                }
+            }
+        }
+        out
+    }
+
+    // whether any walkable tile lies within LANDING_REACH of p
+    fn near_open_ground(&self, p: Point) -> bool {
+        (-LANDING_REACH..=LANDING_REACH).any(|dy| (-LANDING_REACH..=LANDING_REACH).any(|dx| self.get(p.offset(dx, dy)).is_walkable()))
+    }
+
+    // opens up the rock around p and joins it to the rest of the level. only the map's
+    // edge is kept clear, since p has to stay where it is whatever the level's border.
+    fn dig_landing(&mut self, p: Point, ground: Tile) {
+        let size = self.size();
+        let mut pocket = Vec::new();
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let q = p.offset(dx, dy);
+                if q.x < 1 || q.y < 1 || q.x >= size.width - 1 || q.y >= size.height - 1 {
+                    continue;
+                }
+
+                if matches!(self.get(q), Tile::Unused | Tile::Wall | Tile::Torch | Tile::Window) {
+                    self.set_tile(q.x, q.y, ground);
+                    pocket.push(q);
+                }
+            }
+        }
+
+        self.tunnel_from(&pocket, ground, 1);
+        self.wall_in();
+        self.sync_rooms();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, RoomSizeDistribution };
+    use dungeon::{ Dungeon, Point, Tile };
+    use error::{ LoadError, StackError };
+    use explore::Exploration;
+    use nav::Connectivity;
+    use rng::DungenRng;
+    use stack::*;
+
+    fn plan(depth: usize) -> LevelSpec {
+        match depth {
+            0 => LevelSpec { passes: vec![LevelPass::Torches(0.5)], ..LevelSpec::default() },
+            1 => LevelSpec { algorithm: LevelAlgorithm::Caves, ..LevelSpec::default() },
+            2 => LevelSpec { algorithm: LevelAlgorithm::Maze, ..LevelSpec::default() },
+            3 => LevelSpec { algorithm: LevelAlgorithm::Hybrid, maxfeatures: 20, ..LevelSpec::default() },
+            _ => LevelSpec {
+                config: DungeonConfig { room_size: RoomSizeDistribution::Uniform { min: 8, max: 14 }, ..DungeonConfig::default() },
+                passes: vec![LevelPass::SmoothWalls { aggressive: true }],
+                ..LevelSpec::default()
+            },
+        }
+    }
+
+    fn walkable_from_entrance(d: &Dungeon) -> bool {
+        let dist = d.distance_map(d.entrance().unwrap(), Connectivity::Four);
+        d.exits().iter().all(|p| dist[(p.x + p.y * d.size().width) as usize].is_some())
+    }
+
+    #[test]
+    fn test_stairs_line_up_across_layouts() {
+        let mut built = 0;
+        for seed in 0..8 {
+            let stack = match DungeonStack::generate(60, 40, 5, seed, plan) {
+                Ok(stack) => stack,
+                Err(StackError::Level { .. }) => continue,
+                Err(e) => panic!("seed {}: {}", seed, e),
+            };
+
+            assert_eq!(stack.len(), 5);
+            let levels: Vec<&Dungeon> = (0..5).map(|depth| stack.level(depth).unwrap()).collect();
+            for (depth, pair) in levels.windows(2).enumerate() {
+                assert_eq!(pair[0].exit(), pair[1].entrance(), "seed {} depth {}", seed, depth);
+            }
+            for (depth, level) in levels.iter().enumerate() {
+                assert!(level.validate_boundaries().is_empty(), "seed {} depth {}", seed, depth);
+                assert!(walkable_from_entrance(level), "seed {} depth {}", seed, depth);
+                assert_eq!(level.render().matches('<').count(), 1, "seed {} depth {}", seed, depth);
+                assert_eq!(level.render().matches('>').count(), 1, "seed {} depth {}", seed, depth);
+            }
+            assert!(!levels[0].torches().is_empty());
+            assert_eq!(stack.spec().unwrap().levels[2].algorithm, LevelAlgorithm::Maze);
+            assert!(levels[2].get(levels[2].exit().unwrap()) == Tile::Exit);
+            built += 1;
+        }
+        assert!(built > 4);
+
+        assert_eq!(DungeonStack::generate(60, 40, 0, 1, plan).err(), Some(StackError::NoLevels));
+    }
+
+    #[test]
+    fn test_landing_in_rock() {
+        let mut d = Dungeon::parse(&[
+            "            ",
+            " #####      ",
+            " #<.>#      ",
+            " #####      ",
+            "            ",
+            "            ",
+        ]);
+        d.land_stairs((8, 3).into(), Tile::Floor);
+
+        assert_eq!(d.entrance(), Some((8, 3).into()));
+        assert_eq!(d.get((2, 2)), Tile::Floor);
+        assert!(d.validate_boundaries().is_empty());
+        assert!(walkable_from_entrance(&d));
+    }
+
+    fn saved_stack() -> DungeonStack {
+        let mut stack = (0..6).filter_map(|seed| DungeonStack::generate(40, 30, 3, seed, plan).ok()).next().unwrap();
+        let stairs = stack.level(1).unwrap().entrance().unwrap();
+        stack.set_current(1).unwrap();
+        let seen = stack.level(1).unwrap().field_of_view(stairs, 6);
+        stack.exploration_mut(1).unwrap().mark_seen(seen);
+        stack
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let stack = saved_stack();
+        let mut bytes = Vec::new();
+        stack.save(&mut bytes).unwrap();
+
+        let mut loaded = DungeonStack::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded.current(), 1);
+        assert!(loaded.spec().is_none());
+        // only the current level is decoded up front
+        assert!(loaded.is_loaded(1) && !loaded.is_loaded(0) && !loaded.is_loaded(2));
+        assert_eq!(loaded.level(1).unwrap().render(), stack.level(1).unwrap().render());
+        assert_eq!(loaded.exploration(1), stack.exploration(1));
+        assert!(loaded.exploration(1).unwrap().seen_fraction() > 0.0);
+
+        // levels never loaded are written back as they were read
+        let mut again = Vec::new();
+        loaded.save(&mut again).unwrap();
+        assert_eq!(again, bytes);
+
+        for depth in 0..3 {
+            assert_eq!(loaded.load_level(depth).unwrap().render(), stack.level(depth).unwrap().render());
+            assert_eq!(loaded.level(depth).unwrap().exits(), stack.level(depth).unwrap().exits());
+        }
+        assert_eq!(loaded.load_level(3).err(), Some(LoadError::NoLevel(3)));
+        assert_eq!(loaded.set_current(3).err(), Some(LoadError::NoLevel(3)));
+        assert_eq!(loaded.current(), 1);
+    }
+
+    #[test]
+    fn test_corrupt_saves_are_errors() {
+        let mut bytes = Vec::new();
+        saved_stack().save(&mut bytes).unwrap();
+
+        for len in 0..bytes.len() {
+            assert!(DungeonStack::load(&bytes[..len]).is_err(), "truncated to {}", len);
+        }
+
+        // whatever a mangled save decodes to, reading it and every level in it must not panic
+        let mut rng = DungenRng::new(165);
+        for _ in 0..300 {
+            let mut mangled = bytes.clone();
+            for _ in 0..rng.range_inclusive(1, 4) {
+                let i = rng.range_exclusive(mangled.len() as isize) as usize;
+                mangled[i] = rng.next_u64() as u8;
+            }
+
+            if let Ok(mut stack) = DungeonStack::load(&mangled[..]) {
+                for depth in 0..stack.len() {
+                    let _ = stack.load_level(depth);
+                }
+            }
+        }
+
+        assert_eq!(DungeonStack::load(&b"DGNM\x01\x00"[..]).err(), Some(LoadError::BadMagic));
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        assert_eq!(DungeonStack::load(&newer[..]).err(), Some(LoadError::UnsupportedVersion(9)));
+    }
+
+    #[test]
+    fn test_pits() {
+        let mut checked = 0;
+        for seed in 0..6 {
+            // rooms over caves, so some pits need a landing dug
+            let mut stack = match DungeonStack::generate(60, 40, 3, seed, plan) {
+                Ok(stack) => stack,
+                Err(_) => continue,
+            };
+            let paths: Vec<Vec<Point>> = (0..2).map(|depth| stack.level(depth).unwrap().main_path().unwrap().tiles).collect();
+
+            let added = stack.add_pits(12, &mut DungenRng::new(seed)).unwrap();
+            assert!(added > 0, "seed {}", seed);
+            assert_eq!(stack.pits().len(), added);
+
+            for pit in stack.pits() {
+                let (upper, lower) = (stack.level(pit.depth).unwrap(), stack.level(pit.depth + 1).unwrap());
+                assert_eq!(upper.get(pit.pos), Tile::Pit);
+                assert!(matches!(lower.get(pit.pos), Tile::Floor | Tile::Grass | Tile::Corridor), "seed {} {:?}", seed, pit);
+                assert!(!paths[pit.depth].contains(&pit.pos));
+            }
+            for depth in 0..3 {
+                let level = stack.level(depth).unwrap();
+                assert!(level.validate_boundaries().is_empty(), "seed {} depth {}", seed, depth);
+                // nothing is cut off by a pit or left stranded under one
+                let dist = level.distance_map(level.entrance().unwrap(), Connectivity::Four);
+                let size = level.size();
+                for y in 0..size.height {
+                    for x in 0..size.width {
+                        let reached = dist[(x + y * size.width) as usize].is_some();
+                        assert_eq!(reached, level.get((x, y)).is_walkable(), "seed {} depth {} ({}, {})", seed, depth, x, y);
+                    }
+                }
+            }
+
+            let mut bytes = Vec::new();
+            stack.save(&mut bytes).unwrap();
+            let mut loaded = DungeonStack::load(&bytes[..]).unwrap();
+            assert_eq!(loaded.pits(), stack.pits());
+            assert!(loaded.load_level(1).is_ok());
+            checked += 1;
+        }
+        assert!(checked > 2);
+    }
+
+    #[test]
+    fn test_pit_landing_dug_below() {
+        let upper = Dungeon::parse(&[
+            "################",
+            "#<.............#",
+            "#..............#",
+            "#..............#",
+            "#.............>#",
+            "################",
+        ]);
+        let lower = Dungeon::parse(&[
+            "########        ",
+            "#<....>#        ",
+            "########        ",
+            "                ",
+            "                ",
+            "                ",
+        ]);
+        let levels = vec![upper, lower].into_iter().map(|d| {
+            let explored = Exploration::new(&d);
+            Level::Loaded(Box::new(d), explored)
+        }).collect();
+        let mut stack = DungeonStack { spec: None, levels, current: 0, pits: Vec::new(), locks: Vec::new() };
+
+        assert_eq!(stack.add_pits(1, &mut DungenRng::new(3)).unwrap(), 1);
+        let pit = stack.pits()[0];
+        // nothing below is open under the room above, so the pit has to land within reach
+        assert!(pit.pos.x <= 11 && pit.pos.y >= 2 && pit.pos.y <= 3);
+        let lower = stack.level(1).unwrap();
+        assert_eq!(lower.get(pit.pos), Tile::Floor);
+        assert!(lower.validate_boundaries().is_empty());
+        let dist = lower.distance_map(lower.entrance().unwrap(), Connectivity::Four);
+        assert!(dist[(pit.pos.x + pit.pos.y * 16) as usize].is_some());
+    }
+
+    #[test]
+    fn test_cross_level_locks() {
+        let mut locked = 0;
+        for seed in 0..24 {
+            let mut stack = match DungeonStack::generate(50, 35, 4, seed, |_| LevelSpec::default()) {
+                Ok(stack) => stack,
+                Err(_) => continue,
+            };
+            let mut rng = DungenRng::new(seed);
+            if seed % 2 == 0 {
+                stack.add_pits(2, &mut rng).unwrap();
+            }
+
+            let added = stack.add_cross_level_locks(&mut rng).unwrap();
+            assert_eq!(stack.locks().len(), added);
+            assert!(stack.can_reach_final_exit(), "seed {}", seed);
+
+            for lock in stack.locks() {
+                let (upper, lower) = (stack.level(lock.depth).unwrap(), stack.level(lock.depth + 1).unwrap());
+                assert_eq!(lower.get(lock.door), Tile::ClosedDoor);
+                let (entrance, exit) = (lower.entrance().unwrap(), lower.exit().unwrap());
+                assert!(lower.find_path_where(entrance, exit, Connectivity::Four, |p| p != lock.door).is_none());
+                assert_eq!(upper.get(lock.key), Tile::Floor);
+                assert!(upper.off_path_rooms().contains(&upper.graph().room_at(lock.key).unwrap()));
+            }
+            locked += added;
+
+            // with a key out of reach the stack can't be finished, unless a pit drops past
+            // the door
+            if stack.pits().is_empty() && added > 0 {
+                stack.locks[0].key = Point::new(0, 0);
+                assert!(!stack.can_reach_final_exit(), "seed {}", seed);
+            }
+        }
+        assert!(locked > 10, "{} locks", locked);
+    }
+
+    #[test]
+    fn test_locks_survive_saving() {
+        let mut stack = (0..10).filter_map(|seed| DungeonStack::generate(50, 35, 3, seed, |_| LevelSpec::default()).ok())
+            .find(|stack| stack.level(1).unwrap().main_path().is_some())
+            .unwrap();
+        stack.add_cross_level_locks(&mut DungenRng::new(1)).unwrap();
+        assert!(!stack.locks().is_empty());
+
+        let mut bytes = Vec::new();
+        stack.save(&mut bytes).unwrap();
+        let mut loaded = DungeonStack::load(&bytes[..]).unwrap();
+        assert_eq!(loaded.locks(), stack.locks());
+        assert!(!loaded.can_reach_final_exit());
+        loaded.load_all().unwrap();
+        assert!(loaded.can_reach_final_exit());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_spec_round_trip() {
+        use serde_json;
+
+        let stack = (0..6).filter_map(|seed| DungeonStack::generate(60, 40, 4, seed, plan).ok()).next().unwrap();
+        let json = serde_json::to_string(stack.spec().unwrap()).unwrap();
+        let spec: StackSpec = serde_json::from_str(&json).unwrap();
+        assert_eq!(Some(&spec), stack.spec());
+
+        let rebuilt = DungeonStack::from_spec(spec).unwrap();
+        for depth in 0..4 {
+            assert_eq!(stack.level(depth).unwrap().render(), rebuilt.level(depth).unwrap().render());
+        }
+    }
+}
@@ -0,0 +1,267 @@
+/*
+Stamping one dungeon into another, for set pieces generated apart, a prison block built
+with one preset say, and then laid into a larger map built with another. Every tile of the
+source that isn't Unused is copied in at an offset, and the mode decides what happens where
+it lands on tiles already in use:
+
+  Strict     nothing may be in the way; the stamp fails instead
+  Overwrite  the source's tile replaces whatever was there
+  Merge      a wall is kept only where both maps have one. where either has something
+             that isn't wall, that wins, the source's tile if both do. two maps whose walls
+             overlap are joined wherever one's floor meets the other's wall.
+
+The source's records come along, moved by the offset: its rooms get fresh ids in this map,
+in the source's id order, with their purposes, names and other tags, and its corridors,
+entrances, exits and chests are appended after this map's own. Its broken and barred doors
+stay so where they land as doors. The source's boss and safe rooms only become this map's
+if it has none. Entrances, exits and chests of this map that the stamp covered with
+something else are dropped. Growth faces aren't carried over, so a stamped map isn't grown
+any further from the source's edges.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use std::collections::BTreeMap;
+use corridors::CorridorInfo;
+use dungeon::{ Dungeon, Tile };
+use error::StampError;
+use geom::{ Point, Rect };
+use graph::CorridorId;
+use spawn::ChestPlacement;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StampMode {
+    #[default]
+    Strict,
+    Overwrite,
+    Merge,
+}
+
+impl Dungeon {
+    // copies src's used tiles and its records into this map, src's (0, 0) landing on
+    // offset. fails without changing anything if a used tile would land off the map, or
+    // with StampMode::Strict, on a used tile.
+    pub fn stamp(&mut self, src: &Dungeon, offset: Point, mode: StampMode) -> Result<(), StampError> {
+        let size = self.size();
+        let map = Rect::new(0, 0, size.width, size.height);
+        let from = src.size();
+
+        let mut writes = Vec::new();
+        for y in 0..from.height {
+            for x in 0..from.width {
+                let tile = src.get((x, y));
+                if tile == Tile::Unused {
+                    continue;
+                }
+
+                let at = self.wrap_point(offset.offset(x, y));
+                if !map.contains(at) {
+                    return Err(StampError::OutOfBounds { at })
+                }
+
+                let here = self.get(at);
+                let tile = match mode {
+                    StampMode::Strict if here != Tile::Unused => return Err(StampError::Occupied { at }),
+                    StampMode::Merge => merged(here, tile),
+                    _ => tile,
+                };
+                writes.push((at, tile));
+            }
+        }
+
+        for &(at, tile) in &writes {
+            self.set_tile(at.x, at.y, tile);
+        }
+        let entrances: Vec<Point> = self.entrances.iter().cloned().filter(|&p| self.get(p) == Tile::Entrance).collect();
+        let exits: Vec<Point> = self.exits.iter().cloned().filter(|&p| self.get(p) == Tile::Exit).collect();
+        self.entrances = entrances;
+        self.exits = exits;
+        self.chests.retain(|chest| !writes.iter().any(|&(at, tile)| at == chest.pos && !matches!(tile, Tile::Floor | Tile::Grass)));
+
+        self.adopt_records(src, offset);
+        self.sync_rooms();
+        Ok(())
+    }
+
+    // src's rooms, corridors and objects, moved by offset, after this map's own
+    fn adopt_records(&mut self, src: &Dungeon, offset: Point) {
+        let moved = |d: &Dungeon, p: Point| d.wrap_point(p.offset(offset.x, offset.y));
+
+        let mut ids = BTreeMap::new();
+        for (id, anchor) in src.room_anchors() {
+            let new = self.register_room_at(moved(self, anchor));
+            ids.insert(id, new);
+        }
+        for (&old, &new) in &ids {
+            if let Some(&purpose) = src.purposes.get(&old) {
+                self.purposes.insert(new, purpose);
+            }
+            if let Some(name) = src.names.get(&old) {
+                self.names.insert(new, name.clone());
+            }
+            if src.dead_end_rooms.contains(&old) {
+                self.dead_end_rooms.insert(new);
+            }
+            if src.courtyards.contains(&old) {
+                self.courtyards.insert(new);
+            }
+            if src.caves.contains(&old) {
+                self.caves.insert(new);
+            }
+        }
+        if self.boss_room.is_none() {
+            self.boss_room = src.boss_room.and_then(|id| ids.get(&id).cloned());
+        }
+        if self.safe_room.is_none() {
+            self.safe_room = src.safe_room.and_then(|id| ids.get(&id).cloned());
+        }
+
+        self.rooms.extend(src.rooms.iter().map(|r| Rect::new(r.x + offset.x, r.y + offset.y, r.width, r.height)));
+        for corridor in &src.corridors {
+            let id = CorridorId(self.corridors.len() as u32);
+            let rect = Rect::new(corridor.rect.x + offset.x, corridor.rect.y + offset.y, corridor.rect.width, corridor.rect.height);
+            let path = corridor.path.iter().map(|&p| moved(self, p)).collect();
+            self.corridors.push(CorridorInfo { id, rect, path });
+        }
+
+        for &p in &src.entrances {
+            let p = moved(self, p);
+            if self.get(p) == Tile::Entrance {
+                self.entrances.push(p);
+            }
+        }
+        for &p in &src.exits {
+            let p = moved(self, p);
+            if self.get(p) == Tile::Exit {
+                self.exits.push(p);
+            }
+        }
+        for chest in &src.chests {
+            let room = ids.get(&chest.room).cloned().unwrap_or(chest.room);
+            self.chests.push(ChestPlacement { pos: moved(self, chest.pos), tier: chest.tier, room });
+        }
+        for (&p, &state) in &src.door_states {
+            let p = moved(self, p);
+            if self.get(p) == state.tile() {
+                self.door_states.insert(p, state);
+            }
+        }
+    }
+}
+
+// what StampMode::Merge leaves where the source's tile lands on here
+fn merged(here: Tile, tile: Tile) -> Tile {
+    if here == Tile::Unused || !tile.is_wall() {
+        tile
+    }
+
+    else {
+        here
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, Preset };
+    use dungeon::{ Dungeon, Tile };
+    use error::StampError;
+    use geom::{ Point, Rect };
+    use nav::Connectivity;
+    use rooms::RoomPurpose;
+    use stamp::*;
+
+    // a prison block built with one preset
+    fn block() -> Dungeon {
+        let mut d = Dungeon::with_config(30, 20, DungeonConfig::preset(Preset::Catacombs));
+        d.generate_seeded(12, 4).unwrap();
+        let id = d.room_ids().nth(1).unwrap();
+        d.purposes.insert(id, RoomPurpose::Prison);
+        d.sync_rooms();
+        d
+    }
+
+    #[test]
+    fn test_stamp_carries_records() {
+        let src = block();
+        let mut d = Dungeon::new(80, 50);
+        d.carve_rect(Rect::new(2, 2, 5, 4), Tile::Floor).unwrap();
+        let before = d.room_ids().count();
+        let offset = Point::new(40, 20);
+        d.stamp(&src, offset, StampMode::Strict).unwrap();
+
+        // the tiles, moved by the offset
+        for y in 0..20 {
+            for x in 0..30 {
+                if src.get((x, y)) != Tile::Unused {
+                    assert_eq!(d.get((x + 40, y + 20)), src.get((x, y)), "({}, {})", x, y);
+                }
+            }
+        }
+
+        // and every record
+        assert_eq!(d.room_ids().count(), before + src.room_ids().count());
+        let graph = d.graph();
+        let prison = graph.room_at(src.graph().room_tiles(src.room_ids().nth(1).unwrap())[0].offset(40, 20)).unwrap();
+        assert_eq!(d.room(prison).unwrap().purpose, Some(RoomPurpose::Prison));
+        assert_eq!(d.entrances(), &[src.entrance().unwrap().offset(40, 20)]);
+        assert_eq!(d.exits(), &[src.exit().unwrap().offset(40, 20)]);
+        assert_eq!(d.corridors().len(), src.corridors().len());
+        assert_eq!(d.corridors()[0].rect.origin(), src.corridors()[0].rect.origin().offset(40, 20));
+        for corridor in d.corridors() {
+            assert_eq!(d.corridor_at(corridor.rect.origin()), Some(corridor.id));
+        }
+        let (from, to) = (d.entrance().unwrap(), d.exit().unwrap());
+        assert_eq!(d.find_path(from, to, Connectivity::Four).map(|p| p.len()), src.find_path(src.entrance().unwrap(), src.exit().unwrap(), Connectivity::Four).map(|p| p.len()));
+    }
+
+    #[test]
+    fn test_stamp_modes() {
+        let src = block();
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(30, 2).unwrap();
+        let rendered = d.render();
+
+        // strict and off the map both leave everything alone
+        let err = d.stamp(&src, Point::new(10, 10), StampMode::Strict).unwrap_err();
+        assert!(matches!(err, StampError::Occupied { .. }));
+        assert!(d.stamp(&src, Point::new(40, 10), StampMode::Overwrite).is_err());
+        assert!(matches!(d.stamp(&src, Point::new(-31, 0), StampMode::Overwrite), Err(StampError::OutOfBounds { at }) if at.x < 0));
+        assert_eq!(d.render(), rendered);
+
+        // overwriting takes the old entrance and exit with it where they were covered
+        let (entrance, exit) = (d.entrance().unwrap(), d.exit().unwrap());
+        d.stamp(&src, Point::new((entrance.x - 10).clamp(0, 30), (entrance.y - 5).clamp(0, 20)), StampMode::Overwrite).unwrap();
+        assert!(d.entrances().iter().all(|&p| d.get(p) == Tile::Entrance));
+        assert!(d.exits().iter().all(|&p| d.get(p) == Tile::Exit));
+        let kept = [entrance, exit].iter().filter(|p| d.entrances().contains(p) || d.exits().contains(p)).count();
+        assert!(kept < 2);
+        assert_eq!(d.entrances().len() + d.exits().len(), kept + 2);
+    }
+
+    #[test]
+    fn test_merge_joins_walls() {
+        let mut d = Dungeon::parse(&[
+            "#####     ",
+            "#...#     ",
+            "#...#     ",
+            "#####     ",
+        ]);
+        let src = Dungeon::parse(&[
+            "######",
+            "#....#",
+            "#.####",
+            "###   ",
+        ]);
+        d.stamp(&src, Point::new(3, 0), StampMode::Merge).unwrap();
+
+        // each room's floor opened the other's wall where they overlap, and the walls
+        // both had stayed
+        assert_eq!(d.render(), "######### \n#.......# \n#....#### \n######    \n");
+        assert_eq!(d.room_ids().count(), 1);
+        assert_eq!(merged(Tile::Wall, Tile::Floor), Tile::Floor);
+        assert_eq!(merged(Tile::Floor, Tile::Wall), Tile::Floor);
+        assert_eq!(merged(Tile::Wall, Tile::Wall), Tile::Wall);
+        assert_eq!(merged(Tile::Unused, Tile::Wall), Tile::Wall);
+    }
+}
@@ -0,0 +1,386 @@
+/*
+Tile statistics: how many of each tile a map holds and how much of it is used, gathered in a
+single pass over the grid. Fractions are of every tile on the map, Unused included.
+
+The similarity measures compare two maps, for checking that different seeds really give
+different dungeons. Both run from 0.0 to 1.0, where 1.0 is a match.
+
+sample_seeds is for tuning map size and maxfeatures: it generates a run of seeds and sums up
+how far the exit is, how many rooms there are and how much of the map is walkable, with the
+seeds that stand out from the rest. One map is generated into over and over, put back blank
+between seeds, or one per thread with the rayon feature. The summary is the same either way.
+*/
+
+use std::ops::Range;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use config::DungeonConfig;
+use dungeon::{ Dungeon, DungeonSnapshot, Tile };
+use geom::Rect;
+
+// cells across and down in the grid profile_similarity lays over each map
+const PROFILE_GRID: isize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileCounts {
+    // indexed by the tile's position in Tile::iterator
+    pub counts: [usize; 18],
+    // smallest rect holding every tile that isn't Unused
+    bounds: Option<Rect>,
+}
+
+impl TileCounts {
+    pub fn get(&self, tile: Tile) -> usize {
+        self.counts[tile as usize]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn walkable_fraction(&self) -> f32 {
+        self.fraction(Tile::is_walkable)
+    }
+
+    // walls and torches
+    pub fn wall_fraction(&self) -> f32 {
+        self.fraction(Tile::is_wall)
+    }
+
+    // none if every tile is Unused
+    pub fn used_bounding_box(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    fn fraction(&self, f: fn(&Tile) -> bool) -> f32 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0
+        }
+
+        Tile::iterator().filter(|t| f(t)).map(|&t| self.get(t)).sum::<usize>() as f32 / total as f32
+    }
+}
+
+impl Dungeon {
+    pub fn tile_counts(&self) -> TileCounts {
+        let mut counts = [0; 18];
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (isize::MAX, isize::MAX, -1, -1);
+
+        for (y, row) in self.rows().enumerate() {
+            for (x, &tile) in row.iter().enumerate() {
+                counts[tile as usize] += 1;
+                if tile != Tile::Unused {
+                    let (x, y) = (x as isize, y as isize);
+                    min_x = min_x.min(x);
+                    min_y = min_y.min(y);
+                    max_x = max_x.max(x);
+                    max_y = max_y.max(y);
+                }
+            }
+        }
+
+        let bounds = if max_x < 0 { None } else { Some(Rect::new(min_x, min_y, max_x - min_x + 1, max_y - min_y + 1)) };
+        TileCounts { counts, bounds }
+    }
+
+    // smallest rect holding every tile that isn't Unused, none for a blank map
+    pub fn used_bounding_box(&self) -> Option<Rect> {
+        self.tile_counts().used_bounding_box()
+    }
+}
+
+// one seed's numbers
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SeedMetrics {
+    pub seed: u64,
+    // steps from the first entrance to the first exit, none if there's no way there
+    pub exit_distance: Option<u32>,
+    pub rooms: usize,
+    // walkable fraction of the whole map
+    pub coverage: f32,
+}
+
+// one metric over every seed that has it. all zeros when none does.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MetricSummary {
+    pub count: usize,
+    pub mean: f32,
+    pub median: f32,
+    pub p10: f32,
+    pub p90: f32,
+}
+
+impl MetricSummary {
+    fn of(mut values: Vec<f32>) -> MetricSummary {
+        let n = values.len();
+        if n == 0 {
+            return MetricSummary::default()
+        }
+
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // nearest rank
+        let rank = |p: f32| values[((p * n as f32).ceil() as usize).max(1) - 1];
+        let median = if n % 2 == 1 { values[n / 2] } else { (values[n / 2 - 1] + values[n / 2]) / 2.0 };
+
+        MetricSummary {
+            count: n,
+            mean: values.iter().sum::<f32>() / n as f32,
+            median,
+            p10: rank(0.1),
+            p90: rank(0.9),
+        }
+    }
+
+    // further below p10 or above p90 than half the distance between them
+    fn is_outlier(&self, value: f32) -> bool {
+        let slack = (self.p90 - self.p10) / 2.0;
+        value < self.p10 - slack || value > self.p90 + slack
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SampleSummary {
+    // every seed that generated, in seed order, for histograms
+    pub samples: Vec<SeedMetrics>,
+    // seeds whose generation failed, which count towards nothing else
+    pub failed: Vec<u64>,
+    pub exit_distance: MetricSummary,
+    pub rooms: MetricSummary,
+    pub coverage: MetricSummary,
+    // seeds with any metric an outlier, see MetricSummary::is_outlier, or with no way from
+    // the entrance to the exit, in order
+    pub outliers: Vec<u64>,
+}
+
+// generates every seed in seeds on a width by height map with the default config and sums
+// up the results
+pub fn sample_seeds(width: isize, height: isize, maxfeatures: isize, seeds: Range<u64>) -> SampleSummary {
+    sample_seeds_with_config(&DungeonConfig::default(), width, height, maxfeatures, seeds)
+}
+
+// sample_seeds with config
+pub fn sample_seeds_with_config(config: &DungeonConfig, width: isize, height: isize, maxfeatures: isize, seeds: Range<u64>) -> SampleSummary {
+    let blank = || {
+        let d = Dungeon::with_config(width, height, config.clone());
+        let snap = d.snapshot();
+        (d, snap)
+    };
+
+    #[cfg(feature = "rayon")]
+    let results: Vec<Result<SeedMetrics, u64>> = seeds.into_par_iter()
+        .map_init(blank, |(d, snap), seed| measure(d, snap, maxfeatures, seed))
+        .collect();
+
+    #[cfg(not(feature = "rayon"))]
+    let results: Vec<Result<SeedMetrics, u64>> = {
+        let (mut d, snap) = blank();
+        seeds.map(|seed| measure(&mut d, &snap, maxfeatures, seed)).collect()
+    };
+
+    let mut samples = Vec::new();
+    let mut failed = Vec::new();
+    for result in results {
+        match result {
+            Ok(metrics) => samples.push(metrics),
+            Err(seed) => failed.push(seed),
+        }
+    }
+
+    let exit_distance = MetricSummary::of(samples.iter().filter_map(|s| s.exit_distance).map(|d| d as f32).collect());
+    let rooms = MetricSummary::of(samples.iter().map(|s| s.rooms as f32).collect());
+    let coverage = MetricSummary::of(samples.iter().map(|s| s.coverage).collect());
+    let outliers = samples.iter()
+        .filter(|s| {
+            s.exit_distance.is_none_or(|d| exit_distance.is_outlier(d as f32))
+                || rooms.is_outlier(s.rooms as f32)
+                || coverage.is_outlier(s.coverage)
+        })
+        .map(|s| s.seed)
+        .collect();
+
+    SampleSummary { samples, failed, exit_distance, rooms, coverage, outliers }
+}
+
+// generates seed into d, put back to blank first, or gives the seed back if it fails
+fn measure(d: &mut Dungeon, blank: &DungeonSnapshot, maxfeatures: isize, seed: u64) -> Result<SeedMetrics, u64> {
+    d.restore(blank);
+    let report = d.generate_seeded(maxfeatures, seed).map_err(|_| seed)?;
+    Ok(SeedMetrics {
+        seed,
+        exit_distance: report.exit_distance,
+        rooms: report.rooms,
+        coverage: d.tile_counts().walkable_fraction(),
+    })
+}
+
+// fraction of tiles that are walkable in both maps or in neither. maps of different sizes
+// are laid over each other from the top left, and every tile only one of them has counts
+// as differing, as does the area neither covers.
+pub fn similarity(a: &Dungeon, b: &Dungeon) -> f32 {
+    let (sa, sb) = (a.size(), b.size());
+    let total = sa.width.max(sb.width) * sa.height.max(sb.height);
+    if total <= 0 {
+        return 1.0
+    }
+
+    let mut matching = 0;
+    for y in 0..sa.height.min(sb.height) {
+        for x in 0..sa.width.min(sb.width) {
+            if a.get((x, y)).is_walkable() == b.get((x, y)).is_walkable() {
+                matching += 1;
+            }
+        }
+    }
+    matching as f32 / total as f32
+}
+
+// a coarser comparison that doesn't need the maps to line up tile for tile: the average of
+// how close the room counts are and how close the walkable fractions of the cells of a 4x4
+// grid laid over each map are. two maps without rooms count as having the same number.
+pub fn profile_similarity(a: &Dungeon, b: &Dungeon) -> f32 {
+    let (ra, rb) = (a.room_ids().count(), b.room_ids().count());
+    let rooms = if ra.max(rb) == 0 { 1.0 } else { ra.min(rb) as f32 / ra.max(rb) as f32 };
+
+    let (ca, cb) = (coverage(a), coverage(b));
+    let apart: f32 = ca.iter().zip(cb.iter()).map(|(x, y)| (x - y).abs()).sum();
+    let coverage = 1.0 - apart / ca.len() as f32;
+
+    (rooms + coverage) / 2.0
+}
+
+// walkable fraction of each grid cell, row by row. a cell with no tiles, on a map smaller
+// than the grid, is 0.0.
+fn coverage(d: &Dungeon) -> Vec<f32> {
+    let size = d.size();
+    let cells = (PROFILE_GRID * PROFILE_GRID) as usize;
+    let (mut walkable, mut tiles) = (vec![0; cells], vec![0; cells]);
+
+    for (y, row) in d.rows().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let cx = x as isize * PROFILE_GRID / size.width;
+            let cy = y as isize * PROFILE_GRID / size.height;
+            let cell = (cx + cy * PROFILE_GRID) as usize;
+            tiles[cell] += 1;
+            if tile.is_walkable() {
+                walkable[cell] += 1;
+            }
+        }
+    }
+
+    walkable.iter().zip(tiles.iter()).map(|(&w, &t)| if t == 0 { 0.0 } else { w as f32 / t as f32 }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Rect;
+    use super::{ profile_similarity, sample_seeds, similarity };
+
+    #[test]
+    fn test_tile_counts() {
+        let d = Dungeon::parse(&[
+            "          ",
+            "  #####   ",
+            "  #..!#   ",
+            "  #<.+,,  ",
+            "  #####   ",
+            "          ",
+        ]);
+        let counts = d.tile_counts();
+
+        assert_eq!(counts.total(), 60);
+        assert_eq!(counts.get(Tile::Unused), 39);
+        assert_eq!(counts.get(Tile::Wall), 13);
+        assert_eq!(counts.get(Tile::Torch), 1);
+        assert_eq!(counts.get(Tile::Floor), 3);
+        assert_eq!(counts.get(Tile::Entrance), 1);
+        assert_eq!(counts.get(Tile::ClosedDoor), 1);
+        assert_eq!(counts.get(Tile::Corridor), 2);
+        assert_eq!(counts.walkable_fraction(), 7.0 / 60.0);
+        assert_eq!(counts.wall_fraction(), 14.0 / 60.0);
+        assert_eq!(d.used_bounding_box(), Some(Rect::new(2, 1, 6, 4)));
+
+        assert_eq!(Dungeon::parse(&["    "; 3]).used_bounding_box(), None);
+    }
+
+    #[test]
+    fn test_similarity_edge_cases() {
+        let room = Dungeon::parse(&[
+            "      ",
+            " #### ",
+            " #..# ",
+            " #### ",
+        ]);
+        let blank = Dungeon::parse(&["      "; 4]);
+        let wide = Dungeon::parse(&["            "; 4]);
+
+        assert_eq!(similarity(&room, &room), 1.0);
+        assert_eq!(similarity(&blank, &blank), 1.0);
+        assert_eq!(similarity(&room, &blank), 22.0 / 24.0);
+        // the half only wide has counts against it
+        assert_eq!(similarity(&blank, &wide), 0.5);
+        assert_eq!(similarity(&wide, &blank), 0.5);
+
+        assert_eq!(profile_similarity(&room, &room), 1.0);
+        assert_eq!(profile_similarity(&blank, &wide), 1.0);
+        assert!(profile_similarity(&room, &blank) < 1.0);
+        assert_eq!(profile_similarity(&Dungeon::parse(&["."]), &Dungeon::parse(&["."])), 1.0);
+    }
+
+    #[test]
+    fn test_neighbouring_seeds_differ() {
+        let generate = |seed| {
+            let mut d = Dungeon::new(80, 50);
+            d.generate_seeded(60, seed).ok().map(|_| d)
+        };
+
+        let maps: Vec<Dungeon> = (0..30).filter_map(generate).collect();
+        assert!(maps.len() > 20);
+        assert_eq!(similarity(&maps[0], &maps[0]), 1.0);
+
+        let pairs: Vec<(f32, f32)> = maps.windows(2).map(|w| (similarity(&w[0], &w[1]), profile_similarity(&w[0], &w[1]))).collect();
+        let mean = pairs.iter().map(|p| p.0).sum::<f32>() / pairs.len() as f32;
+        let mean_profile = pairs.iter().map(|p| p.1).sum::<f32>() / pairs.len() as f32;
+        // unrelated maps still agree on most of the rock between rooms, which keeps the
+        // tile measure well above zero
+        assert!(mean < 0.7, "mean similarity {}", mean);
+        assert!(pairs.iter().all(|p| p.0 < 0.75), "{:?}", pairs);
+        assert!(mean_profile < 0.95, "mean profile similarity {}", mean_profile);
+    }
+
+    #[test]
+    fn test_sample_seeds() {
+        let summary = sample_seeds(80, 50, 60, 10..30);
+        assert_eq!(summary.samples.len() + summary.failed.len(), 20);
+        assert!(summary.samples.len() > 15);
+        assert!(summary.samples.windows(2).all(|w| w[0].seed < w[1].seed));
+
+        for metric in &[summary.exit_distance, summary.rooms, summary.coverage] {
+            assert!(metric.count > 15, "{:?}", metric);
+            assert!(metric.p10 <= metric.median && metric.median <= metric.p90, "{:?}", metric);
+            assert!(metric.p10 <= metric.mean && metric.mean <= metric.p90, "{:?}", metric);
+        }
+        assert_eq!(summary.rooms.count, summary.samples.len());
+        assert!(summary.rooms.p10 >= 1.0);
+        assert!(summary.coverage.p90 < 1.0);
+        assert!(summary.outliers.iter().all(|seed| summary.samples.iter().any(|s| s.seed == *seed)));
+
+        // the reused map gives what a fresh one does
+        for s in summary.samples.iter().step_by(7) {
+            let mut d = Dungeon::new(80, 50);
+            let report = d.generate_seeded(60, s.seed).unwrap();
+            assert_eq!((report.exit_distance, report.rooms), (s.exit_distance, s.rooms), "seed {}", s.seed);
+            assert_eq!(d.tile_counts().walkable_fraction(), s.coverage);
+        }
+
+        assert_eq!(sample_seeds(80, 50, 60, 5..5), sample_seeds(80, 50, 60, 7..7));
+        assert_eq!(sample_seeds(80, 50, 60, 5..5).rooms.count, 0);
+    }
+}
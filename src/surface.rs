@@ -0,0 +1,194 @@
+/*
+Levels entered from an overworld. The top rows of the map are a strip of open ground, grass
+walled in along the map's edges, and the dungeon is laid out below it as generate_seeded
+would, kept out of the strip and off the row of cliff under it. The topmost room gets a
+corridor straight up to the cliff, and the tile where it breaks through is the entrance, the
+cave mouth. The corridor goes up from the topmost room whose way up crosses no other room,
+and runs beside none, falling back on the topmost room of all.
+
+A few boulders, single wall tiles, are dropped on the grass, each only where grass surrounds
+it on all eight sides, so none of them can cut the strip in two.
+*/
+
+use dungeon::{ Dungeon, GenerationReport, Tile };
+use error::{ DungeonError, PlacementError };
+use geom::{ Point, Rect };
+use graph::{ self, NodeKind };
+use rng::{ self, DungenRng };
+
+// the dungeon below the strip has at least this many rows, walls included
+const MIN_DUNGEON_ROWS: isize = 8;
+// grass tiles per boulder, roughly
+const BOULDER_SPACING: isize = 24;
+
+impl Dungeon {
+    // the rooms and corridors of generate_seeded below a strip of surface_rows rows of open
+    // ground along the top of the map, joined to it by a cave mouth that is the entrance.
+    // fails with DungeonError::Surface if the strip leaves too little map below it, or
+    // the map wraps.
+    pub fn generate_with_surface_seeded(&mut self, surface_rows: isize, maxfeatures: isize, seed: u64) -> Result<GenerationReport, DungeonError> {
+        let size = self.size();
+        let inset = self.config().border.max(1);
+        if self.wraps() || surface_rows <= inset || size.height - surface_rows < MIN_DUNGEON_ROWS + inset {
+            return Err(DungeonError::Surface { rows: surface_rows })
+        }
+
+        // the room tiles stay a row clear of the cliff, so their walls never make it
+        self.bounds = Some(Rect::new(0, surface_rows + 2, size.width, size.height - surface_rows - 2));
+        let generated = self.generate_seeded(maxfeatures, seed);
+        self.bounds = None;
+        let generated = generated?;
+
+        let mut rng = DungenRng::new(rng::stream_seed(seed, "surface"));
+        let mouth = self.dig_cave_mouth(surface_rows, &mut rng).ok_or(DungeonError::NoEntrance(PlacementError::NoRooms))?;
+        let grass = Rect::new(inset, inset, size.width - inset * 2, surface_rows - inset);
+        for p in grass.points() {
+            self.set_tile(p.x, p.y, Tile::Grass);
+        }
+        self.wall_in();
+        self.drop_boulders(&grass, &mut rng);
+
+        // the entrance placed in the dungeon moves out to the mouth
+        match self.entrance() {
+            Some(old) => {
+                self.move_object(old, mouth);
+                self.set_tile(old.x, old.y, Tile::Floor);
+            }
+            None => self.set_object(mouth, Tile::Entrance),
+        }
+
+        self.sync_rooms();
+        self.finish(generated.features, seed)
+    }
+
+    // generate_with_surface_seeded, seeded from rng
+    pub fn generate_with_surface(&mut self, surface_rows: isize, maxfeatures: isize, rng: &mut DungenRng) -> Result<GenerationReport, DungeonError> {
+        let seed = rng.next_u64();
+        self.generate_with_surface_seeded(surface_rows, maxfeatures, seed)
+    }
+
+    // opens the cliff row above the topmost room that has a clear column up to it, through
+    // a door in that room's wall. a clear column crosses only rock, walls and corridors,
+    // with no room beside it. failing one, any column over the topmost room is dug, which
+    // always works since nothing of a room lies above its top row. returns the mouth, the
+    // tile in the cliff row, or none if the map has no rooms.
+    fn dig_cave_mouth(&mut self, cliff: isize, rng: &mut DungenRng) -> Option<Point> {
+        let graph = self.graph();
+        let mut rooms: Vec<&[Point]> = graph.room_ids().into_iter().map(|id| graph.room_tiles(id)).collect();
+        rooms.sort_by_key(|tiles| (tiles[0].y, tiles[0].x));
+
+        let room = |d: &Dungeon, x: isize, y: isize| graph::node_kind(d.get((x, y))) == Some(NodeKind::Room);
+        let clear = |d: &Dungeon, x: isize, top: isize, strict: bool| {
+            (cliff + 1..top - 1).all(|y| if strict {
+                matches!(d.get((x, y)), Tile::Unused | Tile::Wall | Tile::Corridor) && !room(d, x - 1, y) && !room(d, x + 1, y)
+            }
+
+            else {
+                !room(d, x, y)
+            })
+        };
+
+        for &strict in &[true, false] {
+            for tiles in &rooms {
+                let top = tiles[0].y;
+                let columns: Vec<isize> = tiles.iter().take_while(|p| p.y == top).map(|p| p.x).filter(|&x| clear(self, x, top, strict)).collect();
+                if columns.is_empty() {
+                    continue;
+                }
+
+                let x = columns[rng.range_exclusive(columns.len() as isize) as usize];
+                for y in cliff..top - 1 {
+                    self.set_tile(x, y, Tile::Corridor);
+                }
+                self.write_door(x, top - 1, Tile::Corridor, rng);
+                self.wall_in();
+                return Some(Point::new(x, cliff))
+            }
+        }
+
+        None
+    }
+
+    // a boulder for about every BOULDER_SPACING tiles of grass, each with grass all around
+    fn drop_boulders(&mut self, grass: &Rect, rng: &mut DungenRng) {
+        let wanted = grass.width * grass.height / BOULDER_SPACING;
+        let surrounded = |d: &Dungeon, p: Point| (-1..=1).all(|dy| (-1..=1).all(|dx| d.get(p.offset(dx, dy)) == Tile::Grass));
+        for _ in 0..wanted * 4 {
+            if wanted == 0 {
+                break;
+            }
+
+            let p = Point::new(rng.range_exclusive(grass.width) + grass.x, rng.range_exclusive(grass.height) + grass.y);
+            if surrounded(self, p) {
+                self.set_tile(p.x, p.y, Tile::Wall);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::DungeonConfig;
+    use dungeon::{ Dungeon, Tile };
+    use error::DungeonError;
+    use nav::Connectivity;
+    use rng::DungenRng;
+
+    #[test]
+    fn test_surface_levels() {
+        for seed in 0..12 {
+            let rows = 4 + seed as isize % 4;
+            let mut d = Dungeon::new(70, 50);
+            d.generate_with_surface_seeded(rows, 50, seed).unwrap();
+
+            // the strip is grass and boulders, open to the dungeon only at the mouth
+            let entrance = d.entrance().unwrap();
+            assert_eq!(entrance.y, rows, "seed {}\n{}", seed, d.render());
+            assert_eq!(d.get(entrance.offset(0, -1)), Tile::Grass);
+            for y in 0..rows {
+                for x in 0..70 {
+                    assert!(matches!(d.get((x, y)), Tile::Grass | Tile::Wall), "seed {}: ({}, {})", seed, x, y);
+                }
+            }
+            let cliff: Vec<Tile> = (0..70).map(|x| d.get((x, rows))).filter(|t| t.is_walkable()).collect();
+            assert_eq!(cliff, vec![Tile::Entrance]);
+            assert!((0..70).any(|x| d.get((x, rows / 2)) == Tile::Grass));
+
+            // everything walkable, the surface included, is reached from the entrance
+            assert!(d.validate_boundaries().is_empty(), "seed {}", seed);
+            assert_eq!(d.regions(Connectivity::Four).len(), 1, "seed {}\n{}", seed, d.render());
+            assert_eq!(d.entrances().len(), 1);
+            assert!(d.main_path().is_some());
+            assert!(!d.exits().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_surface_boulders_and_repeats() {
+        let mut a = Dungeon::new(80, 50);
+        let mut b = Dungeon::new(80, 50);
+        a.generate_with_surface_seeded(8, 50, 3).unwrap();
+        b.generate_with_surface(8, 50, &mut DungenRng::new(9)).unwrap();
+        let mut c = Dungeon::new(80, 50);
+        c.generate_with_surface(8, 50, &mut DungenRng::new(9)).unwrap();
+        assert_eq!(b.render(), c.render());
+
+        // boulders stand alone in the grass
+        let boulders: Vec<(isize, isize)> = (2..7).flat_map(|y| (2..78).map(move |x| (x, y))).filter(|&p| a.get(p) == Tile::Wall).collect();
+        assert!(!boulders.is_empty());
+        for &(x, y) in &boulders {
+            let walls = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy))).filter(|&p| a.get(p) == Tile::Wall).count();
+            assert_eq!(walls, 1, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_surface_that_doesnt_fit() {
+        let mut d = Dungeon::new(60, 30);
+        assert_eq!(d.generate_with_surface_seeded(1, 40, 1).err(), Some(DungeonError::Surface { rows: 1 }));
+        assert_eq!(d.generate_with_surface_seeded(25, 40, 1).err(), Some(DungeonError::Surface { rows: 25 }));
+
+        let mut wrapping = Dungeon::with_config(60, 30, DungeonConfig { wrap: true, ..DungeonConfig::default() });
+        assert_eq!(wrapping.generate_with_surface_seeded(5, 40, 1).err(), Some(DungeonError::Surface { rows: 5 }));
+    }
+}
@@ -0,0 +1,236 @@
+/*
+Invariant checks and random configurations for fuzzing, behind the "testing" feature.
+check_invariants bundles everything a generated dungeon is expected to satisfy, so
+downstream pipelines can assert the same things the crate's own tests do.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use config::{ CorridorStyle, DoorState, DungeonConfig, RoomSizeDistribution };
+use dungeon::{ Dungeon, Rect, Tile };
+use features::FeatureRef;
+use geom::Point;
+use nav::Connectivity;
+use rng::DungenRng;
+use validate::{ BoundaryViolation, DoorViolation };
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum InvariantViolation {
+    // a walkable tile open to the void or the map edge
+    Boundary(BoundaryViolation),
+    // a door that isn't in a straight wall, or sits beside another door
+    Door(DoorViolation),
+    MissingEntrance,
+    MissingExit,
+    // the entrance or exit lies outside the map
+    OutOfBounds(Point),
+    // a walkable tile the entrance can't reach
+    Unreachable(Point),
+    // a recorded room covers a tile that isn't room floor
+    RoomTile { room: Rect, at: Point },
+    // the tile grid doesn't hold width * height tiles
+    TileCount { expected: usize, found: usize },
+    // the feature layer gives a room tile to some other room, or none
+    FeatureLayer(Point),
+    // the orientation layer is out of date for a door or corridor tile
+    OrientationLayer(Point),
+}
+
+// everything a generated dungeon breaks, in a fixed order. empty for a sound map.
+pub fn check_invariants(d: &Dungeon) -> Vec<InvariantViolation> {
+    let mut out = Vec::new();
+    let size = d.size();
+
+    let expected = (size.width * size.height) as usize;
+    let found = d.tile_indices(|_| 0).len();
+    if found != expected {
+        out.push(InvariantViolation::TileCount { expected, found });
+        return out
+    }
+
+    out.extend(d.validate_boundaries().into_iter().map(InvariantViolation::Boundary));
+    out.extend(d.validate_doors().into_iter().map(InvariantViolation::Door));
+
+    let inside = |p: Point| p.x >= 0 && p.y >= 0 && p.x < size.width && p.y < size.height;
+    if d.exit().is_none() {
+        out.push(InvariantViolation::MissingExit);
+    }
+    if let Some(exit) = d.exit().filter(|&p| !inside(p)) {
+        out.push(InvariantViolation::OutOfBounds(exit));
+    }
+
+    match d.entrance() {
+        None => out.push(InvariantViolation::MissingEntrance),
+        Some(entrance) if !inside(entrance) => out.push(InvariantViolation::OutOfBounds(entrance)),
+        Some(entrance) => {
+            let dist = d.distance_map(entrance, Connectivity::Four);
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    if d.get((x, y)).is_walkable() && dist[(x + y * size.width) as usize].is_none() {
+                        out.push(InvariantViolation::Unreachable(Point::new(x, y)));
+                    }
+                }
+            }
+        }
+    }
+
+    for &room in d.room_rects() {
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                let at = d.wrap_point(Point::new(x, y));
+                match d.get(at) {
+                    Tile::Floor | Tile::Grass | Tile::Entrance | Tile::Exit | Tile::Npc | Tile::Pit | Tile::Altar | Tile::Pillar | Tile::Water => {}
+                    _ => out.push(InvariantViolation::RoomTile { room, at }),
+                }
+            }
+        }
+    }
+
+    let graph = d.graph();
+    for y in 0..size.height {
+        for x in 0..size.width {
+            let p = Point::new(x, y);
+            if let Some(id) = graph.room_at(p) {
+                if d.feature_at(p) != Some(FeatureRef::Room(id)) {
+                    out.push(InvariantViolation::FeatureLayer(p));
+                }
+            }
+            if d.orientation_layer()[d.index(p)] != d.orientation_value(p) {
+                out.push(InvariantViolation::OrientationLayer(p));
+            }
+        }
+    }
+
+    out
+}
+
+// a random configuration that generation accepts
+pub fn arbitrary_config(rng: &mut DungenRng) -> DungeonConfig {
+    let mut direction_weights = [0; 4];
+    for w in direction_weights.iter_mut() {
+        *w = rng.range_inclusive(1, 10) as u32;
+    }
+
+    let min = rng.range_inclusive(1, 6);
+    let max = rng.range_inclusive(min, 16);
+    let room_size = match rng.range_exclusive(3) {
+        0 => RoomSizeDistribution::Uniform { min, max },
+        1 => RoomSizeDistribution::Normal {
+            mean: (min + max) as f64 / 2.0,
+            std_dev: 0.5 + rng.next_f64() * 4.0,
+            min,
+            max,
+        },
+        _ => {
+            let entries = rng.range_inclusive(1, 4);
+            RoomSizeDistribution::Weighted((0..entries).map(|_| (rng.range_inclusive(min, max), rng.range_inclusive(1, 5) as u32)).collect())
+        }
+    };
+
+    let max_aspect_ratio = if rng.coin() { Some(1.0 + rng.next_f64() as f32 * 2.0) } else { None };
+    let min_corridor_length = rng.range_inclusive(1, 6);
+
+    DungeonConfig {
+        direction_weights,
+        room_size,
+        max_aspect_ratio,
+        wrap: rng.coin(),
+        sealed: rng.coin(),
+        room_chance: rng.range_inclusive(10, 90) as u32,
+        min_corridor_length,
+        max_corridor_length: rng.range_inclusive(min_corridor_length, 16),
+        corridor_width: rng.range_inclusive(1, 3),
+        loops: rng.range_inclusive(0, 4) as usize,
+        ruins: rng.coin(),
+        door_chance: if rng.coin() { 1.0 } else { rng.next_f64() as f32 },
+        archway_tile: if rng.coin() { Some(Tile::Archway) } else { None },
+        door_state: match rng.range_exclusive(3) {
+            0 => DoorState::AllClosed,
+            1 => DoorState::AllOpen,
+            _ => DoorState::Random { open_chance: rng.next_f64() as f32 },
+        },
+        corridor_style: match rng.range_exclusive(3) {
+            0 => CorridorStyle::Straight,
+            1 => CorridorStyle::Meandering { jitter: rng.next_f64() as f32 },
+            _ => CorridorStyle::Organic,
+        },
+        ..DungeonConfig::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Rect };
+    use geom::Point;
+    use rng::DungenRng;
+    use testing::*;
+
+    #[test]
+    fn test_invariants_catch_broken_maps() {
+        let d = Dungeon::parse(&[
+            "##########",
+            "#..<#.>..#",
+            "#.......#.",
+            "#########.",
+        ]);
+        let found = check_invariants(&d);
+
+        assert!(found.iter().any(|v| matches!(*v, InvariantViolation::Boundary(_))));
+        assert!(!found.contains(&InvariantViolation::MissingEntrance));
+
+        let empty = Dungeon::parse(&[
+            "#####",
+            "#...#",
+            "#####",
+        ]);
+        assert_eq!(check_invariants(&empty), vec![InvariantViolation::MissingExit, InvariantViolation::MissingEntrance]);
+
+        let split = Dungeon::parse(&[
+            "#########",
+            "#.<.#.>.#",
+            "#########",
+        ]);
+        assert_eq!(check_invariants(&split), vec![
+            InvariantViolation::Unreachable(Point::new(5, 1)),
+            InvariantViolation::Unreachable(Point::new(6, 1)),
+            InvariantViolation::Unreachable(Point::new(7, 1)),
+        ]);
+    }
+
+    #[test]
+    fn test_room_rects_must_be_floor() {
+        let mut d = Dungeon::new(20, 20);
+        d.carve_rect(Rect::new(2, 2, 4, 3), Tile::Floor).unwrap();
+        d.set_tile(3, 3, Tile::Wall);
+
+        assert!(check_invariants(&d).contains(&InvariantViolation::RoomTile { room: Rect::new(2, 2, 4, 3), at: Point::new(3, 3) }));
+    }
+
+    // random configs, dimensions and seeds. every map that generates must be sound.
+    #[test]
+    fn test_arbitrary_dungeons_hold_invariants() {
+        let mut rng = DungenRng::new(138);
+        let mut generated = 0;
+
+        for _ in 0..300 {
+            let config = arbitrary_config(&mut rng);
+            let (width, height) = (rng.range_inclusive(10, 199), rng.range_inclusive(10, 199));
+            let seed = rng.next_u64();
+            let features = rng.range_inclusive(1, 120);
+
+            let mut d = match Dungeon::try_with_config(width, height, config.clone()) {
+                Ok(d) => d,
+                Err(_) => continue,
+            };
+            if d.generate_seeded(features, seed).is_err() {
+                continue;
+            }
+
+            generated += 1;
+            assert_eq!(check_invariants(&d), vec![], "{}x{} seed {} features {} {:?}", width, height, seed, features, config);
+        }
+
+        assert!(generated > 200, "only {} of 300 dungeons generated", generated);
+    }
+}
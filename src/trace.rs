@@ -0,0 +1,68 @@
+/*
+Placement events emitted while a dungeon is generated, and the debug trace built on them.
+
+A DebugTrace holds everything needed to rebuild a map exactly: the seed and config it was
+generated with, plus every placement in order. Replaying it re-applies the placements
+directly instead of re-rolling randomness, so a trace attached to a bug report reproduces
+the broken map even if generation itself has changed since. Dungeon::from_trace is the
+strict version for stored levels: it refuses traces from another generation version and
+any event that doesn't fit the map, instead of silently building something different.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use config::DungeonConfig;
+use doors::DoorCondition;
+use dungeon::{ Dungeon, Rect, Tile };
+use geom::Point;
+
+// a single change made to the map during generation
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum GenEvent {
+    // a room was carved, walls included
+    RoomPlaced(Rect),
+    // a corridor was carved, walls included
+    CorridorPlaced(Rect),
+    // a meandering corridor was carved tile by tile, walls included
+    PathPlaced(Vec<Point>),
+    // a connecting tile (door or corridor opening) was written
+    TilePlaced { x: isize, y: isize, tile: Tile },
+    // the entrance or exit was placed in a room
+    ObjectPlaced { x: isize, y: isize, tile: Tile },
+    // an exit the entrances couldn't all reach was taken up, leaving floor
+    ObjectRemoved { x: isize, y: isize },
+    // a ruins layout was walled in
+    WallsRaised,
+    // the border was filled with the config's border_tile
+    BorderFilled,
+    // the map was sealed, its rock turned to wall
+    Sealed,
+    // a door was broken or barred
+    DoorDamaged { x: isize, y: isize, state: DoorCondition },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DebugTrace {
+    pub generation_version: u32,
+    pub seed: u64,
+    pub maxfeatures: isize,
+    pub width: isize,
+    pub height: isize,
+    pub config: DungeonConfig,
+    pub events: Vec<GenEvent>,
+}
+
+impl DebugTrace {
+    // rebuilds the traced map by re-applying its events. the growth frontier is not
+    // part of the trace, so the result can be inspected but not grown further.
+    pub fn replay(&self) -> Dungeon {
+        let mut d = Dungeon::with_config(self.width, self.height, self.config.clone());
+        for event in &self.events {
+            d.apply_event(event);
+        }
+
+        d
+    }
+}
@@ -0,0 +1,201 @@
+/*
+Post-generation checks. Validators never change the map; each returns the offending
+positions in row-major order, so an empty list means the rule holds.
+*/
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DoorRule {
+    // a door must be walkable on exactly two opposite sides and blocked on the other two
+    Misaligned,
+    // two doors must never touch orthogonally
+    Adjacent,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DoorViolation {
+    pub pos: Point,
+    pub rule: DoorRule,
+}
+
+// a walkable tile with an unenclosed side
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoundaryViolation {
+    pub pos: Point,
+    // the side that is open
+    pub dir: Dir,
+    // what lies that way: Unused, or none past the edge of the map
+    pub neighbor: Option<Tile>,
+}
+
+impl Dungeon {
+    // whether a door at p would sit in a straight wall between two walkable tiles
+    pub fn door_fits(&self, p: Point) -> bool {
+        let open = |dx, dy| self.get(p.offset(dx, dy)).is_walkable();
+        let north_south = open(0, -1) && open(0, 1);
+        let east_west = open(-1, 0) && open(1, 0);
+        let blocked_ns = !open(0, -1) && !open(0, 1);
+        let blocked_ew = !open(-1, 0) && !open(1, 0);
+
+        (north_south && blocked_ew) || (east_west && blocked_ns)
+    }
+
+    pub fn touches_door(&self, p: Point) -> bool {
+        Dir::iterator().any(|dir| {
+            let (dx, dy) = dir.offset();
+            self.get(p.offset(dx, dy)).is_door()
+        })
+    }
+
+    // every walkable tile must be closed in on all four sides by walkable tiles, walls or
+    // objects. one violation is reported per open side.
+    pub fn validate_boundaries(&self) -> Vec<BoundaryViolation> {
+        let size = self.size();
+        let mut out = Vec::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if !self.get(p).is_walkable() {
+                    continue;
+                }
+
+                for dir in Dir::iterator() {
+                    let (dx, dy) = dir.offset();
+                    let n = self.wrap_point(p.offset(dx, dy));
+                    if n.x < 0 || n.y < 0 || n.x >= size.width || n.y >= size.height {
+                        out.push(BoundaryViolation { pos: p, dir: *dir, neighbor: None });
+                    }
+
+                    else if self.get(n) == Tile::Unused {
+                        out.push(BoundaryViolation { pos: p, dir: *dir, neighbor: Some(Tile::Unused) });
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    // a door breaking both rules is reported once for each
+    pub fn validate_doors(&self) -> Vec<DoorViolation> {
+        let size = self.size();
+        let mut out = Vec::new();
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if !self.get(p).is_door() {
+                    continue;
+                }
+
+                if !self.door_fits(p) {
+                    out.push(DoorViolation { pos: p, rule: DoorRule::Misaligned });
+                }
+
+                if self.touches_door(p) {
+                    out.push(DoorViolation { pos: p, rule: DoorRule::Adjacent });
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dir, Dungeon, Tile };
+    use geom::Point;
+    use validate::*;
+
+    #[test]
+    fn test_door_alignment() {
+        let d = Dungeon::parse(&[
+            "#########",
+            "#...#...#",
+            "#...+...#",
+            "#+#####.#",
+            "#.#   .+#",
+            "#.+   ..#",
+            "#########",
+        ]);
+
+        assert!(d.door_fits(Point::new(4, 2)));
+        assert!(d.door_fits(Point::new(1, 3)));
+        assert_eq!(d.validate_doors(), vec![
+            // open on three sides, a corner the walls left open
+            DoorViolation { pos: Point::new(7, 4), rule: DoorRule::Misaligned },
+            // nothing walkable past it
+            DoorViolation { pos: Point::new(2, 5), rule: DoorRule::Misaligned },
+        ]);
+    }
+
+    #[test]
+    fn test_adjacent_doors() {
+        let d = Dungeon::parse(&[
+            "#######",
+            "#..#..#",
+            "#..+..#",
+            "###-###",
+            "  #.#  ",
+            "  ###  ",
+        ]);
+
+        assert_eq!(d.validate_doors(), vec![
+            // the door below opens this one's wall as well
+            DoorViolation { pos: Point::new(3, 2), rule: DoorRule::Misaligned },
+            DoorViolation { pos: Point::new(3, 2), rule: DoorRule::Adjacent },
+            DoorViolation { pos: Point::new(3, 3), rule: DoorRule::Adjacent },
+        ]);
+    }
+
+    #[test]
+    fn test_generated_doors_are_valid() {
+        for seed in 0..60 {
+            let (width, height, features) = [(80, 50, 200), (40, 40, 100), (150, 150, 500)][seed as usize % 3];
+            let mut d = Dungeon::new(width, height);
+            d.generate_seeded(features, seed).unwrap();
+            assert_eq!(d.validate_doors(), vec![], "seed {}", seed);
+
+            let size = d.size();
+            let pairs = (0..size.height)
+                .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+                .filter(|&p| d.get(p).is_door() && (d.get(p.offset(1, 0)).is_door() || d.get(p.offset(0, 1)).is_door()))
+                .count();
+            assert_eq!(pairs, 0, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_boundary_violations() {
+        let d = Dungeon::parse(&[
+            "#### ",
+            "#..,,",
+            "#### ",
+        ]);
+
+        assert_eq!(d.validate_boundaries(), vec![
+            BoundaryViolation { pos: Point::new(4, 1), dir: Dir::North, neighbor: Some(Tile::Unused) },
+            BoundaryViolation { pos: Point::new(4, 1), dir: Dir::South, neighbor: Some(Tile::Unused) },
+            BoundaryViolation { pos: Point::new(4, 1), dir: Dir::East, neighbor: None },
+        ]);
+    }
+
+    #[test]
+    fn test_generated_maps_are_enclosed() {
+        for seed in 0..60 {
+            let (width, height, features) = [(30, 30, 100), (80, 50, 200), (24, 60, 80)][seed as usize % 3];
+            let mut d = Dungeon::new(width, height);
+            let _ = d.generate_seeded(features, seed);
+            assert_eq!(d.validate_boundaries(), vec![], "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,334 @@
+/*
+Thin walls, single tiles of wall with room floor straight across them on both sides. Rooms
+are placed against the walls of rooms already there, so two of them often end up sharing a
+wall one tile thick, which some games would rather treat as an opening or as solid rock.
+
+thin_walls lists them as straight runs between the same two rooms. A door or anything else
+that isn't wall ends a run. reinforce_thin_walls deals with them by the config's
+ThinWallPolicy: a door for each pair of rooms that no door joins yet, put in the longest run
+between them, or a second layer of wall on one side. Only a run that spans a whole side of
+a room can be thickened, so the room loses a full row or column and stays a clean shape,
+and never when that would cut the room in two, block a door or bury a chest or an object.
+*/
+
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+use config::ThinWallPolicy;
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+use graph::{ self, DungeonGraph, NodeKind, RoomId };
+use rng::DungenRng;
+
+// a straight run of thin wall between the same two rooms
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WallSegment {
+    // the wall tiles, in order along the run
+    pub tiles: Vec<Point>,
+    // the room on either side, the second one across dir from the first. both are the same
+    // room where a room wraps around a wall.
+    pub rooms: (RoomId, RoomId),
+    // across the wall: south for a run along a row, east for one down a column
+    pub dir: Dir,
+}
+
+impl Dungeon {
+    // every thin wall on the map, ordered by first tile in row-major order
+    pub fn thin_walls(&self) -> Vec<WallSegment> {
+        let graph = self.graph();
+        self.thin_walls_in(&graph)
+    }
+
+    // deals with every thin wall by config().thin_walls. only the door policy draws, and
+    // doors are written like generation's, so door_chance and door_state hold for them.
+    // returns the number of runs dealt with.
+    pub fn reinforce_thin_walls(&mut self, rng: &mut DungenRng) -> usize {
+        let graph = self.graph();
+        let segments = self.thin_walls_in(&graph);
+        let fixed = match self.config().thin_walls {
+            ThinWallPolicy::Door => self.punch_thin_walls(&segments, &graph, rng),
+            ThinWallPolicy::Thicken => segments.iter().filter(|segment| self.thicken(segment, &graph)).count(),
+        };
+
+        if fixed > 0 {
+            self.sync_rooms();
+        }
+        fixed
+    }
+
+    fn thin_walls_in(&self, graph: &DungeonGraph) -> Vec<WallSegment> {
+        let size = self.size();
+        let mut out = Vec::new();
+
+        for &dir in &[Dir::South, Dir::East] {
+            let (dx, dy) = dir.offset();
+            // a step along the run
+            let (ax, ay) = (dy, dx);
+            let across = |p: Point| {
+                if p.x < 0 || p.y < 0 || p.x >= size.width || p.y >= size.height || !self.get(p).is_wall() {
+                    return None
+                }
+
+                let a = graph.room_at(self.wrap_point(p.offset(-dx, -dy)))?;
+                let b = graph.room_at(self.wrap_point(p.offset(dx, dy)))?;
+                Some((a, b))
+            };
+
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    let p = Point::new(x, y);
+                    let rooms = match across(p) {
+                        Some(rooms) if across(p.offset(-ax, -ay)) != Some(rooms) => rooms,
+                        _ => continue,
+                    };
+
+                    let mut tiles = vec![p];
+                    while across(p.offset(ax * tiles.len() as isize, ay * tiles.len() as isize)) == Some(rooms) {
+                        tiles.push(p.offset(ax * tiles.len() as isize, ay * tiles.len() as isize));
+                    }
+                    out.push(WallSegment { tiles, rooms, dir });
+                }
+            }
+        }
+
+        out.sort_by_key(|segment| (segment.tiles[0].y, segment.tiles[0].x));
+        out
+    }
+
+    // a door between each pair of different rooms not joined by one already, in the
+    // longest of their runs it fits in, as near its middle as it fits
+    fn punch_thin_walls(&mut self, segments: &[WallSegment], graph: &DungeonGraph, rng: &mut DungenRng) -> usize {
+        let joined = |a: RoomId, b: RoomId| match (graph.room_node(a), graph.room_node(b)) {
+            (Some(a), Some(b)) => graph.edges.iter().any(|e| (e.a, e.b) == (a.min(b), a.max(b))),
+            _ => false,
+        };
+
+        let mut order: Vec<&WallSegment> = segments.iter().filter(|s| s.rooms.0 != s.rooms.1 && !joined(s.rooms.0, s.rooms.1)).collect();
+        order.sort_by_key(|s| Reverse(s.tiles.len()));
+        let mut done = BTreeSet::new();
+
+        for segment in order {
+            if done.contains(&segment.rooms) {
+                continue;
+            }
+
+            let (start, middle) = (segment.tiles[0], segment.tiles.len() as isize / 2);
+            let mut tiles = segment.tiles.clone();
+            tiles.sort_by_key(|p| (p.x - start.x + p.y - start.y - middle).abs());
+            let door = tiles.into_iter().find(|&p| self.get(p) == Tile::Wall && self.door_fits(p) && !self.touches_door(p));
+
+            if let Some(p) = door {
+                self.write_door(p.x, p.y, Tile::Floor, rng);
+                done.insert(segment.rooms);
+            }
+        }
+
+        done.len()
+    }
+
+    // walls over the row of floor beside segment, on the bigger room's side if it can be
+    // done there, otherwise on the other. graph must be of the map before any thickening.
+    fn thicken(&mut self, segment: &WallSegment, graph: &DungeonGraph) -> bool {
+        let (dx, dy) = segment.dir.offset();
+        let room = |d: &Dungeon, p: Point| graph::node_kind(d.get(p)) == Some(NodeKind::Room);
+        // an earlier run may have been thickened over one of this one's sides
+        let thin = segment.tiles.iter().all(|&p| room(self, self.wrap_point(p.offset(-dx, -dy))) && room(self, self.wrap_point(p.offset(dx, dy))));
+        if !thin {
+            return false
+        }
+
+        let mut sides = vec![(segment.rooms.1, dx, dy), (segment.rooms.0, -dx, -dy)];
+        sides.sort_by_key(|&(id, _, _)| Reverse(graph.room_tiles(id).len()));
+
+        for (id, dx, dy) in sides {
+            let line: Vec<Point> = segment.tiles.iter().map(|p| self.wrap_point(p.offset(dx, dy))).collect();
+            if !self.can_wall_over(&line, id, (dx, dy), graph) {
+                continue;
+            }
+
+            self.reanchor_rooms(&line, graph);
+            for &p in &line {
+                self.set_tile(p.x, p.y, Tile::Wall);
+            }
+
+            // a placement rect losing its whole side to the new wall loses that side
+            for rect in self.rooms.iter_mut() {
+                let side: Vec<Point> = match (dx, dy) {
+                    (0, 1) => (0..rect.width).map(|i| Point::new(rect.x + i, rect.y)).collect(),
+                    (0, _) => (0..rect.width).map(|i| Point::new(rect.x + i, rect.y + rect.height - 1)).collect(),
+                    (1, _) => (0..rect.height).map(|i| Point::new(rect.x, rect.y + i)).collect(),
+                    _ => (0..rect.height).map(|i| Point::new(rect.x + rect.width - 1, rect.y + i)).collect(),
+                };
+                if side.iter().all(|p| line.contains(p)) {
+                    if dx == 1 {
+                        rect.x += 1;
+                    }
+
+                    if dy == 1 {
+                        rect.y += 1;
+                    }
+                    rect.width -= dx.abs();
+                    rect.height -= dy.abs();
+                }
+            }
+            self.rooms.retain(|rect| rect.width > 0 && rect.height > 0);
+            return true
+        }
+
+        false
+    }
+
+    // whether line, the row of room id's tiles beside a thin wall with (dx, dy) pointing
+    // away from it, can be walled over
+    fn can_wall_over(&self, line: &[Point], id: RoomId, (dx, dy): (isize, isize), graph: &DungeonGraph) -> bool {
+        let ours = |p: Point| {
+            let p = self.wrap_point(p);
+            graph.room_at(p) == Some(id) && graph::node_kind(self.get(p)) == Some(NodeKind::Room)
+        };
+
+        // the whole side of the room, and the room goes on past it
+        let (ax, ay) = (dy.abs(), dx.abs());
+        let (first, last) = (line[0], line[line.len() - 1]);
+        if self.get(self.wrap_point(first.offset(-ax, -ay))).is_walkable() || self.get(self.wrap_point(last.offset(ax, ay))).is_walkable() {
+            return false
+        }
+
+        let clear = line.iter().all(|&p| {
+            self.get(p) == Tile::Floor && graph.room_at(p) == Some(id) && ours(p.offset(dx, dy)) && !self.touches_door(p)
+                && !self.chests.iter().any(|chest| chest.pos == p)
+                && Dir::iterator().all(|dir| {
+                    let (nx, ny) = dir.offset();
+                    let n = p.offset(nx, ny);
+                    !self.get(self.wrap_point(n)).is_walkable() || ours(n)
+                })
+        });
+        if !clear {
+            return false
+        }
+
+        // what's left of the room must still be in one piece
+        let left: BTreeSet<Point> = graph.room_tiles(id).iter().cloned().filter(|&p| !line.contains(&p) && ours(p)).collect();
+        let start = match left.iter().next() {
+            Some(&p) => p,
+            None => return false,
+        };
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![start];
+        seen.insert(start);
+        while let Some(p) = stack.pop() {
+            for dir in Dir::iterator() {
+                let (nx, ny) = dir.offset();
+                let n = self.wrap_point(p.offset(nx, ny));
+                if left.contains(&n) && seen.insert(n) {
+                    stack.push(n);
+                }
+            }
+        }
+        seen.len() == left.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ DungeonConfig, ThinWallPolicy };
+    use dungeon::{ Dir, Dungeon, Tile };
+    use geom::{ Point, Rect };
+    use nav::Connectivity;
+    use rng::DungenRng;
+
+    // two rooms side by side, sharing the wall between them
+    fn rooms(thin_walls: ThinWallPolicy) -> Dungeon {
+        let mut d = Dungeon::with_config(13, 7, DungeonConfig { thin_walls, ..DungeonConfig::default() });
+        d.carve_rect(Rect::new(2, 2, 3, 3), Tile::Floor).unwrap();
+        d.carve_rect(Rect::new(6, 2, 5, 3), Tile::Floor).unwrap();
+        d
+    }
+
+    #[test]
+    fn test_thin_walls() {
+        let mut d = rooms(ThinWallPolicy::Door);
+        let graph = d.graph();
+        let found = d.thin_walls();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].tiles, vec![Point::new(5, 2), Point::new(5, 3), Point::new(5, 4)]);
+        assert_eq!(found[0].dir, Dir::East);
+        assert_eq!(found[0].rooms, (graph.room_at(Point::new(2, 2)).unwrap(), graph.room_at(Point::new(6, 2)).unwrap()));
+
+        // a door splits the run in two, and then there's nothing to do
+        assert_eq!(d.reinforce_thin_walls(&mut DungenRng::new(1)), 1);
+        assert_eq!(d.get((5, 3)), Tile::ClosedDoor);
+        assert_eq!(d.thin_walls().len(), 2);
+        assert_eq!(d.reinforce_thin_walls(&mut DungenRng::new(1)), 0);
+        assert_eq!(d.regions(Connectivity::Four).len(), 1);
+    }
+
+    #[test]
+    fn test_thickening() {
+        let mut d = rooms(ThinWallPolicy::Thicken);
+        let bigger = d.graph().room_at(Point::new(6, 2)).unwrap();
+
+        // the bigger room gives up its column, and keeps its id
+        assert_eq!(d.reinforce_thin_walls(&mut DungenRng::new(1)), 1);
+        assert_eq!(d.render(), [
+            "             ",
+            " ########### ",
+            " #...##....# ",
+            " #...##....# ",
+            " #...##....# ",
+            " ########### ",
+            "             ",
+            "",
+        ].join("\n"));
+        assert!(d.thin_walls().is_empty());
+        assert_eq!(d.room(bigger).unwrap().area, 12);
+
+        // a run along only part of a room's side is left alone
+        let mut d = Dungeon::with_config(11, 8, DungeonConfig { thin_walls: ThinWallPolicy::Thicken, ..DungeonConfig::default() });
+        d.carve_rect(Rect::new(2, 2, 7, 2), Tile::Floor).unwrap();
+        d.carve_rect(Rect::new(2, 5, 5, 1), Tile::Floor).unwrap();
+        d.set_tile(4, 4, Tile::Floor);
+        d.sync_rooms();
+        let before = d.thin_walls();
+        assert_eq!(before.len(), 2);
+        assert_eq!(d.reinforce_thin_walls(&mut DungenRng::new(1)), 0);
+        assert_eq!(d.thin_walls(), before);
+    }
+
+    #[test]
+    fn test_generated_thin_walls() {
+        let (mut punched, mut thickened) = (0, 0);
+        for seed in 0..8 {
+            let mut doors = Dungeon::new(80, 50);
+            doors.generate_seeded(60, seed).unwrap();
+            assert!(!doors.thin_walls().is_empty(), "seed {}", seed);
+            let regions = doors.regions(Connectivity::Four).len();
+
+            // afterwards every pair of rooms with a thin wall between them has a door
+            punched += doors.reinforce_thin_walls(&mut DungenRng::new(seed));
+            assert_eq!(doors.validate_doors(), vec![], "seed {}", seed);
+            let graph = doors.graph();
+            for segment in doors.thin_walls().iter().filter(|s| s.rooms.0 != s.rooms.1) {
+                let (a, b) = (graph.room_node(segment.rooms.0).unwrap(), graph.room_node(segment.rooms.1).unwrap());
+                assert!(graph.edges.iter().any(|e| (e.a, e.b) == (a.min(b), a.max(b))), "seed {}: {:?}", seed, segment);
+            }
+            assert_eq!(doors.reinforce_thin_walls(&mut DungenRng::new(seed)), 0);
+
+            let mut thick = Dungeon::with_config(80, 50, DungeonConfig { thin_walls: ThinWallPolicy::Thicken, ..DungeonConfig::default() });
+            thick.generate_seeded(60, seed).unwrap();
+            let before = thick.thin_walls().len();
+            let ids: Vec<_> = thick.room_ids().collect();
+            let count = thick.reinforce_thin_walls(&mut DungenRng::new(seed));
+            // thickening one run can leave another beside it with floor on one side only
+            assert!(thick.thin_walls().len() <= before - count, "seed {}", seed);
+            thickened += count;
+            assert!(thick.validate_boundaries().is_empty());
+            assert_eq!(thick.validate_doors(), vec![], "seed {}", seed);
+            assert_eq!(thick.regions(Connectivity::Four).len(), regions, "seed {}", seed);
+            assert_eq!(thick.room_ids().collect::<Vec<_>>(), ids, "seed {}", seed);
+        }
+        // most rooms that share a wall already have a door through it
+        assert!(punched > 0 && thickened > 0);
+    }
+}
@@ -0,0 +1,20 @@
+/*
+Browser bindings, behind the "wasm" feature. Build with default features off so nothing
+asks the os for entropy:
+
+cargo build --target wasm32-unknown-unknown --no-default-features --features wasm
+*/
+
+use wasm_bindgen::prelude::*;
+use dungeon::Dungeon;
+
+// a seeded dungeon as glyph rows, one line per row. errors come back as their messages.
+#[wasm_bindgen]
+pub fn generate_glyphs(width: i32, height: i32, maxfeatures: i32, seed: u64) -> Result<String, JsValue> {
+    let mut d = Dungeon::try_new(width as isize, height as isize)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    d.generate_seeded(maxfeatures as isize, seed)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    Ok(d.render())
+}
@@ -0,0 +1,141 @@
+/*
+How deep standing water is, and the ground along its edge. Nothing the crate generates
+floods yet, so water comes from the caller: set into the map, or read from a prefab or a
+parsed map as '~'.
+
+A water tile's depth is the number of orthogonal steps to the nearest shore, a walkable tile
+that isn't water, so the ring of water touching the shore is depth 1 and it deepens toward
+the middle. Walls and other unwalkable tiles aren't shore: a pool running up to a wall is as
+deep there as the water in front of it. Water with no shore at all, sealed in by walls, is
+255, and so is anything deeper than that.
+*/
+
+use std::collections::VecDeque;
+use dungeon::{ Dir, Dungeon, Tile };
+use geom::Point;
+
+impl Dungeon {
+    // each tile's depth, row-major: 0 for every tile that isn't water, see the module
+    // comment for those that are
+    pub fn water_depth(&self) -> Vec<u8> {
+        let size = self.size();
+        let mut depth = vec![0u8; size.area() as usize];
+        let mut queue = VecDeque::new();
+
+        // every water tile on the shore starts off the search at 1
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) == Tile::Water && self.water_neighbors(p).any(|n| self.get(n).is_walkable()) {
+                    depth[self.index(p)] = 1;
+                    queue.push_back(p);
+                }
+            }
+        }
+
+        while let Some(p) = queue.pop_front() {
+            let next = depth[self.index(p)].saturating_add(1);
+            for n in self.water_neighbors(p) {
+                if self.get(n) == Tile::Water && depth[self.index(n)] == 0 {
+                    depth[self.index(n)] = next;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        // what the search never reached has no shore
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let p = Point::new(x, y);
+                if self.get(p) == Tile::Water && depth[self.index(p)] == 0 {
+                    depth[self.index(p)] = u8::MAX;
+                }
+            }
+        }
+
+        depth
+    }
+
+    // floor and grass tiles with water on one of their four sides, in row-major order
+    pub fn shoreline(&self) -> Vec<Point> {
+        let size = self.size();
+        (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x, y)))
+            .filter(|&p| matches!(self.get(p), Tile::Floor | Tile::Grass))
+            .filter(|&p| self.water_neighbors(p).any(|n| self.get(n) == Tile::Water))
+            .collect()
+    }
+
+    // p's four neighbours on the map, wrapped
+    fn water_neighbors(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        let size = self.size();
+        Dir::iterator()
+            .map(move |dir| {
+                let (dx, dy) = dir.offset();
+                self.wrap_point(p.offset(dx, dy))
+            })
+            .filter(move |n| n.x >= 0 && n.y >= 0 && n.x < size.width && n.y < size.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dungeon::{ Dungeon, Tile };
+    use geom::Point;
+
+    // depths drawn one digit a tile, '.' for dry ground and '*' for no shore
+    fn depths(d: &Dungeon) -> Vec<String> {
+        let width = d.size().width as usize;
+        d.water_depth().chunks(width).map(|row| row.iter().map(|&n| match n {
+            0 => '.',
+            255 => '*',
+            n => (b'0' + n) as char,
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn test_lake_depths() {
+        // a lake running out through a gap in the wall, deepest where it's furthest from the shore, and a
+        // pool sealed in the corner
+        let d = Dungeon::parse(&[
+            "#############",
+            "#...........#",
+            "#.~~~~~~~~..#",
+            "#.~~~~~~~~~.#",
+            "#.~~~~~~~~~.#",
+            "#.~~~~~~~~~.#",
+            "#..~~~~~~~..#",
+            "####~~~######",
+            "#~~##########",
+        ]);
+        assert_eq!(depths(&d), vec![
+            ".............",
+            ".............",
+            "..11111111...",
+            "..122222221..",
+            "..123333321..",
+            "..123444321..",
+            "...1234321...",
+            "....345......",
+            ".**..........",
+        ]);
+
+        // and the ground around it, reeds going where it meets the water
+        let shore = d.shoreline();
+        assert!(shore.iter().all(|&p| d.get(p) == Tile::Floor));
+        assert_eq!(shore.len(), 18);
+        assert_eq!(shore[0], Point::new(2, 1));
+        assert!(shore.contains(&Point::new(11, 3)) && shore.contains(&Point::new(10, 2)) && !shore.contains(&Point::new(1, 1)));
+        let mut sorted = shore.clone();
+        sorted.sort_by_key(|p| (p.y, p.x));
+        assert_eq!(sorted, shore);
+    }
+
+    #[test]
+    fn test_dry_maps() {
+        let mut d = Dungeon::new(60, 40);
+        d.generate_seeded(30, 2).unwrap();
+        assert!(d.water_depth().iter().all(|&n| n == 0));
+        assert!(d.shoreline().is_empty());
+    }
+}
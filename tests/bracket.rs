@@ -0,0 +1,50 @@
+// walks generated dungeons with bracket-lib's pathfinding and field of view
+// cargo test --test bracket --features bracket
+#![cfg(feature = "bracket")]
+
+extern crate bracket_pathfinding;
+extern crate dungen;
+
+use bracket_pathfinding::prelude::{ a_star_search, field_of_view, BaseMap, DijkstraMap, Point };
+use dungen::dungeon::Dungeon;
+use dungen::nav::Connectivity;
+
+#[test]
+fn test_bracket_paths() {
+    for seed in 0..20 {
+        let mut d = Dungeon::new(80, 50);
+        d.generate_seeded(50, seed).unwrap();
+
+        let map = d.bracket_map();
+        let (entrance, exit) = (d.entrance().unwrap(), d.exit().unwrap());
+        let ours = d.find_path(entrance, exit, Connectivity::Four).unwrap();
+
+        // bracket's dijkstra over the adapter's exits and costs measures the same distance
+        // as the crate's own shortest path
+        let dijkstra = DijkstraMap::new(80, 50, &[map.index_of(entrance)], &map, 1000.0);
+        assert_eq!(dijkstra.map[map.index_of(exit)], (ours.len() - 1) as f32, "seed {}", seed);
+
+        // every tile's exits are its walkable neighbours, as nav has them
+        for i in 0..80 * 50 {
+            let exits: Vec<usize> = map.get_available_exits(i).iter().map(|&(n, _)| n).collect();
+            let neighbors: Vec<usize> = d.neighbors(map.point_of(i), Connectivity::Four).into_iter().map(|p| map.index_of(p)).collect();
+            assert_eq!(exits, neighbors, "seed {} at {:?}", seed, map.point_of(i));
+        }
+
+        // bracket 0.8's a_star_search doesn't add up costs along the way, so its route can
+        // be longer than the shortest, and on a large map it can run out of steps and give
+        // up. a route it does find is made of the adapter's exits from entrance to exit.
+        let path = a_star_search(map.index_of(entrance), map.index_of(exit), &map);
+        if path.success {
+            assert!(path.steps.len() >= ours.len(), "seed {}", seed);
+            assert_eq!(path.steps.first(), Some(&map.index_of(entrance)));
+            assert_eq!(path.steps.last(), Some(&map.index_of(exit)));
+            for step in path.steps.windows(2) {
+                assert!(map.get_available_exits(step[0]).iter().any(|&(n, _)| n == step[1]), "seed {}", seed);
+            }
+        }
+
+        let seen = field_of_view(Point::new(entrance.x as i32, entrance.y as i32), 8, &map);
+        assert!(seen.contains(&Point::new(entrance.x as i32, entrance.y as i32)), "seed {}", seed);
+    }
+}
@@ -0,0 +1,96 @@
+// runs the dungen binary and inspects what it writes
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{ Command, Output };
+
+fn dungen(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dungen")).args(args).output().unwrap()
+}
+
+// an empty scratch directory per test
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("dungen-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_ascii_to_stdout() {
+    let out = dungen(&["--width", "40", "--height", "20", "--features", "15", "--seed", "3"]);
+    let text = String::from_utf8(out.stdout).unwrap();
+    let rows: Vec<&str> = text.lines().collect();
+
+    assert!(out.status.success());
+    assert_eq!(rows.len(), 20);
+    assert!(rows.iter().all(|r| r.chars().count() == 40));
+    assert_eq!(text.matches('<').count(), 1);
+    assert_eq!(text.matches('>').count(), 1);
+
+    // the same seed always draws the same map
+    assert_eq!(dungen(&["--width", "40", "--height", "20", "--features", "15", "--seed", "3"]).stdout, text.as_bytes());
+}
+
+#[test]
+fn test_json_file() {
+    let dir = scratch("json");
+    let path = dir.join("map.json");
+    let out = dungen(&["--width", "30", "--height", "25", "--seed", "7", "--format", "json", "--out", path.to_str().unwrap()]);
+    let json = fs::read_to_string(&path).unwrap();
+
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(json.starts_with("{\"width\":30,\"height\":25,\"seed\":7,\"entrance\":["));
+    assert_eq!(json.matches("\",\"").count(), 24);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_png_file() {
+    let dir = scratch("png");
+    let path = dir.join("map.png");
+    let out = dungen(&["--width", "40", "--height", "30", "--seed", "3", "--format", "png", "--scale", "2", "--out", path.to_str().unwrap()]);
+    let png = fs::read(&path).unwrap();
+
+    assert!(out.status.success());
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    // 80x60 pixels
+    assert_eq!(&png[16..24], &[0, 0, 0, 80, 0, 0, 0, 60]);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_count_writes_numbered_files() {
+    let dir = scratch("count");
+    let path = dir.join("map.txt");
+    let out = dungen(&["--width", "40", "--height", "30", "--seed", "100", "--count", "3", "--out", path.to_str().unwrap()]);
+
+    assert!(out.status.success());
+    for seed in 100..103 {
+        let single = dungen(&["--width", "40", "--height", "30", "--seed", &seed.to_string()]);
+        assert_eq!(fs::read(dir.join(format!("map-{}.txt", seed))).unwrap(), single.stdout);
+    }
+    assert!(!path.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_failures_exit_nonzero() {
+    let small = dungen(&["--width", "3", "--height", "3"]);
+    assert_eq!(small.status.code(), Some(1));
+    assert!(String::from_utf8(small.stderr).unwrap().contains("too small"));
+
+    let features = dungen(&["--features", "0", "--seed", "1"]);
+    assert_eq!(features.status.code(), Some(1));
+
+    for args in [&["--format", "svg"][..], &["--width"], &["--width", "wide"], &["--format", "png"], &["--bogus", "1"]].iter() {
+        let out = dungen(args);
+        assert_eq!(out.status.code(), Some(2), "{:?}", args);
+        assert!(out.stdout.is_empty());
+        assert!(String::from_utf8(out.stderr).unwrap().starts_with("dungen: "));
+    }
+}
@@ -0,0 +1,28 @@
+// generation in the browser, with no os entropy available.
+// wasm-pack test --node -- --no-default-features --features wasm
+#![cfg(target_arch = "wasm32")]
+
+extern crate dungen;
+extern crate wasm_bindgen_test;
+
+use dungen::dungeon::{ Dungeon, Tile };
+use dungen::wasm::generate_glyphs;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn test_generate_50x50() {
+    let mut d = Dungeon::new(50, 50);
+    d.generate_seeded(35, 12345).unwrap();
+
+    let tiles = d.tile_indices(|t| t as u32);
+    let count = |tile: Tile| tiles.iter().filter(|&&t| t == tile as u32).count();
+    assert_eq!(tiles.len(), 50 * 50);
+    assert_eq!(count(Tile::Entrance), 1);
+    assert_eq!(count(Tile::Exit), 1);
+    assert!(count(Tile::Floor) > 0);
+    assert!(count(Tile::Wall) > 0);
+
+    let glyphs = generate_glyphs(50, 50, 35, 12345).unwrap();
+    assert_eq!(glyphs, d.render());
+    assert!(generate_glyphs(0, 50, 35, 1).is_err());
+}